@@ -19,12 +19,13 @@
     unused_assignments
 )]
 
-use crate::bcos2sdk::bcosrpcwraper::BcosRPC;
+use crate::bcos2sdk::bcosrpcwraper::{receipt_from_committed_pack, BcosRPC};
 use crate::bcos2sdk::bcostransaction;
 use crate::bcos2sdk::bcostransaction::{BcosTransaction, BcosTransactionWithSig};
+use crate::bcos2sdk::channelpack::CHANNEL_PACK_TYPE;
 use crate::bcossdkutil::accountutil::{account_from_pem, BcosAccount};
 use crate::bcossdkutil::bcosclientconfig::BcosClientProtocol;
-use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ClientConfig};
+use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ClientConfig, RequestPolicy};
 use crate::bcossdkutil::commonhash::{CommonHash, HashType};
 use crate::bcossdkutil::commonsigner::{
     CommonSignerWeDPR_SM2, CommonSignerWeDPR_Secp256, ICommonSigner,
@@ -37,6 +38,7 @@ use ethabi::Token;
 use ethereum_types::U256;
 use serde_json::{json, Value as JsonValue};
 use time::Tm;
+use tokio::sync::watch;
 
 #[derive()]
 pub struct Bcos2Client {
@@ -54,12 +56,26 @@ pub struct Bcos2Client {
 //unsafe impl Send for BcosSDK{}
 //unsafe impl Sync for BcosSDK{}
 
+//with_policy用的恢复策略的守卫，离开作用域时把保存的策略设置回去
+struct Bcos2PolicyGuard<'a> {
+    client: &'a mut Bcos2Client,
+    saved: Option<RequestPolicy>,
+}
+
+impl Drop for Bcos2PolicyGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            self.client.netclient.set_policy(saved);
+        }
+    }
+}
+
 impl Bcos2Client {
     pub fn to_summary(&self) -> String {
         let basic = format!(
             "Crypto kind:{:?},configfile:{}",
             &self.config.common.crypto,
-            &self.config.configfile.as_ref().unwrap()
+            self.config.configfile.as_deref().unwrap_or("(none)")
         );
         let protocol;
         match self.config.bcos2.protocol {
@@ -81,7 +97,11 @@ impl Bcos2Client {
     }
 
     pub fn new_from_config(configfile: &str) -> Result<Bcos2Client, KissError> {
-        let config = ClientConfig::load(configfile)?;
+        Bcos2Client::new_with_config(ClientConfig::load(configfile)?)
+    }
+
+    ///用已经构造好的配置创建client，配置可以来自ClientConfig::builder()，不需要配置文件
+    pub fn new_with_config(config: ClientConfig) -> Result<Bcos2Client, KissError> {
         printlnex!("config is {:?}", config);
 
         //国密和非国密关键步骤，设置hash,账户类型和签名的密码学方法
@@ -114,7 +134,7 @@ impl Bcos2Client {
             ecdsasigner: ecdsasigner.clone(),
             hashtype: hashtype.clone(),
             //默认初始化为500秒前，以便第一次一定会去取一下blocknum
-            updateblocknum_tick: time::now() - chrono::Duration::seconds(500),
+            updateblocknum_tick: time::now() - time::Duration::seconds(500),
             lastblocknum: 0,
         })
     }
@@ -130,6 +150,54 @@ impl Bcos2Client {
         self.netclient.finish();
     }
 
+    ///当前生效的请求策略
+    pub fn policy(&self) -> &RequestPolicy {
+        &self.netclient.policy
+    }
+
+    pub fn set_policy(&mut self, policy: RequestPolicy) {
+        self.netclient.set_policy(policy);
+    }
+
+    ///临时换一个请求策略执行一次调用，如单独放宽某个请求的超时，调用完恢复原来的策略
+    pub fn with_policy<T, F>(&mut self, policy: RequestPolicy, f: F) -> T
+    where
+        F: FnOnce(&mut Self) -> T,
+    {
+        let saved = self.netclient.policy.clone();
+        self.netclient.set_policy(policy);
+        //f里panic时也要恢复原来的策略
+        let guard = Bcos2PolicyGuard {
+            client: self,
+            saved: Some(saved),
+        };
+        f(&mut *guard.client)
+    }
+
+    ///切换到同一节点上的另一个群组，之后的请求都发往新群组。切换后块高缓存作废，下次重新获取
+    pub fn set_groupid(&mut self, groupid: u32) {
+        if self.config.bcos2.groupid == groupid {
+            return;
+        }
+        self.config.bcos2.groupid = groupid;
+        self.netclient.config.bcos2.groupid = groupid;
+        self.lastblocknum = 0;
+        self.updateblocknum_tick = time::now() - time::Duration::seconds(500);
+    }
+
+    ///当前群组最新块高的订阅。channel方式下节点推送的块高在client读连接时更新：
+    /// 每次请求、getBlockLimit和poll_blocknumber都会处理收到的推送，没有后台读任务，
+    /// 不发请求时要定时调用poll_blocknumber，或者改用BcosChannelAsyncClient::subscribe_block_notify。
+    /// rpc方式下随getBlockLimit刷新
+    pub fn watch_blocknumber(&self) -> watch::Receiver<u64> {
+        self.netclient.channel_client.blocknotifier.subscribe()
+    }
+
+    ///非阻塞地处理连接上已收到的推送，返回节点推送的最新块高，rpc方式或还没收到推送时返回None
+    pub fn poll_blocknumber(&mut self) -> Option<u64> {
+        self.netclient.notified_blocknumber()
+    }
+
     pub fn deploy_hexcode(&mut self, hexcode: &str) -> Result<JsonValue, KissError> {
         let block_limit = self.getBlockLimit()?;
         let to_address = "".to_string();
@@ -287,28 +355,77 @@ impl Bcos2Client {
         let rawdata = txsig.encode();
         Ok(rawdata)
     }
-    ///输入已经解析好的param，直接根据组包，调用合约
-    pub fn send_raw_transaction_withtokenparam(
+    ///按ABI编码调用参数，组成交易并签名，返回hex格式的交易数据
+    pub fn make_signed_rawtx_hex(
         &mut self,
         contract: &ContractABI,
         to_address: &str,
         methodname: &str,
         params: &[Token],
-    ) -> Result<JsonValue, KissError> {
+    ) -> Result<String, KissError> {
         let block_limit = self.getBlockLimit()?;
         let function = contract.find_function_unwrap(methodname)?;
         //println!("function : {:?}",function);
         let txinput =
             ContractABI::encode_function_input_to_abi_by_tokens(&function, params, &self.hashtype)?;
         let tx = self.make_transaction(to_address, &hex::encode(txinput), block_limit);
+        let rawdata = self.encode_sign_raw_tx(&tx.unwrap())?;
+        Ok(hex::encode(rawdata))
+    }
+
+    ///输入已经解析好的param，直接根据组包，调用合约
+    pub fn send_raw_transaction_withtokenparam(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        methodname: &str,
+        params: &[Token],
+    ) -> Result<JsonValue, KissError> {
+        let hexdata = self.make_signed_rawtx_hex(contract, to_address, methodname, params)?;
         let groupid = self.config.bcos2.groupid;
         let cmd = "sendRawTransaction";
-        let rawdata = self.encode_sign_raw_tx(&tx.unwrap())?;
-        let hexdata = hex::encode(rawdata);
         let paramobj = json!([groupid, hexdata]);
         let value = self.netclient.rpc_request_sync(cmd, &paramobj)?;
         Ok(value)
     }
+
+    ///发交易并等待回执。channel方式下等待节点按请求seq推送的TX_COMMITTED，
+    /// rpc方式下没有推送，退回到轮询getTransactionReceipt。返回格式和getTransactionReceipt一致
+    pub fn send_raw_transaction_wait_receipt(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        methodname: &str,
+        params: &[Token],
+        timeoutsec: u64,
+    ) -> Result<JsonValue, KissError> {
+        let hexdata = self.make_signed_rawtx_hex(contract, to_address, methodname, params)?;
+        let groupid = self.config.bcos2.groupid;
+        let cmd = "sendRawTransaction";
+        let paramobj = json!([groupid, hexdata]);
+        match self.config.bcos2.protocol {
+            BcosClientProtocol::CHANNEL => {
+                let (response, pushpack) = self.netclient.rpc_request_and_wait_push(
+                    cmd,
+                    &paramobj,
+                    CHANNEL_PACK_TYPE::TX_COMMITTED,
+                    timeoutsec,
+                )?;
+                printlnex!("response {:?}", response);
+                receipt_from_committed_pack(&pushpack)
+            }
+            _ => {
+                let response = self.netclient.rpc_request_sync(cmd, &paramobj)?;
+                let txhash = match response["result"].as_str() {
+                    Some(h) => h.to_string(),
+                    None => {
+                        return kisserr!(KissErrKind::Error, "{} error {:?}", cmd, response);
+                    }
+                };
+                self.try_getTransactionReceipt(txhash.as_str(), timeoutsec as i64, false)
+            }
+        }
+    }
     ///输入字符串数组类型的param,根据合约ABI解析并组包，调用合约
     pub fn send_raw_transaction(
         &mut self,
@@ -332,13 +449,11 @@ impl Bcos2Client {
         methodname: &str,
         params: &[Token],
     ) -> Result<JsonValue, KissError> {
-        let response =
-            self.send_raw_transaction_withtokenparam(&contract, &to_address, methodname, params)?;
-        let txhash = response["result"].as_str().unwrap();
-        self.try_getTransactionReceipt(txhash, 3, false)
+        let timeout = self.netclient.policy.receipt_timeout;
+        self.send_raw_transaction_wait_receipt(&contract, &to_address, methodname, params, timeout)
     }
 
-    ///简单封装下同步的发送交易且获得回执的方法。默认等待1s,这是个非常常用的方法，尤其是用于demo时
+    ///简单封装下同步的发送交易且获得回执的方法。等待时间见请求策略的receipt_timeout,这是个非常常用的方法，尤其是用于demo时
     pub fn sendRawTransactionGetReceipt(
         &mut self,
         contract: &ContractABI,
@@ -346,10 +461,13 @@ impl Bcos2Client {
         methodname: &str,
         params: &[String],
     ) -> Result<JsonValue, KissError> {
-        let response = self.send_raw_transaction(&contract, &to_address, methodname, &params)?;
-        println!("response {:?}", response);
-        let txhash = response["result"].as_str().unwrap();
-        self.try_getTransactionReceipt(txhash, 3, false)
+        let txinput = contract.convert_function_input_str_to_token(methodname, params, true)?;
+        self.sendRawTransactionGetReceiptWithTokenParam(
+            &contract,
+            &to_address,
+            methodname,
+            &txinput,
+        )
     }
     ///https://fisco-bcos-documentation.readthedocs.io/zh_CN/latest/docs/api.html#sendrawtransactionandgetproof
     pub fn sendRawTransactionAndGetProof(
@@ -0,0 +1,228 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 节点通过TX_BLOCKNUM(0x1001)推送最新块高，需要先用TOPIC_REPORT上报_block_notify_{groupid}。
+//! 块高放在tokio::sync::watch里，组交易算blocklimit和监听出块的应用都可以读取或等待变化
+use std::sync::Arc;
+
+use serde_json::Value as JsonValue;
+use tokio::sync::watch;
+
+use crate::bcos2sdk::bcos_channel_handler_manager::IChannelPushHandlerFacade;
+use crate::bcos2sdk::channelpack::{unpack_amop, ChannelPack};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+pub fn block_notify_topic(groupid: u32) -> String {
+    format!("_block_notify_{}", groupid)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockNumberNotification {
+    pub groupid: u32,
+    pub blocknumber: u64,
+}
+
+///字段可能是数字，也可能是十进制或0x开头的字符串
+fn json_to_u64(v: &JsonValue) -> Option<u64> {
+    match v {
+        JsonValue::Number(n) => n.as_u64(),
+        JsonValue::String(s) => {
+            let s = s.trim();
+            if s.starts_with("0x") {
+                u64::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+            } else {
+                s.parse().ok()
+            }
+        }
+        _ => None,
+    }
+}
+
+///包体有两种格式：json {"groupID":"1","blockNumber":"100"}，
+/// 或老版本协议下amop格式打包的"groupid,blocknumber"
+pub fn parse_block_notify(pack: &ChannelPack) -> Result<BlockNumberNotification, KissError> {
+    if let Ok(v) = serde_json::from_slice::<JsonValue>(&pack.data[..]) {
+        if let (Some(groupid), Some(blocknumber)) =
+            (json_to_u64(&v["groupID"]), json_to_u64(&v["blockNumber"]))
+        {
+            return Ok(BlockNumberNotification {
+                groupid: groupid as u32,
+                blocknumber,
+            });
+        }
+    }
+    if let Some(n) = parse_block_notify_csv(&pack.data[..]) {
+        return Ok(n);
+    }
+    if pack.data.len() > 1 && (pack.data[0] as usize) <= pack.data.len() {
+        let (_topic, data) = unpack_amop(&pack.data);
+        if let Some(n) = parse_block_notify_csv(data.as_slice()) {
+            return Ok(n);
+        }
+    }
+    kisserr!(
+        KissErrKind::EFormat,
+        "unknown block notify data {:?}",
+        String::from_utf8_lossy(&pack.data[..])
+    )
+}
+
+fn parse_block_notify_csv(data: &[u8]) -> Option<BlockNumberNotification> {
+    let text = String::from_utf8_lossy(data);
+    let fields: Vec<&str> = text.trim().split(',').collect();
+    if fields.len() != 2 {
+        return None;
+    }
+    match (
+        fields[0].trim().parse::<u32>(),
+        fields[1].trim().parse::<u64>(),
+    ) {
+        (Ok(groupid), Ok(blocknumber)) => Some(BlockNumberNotification {
+            groupid,
+            blocknumber,
+        }),
+        _ => None,
+    }
+}
+
+///保存某个群组的最新块高，只会往大了更新
+#[derive(Debug, Clone)]
+pub struct BlockNumberNotifier {
+    pub groupid: u32,
+    pub sender: Arc<watch::Sender<u64>>,
+}
+
+impl BlockNumberNotifier {
+    pub fn new(groupid: u32) -> BlockNumberNotifier {
+        let (sender, _) = watch::channel(0u64);
+        BlockNumberNotifier {
+            groupid,
+            sender: Arc::new(sender),
+        }
+    }
+
+    ///订阅块高的变化，0表示还没有收到过块高
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.sender.subscribe()
+    }
+
+    pub fn latest(&self) -> u64 {
+        *self.sender.borrow()
+    }
+
+    pub fn update(&self, blocknumber: u64) {
+        self.sender.send_if_modified(|current| {
+            if blocknumber > *current {
+                *current = blocknumber;
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
+impl IChannelPushHandlerFacade for BlockNumberNotifier {
+    fn handle(&self, pack: &ChannelPack) {
+        match parse_block_notify(pack) {
+            Ok(n) => {
+                if n.groupid == self.groupid {
+                    log::debug!("block notify group {} number {}", n.groupid, n.blocknumber);
+                    self.update(n.blocknumber);
+                }
+            }
+            Err(e) => {
+                log::warn!("parse block notify error {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::bcos2client::Bcos2Client;
+    use crate::bcos2sdk::channelpack::{make_channel_pack_by_rawdata, CHANNEL_PACK_TYPE};
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_block_notify() {
+        let pack = |data: &[u8]| {
+            make_channel_pack_by_rawdata(CHANNEL_PACK_TYPE::TX_BLOCKNUM, &data.to_vec()).unwrap()
+        };
+        let n = parse_block_notify(&pack(br#"{"groupID":"2","blockNumber":"0x10"}"#)).unwrap();
+        assert_eq!((n.groupid, n.blocknumber), (2, 16));
+        let n = parse_block_notify(&pack(b"1,100")).unwrap();
+        assert_eq!((n.groupid, n.blocknumber), (1, 100));
+        assert!(parse_block_notify(&pack(b"bad")).is_err());
+    }
+
+    #[test]
+    fn test_blocknumber_push_to_client() {
+        let node = MockChannelNode::start().unwrap();
+        let mut client =
+            Bcos2Client::new_from_config(&node.write_client_config().unwrap()).unwrap();
+        let rx = client.watch_blocknumber();
+        assert_eq!(*rx.borrow(), 0);
+        //topic上报没有回包，等节点收到后再推送
+        let start = Instant::now();
+        while !node.topics().contains(&block_notify_topic(1)) {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        node.chain().blocknumber = 10;
+        assert_eq!(node.push_block_number(), 1);
+        let start = Instant::now();
+        while client.poll_blocknumber() != Some(10) {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(*rx.borrow(), 10);
+
+        //块高只会往大了更新，getBlockLimit直接用推送的块高，不再发getBlockNumber
+        node.chain().blocknumber = 5;
+        node.push_block_number();
+        std::thread::sleep(Duration::from_millis(100));
+        let limit = client.getBlockLimit().unwrap();
+        assert_eq!(*rx.borrow(), 10);
+        assert_eq!(limit, 10 + 500);
+        let requested = node
+            .received_packs()
+            .iter()
+            .any(|p| String::from_utf8_lossy(&p.data).contains("getBlockNumber"));
+        assert!(!requested);
+
+        //推送停了，块高没有再变大，超过30秒后要回到getBlockNumber查询
+        node.chain().blocknumber = 800;
+        client.updateblocknum_tick = time::now() - time::Duration::seconds(31);
+        let limit = client.getBlockLimit().unwrap();
+        assert_eq!(limit, 800 + 500);
+        let requested = node
+            .received_packs()
+            .iter()
+            .any(|p| String::from_utf8_lossy(&p.data).contains("getBlockNumber"));
+        assert!(requested);
+        assert_eq!(*rx.borrow(), 800);
+        client.finish();
+    }
+}
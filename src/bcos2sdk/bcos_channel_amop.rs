@@ -0,0 +1,353 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! AMOP(链上信使协议)：
+//! 订阅方用TOPIC_REPORT上报topic，节点把发往这个topic的AMOP_REQ转给订阅方，
+//! 订阅方用同一个seq回AMOP_RESP；发送方等待AMOP_RESP，result非0表示失败。
+//! 广播用TOPIC_MULTICAST，不等待回包。
+//! 参考 https://fisco-bcos-documentation.readthedocs.io/zh_CN/latest/docs/manual/amop_protocol.html
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::bcos2sdk::bcos_channel_amop_private::VERIFY_CHANNEL_PREFIX;
+use crate::bcos2sdk::bcos_channel_async_client::{
+    send_pack_on, BcosChannelAsyncClient, ChannelSendLock, ChannelTransportSlot,
+};
+use crate::bcos2sdk::bcos_channel_handler_manager::IChannelPushHandlerFacade;
+use crate::bcos2sdk::channelpack::{
+    make_channel_pack_by_rawdata, pack_amop, unpack_amop, ChannelPack, CHANNEL_PACK_TYPE,
+};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::{kisserr, kisserrcode};
+
+///AMOP回包里的错误码
+pub const AMOP_RESULT_NODE_UNREACHABLE: u32 = 100;
+pub const AMOP_RESULT_SDK_UNREACHABLE: u32 = 101;
+pub const AMOP_RESULT_TIMEOUT: u32 = 102;
+
+///topic长度放在1字节的头部里
+pub const AMOP_TOPIC_MAX_LEN: usize = 254;
+
+pub trait IAmopTopicHandler {
+    ///收到topic上的消息。单播消息返回Some时作为AMOP_RESP回给发送方，广播消息的返回值被忽略。
+    /// 在读任务里被调用，耗时的处理应自行转到其他任务
+    fn on_message(&self, topic: &str, data: &[u8]) -> Option<Vec<u8>>;
+}
+pub type AMOP_HANDLER_OBJ = Arc<dyn IAmopTopicHandler + Send + Sync>;
+pub type AMOP_HANDLER_MAP = HashMap<String, AMOP_HANDLER_OBJ>;
+
+///用闭包作为topic处理器
+pub struct AmopFnHandler<F>
+where
+    F: Fn(&str, &[u8]) -> Option<Vec<u8>>,
+{
+    pub func: F,
+}
+
+impl<F> IAmopTopicHandler for AmopFnHandler<F>
+where
+    F: Fn(&str, &[u8]) -> Option<Vec<u8>>,
+{
+    fn on_message(&self, topic: &str, data: &[u8]) -> Option<Vec<u8>> {
+        (self.func)(topic, data)
+    }
+}
+
+///注册在ChannelPushHandlerManager上，按topic把收到的AMOP包分发给处理器
+pub struct AmopDispatcher {
+    pub transport: ChannelTransportSlot,
+    pub sendlock: ChannelSendLock,
+    pub handlers: Arc<Mutex<AMOP_HANDLER_MAP>>,
+}
+
+impl AmopDispatcher {
+    ///精确匹配topic；私有topic的VerifyChannel可能带节点加的后缀，按最长前缀匹配
+    pub fn find_handler(&self, topic: &str) -> Option<AMOP_HANDLER_OBJ> {
+        let handlers = self.handlers.lock().unwrap();
+        if let Some(h) = handlers.get(topic) {
+            return Some(h.clone());
+        }
+        if !topic.starts_with(VERIFY_CHANNEL_PREFIX) {
+            return None;
+        }
+        handlers
+            .iter()
+            .filter(|(k, _)| k.starts_with(VERIFY_CHANNEL_PREFIX) && topic.starts_with(k.as_str()))
+            .max_by_key(|(k, _)| k.len())
+            .map(|(_, h)| h.clone())
+    }
+
+    ///订阅方回包，seq和请求一致。处理器在读任务里被调用，回包放到单独的任务里发
+    pub fn reply(&self, reqpack: &ChannelPack, topic: &str, data: &Vec<u8>) {
+        let mut pack = make_channel_pack_by_rawdata(
+            CHANNEL_PACK_TYPE::AMOP_RESP,
+            &pack_amop(&topic.as_bytes().to_vec(), data),
+        )
+        .unwrap();
+        pack.seq = reqpack.seq;
+        let transport = self.transport.clone();
+        let sendlock = self.sendlock.clone();
+        let topic = topic.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = send_pack_on(&transport, &sendlock, &pack).await {
+                log::error!("amop reply to topic {} error {:?}", topic, e);
+            }
+        });
+    }
+}
+
+impl IChannelPushHandlerFacade for AmopDispatcher {
+    fn handle(&self, pack: &ChannelPack) {
+        let (topicbytes, data) = unpack_amop(&pack.data);
+        let topic = String::from_utf8_lossy(topicbytes.as_slice()).to_string();
+        let handler = match self.find_handler(topic.as_str()) {
+            Some(h) => h,
+            None => {
+                log::warn!("amop message on unsubscribed topic {}", topic);
+                return;
+            }
+        };
+        let response = handler.on_message(topic.as_str(), &data);
+        if pack.packtype == CHANNEL_PACK_TYPE::AMOP_REQ as u16 {
+            if let Some(r) = response {
+                self.reply(pack, topic.as_str(), &r);
+            }
+        }
+    }
+}
+
+fn check_topic(topic: &str) -> Result<(), KissError> {
+    if topic.is_empty() || topic.len() > AMOP_TOPIC_MAX_LEN {
+        return kisserr!(
+            KissErrKind::EArgument,
+            "amop topic length should be 1~{}, got {}",
+            AMOP_TOPIC_MAX_LEN,
+            topic.len()
+        );
+    }
+    Ok(())
+}
+
+impl BcosChannelAsyncClient {
+    fn ensure_amop_dispatcher(&self) {
+        let mut manager = self.handlemanager.lock().unwrap();
+        if manager
+            .get_handle(&(CHANNEL_PACK_TYPE::AMOP_REQ as u16))
+            .is_some()
+        {
+            return;
+        }
+        let dispatcher = Arc::new(Mutex::new(AmopDispatcher {
+            transport: self.transport.clone(),
+            sendlock: self.sendlock.clone(),
+            handlers: self.amophandlers.clone(),
+        }));
+        manager.set_handle(&(CHANNEL_PACK_TYPE::AMOP_REQ as u16), dispatcher.clone());
+        manager.set_handle(&(CHANNEL_PACK_TYPE::TOPIC_MULTICAST as u16), dispatcher);
+    }
+
+    ///订阅topic，同一个topic重复订阅时替换处理器
+    pub async fn subscribe_topic(
+        &self,
+        topic: &str,
+        handler: AMOP_HANDLER_OBJ,
+    ) -> Result<(), KissError> {
+        check_topic(topic)?;
+        self.ensure_amop_dispatcher();
+        self.amophandlers
+            .lock()
+            .unwrap()
+            .insert(topic.to_string(), handler);
+        self.add_topic(topic).await
+    }
+
+    pub async fn unsubscribe_topic(&self, topic: &str) -> Result<(), KissError> {
+        self.amophandlers.lock().unwrap().remove(topic);
+        self.remove_topic(topic).await
+    }
+
+    ///单播：节点挑选一个订阅了topic的sdk转发，等待对方的回包，返回回包的数据
+    pub async fn send_amop(
+        &self,
+        topic: &str,
+        data: &Vec<u8>,
+        timeoutsec: u64,
+    ) -> Result<Vec<u8>, KissError> {
+        check_topic(topic)?;
+        let outpack = make_channel_pack_by_rawdata(
+            CHANNEL_PACK_TYPE::AMOP_REQ,
+            &pack_amop(&topic.as_bytes().to_vec(), data),
+        )
+        .unwrap();
+        let resptype = CHANNEL_PACK_TYPE::AMOP_RESP as u16;
+        let rx = self.register_waiter(resptype, outpack.seq);
+        if let Err(e) = self.send_pack(&outpack).await {
+            self.remove_waiter(resptype, &outpack.seq);
+            return Err(e);
+        }
+        let resppack = self
+            .wait_response(resptype, &outpack.seq, rx, timeoutsec)
+            .await?;
+        if resppack.result != 0 {
+            let reason = match resppack.result {
+                AMOP_RESULT_NODE_UNREACHABLE => "no subscriber or node unreachable",
+                AMOP_RESULT_SDK_UNREACHABLE => "subscriber sdk unreachable",
+                AMOP_RESULT_TIMEOUT => "timeout",
+                _ => "unknown",
+            };
+            return kisserrcode!(
+                KissErrKind::ENetwork,
+                resppack.result as i64,
+                "amop send to topic {} fail: {} ({})",
+                topic,
+                reason,
+                resppack.result
+            );
+        }
+        if resppack.data.is_empty() {
+            return Ok(vec![]);
+        }
+        let (_topic, respdata) = unpack_amop(&resppack.data);
+        Ok(respdata)
+    }
+
+    ///广播：发给所有订阅了topic的sdk，不等待回包
+    pub async fn broadcast_amop(&self, topic: &str, data: &Vec<u8>) -> Result<(), KissError> {
+        check_topic(topic)?;
+        let outpack = make_channel_pack_by_rawdata(
+            CHANNEL_PACK_TYPE::TOPIC_MULTICAST,
+            &pack_amop(&topic.as_bytes().to_vec(), data),
+        )
+        .unwrap();
+        self.send_pack(&outpack).await
+    }
+}
+
+//----------------------------------------------------------------------
+///mode: "sub"订阅topic并把收到的消息原样回复，"pub"向topic发一条消息并打印回复
+pub async fn demo_amop(configfile: &str, mode: &str, topic: &str) -> Result<(), KissError> {
+    let config = crate::bcossdkutil::bcosclientconfig::ClientConfig::load(configfile)?;
+    let client = BcosChannelAsyncClient::connect(&config.channel).await?;
+    match mode {
+        "sub" => {
+            let handler = AmopFnHandler {
+                func: |topic: &str, data: &[u8]| {
+                    println!("amop recv on {}: {}", topic, String::from_utf8_lossy(data));
+                    Some(data.to_vec())
+                },
+            };
+            client.subscribe_topic(topic, Arc::new(handler)).await?;
+            println!("subscribed topic {}, ctrl-c to quit", topic);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+        _ => {
+            let msg = format!("hello amop from rust sdk {}", chrono::Local::now());
+            let resp = client.send_amop(topic, &msg.into_bytes(), 5).await?;
+            println!("amop response: {}", String::from_utf8_lossy(&resp));
+        }
+    }
+    client.stop().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use std::time::{Duration, Instant};
+
+    async fn wait_until(f: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while !f() && start.elapsed() < Duration::from_secs(5) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        f()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_amop_with_mock_node() {
+        let node = MockChannelNode::start().unwrap();
+        let config = node.channel_config();
+        let subscriber = BcosChannelAsyncClient::connect(&config).await.unwrap();
+        let publisher = Arc::new(BcosChannelAsyncClient::connect(&config).await.unwrap());
+
+        //"echo"上的消息加前缀回复，"silent"上的消息不回复
+        let received = Arc::new(Mutex::new(vec![]));
+        let echo_received = received.clone();
+        let echo = AmopFnHandler {
+            func: move |topic: &str, data: &[u8]| {
+                echo_received.lock().unwrap().push(data.to_vec());
+                Some([b"re:", data].concat())
+            },
+        };
+        let silent = AmopFnHandler {
+            func: |topic: &str, data: &[u8]| None,
+        };
+        subscriber
+            .subscribe_topic("echo", Arc::new(echo))
+            .await
+            .unwrap();
+        subscriber
+            .subscribe_topic("silent", Arc::new(silent))
+            .await
+            .unwrap();
+        //TOPIC_REPORT没有回包，等节点记下topic
+        assert!(wait_until(|| node.topics().contains(&"silent".to_string())).await);
+
+        let resp = publisher
+            .send_amop("echo", &b"hello".to_vec(), 5)
+            .await
+            .unwrap();
+        assert_eq!(resp, b"re:hello".to_vec());
+        publisher
+            .broadcast_amop("echo", &b"all".to_vec())
+            .await
+            .unwrap();
+        assert!(wait_until(|| received.lock().unwrap().len() == 2).await);
+        assert_eq!(received.lock().unwrap()[1], b"all".to_vec());
+
+        let err = publisher
+            .send_amop("nobody", &b"hello".to_vec(), 5)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, AMOP_RESULT_NODE_UNREACHABLE as i64);
+
+        //等待中的单播在发送方停止时马上失败，不会等到超时
+        let waiting = {
+            let publisher = publisher.clone();
+            tokio::spawn(async move { publisher.send_amop("silent", &b"x".to_vec(), 30).await })
+        };
+        assert!(
+            wait_until(|| node
+                .received_packs()
+                .iter()
+                .filter(|p| p.packtype == CHANNEL_PACK_TYPE::AMOP_REQ as u16)
+                .count()
+                == 3)
+            .await
+        );
+        let start = Instant::now();
+        publisher.stop().await;
+        let err = waiting.await.unwrap().unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ENetwork);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        subscriber.stop().await;
+    }
+}
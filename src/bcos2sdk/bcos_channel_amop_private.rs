@@ -0,0 +1,538 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! AMOP私有topic(需要身份认证的topic)：
+//! 订阅方持有私钥，上报#!$TopicNeedVerify_{topic}和#!$VerifyChannel_#!$TopicNeedVerify_{topic}；
+//! 发布方持有允许订阅的公钥列表，上报#!$PushChannel_#!$TopicNeedVerify_{topic}。
+//! 节点发现新的订阅方时给发布方发REQUEST_TOPICCERT，发布方通过VerifyChannel给订阅方发一个随机数，
+//! 订阅方签名后回包，发布方用公钥列表验签，再用UPDATE_TOPIC_STATUS把结果告诉节点，
+//! 验证通过后节点才会把这个topic上的消息转给订阅方。
+//! 参考 https://fisco-bcos-documentation.readthedocs.io/zh_CN/latest/docs/manual/amop_protocol.html
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use wedpr_l_crypto_signature_secp256k1::WedprSecp256k1Recover;
+use wedpr_l_crypto_signature_sm2::WedprSm2p256v1;
+use wedpr_l_utils::traits::Signature;
+
+use crate::bcos2sdk::bcos_channel_amop::{IAmopTopicHandler, AMOP_HANDLER_OBJ};
+use crate::bcos2sdk::bcos_channel_async_client::{
+    send_pack_on, BcosChannelAsyncClient, ChannelSendLock, ChannelTransportSlot,
+    CHANNEL_PENDING_MAP,
+};
+use crate::bcos2sdk::bcos_channel_handler_manager::IChannelPushHandlerFacade;
+use crate::bcos2sdk::channelpack::{
+    make_channel_pack, make_channel_pack_by_rawdata, pack_amop, unpack_amop, ChannelPack,
+    CHANNEL_PACK_TYPE,
+};
+use crate::bcossdkutil::accountutil::account_from_privkey;
+use crate::bcossdkutil::bcosclientconfig::BcosCryptoKind;
+use crate::bcossdkutil::commonhash::{CommonHash, HashType};
+use crate::bcossdkutil::commonsigner::{
+    CommonSignerWeDPR_SM2, CommonSignerWeDPR_Secp256, ICommonSigner, Secp256Signature,
+};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+pub const TOPIC_NEED_VERIFY_PREFIX: &str = "#!$TopicNeedVerify_";
+pub const VERIFY_CHANNEL_PREFIX: &str = "#!$VerifyChannel_";
+pub const PUSH_CHANNEL_PREFIX: &str = "#!$PushChannel_";
+
+///UPDATE_TOPIC_STATUS里的验证结果
+pub const TOPIC_CHECK_SUCCESS: i32 = 0;
+pub const TOPIC_CHECK_FAIL: i32 = 1;
+
+///发布方等待订阅方签名的时间
+pub const TOPIC_VERIFY_TIMEOUT_SEC: u64 = 5;
+
+pub fn need_verify_topic(topic: &str) -> String {
+    format!("{}{}", TOPIC_NEED_VERIFY_PREFIX, topic)
+}
+pub fn verify_channel_topic(topic: &str) -> String {
+    format!("{}{}", VERIFY_CHANNEL_PREFIX, need_verify_topic(topic))
+}
+pub fn push_channel_topic(topic: &str) -> String {
+    format!("{}{}", PUSH_CHANNEL_PREFIX, need_verify_topic(topic))
+}
+
+///REQUEST_TOPICCERT的包体，topic是带#!$TopicNeedVerify_前缀的全名，
+/// topicForCert是订阅方用来接收随机数的VerifyChannel topic
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RequestTopicCert {
+    pub topic: String,
+    #[serde(default)]
+    pub topicForCert: String,
+    #[serde(default)]
+    pub nodeId: String,
+}
+
+///UPDATE_TOPIC_STATUS的包体
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UpdateTopicStatus {
+    pub checkResult: i32,
+    pub nodeId: String,
+    pub topic: String,
+}
+
+fn challenge_hash(crypto: &BcosCryptoKind, challenge: &[u8]) -> Vec<u8> {
+    let hashtype = match crypto {
+        BcosCryptoKind::ECDSA => HashType::WEDPR_KECCAK,
+        BcosCryptoKind::GM => HashType::WEDRP_SM3,
+    };
+    CommonHash::hash(&challenge.to_vec(), &hashtype)
+}
+
+///订阅方的私钥，签名随机数证明自己的身份
+#[derive(Clone)]
+pub struct AmopTopicPrivateKey {
+    pub crypto: BcosCryptoKind,
+    pub signer: Arc<dyn ICommonSigner + Send + Sync>,
+}
+
+impl AmopTopicPrivateKey {
+    pub fn from_privkey_bytes(
+        crypto: BcosCryptoKind,
+        privkey: &Vec<u8>,
+    ) -> Result<AmopTopicPrivateKey, KissError> {
+        let account = account_from_privkey(privkey, crypto.clone())?;
+        let signer: Arc<dyn ICommonSigner + Send + Sync> = match crypto {
+            BcosCryptoKind::ECDSA => Arc::new(CommonSignerWeDPR_Secp256 {
+                account,
+                signer: WedprSecp256k1Recover::default(),
+            }),
+            BcosCryptoKind::GM => Arc::new(CommonSignerWeDPR_SM2 {
+                account,
+                signer: WedprSm2p256v1::default(),
+            }),
+        };
+        Ok(AmopTopicPrivateKey { crypto, signer })
+    }
+
+    pub fn from_hex_privkey(
+        crypto: BcosCryptoKind,
+        hextext: &str,
+    ) -> Result<AmopTopicPrivateKey, KissError> {
+        match hex::decode(hextext.trim_start_matches("0x")) {
+            Ok(key) => AmopTopicPrivateKey::from_privkey_bytes(crypto, &key),
+            Err(e) => kisserr!(KissErrKind::EFormat, "bad hex private key {:?}", e),
+        }
+    }
+
+    ///签名随机数。ecdsa回r+s+v(0/1)共65字节，国密回r+s共64字节
+    pub fn sign_challenge(&self, challenge: &[u8]) -> Result<Vec<u8>, KissError> {
+        let hash = challenge_hash(&self.crypto, challenge);
+        let sig = self.signer.sign(hash)?;
+        let mut buffer = sig.r.clone();
+        buffer.append(&mut sig.s.clone());
+        if self.crypto == BcosCryptoKind::ECDSA {
+            //CommonSigner里v是8字节大端并且加了27，验签时要的是原始的recid
+            let mut v8 = [0u8; 8];
+            let start = 8 - sig.v.len().min(8);
+            v8[start..].copy_from_slice(&sig.v[sig.v.len() - (8 - start)..]);
+            buffer.push(Secp256Signature::make_stand_v(u64::from_be_bytes(v8)) as u8);
+        }
+        Ok(buffer)
+    }
+}
+
+///发布方允许订阅私有topic的公钥列表
+#[derive(Debug, Clone)]
+pub struct AmopTopicPublicKeys {
+    pub crypto: BcosCryptoKind,
+    pub pubkeys: Vec<Vec<u8>>,
+}
+
+impl AmopTopicPublicKeys {
+    ///公钥用hex表示，可以带或不带04前缀
+    pub fn from_hex(
+        crypto: BcosCryptoKind,
+        hexkeys: &[&str],
+    ) -> Result<AmopTopicPublicKeys, KissError> {
+        let mut pubkeys = vec![];
+        for k in hexkeys.iter() {
+            match hex::decode(k.trim_start_matches("0x")) {
+                Ok(key) => pubkeys.push(key),
+                Err(e) => {
+                    return kisserr!(KissErrKind::EFormat, "bad hex public key {} {:?}", k, e)
+                }
+            }
+        }
+        Ok(AmopTopicPublicKeys { crypto, pubkeys })
+    }
+
+    ///任意一个公钥验签通过即可
+    pub fn verify_challenge(&self, challenge: &[u8], signature: &[u8]) -> bool {
+        let hash = challenge_hash(&self.crypto, challenge);
+        self.pubkeys.iter().any(|pubkey| match self.crypto {
+            BcosCryptoKind::ECDSA => {
+                WedprSecp256k1Recover::default().verify(pubkey.as_slice(), &hash, signature)
+            }
+            BcosCryptoKind::GM => {
+                let mut fullkey = pubkey.clone();
+                if fullkey.len() == 64 {
+                    fullkey.insert(0, 4);
+                }
+                if signature.len() < 64 {
+                    return false;
+                }
+                WedprSm2p256v1::default().verify(fullkey.as_slice(), &hash, &signature[..64])
+            }
+        })
+    }
+}
+
+pub type AMOP_PRIVATE_TOPIC_MAP = HashMap<String, AmopTopicPublicKeys>;
+
+///订阅方：在VerifyChannel上收到随机数，签名后回包
+pub struct AmopTopicCertSigner {
+    pub key: AmopTopicPrivateKey,
+}
+
+impl IAmopTopicHandler for AmopTopicCertSigner {
+    fn on_message(&self, topic: &str, data: &[u8]) -> Option<Vec<u8>> {
+        match self.key.sign_challenge(data) {
+            Ok(sig) => Some(sig),
+            Err(e) => {
+                log::error!("sign topic challenge on {} error {:?}", topic, e);
+                None
+            }
+        }
+    }
+}
+
+///发布方：处理节点的REQUEST_TOPICCERT，验证订阅方的身份。
+/// 处理器在读任务里被调用，等待订阅方回包的过程放到单独的任务里
+pub struct AmopTopicVerifier {
+    pub transport: ChannelTransportSlot,
+    pub sendlock: ChannelSendLock,
+    pub pending: Arc<Mutex<CHANNEL_PENDING_MAP>>,
+    pub privatetopics: Arc<Mutex<AMOP_PRIVATE_TOPIC_MAP>>,
+}
+
+impl AmopTopicVerifier {
+    async fn verify_subscriber(
+        transport: ChannelTransportSlot,
+        sendlock: ChannelSendLock,
+        pending: Arc<Mutex<CHANNEL_PENDING_MAP>>,
+        keys: AmopTopicPublicKeys,
+        request: RequestTopicCert,
+    ) -> bool {
+        let challenge = hex::encode(ChannelPack::make_seq().as_bytes());
+        let certtopic = if request.topicForCert.is_empty() {
+            format!("{}{}", VERIFY_CHANNEL_PREFIX, request.topic)
+        } else {
+            request.topicForCert.clone()
+        };
+        let outpack = make_channel_pack_by_rawdata(
+            CHANNEL_PACK_TYPE::AMOP_REQ,
+            &pack_amop(
+                &certtopic.as_bytes().to_vec(),
+                &challenge.as_bytes().to_vec(),
+            ),
+        )
+        .unwrap();
+        let resptype = CHANNEL_PACK_TYPE::AMOP_RESP as u16;
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        pending.lock().unwrap().insert((resptype, outpack.seq), tx);
+        if let Err(e) = send_pack_on(&transport, &sendlock, &outpack).await {
+            pending.lock().unwrap().remove(&(resptype, outpack.seq));
+            log::error!("send topic challenge to {} error {:?}", certtopic, e);
+            return false;
+        }
+        let resppack =
+            match tokio::time::timeout(Duration::from_secs(TOPIC_VERIFY_TIMEOUT_SEC), rx).await {
+                Ok(Ok(pack)) => pack,
+                _ => {
+                    pending.lock().unwrap().remove(&(resptype, outpack.seq));
+                    log::warn!("wait topic challenge response on {} fail", certtopic);
+                    return false;
+                }
+            };
+        if resppack.result != 0 {
+            log::warn!(
+                "topic challenge on {} got result {}",
+                certtopic,
+                resppack.result
+            );
+            return false;
+        }
+        let (_topic, signature) = unpack_amop(&resppack.data);
+        keys.verify_challenge(challenge.as_bytes(), signature.as_slice())
+    }
+}
+
+impl IChannelPushHandlerFacade for AmopTopicVerifier {
+    fn handle(&self, pack: &ChannelPack) {
+        let request: RequestTopicCert = match serde_json::from_slice(&pack.data[..]) {
+            Ok(r) => r,
+            Err(e) => {
+                log::warn!("parse topic cert request error {:?}", e);
+                return;
+            }
+        };
+        let keys = match self.privatetopics.lock().unwrap().get(&request.topic) {
+            Some(k) => k.clone(),
+            None => {
+                log::warn!("topic cert request on unknown topic {}", request.topic);
+                return;
+            }
+        };
+        let transport = self.transport.clone();
+        let sendlock = self.sendlock.clone();
+        let pending = self.pending.clone();
+        let seq = pack.seq;
+        tokio::spawn(async move {
+            let passed = AmopTopicVerifier::verify_subscriber(
+                transport.clone(),
+                sendlock.clone(),
+                pending,
+                keys,
+                request.clone(),
+            )
+            .await;
+            let status = UpdateTopicStatus {
+                checkResult: if passed {
+                    TOPIC_CHECK_SUCCESS
+                } else {
+                    TOPIC_CHECK_FAIL
+                },
+                nodeId: request.nodeId.clone(),
+                topic: request.topic.clone(),
+            };
+            log::info!("private topic {} verify result {:?}", request.topic, status);
+            let mut outpack = make_channel_pack(
+                CHANNEL_PACK_TYPE::UPDATE_TOPIC_STATUS,
+                serde_json::to_string(&status).unwrap().as_str(),
+            )
+            .unwrap();
+            outpack.seq = seq;
+            if let Err(e) = send_pack_on(&transport, &sendlock, &outpack).await {
+                log::error!("update topic status error {:?}", e);
+            }
+        });
+    }
+}
+
+impl BcosChannelAsyncClient {
+    ///订阅私有topic，handler收到的topic是带#!$TopicNeedVerify_前缀的全名
+    pub async fn subscribe_private_topic(
+        &self,
+        topic: &str,
+        key: AmopTopicPrivateKey,
+        handler: AMOP_HANDLER_OBJ,
+    ) -> Result<(), KissError> {
+        self.subscribe_topic(
+            verify_channel_topic(topic).as_str(),
+            Arc::new(AmopTopicCertSigner { key }),
+        )
+        .await?;
+        self.subscribe_topic(need_verify_topic(topic).as_str(), handler)
+            .await
+    }
+
+    pub async fn unsubscribe_private_topic(&self, topic: &str) -> Result<(), KissError> {
+        self.unsubscribe_topic(need_verify_topic(topic).as_str())
+            .await?;
+        self.unsubscribe_topic(verify_channel_topic(topic).as_str())
+            .await
+    }
+
+    ///声明自己是私有topic的发布方，只有持有keys里某个私钥的订阅方才能收到消息
+    pub async fn publish_private_topic(
+        &self,
+        topic: &str,
+        keys: AmopTopicPublicKeys,
+    ) -> Result<(), KissError> {
+        if keys.pubkeys.is_empty() {
+            return kisserr!(
+                KissErrKind::EArgument,
+                "private topic {} needs at least one public key",
+                topic
+            );
+        }
+        self.privatetopics
+            .lock()
+            .unwrap()
+            .insert(need_verify_topic(topic), keys);
+        self.set_push_handler(
+            CHANNEL_PACK_TYPE::REQUEST_TOPICCERT,
+            Arc::new(Mutex::new(AmopTopicVerifier {
+                transport: self.transport.clone(),
+                sendlock: self.sendlock.clone(),
+                pending: self.pending.clone(),
+                privatetopics: self.privatetopics.clone(),
+            })),
+        );
+        self.add_topic(push_channel_topic(topic).as_str()).await
+    }
+
+    pub async fn unpublish_private_topic(&self, topic: &str) -> Result<(), KissError> {
+        self.privatetopics
+            .lock()
+            .unwrap()
+            .remove(&need_verify_topic(topic));
+        self.remove_topic(push_channel_topic(topic).as_str()).await
+    }
+
+    ///向私有topic单播，只会送到验证通过的订阅方
+    pub async fn send_private_amop(
+        &self,
+        topic: &str,
+        data: &Vec<u8>,
+        timeoutsec: u64,
+    ) -> Result<Vec<u8>, KissError> {
+        self.send_amop(need_verify_topic(topic).as_str(), data, timeoutsec)
+            .await
+    }
+
+    pub async fn broadcast_private_amop(
+        &self,
+        topic: &str,
+        data: &Vec<u8>,
+    ) -> Result<(), KissError> {
+        self.broadcast_amop(need_verify_topic(topic).as_str(), data)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::bcos_channel_amop::{AmopFnHandler, AMOP_RESULT_NODE_UNREACHABLE};
+    use crate::bcossdkutil::accountutil::create_account;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use std::time::Instant;
+
+    fn check_roundtrip(crypto: BcosCryptoKind) {
+        let account = create_account(&crypto);
+        let key =
+            AmopTopicPrivateKey::from_privkey_bytes(crypto.clone(), &account.privkey).unwrap();
+        let keys = AmopTopicPublicKeys {
+            crypto: crypto.clone(),
+            pubkeys: vec![account.pubkey.clone()],
+        };
+        let challenge = b"0123456789abcdef";
+        let sig = key.sign_challenge(challenge).unwrap();
+        assert!(keys.verify_challenge(challenge, sig.as_slice()));
+        assert!(!keys.verify_challenge(b"another challenge", sig.as_slice()));
+
+        let other = AmopTopicPublicKeys {
+            crypto: crypto.clone(),
+            pubkeys: vec![create_account(&crypto).pubkey],
+        };
+        assert!(!other.verify_challenge(challenge, sig.as_slice()));
+    }
+
+    #[test]
+    fn test_topic_challenge_ecdsa() {
+        check_roundtrip(BcosCryptoKind::ECDSA);
+    }
+
+    #[test]
+    fn test_topic_challenge_gm() {
+        check_roundtrip(BcosCryptoKind::GM);
+    }
+
+    async fn wait_until(f: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while !f() && start.elapsed() < Duration::from_secs(5) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        f()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_private_topic_verify_with_mock_node() {
+        let node = MockChannelNode::start().unwrap();
+        let config = node.channel_config();
+        let crypto = BcosCryptoKind::ECDSA;
+        let allowed = create_account(&crypto);
+        let publisher = BcosChannelAsyncClient::connect(&config).await.unwrap();
+        publisher
+            .publish_private_topic(
+                "secret",
+                AmopTopicPublicKeys {
+                    crypto: crypto.clone(),
+                    pubkeys: vec![allowed.pubkey.clone()],
+                },
+            )
+            .await
+            .unwrap();
+        assert!(wait_until(|| node.topics().contains(&push_channel_topic("secret"))).await);
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let make_handler = |name: &'static str| {
+            let received = received.clone();
+            Arc::new(AmopFnHandler {
+                func: move |topic: &str, data: &[u8]| {
+                    received.lock().unwrap().push(name);
+                    Some(data.to_vec())
+                },
+            })
+        };
+
+        //私钥不在公钥列表里：节点发REQUEST_TOPICCERT，发布方验签失败，回报验证不通过
+        let wrongkey = AmopTopicPrivateKey::from_privkey_bytes(
+            crypto.clone(),
+            &create_account(&crypto).privkey,
+        )
+        .unwrap();
+        let stranger = BcosChannelAsyncClient::connect(&config).await.unwrap();
+        stranger
+            .subscribe_private_topic("secret", wrongkey, make_handler("stranger"))
+            .await
+            .unwrap();
+        assert!(wait_until(|| node.state().topic_status.len() == 1).await);
+        let status = node.state().topic_status[0].clone();
+        assert_eq!(status.checkResult, TOPIC_CHECK_FAIL);
+        assert_eq!(status.topic, need_verify_topic("secret"));
+        let err = publisher
+            .send_private_amop("secret", &b"hello".to_vec(), 5)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, AMOP_RESULT_NODE_UNREACHABLE as i64);
+
+        //持有允许的私钥，验证通过后能收到私有topic上的消息
+        let rightkey =
+            AmopTopicPrivateKey::from_privkey_bytes(crypto.clone(), &allowed.privkey).unwrap();
+        let member = BcosChannelAsyncClient::connect(&config).await.unwrap();
+        member
+            .subscribe_private_topic("secret", rightkey, make_handler("member"))
+            .await
+            .unwrap();
+        assert!(wait_until(|| node.state().topic_status.len() == 2).await);
+        assert_eq!(
+            node.state().topic_status[1].checkResult,
+            TOPIC_CHECK_SUCCESS
+        );
+        let resp = publisher
+            .send_private_amop("secret", &b"hello".to_vec(), 5)
+            .await
+            .unwrap();
+        assert_eq!(resp, b"hello".to_vec());
+        assert_eq!(*received.lock().unwrap(), vec!["member"]);
+
+        for client in [&publisher, &stranger, &member].iter() {
+            client.stop().await;
+        }
+    }
+}
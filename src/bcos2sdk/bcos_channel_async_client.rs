@@ -0,0 +1,574 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 基于tokio的异步channel客户端：
+//! 一个读任务负责从底层连接读数据、解出ChannelPack，
+//! 按(type,seq)唤醒等待中的请求，其他的包交给ChannelPushHandlerManager做推送处理。
+//! 多个请求可以并发的共用同一个ssl/tls连接。
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use ethereum_types::H256;
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+use crate::bcos2sdk::bcos_blocknumber_notify::{block_notify_topic, BlockNumberNotifier};
+use crate::bcos2sdk::bcos_channel_amop::AMOP_HANDLER_MAP;
+use crate::bcos2sdk::bcos_channel_amop_private::AMOP_PRIVATE_TOPIC_MAP;
+use crate::bcos2sdk::bcos_channel_client::{BcosChannelClient, IBcosChannelImpl};
+use crate::bcos2sdk::bcos_channel_handler_manager::{ChannelPushHandlerManager, HANDLE_FACADE_OBJ};
+use crate::bcos2sdk::bcos_event_manager::EventLogManager;
+use crate::bcos2sdk::bcosrpcwraper::{
+    parse_rpc_response, receipt_from_committed_pack, RpcRequestData,
+};
+use crate::bcos2sdk::channelpack::{
+    make_channel_pack, make_handshake_pack, make_topic_report_pack, parse_handshake_response,
+    ChannelPack, ChannelPackCodec, HandshakeResponse, CHANNEL_PACK_TYPE,
+};
+use crate::bcossdkutil::bcosclientconfig::ChannelConfig;
+use crate::bcossdkutil::bufferqueue::BufferQueue;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::{kisserr, printlnex};
+
+///等待回包的请求，按(packtype,seq)索引
+pub type CHANNEL_PENDING_MAP = HashMap<(u16, H256), oneshot::Sender<ChannelPack>>;
+
+///底层连接放在可替换的槽位里，重连时只需要换掉槽位里的对象
+pub type ChannelTransportSlot = Arc<RwLock<IBcosChannelImpl>>;
+
+///发送锁，保证一个包的多次部分写不会和其他包交错。等待时不占用运行时线程
+pub type ChannelSendLock = Arc<tokio::sync::Mutex<()>>;
+
+pub struct BcosChannelAsyncClient {
+    pub config: ChannelConfig,
+    pub transport: ChannelTransportSlot,
+    pub sendlock: ChannelSendLock,
+    pub handlemanager: Arc<Mutex<ChannelPushHandlerManager>>,
+    pub pending: Arc<Mutex<CHANNEL_PENDING_MAP>>,
+    pub is_working: Arc<AtomicBool>,
+    pub protocol_version: AtomicU32, //握手后协商的channel协议版本，0表示还没有握手
+    pub node_version: Mutex<String>,
+    pub topics: Mutex<Vec<String>>, //已上报给节点的topic，重连后要重新上报
+    pub blocknotifier: Mutex<Option<BlockNumberNotifier>>,
+    pub amophandlers: Arc<Mutex<AMOP_HANDLER_MAP>>,
+    pub privatetopics: Arc<Mutex<AMOP_PRIVATE_TOPIC_MAP>>, //作为发布方的私有topic和允许的公钥
+    pub eventmanager: EventLogManager,
+    reader: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl BcosChannelAsyncClient {
+    ///按配置建立连接，但不启动读任务
+    pub fn new(config: &ChannelConfig) -> Result<BcosChannelAsyncClient, KissError> {
+        let channelimpl = BcosChannelClient::build_channel_impl(config)?;
+        Ok(BcosChannelAsyncClient::from_channel(config, channelimpl))
+    }
+
+    ///用已有的底层连接构建，便于接入自定义的IBcosChannel实现
+    pub fn from_channel(
+        config: &ChannelConfig,
+        channelimpl: IBcosChannelImpl,
+    ) -> BcosChannelAsyncClient {
+        BcosChannelAsyncClient {
+            config: config.clone(),
+            transport: Arc::new(RwLock::new(channelimpl)),
+            sendlock: Arc::new(tokio::sync::Mutex::new(())),
+            handlemanager: Arc::new(Mutex::new(ChannelPushHandlerManager::default())),
+            pending: Arc::new(Mutex::new(CHANNEL_PENDING_MAP::new())),
+            is_working: Arc::new(AtomicBool::new(false)),
+            protocol_version: AtomicU32::new(0),
+            node_version: Mutex::new("".to_string()),
+            topics: Mutex::new(vec![]),
+            blocknotifier: Mutex::new(None),
+            amophandlers: Arc::new(Mutex::new(AMOP_HANDLER_MAP::new())),
+            privatetopics: Arc::new(Mutex::new(AMOP_PRIVATE_TOPIC_MAP::new())),
+            eventmanager: EventLogManager::default(),
+            reader: Mutex::new(None),
+        }
+    }
+
+    ///按配置建立连接、启动读任务并完成握手，需要在tokio运行时里调用
+    pub async fn connect(config: &ChannelConfig) -> Result<BcosChannelAsyncClient, KissError> {
+        let client = BcosChannelAsyncClient::new(config)?;
+        client.start();
+        if let Err(e) = client.handshake().await {
+            client.stop().await;
+            return Err(e);
+        }
+        Ok(client)
+    }
+
+    ///和节点协商channel协议版本，结果记录在protocol_version/node_version
+    pub async fn handshake(&self) -> Result<HandshakeResponse, KissError> {
+        let outpack = make_handshake_pack();
+        let returnpack = self.request_channelpack(&outpack).await?;
+        let response = parse_handshake_response(&returnpack)?;
+        log::info!(
+            "channel handshake done, protocol {}, node version {}",
+            response.protocol,
+            response.nodeVersion
+        );
+        self.protocol_version
+            .store(response.protocol, Ordering::SeqCst);
+        *self.node_version.lock().unwrap() = response.nodeVersion.clone();
+        Ok(response)
+    }
+
+    pub fn current_channel(&self) -> IBcosChannelImpl {
+        self.transport.read().unwrap().clone()
+    }
+
+    ///为某种推送包注册处理器，如TX_BLOCKNUM，EVENT_LOG_PUSH等
+    pub fn set_push_handler(&self, packtype: CHANNEL_PACK_TYPE, handler: HANDLE_FACADE_OBJ) {
+        let mut manager = self.handlemanager.lock().unwrap();
+        manager.remove_handler(&(packtype.clone() as u16));
+        manager.set_handle(&(packtype as u16), handler);
+    }
+
+    ///增加要监听的topic并全量上报给节点
+    pub async fn add_topic(&self, topic: &str) -> Result<(), KissError> {
+        {
+            let mut topics = self.topics.lock().unwrap();
+            if !topics.iter().any(|t| t == topic) {
+                topics.push(topic.to_string());
+            }
+        }
+        self.report_topics().await
+    }
+
+    pub async fn remove_topic(&self, topic: &str) -> Result<(), KissError> {
+        self.topics.lock().unwrap().retain(|t| t != topic);
+        self.report_topics().await
+    }
+
+    ///TOPIC_REPORT每次都是全量，节点不回包
+    pub async fn report_topics(&self) -> Result<(), KissError> {
+        let topics = self.topics.lock().unwrap().clone();
+        self.send_pack(&make_topic_report_pack(&topics)).await
+    }
+
+    ///订阅群组的块高推送，返回可以读取和等待块高变化的watch
+    pub async fn subscribe_block_notify(
+        &self,
+        groupid: u32,
+    ) -> Result<watch::Receiver<u64>, KissError> {
+        let notifier = BlockNumberNotifier::new(groupid);
+        let receiver = notifier.subscribe();
+        *self.blocknotifier.lock().unwrap() = Some(notifier.clone());
+        self.set_push_handler(
+            CHANNEL_PACK_TYPE::TX_BLOCKNUM,
+            Arc::new(Mutex::new(notifier)),
+        );
+        self.add_topic(block_notify_topic(groupid).as_str()).await?;
+        Ok(receiver)
+    }
+
+    ///最近一次推送的块高，没有订阅或还没收到推送时为0
+    pub fn latest_blocknumber(&self) -> u64 {
+        match self.blocknotifier.lock().unwrap().as_ref() {
+            Some(n) => n.latest(),
+            None => 0,
+        }
+    }
+
+    ///启动读任务，重复调用无副作用
+    pub fn start(&self) {
+        if self.is_working.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let transport = self.transport.clone();
+        let pending = self.pending.clone();
+        let handlemanager = self.handlemanager.clone();
+        let is_working = self.is_working.clone();
+        let codec = ChannelPackCodec::new(self.config.max_packet_size);
+        let handle = tokio::spawn(async move {
+            BcosChannelAsyncClient::read_loop(transport, pending, handlemanager, is_working, codec)
+                .await;
+        });
+        *self.reader.lock().unwrap() = Some(handle);
+    }
+
+    ///停止读任务并关闭连接，所有未完成的请求会收到网络错误
+    pub async fn stop(&self) {
+        self.is_working.store(false, Ordering::SeqCst);
+        let handle = self.reader.lock().unwrap().take();
+        if let Some(h) = handle {
+            let _ = h.await;
+        }
+        self.pending.lock().unwrap().clear();
+        self.current_channel().lock().unwrap().finish();
+    }
+
+    async fn read_loop(
+        transport: ChannelTransportSlot,
+        pending: Arc<Mutex<CHANNEL_PENDING_MAP>>,
+        handlemanager: Arc<Mutex<ChannelPushHandlerManager>>,
+        is_working: Arc<AtomicBool>,
+        mut codec: ChannelPackCodec,
+    ) {
+        let mut queue = BufferQueue::new();
+        while is_working.load(Ordering::SeqCst) {
+            let channel = transport.read().unwrap().clone();
+            let recvres = channel.lock().unwrap().recv();
+            match recvres {
+                Ok(mut data) => {
+                    if data.is_empty() {
+                        //非阻塞的连接上暂时没有数据，让出运行时
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        continue;
+                    }
+                    queue.append(&mut data);
+                    let packs =
+                        match BcosChannelClient::pop_queue_with_codec(&mut queue, &mut codec) {
+                            Ok(packs) => packs,
+                            Err(e) => {
+                                //长度字段非法，后面的数据已经错位，只能断开
+                                log::error!("decode channel pack error {:?}, stop reading", e);
+                                break;
+                            }
+                        };
+                    for pack in packs {
+                        BcosChannelAsyncClient::dispatch_pack(&pending, &handlemanager, pack);
+                    }
+                }
+                Err(e) => {
+                    log::error!("channel read error {:?}, stop reading", e);
+                    break;
+                }
+            }
+        }
+        is_working.store(false, Ordering::SeqCst);
+        //连接已不可用，丢弃所有等待者，等待方会得到连接关闭的错误
+        pending.lock().unwrap().clear();
+    }
+
+    ///有人在等的包直接交给等待者，否则作为推送交给handler
+    pub fn dispatch_pack(
+        pending: &Arc<Mutex<CHANNEL_PENDING_MAP>>,
+        handlemanager: &Arc<Mutex<ChannelPushHandlerManager>>,
+        pack: ChannelPack,
+    ) {
+        let waiter = pending.lock().unwrap().remove(&(pack.packtype, pack.seq));
+        match waiter {
+            Some(sender) => {
+                let _ = sender.send(pack);
+            }
+            None => {
+                let handler = handlemanager
+                    .lock()
+                    .unwrap()
+                    .get_handle(&pack.packtype)
+                    .cloned();
+                match handler {
+                    Some(h) => h.lock().unwrap().handle(&pack),
+                    None => {
+                        log::debug!(
+                            "no waiter or handler for pack type: 0x{:X},seq: {:?}",
+                            pack.packtype,
+                            pack.seq
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    ///登记一个等待者，要在发送前调用，避免回包比登记还早到
+    pub fn register_waiter(&self, packtype: u16, seq: H256) -> oneshot::Receiver<ChannelPack> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert((packtype, seq), tx);
+        rx
+    }
+
+    pub fn remove_waiter(&self, packtype: u16, seq: &H256) {
+        self.pending.lock().unwrap().remove(&(packtype, *seq));
+    }
+
+    ///把包完整的写到连接上
+    pub async fn send_pack(&self, outpack: &ChannelPack) -> Result<(), KissError> {
+        send_pack_on(&self.transport, &self.sendlock, outpack).await
+    }
+
+    ///等待已登记的回包，超时则注销等待者
+    pub async fn wait_response(
+        &self,
+        packtype: u16,
+        seq: &H256,
+        rx: oneshot::Receiver<ChannelPack>,
+        timeoutsec: u64,
+    ) -> Result<ChannelPack, KissError> {
+        match tokio::time::timeout(Duration::from_secs(timeoutsec), rx).await {
+            Ok(Ok(pack)) => Ok(pack),
+            Ok(Err(_)) => {
+                kisserr!(
+                    KissErrKind::ENetwork,
+                    "channel closed before response, type: 0x{:X}",
+                    packtype
+                )
+            }
+            Err(_) => {
+                self.remove_waiter(packtype, seq);
+                kisserr!(
+                    KissErrKind::ETimeout,
+                    "wait response timeout, type: 0x{:X},seq: {:?}",
+                    packtype,
+                    seq
+                )
+            }
+        }
+    }
+
+    ///发送一个pack，等待同type同seq的回包
+    pub async fn request_channelpack(
+        &self,
+        outpack: &ChannelPack,
+    ) -> Result<ChannelPack, KissError> {
+        if !self.is_working.load(Ordering::SeqCst) {
+            return kisserr!(KissErrKind::ENetwork, "channel client is not working");
+        }
+        let rx = self.register_waiter(outpack.packtype, outpack.seq);
+        if let Err(e) = self.send_pack(outpack).await {
+            self.remove_waiter(outpack.packtype, &outpack.seq);
+            return Err(e);
+        }
+        self.wait_response(
+            outpack.packtype,
+            &outpack.seq,
+            rx,
+            self.config.timeout as u64,
+        )
+        .await
+    }
+
+    ///传入json字符串，用RPC包发出，返回回包里的字符串
+    pub async fn request(&self, reqtext: &str) -> Result<String, KissError> {
+        let outpack = make_channel_pack(CHANNEL_PACK_TYPE::RPC, reqtext).unwrap();
+        let returnpack = self.request_channelpack(&outpack).await?;
+        match String::from_utf8(returnpack.data.to_vec()) {
+            Ok(s) => Ok(s),
+            Err(e) => kisserr!(KissErrKind::EFormat, "pack data is not string {:?}", e),
+        }
+    }
+
+    ///和BcosRPC::rpc_request_sync对应的异步版本
+    pub async fn rpc_request(
+        &self,
+        cmd: &str,
+        params_value: &JsonValue,
+    ) -> Result<JsonValue, KissError> {
+        let outbuffer = BcosChannelAsyncClient::encode_rpc_request(cmd, params_value)?;
+        log::info!("request: {:?}", outbuffer);
+        let responsebuffer = self.request(outbuffer.as_str()).await?;
+        log::info!("response:  {:?}", &responsebuffer);
+        parse_rpc_response(&responsebuffer)
+    }
+
+    fn encode_rpc_request(cmd: &str, params_value: &JsonValue) -> Result<String, KissError> {
+        let req = RpcRequestData {
+            jsonrpc: "2.0".to_string(),
+            method: cmd.to_string(),
+            params: params_value.clone(),
+            id: 1,
+        };
+        req.encode()
+    }
+
+    ///发出RPC请求，再等待节点用同一个seq推送的pushtype包，如TX_COMMITTED。
+    /// 推送的等待者在发送前登记，回包和推送谁先到都不会丢
+    pub async fn rpc_request_and_wait_push(
+        &self,
+        cmd: &str,
+        params_value: &JsonValue,
+        pushtype: CHANNEL_PACK_TYPE,
+        timeoutsec: u64,
+    ) -> Result<(JsonValue, ChannelPack), KissError> {
+        let outbuffer = BcosChannelAsyncClient::encode_rpc_request(cmd, params_value)?;
+        let outpack = make_channel_pack(CHANNEL_PACK_TYPE::RPC, outbuffer.as_str()).unwrap();
+        let pushtype = pushtype as u16;
+        let pushrx = self.register_waiter(pushtype, outpack.seq);
+        let returnpack = match self.request_channelpack(&outpack).await {
+            Ok(p) => p,
+            Err(e) => {
+                self.remove_waiter(pushtype, &outpack.seq);
+                return Err(e);
+            }
+        };
+        let responsebuffer = String::from_utf8_lossy(&returnpack.data[..]).to_string();
+        let response = parse_rpc_response(&responsebuffer)?;
+        if response["error"] != JsonValue::Null {
+            self.remove_waiter(pushtype, &outpack.seq);
+            return kisserr!(KissErrKind::Error, "{} error {:?}", cmd, response["error"]);
+        }
+        let pushpack = self
+            .wait_response(pushtype, &outpack.seq, pushrx, timeoutsec)
+            .await?;
+        Ok((response, pushpack))
+    }
+
+    ///发送已签名的交易，等待TX_COMMITTED推送的回执，返回格式和getTransactionReceipt一致
+    pub async fn send_raw_transaction_wait_receipt(
+        &self,
+        groupid: u32,
+        signed_tx_hex: &str,
+        timeoutsec: u64,
+    ) -> Result<JsonValue, KissError> {
+        let (response, pushpack) = self
+            .rpc_request_and_wait_push(
+                "sendRawTransaction",
+                &json!([groupid, signed_tx_hex]),
+                CHANNEL_PACK_TYPE::TX_COMMITTED,
+                timeoutsec,
+            )
+            .await?;
+        receipt_from_committed_pack(&pushpack)
+    }
+}
+
+///把包完整的写到连接上，非阻塞的连接可能一次只写出一部分。推送处理器回包时也用这个方法。
+/// 写不出去时异步等待，连接的锁只在每次写的时候持有，读任务不会被挡住
+pub async fn send_pack_on(
+    transport: &ChannelTransportSlot,
+    sendlock: &ChannelSendLock,
+    outpack: &ChannelPack,
+) -> Result<(), KissError> {
+    let outbuffer = outpack.pack();
+    printlnex!("channel buffer length {} ", outbuffer.len());
+    let _sending = sendlock.lock().await;
+    let channel = transport.read().unwrap().clone();
+    let mut sent: usize = 0;
+    let mut idle = 0;
+    while sent < outbuffer.len() {
+        let res = channel.lock().unwrap().send(&outbuffer[sent..].to_vec())?;
+        if res > 0 {
+            sent += res as usize;
+            idle = 0;
+            continue;
+        }
+        idle += 1;
+        if idle > 100 {
+            return kisserr!(KissErrKind::ENetwork, "send none bytes after try");
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    Ok(())
+}
+
+//----------------------------------------------------------------------
+pub async fn demo_async_channel(configfile: &str) -> Result<(), KissError> {
+    let config = crate::bcossdkutil::bcosclientconfig::ClientConfig::load(configfile)?;
+    let client = Arc::new(BcosChannelAsyncClient::connect(&config.channel).await?);
+    let groupid = config.bcos2.groupid;
+    let mut tasks = vec![];
+    for cmd in ["getBlockNumber", "getPbftView", "getSealerList"].iter() {
+        let cmd = *cmd;
+        let c = client.clone();
+        tasks.push(tokio::spawn(async move {
+            let res = c.rpc_request(cmd, &json!([groupid])).await;
+            println!("{} -> {:?}", cmd, res);
+        }));
+    }
+    for t in tasks {
+        let _ = t.await;
+    }
+    client.stop().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::bcos_channel_client::IBcosChannel;
+    use crate::bcos2sdk::bcos_channel_handler_manager::IChannelPushHandlerFacade;
+    use std::collections::VecDeque;
+
+    ///收到RPC包就用同一个seq回一个包，回包顺序和请求顺序相反
+    struct LoopbackChannel {
+        inbox: VecDeque<Vec<u8>>,
+        held: Vec<ChannelPack>,
+    }
+
+    impl IBcosChannel for LoopbackChannel {
+        fn connect(&mut self) -> Result<i32, KissError> {
+            Ok(0)
+        }
+        fn send(&mut self, sendbuff: &Vec<u8>) -> Result<i32, KissError> {
+            let pack = ChannelPack::unpack(sendbuff)?;
+            self.held.push(pack);
+            if self.held.len() == 2 {
+                while let Some(mut p) = self.held.pop() {
+                    p.data = format!("echo:{}", String::from_utf8(p.data.to_vec()).unwrap())
+                        .into_bytes()
+                        .into();
+                    p.length = 42 + p.data.len();
+                    self.inbox.push_back(p.pack());
+                }
+                let push = make_channel_pack(CHANNEL_PACK_TYPE::TX_BLOCKNUM, "1,100").unwrap();
+                self.inbox.push_back(push.pack());
+            }
+            Ok(sendbuff.len() as i32)
+        }
+        fn recv(&mut self) -> Result<Vec<u8>, KissError> {
+            Ok(self.inbox.pop_front().unwrap_or_default())
+        }
+        fn finish(&mut self) {}
+    }
+
+    struct CountHandler {
+        count: Arc<Mutex<u32>>,
+    }
+    impl IChannelPushHandlerFacade for CountHandler {
+        fn handle(&self, pack: &ChannelPack) {
+            *self.count.lock().unwrap() += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_channel() {
+        let mut config = ChannelConfig::default();
+        config.timeout = 3;
+        let channel = LoopbackChannel {
+            inbox: VecDeque::new(),
+            held: vec![],
+        };
+        let client = Arc::new(BcosChannelAsyncClient::from_channel(
+            &config,
+            Arc::new(Mutex::new(channel)),
+        ));
+        let count = Arc::new(Mutex::new(0));
+        client.set_push_handler(
+            CHANNEL_PACK_TYPE::TX_BLOCKNUM,
+            Arc::new(Mutex::new(CountHandler {
+                count: count.clone(),
+            })),
+        );
+        client.start();
+        let c1 = client.clone();
+        let c2 = client.clone();
+        let t1 = tokio::spawn(async move { c1.request("first").await });
+        let t2 = tokio::spawn(async move { c2.request("second").await });
+        assert_eq!(t1.await.unwrap().unwrap(), "echo:first");
+        assert_eq!(t2.await.unwrap().unwrap(), "echo:second");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*count.lock().unwrap(), 1);
+        client.stop().await;
+    }
+}
@@ -10,15 +10,25 @@
     unused_assignments
 )]
 
+use crate::bcos2sdk::bcos_blocknumber_notify::{block_notify_topic, BlockNumberNotifier};
+use crate::bcos2sdk::bcos_channel_handler_manager::IChannelPushHandlerFacade;
+use crate::bcos2sdk::bcos_channel_peers::ChannelPeerManager;
+use crate::bcos2sdk::bcos_channel_recorder::BcosChannelRecorder;
 use crate::bcos2sdk::bcos_ssl_native::BcosNativeTlsClient;
 use crate::bcos2sdk::bcos_ssl_normal::BcosSSLClient;
-use crate::bcos2sdk::channelpack::{make_channel_pack, ChannelPack, CHANNEL_PACK_TYPE};
-use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ChannelConfig};
+use crate::bcos2sdk::bcos_ssl_tlcp::BcosTlcpClient;
+use crate::bcos2sdk::channelpack::{
+    make_channel_pack, make_handshake_pack, make_topic_report_pack, parse_handshake_response,
+    ChannelPack, ChannelPackCodec, CHANNEL_PACK_TYPE,
+};
+use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ChannelConfig, RequestPolicy};
 use crate::bcossdkutil::bufferqueue::BufferQueue;
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::{kisserr, printlnex};
+use ethereum_types::H256;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio_util::codec::Decoder;
 
 ///用接口抽象国密和非国密SSL底层实现，
 /// 底层只关注对SSL或GMSSL的API调用，暴露几个简单的接口
@@ -29,7 +39,10 @@ pub trait IBcosChannel {
     fn finish(&mut self);
 }
 
-type IBcosChannelImpl = Arc<Mutex<dyn IBcosChannel + Send + Sync>>;
+pub type IBcosChannelImpl = Arc<Mutex<dyn IBcosChannel + Send + Sync>>;
+
+///同步客户端里暂存未处理的包的上限，超过后丢弃最老的，避免没人读的推送越积越多
+pub const CHANNEL_PACK_POOL_MAX: usize = 1024;
 
 /// 对channel协议的组装和解析，同步异步调用是共通的，封装在BcosChannelClient
 pub struct BcosChannelClient {
@@ -37,6 +50,13 @@ pub struct BcosChannelClient {
     pub config: ChannelConfig,
     pub bufferqueue: BufferQueue,
     pub channelpackpool: Vec<ChannelPack>, //一个池子，存没有被处理的channelpack，在推送等流程用到
+    pub protocol_version: u32,             //握手后协商的channel协议版本，0表示还没有握手
+    pub node_version: String,
+    pub peermanager: ChannelPeerManager,
+    pub topics: Vec<String>, //已上报给节点的topic，重连后要重新上报
+    pub blocknotifier: BlockNumberNotifier,
+    pub codec: ChannelPackCodec,
+    pub request_timeout: u64, //同步请求等待回包的超时(秒)，默认为配置的timeout，BcosRPC按请求策略设置
 }
 
 //unsafe impl Send for BcosChannelClient {}
@@ -75,11 +95,24 @@ impl BcosChannelClient {
             bufferqueue: Default::default(),
             channelimpl: channelimpl,
             channelpackpool: vec![],
+            protocol_version: 0,
+            node_version: "".to_string(),
+            peermanager: match ChannelPeerManager::from_config(config) {
+                Ok(m) => m,
+                Err(e) => {
+                    log::error!("bad channel peers config {:?}", e);
+                    ChannelPeerManager::single(config)
+                }
+            },
+            topics: vec![],
+            blocknotifier: BlockNumberNotifier::new(0),
+            codec: ChannelPackCodec::new(config.max_packet_size),
+            request_timeout: config.timeout as u64,
         }
     }
-    pub fn new(config: &ChannelConfig) -> Result<BcosChannelClient, KissError> {
+    ///按配置的tlskind建立底层的ssl/tls连接，同步和异步的channel客户端共用
+    pub fn build_channel_impl(config: &ChannelConfig) -> Result<IBcosChannelImpl, KissError> {
         let channelimpl: IBcosChannelImpl;
-        println!("config.tlskind {:?}", &config);
         match config.tlskind {
             BcosCryptoKind::ECDSA => {
                 let mut ssl_client = BcosSSLClient::default(&config);
@@ -87,6 +120,11 @@ impl BcosChannelClient {
                 channelimpl = Arc::new(Mutex::new(ssl_client))
                 //channelimpl = Arc::new(ssl_client)
             }
+            BcosCryptoKind::GM if !config.gmtls_native => {
+                let mut tls_client = BcosTlcpClient::default(config);
+                tls_client.build()?;
+                channelimpl = Arc::new(Mutex::new(tls_client));
+            }
             BcosCryptoKind::GM => {
                 let mut tls_client = BcosNativeTlsClient::default(&config);
                 tls_client.build()?;
@@ -94,16 +132,133 @@ impl BcosChannelClient {
                 //channelimpl = Arc::new(tls_client)
             }
         }
+        if !config.record_file.is_empty() {
+            let recorder = BcosChannelRecorder::new(channelimpl, &config.record_file)?;
+            return Ok(Arc::new(Mutex::new(recorder)));
+        }
+        Ok(channelimpl)
+    }
 
-        let wraper = BcosChannelClient {
-            config: config.clone(),
-            bufferqueue: Default::default(),
-            channelimpl: channelimpl,
-            channelpackpool: vec![],
-        };
+    pub fn new(config: &ChannelConfig) -> Result<BcosChannelClient, KissError> {
+        let mut wraper = BcosChannelClient::default(config);
+        wraper.peermanager = ChannelPeerManager::from_config(config)?;
+        wraper.reconnect()?;
+        Ok(wraper)
+    }
+
+    ///用已有的底层连接构建并握手，便于接入自定义的IBcosChannel实现，如回放录制的流量
+    pub fn from_channel(
+        config: &ChannelConfig,
+        channelimpl: IBcosChannelImpl,
+    ) -> Result<BcosChannelClient, KissError> {
+        let mut wraper = BcosChannelClient::default(config);
+        wraper.channelimpl = channelimpl;
+        wraper.handshake()?;
         Ok(wraper)
     }
 
+    ///按节点列表挑选可用的节点连接并握手，所有节点都失败时返回ENetwork；
+    /// 所有节点都拒绝了握手(如协议版本不兼容)时返回握手的错误类型，不属于可重试的网络错误
+    pub fn reconnect(&mut self) -> Result<(), KissError> {
+        self.channelimpl.lock().unwrap().finish();
+        self.bufferqueue = BufferQueue::new();
+        self.channelpackpool.clear();
+        let mut errors: Vec<String> = vec![];
+        for index in self.peermanager.candidates() {
+            self.peermanager.select(index);
+            let peerconfig = self.peermanager.current_config(&self.config);
+            match self.connect_peer(&peerconfig) {
+                Ok(_) => {
+                    log::info!("channel connected to {}:{}", peerconfig.ip, peerconfig.port);
+                    self.peermanager.mark_success();
+                    if let Err(e) = self.report_topics() {
+                        log::warn!("report topics after reconnect error {:?}", e);
+                    }
+                    return Ok(());
+                }
+                Err(e) => {
+                    //网络故障进入退避，握手被拒绝的节点以后直接跳过
+                    if RequestPolicy::is_retryable(&e) {
+                        self.peermanager.mark_failure();
+                    } else {
+                        self.peermanager.mark_rejected(&e);
+                    }
+                    errors.push(format!("{}:{} {}", peerconfig.ip, peerconfig.port, e.msg));
+                }
+            }
+        }
+        if self.peermanager.all_rejected() {
+            let peers = &self.peermanager.peers;
+            let reasons: Vec<String> = peers
+                .iter()
+                .filter_map(|p| {
+                    p.rejected
+                        .as_ref()
+                        .map(|e| format!("{} {}", p.addr(), e.msg))
+                })
+                .collect();
+            let kind = peers[0].rejected.as_ref().unwrap().kind.clone();
+            return kisserr!(
+                kind,
+                "all channel peers rejected the handshake: {}",
+                reasons.join("; ")
+            );
+        }
+        kisserr!(
+            KissErrKind::ENetwork,
+            "connect to all channel peers failed: {}",
+            errors.join("; ")
+        )
+    }
+
+    ///当前节点出了网络错误，记录失败后换一个节点重连
+    pub fn failover(&mut self) -> Result<(), KissError> {
+        self.peermanager.mark_failure();
+        self.reconnect()
+    }
+
+    ///复制配置、节点列表的状态和topic，得到一个还没有连接的客户端。
+    /// 可以在别的线程里对它reconnect，再用adopt_connection换进来，重连期间不占用原来的客户端
+    pub fn detached(&self) -> BcosChannelClient {
+        let mut client = BcosChannelClient::default(&self.config);
+        client.peermanager = self.peermanager.clone();
+        client.topics = self.topics.clone();
+        client.blocknotifier = self.blocknotifier.clone();
+        client.request_timeout = self.request_timeout;
+        client
+    }
+
+    ///关掉当前连接，换上other的连接、未处理的包和节点列表的状态，块高等其他状态保留
+    pub fn adopt_connection(&mut self, other: BcosChannelClient) {
+        self.channelimpl.lock().unwrap().finish();
+        self.channelimpl = other.channelimpl;
+        self.bufferqueue = other.bufferqueue;
+        self.channelpackpool = other.channelpackpool;
+        self.protocol_version = other.protocol_version;
+        self.node_version = other.node_version;
+        self.peermanager = other.peermanager;
+    }
+
+    fn connect_peer(&mut self, peerconfig: &ChannelConfig) -> Result<u32, KissError> {
+        self.channelimpl = BcosChannelClient::build_channel_impl(peerconfig)?;
+        self.handshake()
+    }
+
+    ///tls连接后先和节点协商channel协议版本
+    pub fn handshake(&mut self) -> Result<u32, KissError> {
+        let outpack = make_handshake_pack();
+        let returnpack = self.request_channelpack_sync(&outpack)?;
+        let response = parse_handshake_response(&returnpack)?;
+        log::info!(
+            "channel handshake done, protocol {}, node version {}",
+            response.protocol,
+            response.nodeVersion
+        );
+        self.protocol_version = response.protocol;
+        self.node_version = response.nodeVersion;
+        Ok(self.protocol_version)
+    }
+
     ///尝试最多5次异步发送
     pub fn try_send(&mut self, outbuffer: &Vec<u8>) -> Result<i32, KissError> {
         let mut i: u32 = 0;
@@ -117,11 +272,11 @@ impl BcosChannelClient {
         }
         kisserr!(KissErrKind::ENetwork, "send none bytes after try")
     }
-    /// 按配置的超时时间读socket
+    /// 按请求超时时间(request_timeout)读socket
     pub fn try_recv(&mut self) -> Result<Vec<u8>, KissError> {
         let mut i = 0;
         let start = time::now();
-        while time::now() - start < chrono::Duration::seconds(self.config.timeout as i64) {
+        while time::now() - start < time::Duration::seconds(self.request_timeout as i64) {
             let res = self.recv()?;
             //println!(">> try recv {}", res.len());
             if res.len() > 0 {
@@ -137,7 +292,7 @@ impl BcosChannelClient {
     pub fn request_sync(&mut self, reqtext: &str) -> Result<String, KissError> {
         let outpack = make_channel_pack(CHANNEL_PACK_TYPE::RPC, reqtext).unwrap();
         let returnpack = self.request_channelpack_sync(&outpack)?;
-        let res = String::from_utf8(returnpack.data);
+        let res = String::from_utf8(returnpack.data.to_vec());
         match res {
             Ok(s) => {
                 return Ok(s);
@@ -148,46 +303,148 @@ impl BcosChannelClient {
         }
     }
 
-    ///尝试从缓冲区获得一个对应的回包
+    ///一组json rpc请求作为并发的包全部发出，再按seq收回包，返回和请求顺序一致的回包文本
+    pub fn request_batch_sync(&mut self, reqtexts: &[String]) -> Result<Vec<String>, KissError> {
+        let mut packs: Vec<ChannelPack> = vec![];
+        for reqtext in reqtexts.iter() {
+            match make_channel_pack(CHANNEL_PACK_TYPE::RPC, reqtext) {
+                Some(pack) => packs.push(pack),
+                None => {
+                    return kisserr!(KissErrKind::EFormat, "make channel pack error {}", reqtext)
+                }
+            }
+        }
+        for pack in packs.iter() {
+            self.try_send(&pack.pack())?;
+        }
+        //整批共用一个超时时间，而不是每个包各等一个request_timeout
+        let deadline = Instant::now() + Duration::from_secs(self.request_timeout);
+        let mut responses = vec![];
+        for pack in packs.iter() {
+            let returnpack = self.wait_channelpack_until(pack.packtype, &pack.seq, deadline)?;
+            match String::from_utf8(returnpack.data.to_vec()) {
+                Ok(s) => responses.push(s),
+                Err(e) => {
+                    return kisserr!(KissErrKind::ENetwork, "pack data is not string {:?}", e)
+                }
+            }
+        }
+        Ok(responses)
+    }
+
+    ///把全部topic上报给节点，节点不回包
+    pub fn report_topics(&mut self) -> Result<(), KissError> {
+        if self.topics.len() == 0 {
+            return Ok(());
+        }
+        let outpack = make_topic_report_pack(&self.topics);
+        self.try_send(&outpack.pack())?;
+        Ok(())
+    }
+
+    ///订阅群组的块高推送，推送的块高记录在blocknotifier里
+    pub fn subscribe_block_notify(&mut self, groupid: u32) -> Result<(), KissError> {
+        self.blocknotifier.groupid = groupid;
+        let topic = block_notify_topic(groupid);
+        if !self.topics.contains(&topic) {
+            self.topics.push(topic);
+        }
+        self.report_topics()
+    }
+
+    ///非阻塞的读一次网络，把收到的推送处理掉，回包放入pool
+    pub fn poll_pushes(&mut self) -> Result<(), KissError> {
+        let mut res = self.recv()?;
+        if res.len() > 0 {
+            self.bufferqueue.append(&mut res);
+            //用一个不会匹配的空包，把缓冲区里的包都解出来
+            let _ = self.try_match_channelpack(&ChannelPack::default());
+        }
+        Ok(())
+    }
+
+    ///和request_sync一样，同时返回请求包的seq，用于等待同一个seq的推送，如TX_COMMITTED
+    pub fn request_sync_with_seq(&mut self, reqtext: &str) -> Result<(String, H256), KissError> {
+        let outpack = make_channel_pack(CHANNEL_PACK_TYPE::RPC, reqtext).unwrap();
+        let returnpack = self.request_channelpack_sync(&outpack)?;
+        match String::from_utf8(returnpack.data.to_vec()) {
+            Ok(s) => Ok((s, outpack.seq)),
+            Err(e) => kisserr!(KissErrKind::ENetwork, "pack data is not string {:?}", e),
+        }
+    }
+
+    ///等待指定type和seq的包，先在pool里找，找不到再从网络读，直到超时
+    pub fn wait_channelpack(
+        &mut self,
+        packtype: u16,
+        seq: &H256,
+        timeoutsec: u64,
+    ) -> Result<ChannelPack, KissError> {
+        let deadline = Instant::now() + Duration::from_secs(timeoutsec);
+        self.wait_channelpack_until(packtype, seq, deadline)
+    }
+
+    ///和wait_channelpack一样，但等到deadline为止，用于多个包共用一个超时时间
+    pub fn wait_channelpack_until(
+        &mut self,
+        packtype: u16,
+        seq: &H256,
+        deadline: Instant,
+    ) -> Result<ChannelPack, KissError> {
+        if let Some(pos) = self
+            .channelpackpool
+            .iter()
+            .position(|p| p.packtype == packtype && p.seq == *seq)
+        {
+            return Ok(self.channelpackpool.remove(pos));
+        }
+        let target = ChannelPack {
+            packtype,
+            seq: seq.clone(),
+            ..Default::default()
+        };
+        while Instant::now() < deadline {
+            let mut res = self.recv()?;
+            if res.len() == 0 {
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+            self.bufferqueue.append(&mut res);
+            match self.try_match_channelpack(&target) {
+                Ok(pack) => return Ok(pack),
+                Err(e) if e.kind == KissErrKind::EAgain => {}
+                Err(e) => return Err(e),
+            }
+        }
+        kisserr!(
+            KissErrKind::ETimeout,
+            "wait pack timeout, type: 0x{:X},seq: {:?}",
+            packtype,
+            seq
+        )
+    }
+
+    ///尝试从缓冲区获得一个对应的回包，其他的包放入pool，块高推送直接交给blocknotifier
     pub fn try_match_channelpack(
         &mut self,
         outpack: &ChannelPack,
     ) -> Result<ChannelPack, KissError> {
-        //从缓冲区中match出一个回包，最多尝试50次
-        let mut i = 0;
+        let mut codec = self.codec;
         let mut thepack: Option<ChannelPack> = Option::None;
-        //println!("want pack type: 0x{:02X},seq: {}",outpack.packtype,outpack.seq);
-
-        while i < 50 {
-            // println!("before peek, queue size : {}",&self.bufferqueue.queue.len());
-            if self.bufferqueue.queue.len() <= 42 {
-                break;
-            }
-            let packres = ChannelPack::unpack(&self.bufferqueue.queue);
-            match packres {
-                Ok(pack) => {
-                    //println!(">>>> get pack type: 0x{:02X},seq: {}",pack.packtype,pack.seq);
-                    //从缓冲区中去掉已经解码的部分
-                    self.bufferqueue.cut(pack.length);
-                    //获得了一个回包，要判断下是否预期的回包，否则仅加入等待区
-
-                    if pack.packtype == outpack.packtype && pack.seq == outpack.seq {
-                        //println!(">>>match pack type: 0x{:02X},seq: {}",pack.packtype,pack.seq);
-                        //是想要的回包
-                        thepack = Option::from(pack);
-                    } else {
-                        //println!("not match  pack type: 0x{:02X},seq: {}",pack.packtype,pack.seq);
-                        //是个完整的pack，但不是想要的，放入pool
-                        self.channelpackpool.push(pack);
-                    }
-                }
-                Err(e) => {
-                    //从缓冲区中解码失败，通常原因是字节不够了，终止
-                    //println!("no more data");
-                    break;
+        while let Some(pack) = self.decode_or_reset(&mut codec)? {
+            if thepack.is_none() && pack.packtype == outpack.packtype && pack.seq == outpack.seq {
+                //是想要的回包
+                thepack = Option::from(pack);
+            } else if pack.packtype == CHANNEL_PACK_TYPE::TX_BLOCKNUM as u16 {
+                //块高推送直接更新到blocknotifier
+                self.blocknotifier.handle(&pack);
+            } else {
+                //是个完整的pack，但不是想要的，放入pool
+                if self.channelpackpool.len() >= CHANNEL_PACK_POOL_MAX {
+                    self.channelpackpool.remove(0);
                 }
+                self.channelpackpool.push(pack);
             }
-            i += 1;
         }
         //返回发现的pack,或反馈一个空包错误
         match thepack {
@@ -198,33 +455,40 @@ impl BcosChannelClient {
         }
     }
 
+    ///从缓冲区解出一个包。长度非法时连接上的数据已经错位，清空缓冲区和pool，
+    /// 返回ENetwork，让调用方按网络错误换节点重连，而不是之后的每个请求都报同一个EFormat
+    fn decode_or_reset(
+        &mut self,
+        codec: &mut ChannelPackCodec,
+    ) -> Result<Option<ChannelPack>, KissError> {
+        match codec.decode(&mut self.bufferqueue.queue) {
+            Ok(pack) => Ok(pack),
+            Err(e) if e.kind == KissErrKind::EFormat => {
+                self.bufferqueue = BufferQueue::new();
+                self.channelpackpool.clear();
+                kisserr!(
+                    KissErrKind::ENetwork,
+                    "bad channel pack, connection is out of sync: {}",
+                    e.msg
+                )
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    ///按默认的长度上限从缓冲区解出所有完整的包
     pub fn pop_queue_to_packet(queue: &mut BufferQueue) -> Result<Vec<ChannelPack>, KissError> {
+        BcosChannelClient::pop_queue_with_codec(queue, &mut ChannelPackCodec::default())
+    }
+
+    ///从缓冲区解出所有完整的包，不完整的留在缓冲区，长度非法时返回EFormat
+    pub fn pop_queue_with_codec(
+        queue: &mut BufferQueue,
+        codec: &mut ChannelPackCodec,
+    ) -> Result<Vec<ChannelPack>, KissError> {
         let mut vecres: Vec<ChannelPack> = vec![];
-        // println!("before peek, queue size : {}",&self.bufferqueue.queue.len());
-        if queue.queue.len() <= 42 {
-            return Ok(vecres);
-        }
-        let mut k = 0;
-        loop {
-            //println!("k = {}",k);
-            if k > 50 {
-                break;
-            }
-            k += 1;
-            let packres = ChannelPack::unpack(&queue.queue);
-            match packres {
-                Ok(pack) => {
-                    //println!(">>>> get pack type: 0x{:02X},seq: {}",pack.packtype,pack.seq);
-                    //从缓冲区中去掉已经解码的部分
-                    queue.cut(pack.length);
-                    vecres.push(pack);
-                }
-                Err(e) => {
-                    //从缓冲区中解码失败，通常原因是字节不够了，终止
-                    //println!("no more data");
-                    break;
-                }
-            }
+        while let Some(pack) = codec.decode(&mut queue.queue)? {
+            vecres.push(pack);
         }
         Ok(vecres)
     }
@@ -241,32 +505,19 @@ impl BcosChannelClient {
             }
             //读到的所有的数据先加入buffer
             self.bufferqueue.append(&mut res);
-            vecres = BcosChannelClient::pop_queue_to_packet(&mut self.bufferqueue)?;
+            let mut codec = self.codec;
+            while let Some(pack) = self.decode_or_reset(&mut codec)? {
+                vecres.push(pack);
+            }
             i += 1
         }
         Ok(vecres)
     }
 
+    ///等待和outpack同一个type和seq的回包，超时时间为request_timeout
     pub fn read_to_match(&mut self, outpack: &ChannelPack) -> Result<ChannelPack, KissError> {
-        let mut i = 0;
-        while i < 50 {
-            let mut res = self.try_recv()?;
-            if res.len() == 0 {
-                continue;
-            }
-            //读到的所有的数据先加入buffer
-            self.bufferqueue.append(&mut res);
-            let matchres = self.try_match_channelpack(outpack);
-            match matchres {
-                Ok(pack) => {
-                    //获得了一个pack，返回即可
-                    return Ok(pack);
-                }
-                _ => { /*继续等待即可*/ }
-            }
-            i += 1;
-        }
-        return kisserr!(KissErrKind::EAgain, "no data return");
+        let timeout = self.request_timeout;
+        self.wait_channelpack(outpack.packtype, &outpack.seq, timeout)
     }
     ///这个方法主要是保证首先数据能发送出去（重试几次），然后从网络读入数据，直到得到发送的pack所对应的type和seq的回包
     pub fn request_channelpack_sync(
@@ -279,3 +530,128 @@ impl BcosChannelClient {
         self.read_to_match(&outpack)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::channelpack::{
+        HandshakeRequest, CHANNEL_CLIENT_TYPE, CHANNEL_PROTOCOL_MAX_SUPPORT,
+        CHANNEL_PROTOCOL_MIN_SUPPORT,
+    };
+    use crate::testsupport::mock_chain::MOCK_NODE_VERSION;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use serde_json::json;
+    use std::collections::VecDeque;
+
+    ///收到包就用同一个type和seq回包，bad_next为true时在回包前面插一个长度非法的包头
+    struct GarbledChannel {
+        inbox: VecDeque<Vec<u8>>,
+        bad_next: bool,
+    }
+
+    impl IBcosChannel for GarbledChannel {
+        fn connect(&mut self) -> Result<i32, KissError> {
+            Ok(0)
+        }
+        fn send(&mut self, sendbuff: &Vec<u8>) -> Result<i32, KissError> {
+            let mut reply = ChannelPack::unpack(sendbuff)?;
+            reply.data = if reply.packtype == CHANNEL_PACK_TYPE::HAND_SHAKE as u16 {
+                br#"{"protocol":3,"nodeVersion":"2.9.0"}"#.to_vec().into()
+            } else {
+                br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#.to_vec().into()
+            };
+            reply.length = 42 + reply.data.len();
+            let mut data = vec![];
+            if self.bad_next {
+                self.bad_next = false;
+                data.extend_from_slice(&(-1i32).to_be_bytes());
+            }
+            data.extend_from_slice(&reply.pack());
+            self.inbox.push_back(data);
+            Ok(sendbuff.len() as i32)
+        }
+        fn recv(&mut self) -> Result<Vec<u8>, KissError> {
+            Ok(self.inbox.pop_front().unwrap_or_default())
+        }
+        fn finish(&mut self) {}
+    }
+
+    #[test]
+    fn test_bad_length_resets_buffer() {
+        let channel = Arc::new(Mutex::new(GarbledChannel {
+            inbox: VecDeque::new(),
+            bad_next: false,
+        }));
+        let mut config = ChannelConfig::default();
+        config.timeout = 2;
+        let mut client = BcosChannelClient::from_channel(&config, channel.clone()).unwrap();
+        let request = r#"{"jsonrpc":"2.0","method":"getBlockNumber","params":[1],"id":1}"#;
+        //长度非法的包头后面跟着一个正常的包：这次请求按网络错误失败，缓冲区被清空
+        channel.lock().unwrap().bad_next = true;
+        let err = client.request_sync(request).unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ENetwork);
+        assert!(client.bufferqueue.is_empty());
+        //之后的请求不再受坏数据影响
+        assert!(client.request_sync(request).unwrap().contains("0x1"));
+    }
+
+    #[test]
+    fn test_handshake_with_mock_node() {
+        let node = MockChannelNode::start().unwrap();
+        let mut client = BcosChannelClient::new(&node.channel_config()).unwrap();
+        assert_eq!(client.protocol_version, CHANNEL_PROTOCOL_MAX_SUPPORT);
+        assert_eq!(client.node_version, MOCK_NODE_VERSION);
+        //连上后的第一个包是握手，带上本sdk支持的版本范围
+        let first = node.received_packs()[0].clone();
+        assert_eq!(first.packtype, CHANNEL_PACK_TYPE::HAND_SHAKE as u16);
+        let req: HandshakeRequest = serde_json::from_slice(&first.data).unwrap();
+        assert_eq!(req.minimumSupport, CHANNEL_PROTOCOL_MIN_SUPPORT);
+        assert_eq!(req.maximumSupport, CHANNEL_PROTOCOL_MAX_SUPPORT);
+        assert_eq!(req.clientType, CHANNEL_CLIENT_TYPE);
+        client.finish();
+
+        //节点协商出的版本不在支持范围内时连接失败，错误里说明原因
+        node.state().handshake_protocol = Some(CHANNEL_PROTOCOL_MAX_SUPPORT + 1);
+        let err = match BcosChannelClient::new(&node.channel_config()) {
+            Ok(_) => panic!("handshake should fail on version mismatch"),
+            Err(e) => e,
+        };
+        assert!(err.msg.contains("version mismatch"), "{}", err.msg);
+        //版本不兼容不是网络错误，按重试策略也不会再试
+        assert_eq!(err.kind, KissErrKind::Error);
+        let handshakes = || {
+            node.received_packs()
+                .iter()
+                .filter(|p| p.packtype == CHANNEL_PACK_TYPE::HAND_SHAKE as u16)
+                .count()
+        };
+        let before = handshakes();
+        let policy = RequestPolicy::default().with_retries(3);
+        let res = policy.retry(|| BcosChannelClient::new(&node.channel_config()));
+        match res {
+            Ok(_) => panic!("handshake should fail on version mismatch"),
+            Err(e) => assert_eq!(e.kind, KissErrKind::Error),
+        }
+        assert_eq!(handshakes(), before + 1);
+    }
+
+    #[test]
+    fn test_batch_shares_one_timeout() {
+        let node = MockChannelNode::start().unwrap();
+        //每个请求节点要处理600ms，三个请求都回完要1.8秒
+        node.chain().set_method("getBlockNumber", |chain, _| {
+            std::thread::sleep(Duration::from_millis(600));
+            Ok(json!("0x1"))
+        });
+        let mut config = node.channel_config();
+        config.timeout = 1;
+        let mut client = BcosChannelClient::new(&config).unwrap();
+        let request = r#"{"jsonrpc":"2.0","method":"getBlockNumber","params":[1],"id":1}"#;
+        let requests = vec![request.to_string(); 3];
+        let start = Instant::now();
+        let err = client.request_batch_sync(&requests).unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ETimeout);
+        assert!(start.elapsed() < Duration::from_millis(1500));
+        client.finish();
+    }
+}
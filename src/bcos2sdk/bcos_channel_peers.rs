@@ -0,0 +1,278 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! channel多节点的管理：记录每个节点的健康状态，连接失败的节点按指数退避暂时跳过，
+//! 拒绝握手(如协议版本不兼容)的节点以后直接跳过，断线后按顺序挑选下一个可用的节点重连
+use std::time::{Duration, Instant};
+
+use crate::bcossdkutil::bcosclientconfig::ChannelConfig;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+#[derive(Debug, Clone)]
+pub struct ChannelPeer {
+    pub ip: String,
+    pub port: u32,
+    pub fail_count: u32,
+    pub next_retry: Option<Instant>, //在此时间之前不再尝试这个节点
+    pub rejected: Option<KissError>, //握手被拒绝的错误，有值时不再尝试这个节点
+}
+
+impl ChannelPeer {
+    pub fn is_available(&self, now: Instant) -> bool {
+        if self.rejected.is_some() {
+            return false;
+        }
+        match self.next_retry {
+            Some(t) => now >= t,
+            None => true,
+        }
+    }
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelPeerManager {
+    pub peers: Vec<ChannelPeer>,
+    pub current: usize,
+    pub backoff_base: Duration,
+    pub backoff_max: Duration,
+}
+
+impl ChannelPeerManager {
+    ///peers为空时用ip/port作为唯一的节点
+    pub fn from_config(config: &ChannelConfig) -> Result<ChannelPeerManager, KissError> {
+        let mut peers: Vec<ChannelPeer> = vec![];
+        if config.peers.is_empty() {
+            peers.push(ChannelPeer {
+                ip: config.ip.clone(),
+                port: config.port,
+                fail_count: 0,
+                next_retry: None,
+                rejected: None,
+            });
+        }
+        for p in config.peers.iter() {
+            peers.push(ChannelPeerManager::parse_peer(p)?);
+        }
+        Ok(ChannelPeerManager {
+            peers,
+            current: 0,
+            backoff_base: Duration::from_millis(config.reconnect_backoff_ms),
+            backoff_max: Duration::from_millis(config.reconnect_backoff_max_ms),
+        })
+    }
+
+    ///只有ip/port一个节点，用于配置里peers无法解析时兜底
+    pub fn single(config: &ChannelConfig) -> ChannelPeerManager {
+        ChannelPeerManager {
+            peers: vec![ChannelPeer {
+                ip: config.ip.clone(),
+                port: config.port,
+                fail_count: 0,
+                next_retry: None,
+                rejected: None,
+            }],
+            current: 0,
+            backoff_base: Duration::from_millis(config.reconnect_backoff_ms),
+            backoff_max: Duration::from_millis(config.reconnect_backoff_max_ms),
+        }
+    }
+
+    ///"ip:port"格式
+    pub fn parse_peer(peer: &str) -> Result<ChannelPeer, KissError> {
+        let pos = match peer.rfind(':') {
+            Some(p) => p,
+            None => return kisserr!(KissErrKind::EArgument, "peer should be ip:port {}", peer),
+        };
+        let port: u32 = match peer[pos + 1..].trim().parse() {
+            Ok(p) => p,
+            Err(e) => return kisserr!(KissErrKind::EArgument, "bad port in peer {},{:?}", peer, e),
+        };
+        Ok(ChannelPeer {
+            ip: peer[..pos].trim().to_string(),
+            port,
+            fail_count: 0,
+            next_retry: None,
+            rejected: None,
+        })
+    }
+
+    pub fn current_peer(&self) -> &ChannelPeer {
+        &self.peers[self.current]
+    }
+
+    ///把当前节点的地址填入config，其他配置不变
+    pub fn current_config(&self, config: &ChannelConfig) -> ChannelConfig {
+        let mut c = config.clone();
+        let peer = self.current_peer();
+        c.ip = peer.ip.clone();
+        c.port = peer.port;
+        c
+    }
+
+    pub fn mark_success(&mut self) {
+        let peer = &mut self.peers[self.current];
+        peer.fail_count = 0;
+        peer.next_retry = None;
+    }
+
+    ///当前节点失败一次，退避时间按失败次数翻倍，不超过上限
+    pub fn mark_failure(&mut self) {
+        let base = self.backoff_base;
+        let max = self.backoff_max;
+        let peer = &mut self.peers[self.current];
+        peer.fail_count += 1;
+        let factor = 1u32 << (peer.fail_count - 1).min(16);
+        let backoff = base.checked_mul(factor).unwrap_or(max).min(max);
+        peer.next_retry = Some(Instant::now() + backoff);
+        log::warn!(
+            "channel peer {} failed {} times, retry after {:?}",
+            peer.addr(),
+            peer.fail_count,
+            backoff
+        );
+    }
+
+    ///当前节点拒绝了握手，和网络故障不同，重试也不会成功，不进入退避而是直接排除
+    pub fn mark_rejected(&mut self, err: &KissError) {
+        let peer = &mut self.peers[self.current];
+        log::error!("channel peer {} rejected handshake {:?}", peer.addr(), err);
+        peer.rejected = Some(err.clone());
+    }
+
+    ///所有节点都拒绝了握手
+    pub fn all_rejected(&self) -> bool {
+        self.peers.iter().all(|p| p.rejected.is_some())
+    }
+
+    ///从当前节点开始，按顺序找出所有已过退避期的节点；
+    ///都在退避期内时，返回最早可以重试的那个，避免一个都不试。拒绝过握手的节点不在其中
+    pub fn candidates(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let n = self.peers.len();
+        let mut res: Vec<usize> = (0..n)
+            .map(|i| (self.current + i) % n)
+            .filter(|i| self.peers[*i].is_available(now))
+            .collect();
+        if res.is_empty() {
+            let earliest = (0..n)
+                .filter(|i| self.peers[*i].rejected.is_none())
+                .min_by_key(|i| self.peers[*i].next_retry);
+            res.extend(earliest);
+        }
+        res
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.current = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::bcos_channel_client::{BcosChannelClient, IBcosChannel};
+    use crate::bcos2sdk::channelpack::{CHANNEL_PACK_TYPE, CHANNEL_PROTOCOL_MAX_SUPPORT};
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_failover_with_mock_nodes() {
+        let mut node_a = MockChannelNode::start().unwrap();
+        let node_b = node_a.start_peer().unwrap();
+        //绑定后马上释放的端口，连上去会被拒绝
+        let dead = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut config = node_a.channel_config();
+        config.peers = vec![
+            dead.to_string(),
+            node_a.addr.to_string(),
+            node_b.addr.to_string(),
+        ];
+        config.reconnect_backoff_ms = 60_000;
+        config.reconnect_backoff_max_ms = 120_000;
+
+        //第一个节点连不上，进入退避，连到第二个
+        let mut client = BcosChannelClient::new(&config).unwrap();
+        let peers = &client.peermanager;
+        assert_eq!(peers.current, 1);
+        assert_eq!(peers.peers[0].fail_count, 1);
+        assert!(!peers.peers[0].is_available(Instant::now()));
+        assert_eq!(peers.peers[1].fail_count, 0);
+
+        //第二个节点停掉，换节点时跳过还在退避期的第一个，连到第三个并重新握手
+        node_a.stop();
+        client.failover().unwrap();
+        let peers = &client.peermanager;
+        assert_eq!(peers.current, 2);
+        assert_eq!(peers.peers[0].fail_count, 1);
+        assert_eq!(peers.peers[1].fail_count, 1);
+        assert!(node_b
+            .received_packs()
+            .iter()
+            .any(|p| p.packtype == CHANNEL_PACK_TYPE::HAND_SHAKE as u16));
+        let request = r#"{"jsonrpc":"2.0","method":"getBlockNumber","params":[1],"id":1}"#;
+        assert!(client.request_sync(request).unwrap().contains("result"));
+        client.finish();
+    }
+
+    #[test]
+    fn test_skip_rejected_peer() {
+        let node = MockChannelNode::start().unwrap();
+        let incompatible = node.start_peer().unwrap();
+        incompatible.state().handshake_protocol = Some(CHANNEL_PROTOCOL_MAX_SUPPORT + 1);
+        let mut config = node.channel_config();
+        config.peers = vec![incompatible.addr.to_string(), node.addr.to_string()];
+        //没有退避，网络故障的节点下次马上会再试
+        config.reconnect_backoff_ms = 0;
+        config.reconnect_backoff_max_ms = 0;
+
+        //第一个节点拒绝握手，不计入退避，直接排除
+        let mut client = BcosChannelClient::new(&config).unwrap();
+        let peers = &client.peermanager;
+        assert_eq!(peers.current, 1);
+        assert!(peers.peers[0].rejected.is_some());
+        assert_eq!(peers.peers[0].fail_count, 0);
+        assert_eq!(peers.candidates(), vec![1]);
+
+        //可用的节点暂时连不上时也不会再去试拒绝过握手的节点，报的是可重试的网络错误
+        node.set_refuse_connections(true);
+        let err = match client.failover() {
+            Ok(_) => panic!("failover should fail while the node refuses connections"),
+            Err(e) => e,
+        };
+        assert_eq!(err.kind, KissErrKind::ENetwork);
+        node.set_refuse_connections(false);
+        client.failover().unwrap();
+        assert_eq!(client.peermanager.current, 1);
+        let handshakes = incompatible
+            .received_packs()
+            .iter()
+            .filter(|p| p.packtype == CHANNEL_PACK_TYPE::HAND_SHAKE as u16)
+            .count();
+        assert_eq!(handshakes, 1);
+        client.finish();
+    }
+}
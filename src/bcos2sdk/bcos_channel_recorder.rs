@@ -0,0 +1,338 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! channel流量的录制和回放。
+//! 录制：包装底层的IBcosChannel，把收发的每个ChannelPack追加写到文件，一行一个包：
+//! 毫秒时间戳 方向(OUT/IN) 包类型 seq 整包hex(ChannelPack::pack的结果)，用tab分隔。
+//! 回放：BcosChannelReplayer实现IBcosChannel，sdk每发出一个包就和录制的下一个OUT包对上，
+//! 然后把它后面录到的IN包吐回去。请求的seq每次都是随机的，回放时把录制的seq换成实际发出的seq，
+//! 这样回包和同seq的推送(如TX_COMMITTED)都能对上。心跳和时间有关，不参与回放，由回放器直接应答
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use chrono::Local;
+use ethereum_types::H256;
+
+use crate::bcos2sdk::bcos_channel_client::{BcosChannelClient, IBcosChannel, IBcosChannelImpl};
+use crate::bcos2sdk::channelpack::{ChannelPack, CHANNEL_PACK_TYPE};
+use crate::bcossdkutil::bufferqueue::BufferQueue;
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelDirection {
+    OUT,
+    IN,
+}
+
+///录制文件里的一条记录
+#[derive(Debug, Clone)]
+pub struct ChannelRecord {
+    pub timestamp: i64,
+    pub direction: ChannelDirection,
+    pub pack: ChannelPack,
+}
+
+impl ChannelRecord {
+    pub fn to_line(&self) -> String {
+        let direction = match self.direction {
+            ChannelDirection::OUT => "OUT",
+            ChannelDirection::IN => "IN",
+        };
+        format!(
+            "{}\t{}\t0x{:04x}\t{:?}\t{}",
+            self.timestamp,
+            direction,
+            self.pack.packtype,
+            self.pack.seq,
+            hex::encode(self.pack.pack())
+        )
+    }
+
+    ///包类型和seq只是方便人看，解析时以整包为准
+    pub fn from_line(line: &str) -> Result<ChannelRecord, KissError> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 5 {
+            return kisserr!(KissErrKind::EFormat, "bad channel record line {}", line);
+        }
+        let timestamp = match fields[0].parse::<i64>() {
+            Ok(t) => t,
+            Err(e) => return kisserr!(KissErrKind::EFormat, "bad record timestamp {:?}", e),
+        };
+        let direction = match fields[1] {
+            "OUT" => ChannelDirection::OUT,
+            "IN" => ChannelDirection::IN,
+            d => return kisserr!(KissErrKind::EFormat, "bad record direction {}", d),
+        };
+        let raw = match hex::decode(fields[4]) {
+            Ok(r) => r,
+            Err(e) => return kisserr!(KissErrKind::EFormat, "bad record pack hex {:?}", e),
+        };
+        let pack = ChannelPack::unpack(&raw)?;
+        Ok(ChannelRecord {
+            timestamp,
+            direction,
+            pack,
+        })
+    }
+}
+
+///读出录制文件里的全部记录，空行忽略
+pub fn load_channel_records(filename: &str) -> Result<Vec<ChannelRecord>, KissError> {
+    let text = fileutils::readstring(filename)?;
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| ChannelRecord::from_line(l.trim_end()))
+        .collect()
+}
+
+///包装一个底层连接，收发的包都追加写到录制文件，数据原样透传
+pub struct BcosChannelRecorder {
+    pub inner: IBcosChannelImpl,
+    pub filename: String,
+    file: File,
+    outqueue: BufferQueue,
+    inqueue: BufferQueue,
+}
+
+impl BcosChannelRecorder {
+    pub fn new(inner: IBcosChannelImpl, filename: &str) -> Result<BcosChannelRecorder, KissError> {
+        let file = match OpenOptions::new().create(true).append(true).open(filename) {
+            Ok(f) => f,
+            Err(e) => {
+                return kisserr!(
+                    KissErrKind::Error,
+                    "open channel record file {} error {:?}",
+                    filename,
+                    e
+                )
+            }
+        };
+        Ok(BcosChannelRecorder {
+            inner,
+            filename: filename.to_string(),
+            file,
+            outqueue: BufferQueue::new(),
+            inqueue: BufferQueue::new(),
+        })
+    }
+
+    ///按包切分后写文件，不完整的包留在队列里等下次的数据
+    fn record(&mut self, direction: ChannelDirection, data: &[u8]) {
+        let queue = match direction {
+            ChannelDirection::OUT => &mut self.outqueue,
+            ChannelDirection::IN => &mut self.inqueue,
+        };
+        queue.append(&mut data.to_vec());
+        let packs = match BcosChannelClient::pop_queue_to_packet(queue) {
+            Ok(packs) => packs,
+            Err(e) => {
+                //数据已经错位，丢掉缓冲区，sdk那边也会按错误处理
+                log::warn!("record channel data error {:?}", e);
+                *queue = BufferQueue::new();
+                return;
+            }
+        };
+        let timestamp = Local::now().timestamp_millis();
+        for pack in packs {
+            let record = ChannelRecord {
+                timestamp,
+                direction,
+                pack,
+            };
+            if let Err(e) = writeln!(self.file, "{}", record.to_line()) {
+                log::warn!("write channel record {} error {:?}", self.filename, e);
+            }
+        }
+    }
+}
+
+impl IBcosChannel for BcosChannelRecorder {
+    fn connect(&mut self) -> Result<i32, KissError> {
+        self.inner.lock().unwrap().connect()
+    }
+
+    fn send(&mut self, sendbuff: &Vec<u8>) -> Result<i32, KissError> {
+        let res = self.inner.lock().unwrap().send(sendbuff)?;
+        //返回0表示没有发出去，sdk会重发同样的数据，这时不记录
+        if res > 0 {
+            self.record(ChannelDirection::OUT, sendbuff);
+        }
+        Ok(res)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, KissError> {
+        let data = self.inner.lock().unwrap().recv()?;
+        if !data.is_empty() {
+            self.record(ChannelDirection::IN, &data);
+        }
+        Ok(data)
+    }
+
+    fn finish(&mut self) {
+        let _ = self.file.flush();
+        self.inner.lock().unwrap().finish()
+    }
+}
+
+///把录制的会话回放给sdk，不需要网络和节点
+pub struct BcosChannelReplayer {
+    pub records: VecDeque<ChannelRecord>,
+    ///录制的seq到实际发出的seq
+    seqmap: HashMap<H256, H256>,
+    outqueue: BufferQueue,
+    pending: Vec<u8>,
+}
+
+impl BcosChannelReplayer {
+    pub fn new(records: Vec<ChannelRecord>) -> BcosChannelReplayer {
+        let heartbeat = CHANNEL_PACK_TYPE::HEART_BEAT as u16;
+        let mut replayer = BcosChannelReplayer {
+            records: records
+                .into_iter()
+                .filter(|r| r.pack.packtype != heartbeat)
+                .collect(),
+            seqmap: HashMap::new(),
+            outqueue: BufferQueue::new(),
+            pending: vec![],
+        };
+        //第一个OUT之前录到的包(连上后节点主动推的)直接吐出去
+        replayer.release_inbound();
+        replayer
+    }
+
+    pub fn from_file(filename: &str) -> Result<BcosChannelReplayer, KissError> {
+        Ok(BcosChannelReplayer::new(load_channel_records(filename)?))
+    }
+
+    ///录制的包是否都已回放完
+    pub fn is_done(&self) -> bool {
+        self.records.is_empty() && self.pending.is_empty()
+    }
+
+    fn release_inbound(&mut self) {
+        while let Some(record) = self.records.front() {
+            if record.direction == ChannelDirection::OUT {
+                break;
+            }
+            let mut pack = self.records.pop_front().unwrap().pack;
+            if let Some(seq) = self.seqmap.get(&pack.seq) {
+                pack.seq = *seq;
+            }
+            self.pending.append(&mut pack.pack());
+        }
+    }
+
+    fn replay_outbound(&mut self, pack: &ChannelPack) -> Result<(), KissError> {
+        if pack.packtype == CHANNEL_PACK_TYPE::HEART_BEAT as u16 {
+            let data = br#"{"heartbeat":"1"}"#.to_vec();
+            let reply = ChannelPack {
+                length: 42 + data.len(),
+                packtype: pack.packtype,
+                seq: pack.seq,
+                result: 0,
+                data: data.into(),
+            };
+            self.pending.append(&mut reply.pack());
+            return Ok(());
+        }
+        let expected = match self.records.pop_front() {
+            Some(r) => r,
+            None => {
+                return kisserr!(
+                    KissErrKind::EFormat,
+                    "replay finished, unexpected pack type 0x{:x}",
+                    pack.packtype
+                )
+            }
+        };
+        if expected.pack.packtype != pack.packtype {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "replay diverged, recorded pack type 0x{:x}, sent 0x{:x}",
+                expected.pack.packtype,
+                pack.packtype
+            );
+        }
+        self.seqmap.insert(expected.pack.seq, pack.seq);
+        self.release_inbound();
+        Ok(())
+    }
+}
+
+impl IBcosChannel for BcosChannelReplayer {
+    fn connect(&mut self) -> Result<i32, KissError> {
+        Ok(0)
+    }
+
+    fn send(&mut self, sendbuff: &Vec<u8>) -> Result<i32, KissError> {
+        self.outqueue.append(&mut sendbuff.clone());
+        for pack in BcosChannelClient::pop_queue_to_packet(&mut self.outqueue)? {
+            self.replay_outbound(&pack)?;
+        }
+        Ok(sendbuff.len() as i32)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, KissError> {
+        Ok(std::mem::take(&mut self.pending))
+    }
+
+    fn finish(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcossdkutil::bcosclientconfig::ChannelConfig;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_and_replay() {
+        let node = MockChannelNode::start().unwrap();
+        let recordfile = node.certdir.join("channel.rec");
+        let mut config = node.channel_config();
+        config.record_file = recordfile.to_string_lossy().to_string();
+        let request =
+            json!({"jsonrpc": "2.0", "method": "getClientVersion", "params": [1], "id": 1});
+        let mut client = BcosChannelClient::new(&config).unwrap();
+        let recorded = client.request_sync(&request.to_string()).unwrap();
+        client.finish();
+
+        let records = load_channel_records(&config.record_file).unwrap();
+        assert_eq!(records[0].direction, ChannelDirection::OUT);
+        assert_eq!(
+            records[0].pack.packtype,
+            CHANNEL_PACK_TYPE::HAND_SHAKE as u16
+        );
+        assert!(records.iter().any(|r| r.direction == ChannelDirection::IN
+            && r.pack.packtype == CHANNEL_PACK_TYPE::RPC as u16));
+
+        let replayer = BcosChannelReplayer::from_file(&config.record_file).unwrap();
+        let channelimpl: IBcosChannelImpl = std::sync::Arc::new(std::sync::Mutex::new(replayer));
+        let mut client =
+            BcosChannelClient::from_channel(&ChannelConfig::default(), channelimpl).unwrap();
+        assert_eq!(client.request_sync(&request.to_string()).unwrap(), recorded);
+        let err = client.request_sync(&request.to_string()).unwrap_err();
+        assert_eq!(err.kind, KissErrKind::EFormat);
+    }
+}
@@ -14,35 +14,38 @@
 //use crate::bcossdkutil::bcos_ssl_normal::BcosSSLClient;
 //use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ChannelConfig};
 //use crate::bcossdkutil::bufferqueue::BufferQueue;
-use crate::bcos2sdk::channelpack::{make_channel_pack, ChannelPack, CHANNEL_PACK_TYPE};
+use crate::bcos2sdk::channelpack::{
+    make_channel_pack, make_heartbeat_pack, ChannelPack, CHANNEL_PACK_TYPE,
+};
 //use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::bcos2sdk::bcos2client::Bcos2Client;
+use crate::bcos2sdk::bcos_channel_client::BcosChannelClient;
 use crate::bcos2sdk::bcos_channel_handler_manager::ChannelPushHandlerManager;
 use crate::bcos2sdk::bcosrpcwraper::RpcRequestData;
-use crate::bcossdkutil::kisserror::KissError;
-use lazy_static::lazy_static;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
 use serde_json::json;
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 
 type BcosSDKArc = Arc<Mutex<Bcos2Client>>;
 type BcosChannelWorkerArc = Arc<Mutex<BcosChannelWorker>>;
-type BcosSDKMap = Arc<Mutex<HashMap<String, Bcos2Client>>>;
-lazy_static! {
-    static ref BCOS_SDK_MAP: BcosSDKMap = {
-        let sdkmap: BcosSDKMap = Arc::new(Mutex::new(HashMap::new()));
-        sdkmap
-    };
-}
+
+///重连成功后调用，用来恢复节点上的状态，如重新注册事件过滤条件。topic由重连过程自动重新上报
+pub type WORKER_RECONNECT_HOOK = Arc<dyn Fn(&mut BcosChannelClient) + Send + Sync>;
 
 ///用线程模式读写channel连接上的数据，并回调处理者
 pub struct BcosChannelWorker {
     pub bcossdk: Bcos2Client,
     pub handlemanager: ChannelPushHandlerManager,
-    pub is_working: bool,
+    pub reconnect_hooks: Vec<WORKER_RECONNECT_HOOK>,
+    pub last_recv: Instant, //最近一次从连接上收到数据的时间，用来判断连接是否还活着
+    pub reconnect_count: u64, //重连成功的次数
+    pub reconnect_delays: Vec<Duration>, //最近一轮重连里每次失败后等待的时间
 }
 
 impl BcosChannelWorker {
@@ -50,16 +53,57 @@ impl BcosChannelWorker {
         BcosChannelWorker {
             bcossdk: Bcos2Client::new_from_config(configfile).unwrap(),
             handlemanager: ChannelPushHandlerManager::default(),
-            is_working: true,
+            reconnect_hooks: vec![],
+            last_recv: Instant::now(),
+            reconnect_count: 0,
+            reconnect_delays: vec![],
         }
     }
+
+    pub fn add_reconnect_hook(&mut self, hook: WORKER_RECONNECT_HOOK) {
+        self.reconnect_hooks.push(hook);
+    }
+
+    ///重连并执行所有的重连回调
+    pub fn reconnect(&mut self) -> Result<(), KissError> {
+        let channel_client = &mut self.bcossdk.netclient.channel_client;
+        channel_client.failover()?;
+        for hook in self.reconnect_hooks.iter() {
+            hook(channel_client);
+        }
+        self.reconnect_done();
+        Ok(())
+    }
+
+    fn reconnect_done(&mut self) {
+        self.last_recv = Instant::now();
+        self.reconnect_count += 1;
+    }
+
+    ///第n次(从0开始)重连失败后的等待时间，从reconnect_backoff_ms开始翻倍，不超过reconnect_backoff_max_ms
+    pub fn reconnect_backoff(&self, attempt: u32) -> Duration {
+        let config = &self.bcossdk.config.channel;
+        let ms = config
+            .reconnect_backoff_ms
+            .saturating_mul(1u64 << attempt.min(20))
+            .min(
+                config
+                    .reconnect_backoff_max_ms
+                    .max(config.reconnect_backoff_ms),
+            );
+        Duration::from_millis(ms)
+    }
 }
 //unsafe impl Send for BcosChannelWorker{}
 //unsafe impl Sync for BcosChannelWorker{}
 
 pub fn read_packets(worker_arc: &BcosChannelWorkerArc) -> Result<Vec<ChannelPack>, KissError> {
     let mut worker = worker_arc.lock().unwrap();
-    worker.bcossdk.netclient.channel_client.read_packets()
+    let packs = worker.bcossdk.netclient.channel_client.read_packets()?;
+    if !packs.is_empty() {
+        worker.last_recv = Instant::now();
+    }
+    Ok(packs)
 }
 
 pub fn send_packet(
@@ -74,40 +118,127 @@ pub fn send_packet(
         .try_send(&pack.pack())
 }
 
-fn is_working(worker_arc: &BcosChannelWorkerArc) -> bool {
-    let worker = worker_arc.lock().unwrap();
-    let working = worker.is_working;
-    working
+///在阻塞线程里对一个复制出来的客户端重连并执行重连回调，不持有worker的锁。
+///tls握手和回调里的请求都是阻塞的，这样不会占住tokio的工作线程，也不会挡住其他使用这个客户端的地方。
+///成功后把新连接换进worker；失败时只把节点列表的状态(失败次数、拒绝握手)带回去
+async fn reconnect_detached(worker_arc: &BcosChannelWorkerArc) -> Result<(), KissError> {
+    let (mut client, hooks) = {
+        let mut worker = worker_arc.lock().unwrap();
+        let channel_client = &mut worker.bcossdk.netclient.channel_client;
+        channel_client.peermanager.mark_failure();
+        (channel_client.detached(), worker.reconnect_hooks.clone())
+    };
+    let joined = tokio::task::spawn_blocking(move || {
+        let res = client.reconnect();
+        if res.is_ok() {
+            for hook in hooks.iter() {
+                hook(&mut client);
+            }
+        }
+        (client, res)
+    })
+    .await;
+    let (client, res) = match joined {
+        Ok(r) => r,
+        Err(e) => return kisserr!(KissErrKind::Error, "reconnect task error {:?}", e),
+    };
+    let mut worker = worker_arc.lock().unwrap();
+    let channel_client = &mut worker.bcossdk.netclient.channel_client;
+    match res {
+        Ok(_) => {
+            channel_client.adopt_connection(client);
+            worker.reconnect_done();
+            Ok(())
+        }
+        Err(e) => {
+            channel_client.peermanager = client.peermanager;
+            Err(e)
+        }
+    }
 }
 
-async fn read_thread(worker_arc: BcosChannelWorkerArc, tx: &Sender<ChannelPack>) {
-    println!("read thread running");
-    //let queue = BufferQueue::default();
-    loop {
-        if !is_working(&worker_arc) {
-            break;
+///读和心跳任务都可能发现连接断开，reconnecting标记保证同时只有一个任务在重连，
+///另一个任务等它结束。重连失败时按次数翻倍退避后再试，收到停止信号时返回false
+async fn reconnect_until_ok(
+    worker_arc: &BcosChannelWorkerArc,
+    reconnecting: &AtomicBool,
+    shutdown: &mut watch::Receiver<bool>,
+) -> bool {
+    if reconnecting
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        while reconnecting.load(Ordering::SeqCst) {
+            tokio::select! {
+                _ = shutdown.changed() => return false,
+                _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+            }
         }
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        return !*shutdown.borrow();
+    }
+    worker_arc.lock().unwrap().reconnect_delays.clear();
+    let mut attempt: u32 = 0;
+    let ok = loop {
+        let backoff = match reconnect_detached(worker_arc).await {
+            Ok(_) => {
+                log::info!("channel worker reconnected");
+                break true;
+            }
+            Err(e) => {
+                log::error!("channel worker reconnect error {:?}", e);
+                let mut worker = worker_arc.lock().unwrap();
+                let backoff = worker.reconnect_backoff(attempt);
+                worker.reconnect_delays.push(backoff);
+                backoff
+            }
+        };
+        attempt = attempt.saturating_add(1);
+        tokio::select! {
+            _ = shutdown.changed() => break false,
+            _ = tokio::time::sleep(backoff) => {}
+        }
+    };
+    reconnecting.store(false, Ordering::SeqCst);
+    ok
+}
 
-        //let mut worker = worker_arc.lock().unwrap();
+async fn read_thread(
+    worker_arc: BcosChannelWorkerArc,
+    tx: Sender<ChannelPack>,
+    reconnecting: Arc<AtomicBool>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    log::info!("channel worker read thread running");
+    while !*shutdown.borrow() {
         let recvres = read_packets(&worker_arc);
         match recvres {
             Ok(res) => {
-                //println!("read from channel ,total {}", res.len());
-                let mut i = 0;
+                if res.is_empty() {
+                    tokio::select! {
+                        changed = shutdown.changed() => {
+                            if changed.is_err() {
+                                break;
+                            }
+                        }
+                        _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+                    }
+                    continue;
+                }
                 for pack in res {
-                    i += 1;
-                    println!("{})-> {}", i, pack.detail());
-                    let res = tx.send(pack).await;
+                    if tx.send(pack).await.is_err() {
+                        return;
+                    }
                 }
             }
             Err(e) => {
-                println!("read_thread error {:?}", e);
+                log::warn!("channel worker read error {:?}, reconnecting", e);
+                if !reconnect_until_ok(&worker_arc, &reconnecting, &mut shutdown).await {
+                    break;
+                }
             }
         }
-        tokio::time::sleep(Duration::from_millis(100)).await;
     }
-    println!("read thread done!!!")
+    log::info!("channel worker read thread done")
 }
 
 pub fn getNodeVersionPack() -> Option<ChannelPack> {
@@ -125,33 +256,44 @@ pub fn getNodeVersionPack() -> Option<ChannelPack> {
     make_channel_pack(CHANNEL_PACK_TYPE::RPC, req.encode().unwrap().as_str())
 }
 
-///每3秒钟发一次heartbeat
-async fn heart_beat_thread(worker_arc: BcosChannelWorkerArc) {
-    let mut last_heartbeat_time = time::now() - chrono::Duration::seconds(10);
-
+///按配置的间隔发HEART_BEAT，连续多个间隔都没有收到任何数据时认为连接已断开，重连
+async fn heart_beat_thread(
+    worker_arc: BcosChannelWorkerArc,
+    reconnecting: Arc<AtomicBool>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let (interval, max_missed) = {
+        let worker = worker_arc.lock().unwrap();
+        let config = &worker.bcossdk.config.channel;
+        (
+            Duration::from_secs(config.heartbeat_interval_sec.max(1)),
+            config.heartbeat_max_missed.max(1),
+        )
+    };
     loop {
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        if !is_working(&worker_arc) {
+        tokio::select! {
+            _ = shutdown.changed() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+        if *shutdown.borrow() {
             break;
         }
-
-        if time::now() - last_heartbeat_time < chrono::Duration::seconds(5) {
+        let idle = worker_arc.lock().unwrap().last_recv.elapsed();
+        if idle > interval * max_missed {
+            log::warn!("no data from node for {:?}, reconnecting", idle);
+            if !reconnect_until_ok(&worker_arc, &reconnecting, &mut shutdown).await {
+                break;
+            }
             continue;
         }
-        //let heartbeatpack = make_channel_pack(CHANNEL_PACK_TYPE::HEART_BEAT, "");
-        let heartbeatpack = getNodeVersionPack();
-        last_heartbeat_time = time::now();
-        println!(
-            "heartbeat send {:?}",
-            heartbeatpack.as_ref().unwrap().detail()
-        );
-        let res = send_packet(&worker_arc, &heartbeatpack.unwrap());
-
-        println!("heartbeat send result {:?}", res);
-        //let recvres = worker.bcossdkutil.netclient.channel_client.try_recv();
-        //println!("heartbeat recv result {:?}",recvres);
+        if let Err(e) = send_packet(&worker_arc, &make_heartbeat_pack()) {
+            log::warn!("heartbeat send error {:?}, reconnecting", e);
+            if !reconnect_until_ok(&worker_arc, &reconnecting, &mut shutdown).await {
+                break;
+            }
+        }
     }
-    println!("heartbeat thread done!!")
+    log::info!("channel worker heartbeat thread done")
 }
 
 pub async fn start(configfile: &str) {
@@ -162,65 +304,202 @@ pub async fn start(configfile: &str) {
 }
 
 pub fn handle_packet(worker_arc: &BcosChannelWorkerArc, pack: &ChannelPack) {
-    println!(">>>handle packet {}", pack.detail());
-    let worker = worker_arc.lock().unwrap();
-    println!("worker ->>>> {}", worker.handlemanager.count_handler());
-    let handleOpt = worker.handlemanager.get_handle(&pack.packtype);
-    match handleOpt {
+    if pack.packtype == CHANNEL_PACK_TYPE::HEART_BEAT as u16 {
+        //心跳回包只用来刷新last_recv，读的时候已经处理了
+        return;
+    }
+    let handle = worker_arc
+        .lock()
+        .unwrap()
+        .handlemanager
+        .get_handle(&pack.packtype)
+        .cloned();
+    match handle {
         Some(handle) => {
-            handle.lock().unwrap().handle(&pack);
+            handle.lock().unwrap().handle(pack);
         }
         None => {
-            println!("Handle not found for type {}", pack.packtype);
+            log::debug!("Handle not found for type {}", pack.packtype);
         }
     }
 }
 
-pub async fn start_bcos_channel_worker(worker_arc: &Arc<Mutex<BcosChannelWorker>>) {
-    println!("start worker");
-    let (readthread_sender, mut readthread_recver) = mpsc::channel::<ChannelPack>(32);
+///运行中的worker，shutdown通知读、心跳和分发任务退出并等待它们结束。handle被丢弃时任务也会退出
+pub struct BcosChannelWorkerHandle {
+    pub worker: BcosChannelWorkerArc,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl BcosChannelWorkerHandle {
+    pub fn is_finished(&self) -> bool {
+        self.tasks.iter().all(|t| t.is_finished())
+    }
+
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        self.join().await;
+    }
+
+    ///等待所有任务结束，不会主动停止它们
+    pub async fn join(self) {
+        for t in self.tasks {
+            if let Err(e) = t.await {
+                log::error!("channel worker task error {:?}", e);
+            }
+        }
+    }
+}
+
+///启动读、心跳和分发三个任务，返回用来停止和等待的handle，需要在tokio运行时里调用
+pub fn spawn_bcos_channel_worker(worker_arc: &BcosChannelWorkerArc) -> BcosChannelWorkerHandle {
+    let (readthread_sender, mut readthread_recver) = mpsc::channel::<ChannelPack>(256);
+    let (shutdown_sender, shutdown_recver) = watch::channel(false);
+    let reconnecting = Arc::new(AtomicBool::new(false));
+    worker_arc.lock().unwrap().last_recv = Instant::now();
 
-    //let worker = BcosChannelWorker::new(configfile);
-    //let worker_arc = Arc::new(Mutex::new(worker));
-    println!("start heatbeat thread");
     let worker_heartbeat = worker_arc.clone();
-    tokio::spawn(async move {
-        heart_beat_thread(worker_heartbeat).await;
+    let heartbeat_reconnecting = reconnecting.clone();
+    let heartbeat_shutdown = shutdown_recver.clone();
+    let heartbeat = tokio::spawn(async move {
+        heart_beat_thread(worker_heartbeat, heartbeat_reconnecting, heartbeat_shutdown).await;
     });
-    println!("start read thread");
 
     let worker_read = worker_arc.clone();
-    tokio::spawn(async move {
-        read_thread(worker_read, &readthread_sender.clone()).await;
+    let read = tokio::spawn(async move {
+        read_thread(
+            worker_read,
+            readthread_sender,
+            reconnecting,
+            shutdown_recver,
+        )
+        .await;
     });
 
-    println!("local waiting");
+    //读任务退出后发送端被释放，分发任务处理完剩下的包后结束
+    let worker_dispatch = worker_arc.clone();
+    let dispatch = tokio::spawn(async move {
+        while let Some(pack) = readthread_recver.recv().await {
+            handle_packet(&worker_dispatch, &pack);
+        }
+    });
 
-    let mut counter = 1;
-    let mut working = true;
-    loop {
-        println!("still waiting");
-
-        match readthread_recver.recv().await {
-            Some(pack) => {
-                counter += 1;
-                println!("rx recv packet {:?}", pack.detail());
-                let worker_local = worker_arc.clone();
-                handle_packet(&worker_local, &pack);
-
-                if counter > 100 {
-                    println!("shutdown threads");
-                    //worker_local = worker_arc.clone();
-                    worker_local.lock().unwrap().is_working = false;
-                    working = false;
-                    break;
-                }
+    BcosChannelWorkerHandle {
+        worker: worker_arc.clone(),
+        shutdown: shutdown_sender,
+        tasks: vec![read, heartbeat, dispatch],
+    }
+}
+
+///启动worker并一直运行，直到ctrl-c
+pub async fn start_bcos_channel_worker(worker_arc: &Arc<Mutex<BcosChannelWorker>>) {
+    let handle = spawn_bcos_channel_worker(worker_arc);
+    let _ = tokio::signal::ctrl_c().await;
+    log::info!("shutdown channel worker");
+    handle.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+
+    ///等到条件成立，最多等timeout
+    async fn wait_until(timeout: Duration, f: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if f() {
+                return true;
             }
-            None => {
-                tokio::time::sleep(Duration::from_micros(1000)).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        f()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_single_reconnect_with_backoff() {
+        let node = MockChannelNode::start().unwrap();
+        let configfile = node.write_client_config().unwrap();
+        let mut worker = BcosChannelWorker::new(&configfile);
+        {
+            let config = &mut worker.bcossdk.config.channel;
+            config.reconnect_backoff_ms = 50;
+            config.reconnect_backoff_max_ms = 1000;
+            config.heartbeat_interval_sec = 1;
+            config.heartbeat_max_missed = 2;
+        }
+        let worker_arc = Arc::new(Mutex::new(worker));
+        let handle = spawn_bcos_channel_worker(&worker_arc);
+
+        //节点断开连接并拒绝新连接，读任务和心跳任务都会发现连接断了
+        node.set_refuse_connections(true);
+        node.close_sessions();
+        assert!(
+            wait_until(Duration::from_secs(10), || {
+                worker_arc.lock().unwrap().reconnect_delays.len() >= 6
+            })
+            .await
+        );
+        node.set_refuse_connections(false);
+        assert!(
+            wait_until(Duration::from_secs(5), || {
+                worker_arc.lock().unwrap().reconnect_count == 1
+            })
+            .await
+        );
+        //再过几个心跳间隔，连接正常，不会有第二次重连
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        {
+            let worker = worker_arc.lock().unwrap();
+            assert_eq!(worker.reconnect_count, 1);
+            let delays = &worker.reconnect_delays;
+            assert_eq!(delays[0], Duration::from_millis(50));
+            for pair in delays.windows(2) {
+                assert!(pair[1] > pair[0] || pair[1] == Duration::from_millis(1000));
             }
         }
+        handle.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_reconnect_outside_worker_lock() {
+        let node = MockChannelNode::start().unwrap();
+        let configfile = node.write_client_config().unwrap();
+        let mut worker = BcosChannelWorker::new(&configfile);
+        {
+            let config = &mut worker.bcossdk.config.channel;
+            config.reconnect_backoff_ms = 50;
+            config.heartbeat_interval_sec = 1;
+            config.heartbeat_max_missed = 2;
+        }
+        //重连回调很慢，执行期间worker的锁应该是空闲的
+        let in_hook = Arc::new(AtomicBool::new(false));
+        let flag = in_hook.clone();
+        worker.add_reconnect_hook(Arc::new(move |client: &mut BcosChannelClient| {
+            assert!(client.protocol_version > 0);
+            flag.store(true, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(1500));
+            flag.store(false, Ordering::SeqCst);
+        }));
+        let worker_arc = Arc::new(Mutex::new(worker));
+        let handle = spawn_bcos_channel_worker(&worker_arc);
+
+        node.close_sessions();
+        assert!(wait_until(Duration::from_secs(5), || in_hook.load(Ordering::SeqCst)).await);
+        let start = Instant::now();
+        assert_eq!(worker_arc.lock().unwrap().reconnect_count, 0);
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert!(in_hook.load(Ordering::SeqCst));
+
+        //回调结束后换上新连接，之后心跳正常，不会再重连
+        assert!(
+            wait_until(Duration::from_secs(5), || {
+                worker_arc.lock().unwrap().reconnect_count == 1
+            })
+            .await
+        );
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(worker_arc.lock().unwrap().reconnect_count, 1);
+        handle.shutdown().await;
     }
-    tokio::time::sleep(Duration::from_millis(3000)).await;
-    println!("local thread done");
 }
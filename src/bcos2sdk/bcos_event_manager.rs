@@ -0,0 +1,502 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 事件日志订阅：每个过滤条件用一个filterID标识，
+//! CLIENT_REGISTER_EVENT_LOG注册、CLIENT_UNREGISTER_EVENT_LOG注销，节点用同seq回包确认。
+//! 节点用EVENT_LOG_PUSH推送匹配的日志，按包体里的filterID找到订阅者，日志用订阅者的合约abi解析后回调。
+//! 注册、注销和推送的包体都是topic为空的amop格式包裹的json
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ethabi::Log as ReceiptLog;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::bcos2sdk::bcos_channel_async_client::BcosChannelAsyncClient;
+use crate::bcos2sdk::bcos_channel_handler_manager::IChannelPushHandlerFacade;
+use crate::bcos2sdk::channelpack::{
+    make_channel_pack_by_rawdata, pack_amop, unpack_amop, ChannelPack, CHANNEL_PACK_TYPE,
+};
+use crate::bcossdkutil::contractabi::ContractABI;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::{kisserr, kisserrcode};
+
+///节点回包和推送里的result
+pub const EVENT_LOG_SUCCESS: i32 = 0;
+///fromBlock~toBlock范围内的日志已经全部推送，节点不会再推送这个filter
+pub const EVENT_LOG_PUSH_COMPLETED: i32 = 1;
+
+/*
+    {
+  "fromBlock": "latest",
+  "toBlock": "latest",
+  "addresses": [
+    0xca5ed56862869c25da0bdf186e634aac6c6361ee
+  ],
+  "topics": [
+    "0x91c95f04198617c60eaf2180fbca88fc192db379657df0e412a9f7dd4ebbe95d"
+  ],
+  "groupID": "1",
+  "filterID": "bb31e4ec086c48e18f21cb994e2e5967"
+}*/
+///fromBlock/toBlock可以是块高的十进制字符串或"latest"
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RegisterEventRequest {
+    pub fromBlock: String,
+    pub toBlock: String,
+    pub addresses: Vec<String>,
+    pub topics: Vec<String>,
+    pub groupID: String,
+    pub filterID: String,
+}
+
+impl RegisterEventRequest {
+    pub fn new(groupid: u32) -> Self {
+        RegisterEventRequest {
+            fromBlock: "latest".to_string(),
+            toBlock: "latest".to_string(),
+            addresses: vec![],
+            topics: vec![],
+            groupID: groupid.to_string(),
+            filterID: make_filter_id(),
+        }
+    }
+
+    ///按合约里的事件名和indexed参数的值生成过滤条件，indexed参数全部用字符串形式传入，可以只传前几个
+    pub fn for_event(
+        contract: &ContractABI,
+        groupid: u32,
+        addresses: &[String],
+        event_name: &str,
+        indexed_values: &Vec<&str>,
+    ) -> Result<Self, KissError> {
+        let event = match contract.find_event_by_name(event_name) {
+            Some(ev) => ev,
+            None => {
+                return kisserr!(
+                    KissErrKind::EArgument,
+                    "event {} not found in {}",
+                    event_name,
+                    contract.abi_file
+                )
+            }
+        };
+        let mut req = RegisterEventRequest::new(groupid);
+        req.addresses.extend_from_slice(addresses);
+        let evhash = contract.event_abi_utils.event_signature(event);
+        req.topics.push(format!("0x{}", hex::encode(evhash)));
+        let indexedevents = contract.event_abi_utils.indexed_params(event, true);
+        for (param, value) in indexedevents.iter().zip(indexed_values.iter()) {
+            let topic = contract
+                .event_abi_utils
+                .topic_by_indexed_params(&param.kind, value);
+            req.topics.push(topic);
+        }
+        Ok(req)
+    }
+
+    pub fn with_block_range(mut self, from_block: &str, to_block: &str) -> Self {
+        self.fromBlock = from_block.to_string();
+        self.toBlock = to_block.to_string();
+        self
+    }
+}
+
+///filterID用32个hex字符，和其他sdk生成的uuid格式一致
+pub fn make_filter_id() -> String {
+    hex::encode(&ChannelPack::make_seq().as_bytes()[..16])
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EventLogResponse {
+    pub filterID: String,
+    pub result: i32,
+    #[serde(default)]
+    pub logs: JsonValue,
+}
+
+///包体一般是amop格式包裹的json，也兼容直接是json的情况
+pub fn parse_event_log_response(pack: &ChannelPack) -> Result<EventLogResponse, KissError> {
+    if let Ok(resp) = serde_json::from_slice::<EventLogResponse>(&pack.data[..]) {
+        return Ok(resp);
+    }
+    let (_topic, data) = unpack_amop(&pack.data);
+    match serde_json::from_slice::<EventLogResponse>(data.as_slice()) {
+        Ok(resp) => Ok(resp),
+        Err(e) => kisserr!(
+            KissErrKind::EFormat,
+            "parse event log pack error {:?},{}",
+            e,
+            String::from_utf8_lossy(data.as_slice())
+        ),
+    }
+}
+
+pub fn make_register_event_pack(req: &RegisterEventRequest) -> ChannelPack {
+    make_event_pack(CHANNEL_PACK_TYPE::CLIENT_REGISTER_EVENT_LOG, req)
+}
+
+pub fn make_unregister_event_pack(req: &RegisterEventRequest) -> ChannelPack {
+    make_event_pack(CHANNEL_PACK_TYPE::CLIENT_UNREGISTER_EVENT_LOG, req)
+}
+
+fn make_event_pack(packtype: CHANNEL_PACK_TYPE, req: &RegisterEventRequest) -> ChannelPack {
+    let reqencode = serde_json::to_string(req).unwrap();
+    let data = pack_amop(&vec![], &Vec::from(reqencode));
+    make_channel_pack_by_rawdata(packtype, &data).unwrap()
+}
+
+///事件回调，日志已经按注册时的合约abi解析过
+pub trait IEventLogCallback {
+    fn on_logs(&self, filterid: &str, logs: &[ReceiptLog]);
+    ///区块范围内的日志已推送完毕
+    fn on_completed(&self, filterid: &str) {}
+    ///注册失败或推送的日志无法解析
+    fn on_error(&self, filterid: &str, err: &KissError) {
+        log::warn!("event filter {} error {:?}", filterid, err);
+    }
+}
+pub type EVENT_CALLBACK_OBJ = Arc<dyn IEventLogCallback + Send + Sync>;
+
+pub struct EventLogSubscription {
+    pub request: RegisterEventRequest,
+    pub contract: ContractABI,
+    pub callback: EVENT_CALLBACK_OBJ,
+}
+
+///按filterID保存所有订阅，可以注册为EVENT_LOG_PUSH和注册/注销回包的处理器
+#[derive(Clone, Default)]
+pub struct EventLogManager {
+    pub subscriptions: Arc<Mutex<HashMap<String, EventLogSubscription>>>,
+}
+
+impl EventLogManager {
+    pub fn add(&self, sub: EventLogSubscription) {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .insert(sub.request.filterID.clone(), sub);
+    }
+
+    pub fn remove(&self, filterid: &str) -> Option<EventLogSubscription> {
+        self.subscriptions.lock().unwrap().remove(filterid)
+    }
+
+    pub fn request_of(&self, filterid: &str) -> Option<RegisterEventRequest> {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .get(filterid)
+            .map(|s| s.request.clone())
+    }
+
+    ///重连后需要把这些过滤条件重新注册一遍
+    pub fn requests(&self) -> Vec<RegisterEventRequest> {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|s| s.request.clone())
+            .collect()
+    }
+
+    pub fn handle_push(&self, resp: &EventLogResponse) {
+        let (contract, callback) = match self.subscriptions.lock().unwrap().get(&resp.filterID) {
+            Some(s) => (s.contract.clone(), s.callback.clone()),
+            None => {
+                log::debug!("event log push for unknown filter {}", resp.filterID);
+                return;
+            }
+        };
+        match resp.result {
+            EVENT_LOG_SUCCESS => match contract.parse_receipt_logs(&resp.logs) {
+                Ok(logs) => callback.on_logs(&resp.filterID, &logs),
+                Err(e) => callback.on_error(&resp.filterID, &e),
+            },
+            EVENT_LOG_PUSH_COMPLETED => {
+                self.remove(&resp.filterID);
+                callback.on_completed(&resp.filterID);
+            }
+            code => {
+                self.remove(&resp.filterID);
+                let err = KissError::new(
+                    KissErrKind::ENetwork,
+                    code as i64,
+                    format!("event log push result {}", code).as_str(),
+                );
+                callback.on_error(&resp.filterID, &err);
+            }
+        }
+    }
+
+    ///节点拒绝注册时去掉这个订阅，并通知回调
+    pub fn handle_register_response(&self, resp: &EventLogResponse) {
+        if resp.result == EVENT_LOG_SUCCESS {
+            log::info!("event filter {} registered", resp.filterID);
+            return;
+        }
+        if let Some(sub) = self.remove(&resp.filterID) {
+            let err = KissError::new(
+                KissErrKind::EArgument,
+                resp.result as i64,
+                format!("register event filter fail, result {}", resp.result).as_str(),
+            );
+            sub.callback.on_error(&resp.filterID, &err);
+        }
+    }
+}
+
+impl IChannelPushHandlerFacade for EventLogManager {
+    fn handle(&self, pack: &ChannelPack) {
+        let resp = match parse_event_log_response(pack) {
+            Ok(r) => r,
+            Err(e) => {
+                log::warn!("{:?}", e);
+                return;
+            }
+        };
+        if pack.packtype == CHANNEL_PACK_TYPE::CLIENT_REGISTER_EVENT_LOG as u16 {
+            self.handle_register_response(&resp);
+        } else if pack.packtype == CHANNEL_PACK_TYPE::CLIENT_UNREGISTER_EVENT_LOG as u16 {
+            log::info!(
+                "event filter {} unregistered, result {}",
+                resp.filterID,
+                resp.result
+            );
+        } else {
+            self.handle_push(&resp);
+        }
+    }
+}
+
+impl BcosChannelAsyncClient {
+    fn ensure_event_manager(&self) {
+        let mut manager = self.handlemanager.lock().unwrap();
+        let packtype = CHANNEL_PACK_TYPE::EVENT_LOG_PUSH as u16;
+        if manager.get_handle(&packtype).is_some() {
+            return;
+        }
+        manager.set_handle(&packtype, Arc::new(Mutex::new(self.eventmanager.clone())));
+    }
+
+    ///注册事件过滤条件，等节点确认后返回filterID
+    pub async fn register_event_filter(
+        &self,
+        request: RegisterEventRequest,
+        contract: &ContractABI,
+        callback: EVENT_CALLBACK_OBJ,
+    ) -> Result<String, KissError> {
+        self.ensure_event_manager();
+        let filterid = request.filterID.clone();
+        let outpack = make_register_event_pack(&request);
+        //先登记订阅，确认包之后紧跟着来的推送也能找到回调
+        self.eventmanager.add(EventLogSubscription {
+            request,
+            contract: contract.clone(),
+            callback,
+        });
+        let resp = match self.request_channelpack(&outpack).await {
+            Ok(pack) => parse_event_log_response(&pack),
+            Err(e) => Err(e),
+        };
+        match resp {
+            Ok(r) if r.result == EVENT_LOG_SUCCESS => Ok(filterid),
+            Ok(r) => {
+                self.eventmanager.remove(&filterid);
+                kisserrcode!(
+                    KissErrKind::EArgument,
+                    r.result as i64,
+                    "register event filter {} fail, result {}",
+                    filterid,
+                    r.result
+                )
+            }
+            Err(e) => {
+                self.eventmanager.remove(&filterid);
+                Err(e)
+            }
+        }
+    }
+
+    ///注销过滤条件。本地的订阅总是会被去掉，节点回包失败时返回错误
+    pub async fn unregister_event_filter(&self, filterid: &str) -> Result<(), KissError> {
+        let sub = match self.eventmanager.remove(filterid) {
+            Some(s) => s,
+            None => {
+                return kisserr!(
+                    KissErrKind::EArgument,
+                    "event filter {} not found",
+                    filterid
+                )
+            }
+        };
+        let outpack = make_unregister_event_pack(&sub.request);
+        let resp = parse_event_log_response(&self.request_channelpack(&outpack).await?)?;
+        if resp.result != EVENT_LOG_SUCCESS {
+            return kisserrcode!(
+                KissErrKind::EArgument,
+                resp.result as i64,
+                "unregister event filter {} fail, result {}",
+                filterid,
+                resp.result
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcossdkutil::commonhash::HashType;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use ethabi::Token;
+    use std::time::{Duration, Instant};
+
+    ///按filterID记下收到的onset(string)参数
+    struct LogRecorder {
+        got: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl IEventLogCallback for LogRecorder {
+        fn on_logs(&self, filterid: &str, logs: &[ReceiptLog]) {
+            for log in logs {
+                let value = match &log.params[0].value {
+                    Token::String(s) => s.clone(),
+                    other => format!("{:?}", other),
+                };
+                self.got.lock().unwrap().push((filterid.to_string(), value));
+            }
+        }
+    }
+
+    fn onset_log(contract: &ContractABI, newname: &str) -> JsonValue {
+        let event = contract.find_event_by_name("onset").unwrap();
+        let topic = contract.event_abi_utils.event_signature(event);
+        let data = ethabi::encode(&[Token::String(newname.to_string())]);
+        serde_json::json!([{
+            "address": "0x882be29b2d5ac85d6c476fa3fd5f0cae4b4585cc",
+            "topics": [format!("0x{}", hex::encode(topic))],
+            "data": format!("0x{}", hex::encode(data)),
+        }])
+    }
+
+    async fn wait_until(f: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while !f() && start.elapsed() < Duration::from_secs(5) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        f()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_event_filters_with_mock_node() {
+        let node = MockChannelNode::start().unwrap();
+        let client = BcosChannelAsyncClient::connect(&node.channel_config())
+            .await
+            .unwrap();
+        let contract =
+            ContractABI::new("contracts/HelloWorld.abi", &HashType::WEDPR_KECCAK).unwrap();
+        let got = Arc::new(Mutex::new(vec![]));
+        let mut filterids = vec![];
+        for _ in 0..2 {
+            let request =
+                RegisterEventRequest::for_event(&contract, 1, &[], "onset", &vec![]).unwrap();
+            let recorder = Arc::new(LogRecorder { got: got.clone() });
+            let filterid = client
+                .register_event_filter(request, &contract, recorder)
+                .await
+                .unwrap();
+            filterids.push(filterid);
+        }
+        let (first, second) = (filterids[0].clone(), filterids[1].clone());
+        assert_ne!(first, second);
+        assert_eq!(node.event_filters().len(), 2);
+
+        //推送按filterID交给各自的回调，日志按abi解析好
+        node.push_event_log(
+            &second,
+            EVENT_LOG_SUCCESS,
+            &onset_log(&contract, "to second"),
+        );
+        node.push_event_log(&first, EVENT_LOG_SUCCESS, &onset_log(&contract, "to first"));
+        assert!(wait_until(|| got.lock().unwrap().len() == 2).await);
+        assert_eq!(
+            *got.lock().unwrap(),
+            vec![
+                (second.clone(), "to second".to_string()),
+                (first.clone(), "to first".to_string())
+            ]
+        );
+
+        //节点拒绝注册时返回错误，本地也不留这个订阅
+        node.state().event_register_result = -51003;
+        let request = RegisterEventRequest::new(1);
+        let rejected = request.filterID.clone();
+        let err = client
+            .register_event_filter(
+                request,
+                &contract,
+                Arc::new(LogRecorder { got: got.clone() }),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, -51003);
+        assert!(client.eventmanager.request_of(&rejected).is_none());
+        node.state().event_register_result = EVENT_LOG_SUCCESS;
+
+        //注销之后不再回调，另一个过滤条件照常收到
+        client.unregister_event_filter(&first).await.unwrap();
+        assert_eq!(node.event_filters().len(), 1);
+        assert_eq!(node.event_filters()[0].filterID, second);
+        node.push_event_log(&first, EVENT_LOG_SUCCESS, &onset_log(&contract, "late"));
+        node.push_event_log(&second, EVENT_LOG_SUCCESS, &onset_log(&contract, "again"));
+        assert!(wait_until(|| got.lock().unwrap().len() == 3).await);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let got = got.lock().unwrap().clone();
+        assert_eq!(got.len(), 3);
+        assert_eq!(got[2], (second, "again".to_string()));
+        client.stop().await;
+    }
+
+    #[test]
+    fn test_parse_event_log_response() {
+        let req = RegisterEventRequest::new(1).with_block_range("1", "100");
+        assert_eq!(req.filterID.len(), 32);
+        assert_eq!(req.groupID, "1");
+        let pack = make_register_event_pack(&req);
+        let (topic, data) = unpack_amop(&pack.data);
+        assert!(topic.is_empty());
+        let decoded: RegisterEventRequest = serde_json::from_slice(data.as_slice()).unwrap();
+        assert_eq!(decoded.toBlock, "100");
+
+        let body = format!(r#"{{"filterID":"{}","result":1}}"#, req.filterID);
+        let mut push = make_channel_pack_by_rawdata(
+            CHANNEL_PACK_TYPE::EVENT_LOG_PUSH,
+            &pack_amop(&vec![], &Vec::from(body.clone())),
+        )
+        .unwrap();
+        let resp = parse_event_log_response(&push).unwrap();
+        assert_eq!(resp.filterID, req.filterID);
+        assert_eq!(resp.result, EVENT_LOG_PUSH_COMPLETED);
+        push.data = body.into();
+        assert_eq!(parse_event_log_response(&push).unwrap().result, 1);
+    }
+}
@@ -196,7 +196,7 @@ impl IBcosChannel for BcosNativeTlsClient {
                 //println!("buffer {:?}",recvbuffer);
                 Ok(recvbuffer)
             } else {
-                return kisserr!(KissErrKind::Error, "recv {}", r);
+                return kisserr!(KissErrKind::ENetwork, "recv {}", r);
             }
         }
     }
@@ -428,7 +428,7 @@ pub fn test_ssl_native() {
                 println!("{:?}", recvbuffer);
                 let p = ChannelPack::unpack(&recvbuffer).unwrap();
                 println!("pack: {}", p.detail());
-                println!("data: {}", String::from_utf8(p.data).unwrap());
+                println!("data: {}", String::from_utf8(p.data.to_vec()).unwrap());
 
                 break;
 
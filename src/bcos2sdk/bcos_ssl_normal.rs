@@ -20,17 +20,18 @@
 )]
 use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::path::Path;
 
+use openssl::pkey::PKey;
 use openssl::ssl::{
-    ShutdownResult, SslConnector, SslConnectorBuilder, SslFiletype, SslMethod, SslStream,
-    SslVerifyMode,
+    ShutdownResult, SslConnector, SslConnectorBuilder, SslMethod, SslStream, SslVerifyMode,
 };
+use openssl::x509::X509;
 
 use crate::bcos2sdk::bcos_channel_client::IBcosChannel;
 use crate::bcos2sdk::channelpack::ChannelPack;
 use crate::bcossdkutil::bcosclientconfig::ChannelConfig;
 use crate::bcossdkutil::bufferqueue::BufferQueue;
+use crate::bcossdkutil::fileutils;
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::{kisserr, printlnex};
 use std::io;
@@ -66,19 +67,11 @@ impl ISslStreamWrap for SslStreamWrap {
         match res {
             Ok(t) => return Ok(t),
             Err(e) => {
-                let raw_code = e.raw_os_error().unwrap();
-                //println!("SslStream send return {:?},",raw_code);
-                match raw_code {
-                    10035 => {
-                        //10035表示would block @windows
-                        return Ok(0);
-                    }
-                    11 => {
-                        //11表示would block @linux
-                        return Ok(0);
-                    }
-                    __ => return Err(e),
+                //非阻塞socket上暂时写不出去，windows上是10035，linux上是11
+                if e.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(0);
                 }
+                return Err(e);
             }
         }
     }
@@ -88,21 +81,20 @@ impl ISslStreamWrap for SslStreamWrap {
         let res = self.ssl_stream.read(buf);
         //println!("SslStreamWrap recv {:?}",res);
         match res {
+            //读到0字节表示对端已经关闭了连接，不能当作暂时没有数据
+            Ok(0) if buf.len() > 0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed by peer",
+                ))
+            }
             Ok(t) => return Ok(t),
             Err(e) => {
-                let raw_code = e.raw_os_error().unwrap();
-                //println!("SslStream read return {:?},", raw_code);
-                match raw_code {
-                    10035 => {
-                        //10035表示would block
-                        return Ok(0);
-                    }
-                    11 => {
-                        //11表示would block @linux
-                        return Ok(0);
-                    }
-                    __ => return Err(e),
+                //暂时没有数据可读，windows上是10035，linux上是11
+                if e.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(0);
                 }
+                return Err(e);
             }
         }
     }
@@ -152,9 +144,13 @@ impl IBcosChannel for BcosSSLClient {
                 return kisserr!(KissErrKind::ENetwork, "ssl_ctx into ssl {:?}", e);
             }
         };
-        let tcp_stream =
-            TcpStream::connect(format!("{}:{}", self.config.ip.as_str(), self.config.port))
-                .unwrap();
+        let addr = format!("{}:{}", self.config.ip.as_str(), self.config.port);
+        let tcp_stream = match TcpStream::connect(addr.as_str()) {
+            Ok(t) => t,
+            Err(e) => {
+                return kisserr!(KissErrKind::ENetwork, "tcp connect {} error {:?}", addr, e);
+            }
+        };
         //let res = tcp_stream.set_nonblocking(true);
         let mut ssl_stream = match SslStream::new(ssl, tcp_stream) {
             Ok(s) => s,
@@ -202,10 +198,13 @@ impl IBcosChannel for BcosSSLClient {
             printlnex!("send res {:?}", res);
             match res {
                 Ok(s) => return Ok(s as i32),
-                Err(e) => return kisserr!(KissErrKind::ENetwork, "ssl send fail {:?}", e),
+                Err(e) => {
+                    self.is_connect = false;
+                    return kisserr!(KissErrKind::ENetwork, "ssl send fail {:?}", e);
+                }
             }
         }
-        return kisserr!(KissErrKind::ENetwork, "");
+        return kisserr!(KissErrKind::ENetwork, "ssl channel is not connected");
     }
 
     ///读取，c api要求输入一个预先分配好的缓冲区，讲读取的信息写入缓冲区带回
@@ -226,10 +225,13 @@ impl IBcosChannel for BcosSSLClient {
                 Ok(size) => {
                     return Ok(recvbuffer[0..size].to_vec());
                 }
-                Err(e) => return kisserr!(KissErrKind::ENetwork, "ssl recv fail {:?}", e),
+                Err(e) => {
+                    self.is_connect = false;
+                    return kisserr!(KissErrKind::ENetwork, "ssl recv fail {:?}", e);
+                }
             };
         }
-        return kisserr!(KissErrKind::ENetwork, "");
+        return kisserr!(KissErrKind::ENetwork, "ssl channel is not connected");
     }
 }
 
@@ -257,13 +259,28 @@ impl BcosSSLClient {
         Ok(())
     }
 
+    ///证书和私钥可以是文件路径，也可以直接是pem内容
     pub fn set_client_certs(
         ctx: &mut SslConnectorBuilder,
         config: &ChannelConfig,
     ) -> anyhow::Result<()> {
-        ctx.set_ca_file(Path::new(config.cacert.as_str()))?;
-        ctx.set_certificate_chain_file(Path::new(config.sdkcert.as_str()))?;
-        ctx.set_private_key_file(Path::new(config.sdkkey.as_str()), SslFiletype::PEM)?;
+        let read = |v: &str| fileutils::read_pem(v).map_err(|e| anyhow::anyhow!("{:?}", e));
+        for cert in X509::stack_from_pem(&read(&config.cacert)?)? {
+            ctx.cert_store_mut().add_cert(cert)?;
+        }
+        let mut chain = X509::stack_from_pem(&read(&config.sdkcert)?)?.into_iter();
+        match chain.next() {
+            Some(cert) => ctx.set_certificate(&cert)?,
+            None => anyhow::bail!(
+                "no certificate in {}",
+                fileutils::pem_source_name(&config.sdkcert)
+            ),
+        }
+        for cert in chain {
+            ctx.add_extra_chain_cert(cert)?;
+        }
+        let key = PKey::private_key_from_pem(&read(&config.sdkkey)?)?;
+        ctx.set_private_key(&key)?;
         ctx.check_private_key()?;
         Ok(())
     }
@@ -291,6 +308,10 @@ impl BcosSSLClient {
                 return kisserr!(KissErrKind::ENetwork, "sslconnector builder error {:?}", e);
             }
         };
+        //fisco bcos 2.x节点走tls1.2+secp256k1，新版openssl在tls1.3下不接受只开secp256k1
+        if let Err(e) = ctx.set_max_proto_version(Some(openssl::ssl::SslVersion::TLS1_2)) {
+            return kisserr!(KissErrKind::ENetwork, "set max proto version error {:?}", e);
+        }
         let res = match BcosSSLClient::set_client_certs(&mut ctx, &self.config) {
             Ok(()) => (),
             Err(e) => {
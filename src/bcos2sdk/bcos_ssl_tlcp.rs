@@ -0,0 +1,741 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 纯rust实现的国密tls客户端(GB/T 38636 TLCP，即TASSL的GMTLS)，不依赖tassl动态库。
+//! 只支持节点默认使用的ECC_SM4_CBC_SM3套件：SM2签名证书验证服务端、SM2加密证书加密预主密钥，
+//! 记录层用SM4-CBC加密、HMAC-SM3校验。
+//! 签名证书和私钥用gmsdkcert/gmsdkkey，加密证书用gmensdkcert，
+//! ECC套件里客户端的加密私钥不参与握手，gmensdkkey不会被读取
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use wedpr_l_libsm::sm4::cipher::Sm4Cipher;
+
+use crate::bcos2sdk::bcos_channel_client::IBcosChannel;
+use crate::bcos2sdk::bcos_tlcp_util::{
+    constant_time_eq, hmac_sm3, load_certs_from_pem, load_sm2_privkey_from_pem, prf_sm3,
+    sm2_encrypt, sm2_sign, sm2_verify, sm3, SM2Certificate,
+};
+use crate::bcossdkutil::bcosclientconfig::ChannelConfig;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::{kisserr, printlnex};
+
+pub const TLCP_VERSION: [u8; 2] = [0x01, 0x01];
+pub const ECC_SM4_CBC_SM3: [u8; 2] = [0xE0, 0x13];
+
+pub(crate) const RECORD_CHANGE_CIPHER_SPEC: u8 = 20;
+pub(crate) const RECORD_ALERT: u8 = 21;
+pub(crate) const RECORD_HANDSHAKE: u8 = 22;
+pub(crate) const RECORD_APPLICATION_DATA: u8 = 23;
+
+pub(crate) const HS_CLIENT_HELLO: u8 = 1;
+pub(crate) const HS_SERVER_HELLO: u8 = 2;
+pub(crate) const HS_CERTIFICATE: u8 = 11;
+pub(crate) const HS_SERVER_KEY_EXCHANGE: u8 = 12;
+pub(crate) const HS_CERTIFICATE_REQUEST: u8 = 13;
+pub(crate) const HS_SERVER_HELLO_DONE: u8 = 14;
+pub(crate) const HS_CERTIFICATE_VERIFY: u8 = 15;
+pub(crate) const HS_CLIENT_KEY_EXCHANGE: u8 = 16;
+pub(crate) const HS_FINISHED: u8 = 20;
+
+pub(crate) const ALERT_CLOSE_NOTIFY: u8 = 0;
+pub(crate) const RECORD_HEADER_LEN: usize = 5;
+pub(crate) const MAX_FRAGMENT_LEN: usize = 16384;
+pub(crate) const MAC_LEN: usize = 32;
+pub(crate) const BLOCK_LEN: usize = 16;
+pub(crate) const VERIFY_DATA_LEN: usize = 12;
+///证书链最多往上找几级
+const MAX_CHAIN_DEPTH: usize = 8;
+
+pub(crate) fn put_u24(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_be_bytes()[1..]);
+}
+
+pub(crate) fn get_u24(buf: &[u8]) -> usize {
+    ((buf[0] as usize) << 16) | ((buf[1] as usize) << 8) | buf[2] as usize
+}
+
+///从握手消息体里按顺序读取字段，越界时返回EFormat
+pub(crate) struct HandshakeParser<'a> {
+    pub(crate) buf: &'a [u8],
+}
+
+impl<'a> HandshakeParser<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], KissError> {
+        if self.buf.len() < n {
+            return kisserr!(KissErrKind::EFormat, "tlcp handshake message too short");
+        }
+        let (head, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(head)
+    }
+    pub(crate) fn take_u8_vec(&mut self) -> Result<&'a [u8], KissError> {
+        let n = self.take(1)?[0] as usize;
+        self.take(n)
+    }
+    pub(crate) fn take_u16_vec(&mut self) -> Result<&'a [u8], KissError> {
+        let n = self.take(2)?;
+        self.take(((n[0] as usize) << 8) | n[1] as usize)
+    }
+    pub(crate) fn take_u24_vec(&mut self) -> Result<&'a [u8], KissError> {
+        let n = get_u24(self.take(3)?);
+        self.take(n)
+    }
+}
+
+///一个方向的记录层加密状态，explicit IV的SM4-CBC + HMAC-SM3
+pub(crate) struct TlcpCipherState {
+    mackey: Vec<u8>,
+    cipher: Sm4Cipher,
+    seq: u64,
+}
+
+impl TlcpCipherState {
+    pub(crate) fn new(mackey: &[u8], key: &[u8]) -> TlcpCipherState {
+        TlcpCipherState {
+            mackey: mackey.to_vec(),
+            cipher: Sm4Cipher::new(key),
+            seq: 0,
+        }
+    }
+
+    fn mac(&self, rtype: u8, data: &[u8]) -> [u8; 32] {
+        let mut input = self.seq.to_be_bytes().to_vec();
+        input.push(rtype);
+        input.extend_from_slice(&TLCP_VERSION);
+        input.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        input.extend_from_slice(data);
+        hmac_sm3(&self.mackey, &input)
+    }
+
+    ///返回IV+密文
+    pub(crate) fn encrypt(&mut self, rtype: u8, data: &[u8]) -> Vec<u8> {
+        let mut plain = data.to_vec();
+        plain.extend_from_slice(&self.mac(rtype, data));
+        let padlen = BLOCK_LEN - 1 - plain.len() % BLOCK_LEN;
+        plain.resize(plain.len() + padlen + 1, padlen as u8);
+        self.seq += 1;
+
+        let iv: [u8; 16] = rand::random();
+        let mut out = iv.to_vec();
+        let mut prev = iv;
+        for block in plain.chunks(BLOCK_LEN) {
+            let mut xored = [0u8; 16];
+            for i in 0..BLOCK_LEN {
+                xored[i] = block[i] ^ prev[i];
+            }
+            prev = self.cipher.encrypt(&xored);
+            out.extend_from_slice(&prev);
+        }
+        out
+    }
+
+    pub(crate) fn decrypt(&mut self, rtype: u8, fragment: &[u8]) -> Result<Vec<u8>, KissError> {
+        //至少一个IV加一个分组，IV之后按分组切开，有零头的长度不对
+        let blocks = fragment
+            .get(BLOCK_LEN..)
+            .filter(|b| !b.is_empty())
+            .map(|b| b.chunks_exact(BLOCK_LEN));
+        let blocks = match blocks {
+            Some(blocks) if blocks.remainder().is_empty() => blocks,
+            _ => {
+                return kisserr!(
+                    KissErrKind::EFormat,
+                    "bad tlcp record length {}",
+                    fragment.len()
+                )
+            }
+        };
+        let mut prev = &fragment[..BLOCK_LEN];
+        let mut plain: Vec<u8> = Vec::with_capacity(fragment.len());
+        for block in blocks {
+            let dec = self.cipher.decrypt(block);
+            plain.extend(dec.iter().zip(prev.iter()).map(|(d, p)| d ^ p));
+            prev = block;
+        }
+        let padlen = plain[plain.len() - 1] as usize;
+        if padlen + 1 + MAC_LEN > plain.len()
+            || plain[plain.len() - 1 - padlen..]
+                .iter()
+                .any(|b| *b as usize != padlen)
+        {
+            return kisserr!(KissErrKind::EFormat, "bad tlcp record padding");
+        }
+        plain.truncate(plain.len() - 1 - padlen);
+        let mac = plain.split_off(plain.len() - MAC_LEN);
+        if !constant_time_eq(&mac, &self.mac(rtype, &plain)) {
+            return kisserr!(KissErrKind::EFormat, "tlcp record mac mismatch");
+        }
+        self.seq += 1;
+        Ok(plain)
+    }
+}
+
+///国密tls客户端，握手时阻塞，握手完成后socket设为非阻塞，和BcosSSLClient的用法一致
+pub struct BcosTlcpClient {
+    pub config: ChannelConfig,
+    pub is_valid: bool,
+    pub is_connect: bool,
+    stream: Option<TcpStream>,
+    inbuf: Vec<u8>,         //收到还没拼成完整record的字节
+    handshake_buf: Vec<u8>, //握手消息可能跨record，先拼起来
+    outbuf: Vec<u8>,        //已加密但socket暂时写不出去的字节
+    reader: Option<TlcpCipherState>,
+    writer: Option<TlcpCipherState>,
+}
+
+impl IBcosChannel for BcosTlcpClient {
+    fn connect(&mut self) -> Result<i32, KissError> {
+        self.finish();
+        let addr = format!("{}:{}", self.config.ip.as_str(), self.config.port);
+        let stream = match TcpStream::connect(addr.as_str()) {
+            Ok(t) => t,
+            Err(e) => {
+                return kisserr!(KissErrKind::ENetwork, "tcp connect {} error {:?}", addr, e);
+            }
+        };
+        let timeout = Some(Duration::from_secs(self.config.timeout.max(1) as u64));
+        let _ = stream.set_read_timeout(timeout);
+        let _ = stream.set_write_timeout(timeout);
+        let _ = stream.set_nodelay(true);
+        self.stream = Some(stream);
+        if let Err(e) = self.handshake() {
+            self.close_stream();
+            return kisserr!(
+                KissErrKind::ENetwork,
+                "tlcp handshake with {} error {}",
+                addr,
+                e.msg
+            );
+        }
+        //握手完成后再设置非阻塞，和BcosSSLClient一致
+        if let Some(stream) = self.stream.as_ref() {
+            let _ = stream.set_read_timeout(None);
+            let _ = stream.set_nonblocking(true);
+        }
+        Ok(0)
+    }
+
+    ///加密后放入发送缓冲再尽量写出，写不出去的部分下次send/recv时继续写
+    fn send(&mut self, sendbuff: &Vec<u8>) -> Result<i32, KissError> {
+        if !self.is_connect {
+            return kisserr!(KissErrKind::ENetwork, "tlcp channel is not connected");
+        }
+        let res = self.write_record(RECORD_APPLICATION_DATA, sendbuff);
+        printlnex!("tlcp send res {:?}", res);
+        if let Err(e) = res {
+            self.is_connect = false;
+            return Err(e);
+        }
+        Ok(sendbuff.len() as i32)
+    }
+
+    ///非阻塞读取，没有完整的record时返回空
+    fn recv(&mut self) -> Result<Vec<u8>, KissError> {
+        if !self.is_connect {
+            return kisserr!(KissErrKind::ENetwork, "tlcp channel is not connected");
+        }
+        let res = self.flush().and_then(|_| self.read_available());
+        if res.is_err() {
+            self.is_connect = false;
+        }
+        res
+    }
+
+    fn finish(&mut self) {
+        if self.is_connect && self.writer.is_some() {
+            let _ = self.write_record(RECORD_ALERT, &[1, ALERT_CLOSE_NOTIFY]);
+        }
+        self.close_stream();
+        self.is_valid = false;
+        self.is_connect = false;
+    }
+}
+
+impl BcosTlcpClient {
+    pub fn default(config: &ChannelConfig) -> BcosTlcpClient {
+        BcosTlcpClient {
+            config: config.clone(),
+            is_valid: false,
+            is_connect: false,
+            stream: None,
+            inbuf: vec![],
+            handshake_buf: vec![],
+            outbuf: vec![],
+            reader: None,
+            writer: None,
+        }
+    }
+
+    pub fn build(&mut self) -> Result<(), KissError> {
+        self.connect()?;
+        self.is_valid = true;
+        self.is_connect = true;
+        Ok(())
+    }
+
+    fn close_stream(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.inbuf.clear();
+        self.handshake_buf.clear();
+        self.outbuf.clear();
+        self.reader = None;
+        self.writer = None;
+    }
+
+    fn handshake(&mut self) -> Result<(), KissError> {
+        let cacerts = load_certs_from_pem(&self.config.gmcacert)?;
+        let signcerts = load_certs_from_pem(&self.config.gmsdkcert)?;
+        let signkey = load_sm2_privkey_from_pem(&self.config.gmsdkkey)?;
+        let encerts = load_certs_from_pem(&self.config.gmensdkcert)?;
+        let mut transcript: Vec<u8> = vec![];
+
+        //ClientHello
+        let client_random = make_hello_random();
+        let mut hello = TLCP_VERSION.to_vec();
+        hello.extend_from_slice(&client_random);
+        hello.push(0); //不复用session
+        hello.extend_from_slice(&[0, 2]);
+        hello.extend_from_slice(&ECC_SM4_CBC_SM3);
+        hello.extend_from_slice(&[1, 0]); //不压缩
+        self.write_handshake(&mut transcript, HS_CLIENT_HELLO, &hello)?;
+
+        //ServerHello
+        let body = self.expect_handshake(&mut transcript, HS_SERVER_HELLO)?;
+        let mut parser = HandshakeParser { buf: &body };
+        let version = parser.take(2)?;
+        if version != TLCP_VERSION {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "unsupported tlcp version {:?}",
+                version
+            );
+        }
+        let server_random = parser.take(32)?.to_vec();
+        parser.take_u8_vec()?;
+        let suite = parser.take(2)?;
+        if suite != ECC_SM4_CBC_SM3 {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "unsupported tlcp cipher suite {:?}",
+                suite
+            );
+        }
+
+        //Certificate，依次是签名证书、加密证书，然后是证书链
+        let body = self.expect_handshake(&mut transcript, HS_CERTIFICATE)?;
+        let mut parser = HandshakeParser {
+            buf: HandshakeParser { buf: &body }.take_u24_vec()?,
+        };
+        let mut servercerts = vec![];
+        while !parser.buf.is_empty() {
+            servercerts.push(SM2Certificate::from_der(parser.take_u24_vec()?)?);
+        }
+        let (server_signcert, server_encert) = pick_server_certs(&servercerts)?;
+        let now = chrono::Utc::now().naive_utc();
+        verify_cert_chain(&server_signcert, &servercerts, &cacerts, &now)?;
+        verify_cert_chain(&server_encert, &servercerts, &cacerts, &now)?;
+
+        //ServerKeyExchange，服务端用签名证书对双方随机数和加密证书签名
+        let body = self.expect_handshake(&mut transcript, HS_SERVER_KEY_EXCHANGE)?;
+        let signature = HandshakeParser { buf: &body }.take_u16_vec()?;
+        let mut signed = client_random.to_vec();
+        signed.extend_from_slice(&server_random);
+        put_u24(&mut signed, server_encert.der.len());
+        signed.extend_from_slice(&server_encert.der);
+        if !sm2_verify(&server_signcert.pubkey, &signed, signature) {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "tlcp server key exchange signature mismatch"
+            );
+        }
+
+        //可选的CertificateRequest，然后是ServerHelloDone
+        let mut cert_requested = false;
+        loop {
+            let (hstype, body) = self.read_handshake(&mut transcript)?;
+            match hstype {
+                HS_CERTIFICATE_REQUEST => cert_requested = true,
+                HS_SERVER_HELLO_DONE => break,
+                _ => {
+                    return kisserr!(KissErrKind::EFormat, "unexpected tlcp handshake {}", hstype);
+                }
+            }
+        }
+
+        if cert_requested {
+            let mut clientcerts = vec![&signcerts[0], &encerts[0]];
+            clientcerts.extend(signcerts[1..].iter());
+            let mut certlist = vec![];
+            for cert in clientcerts {
+                put_u24(&mut certlist, cert.der.len());
+                certlist.extend_from_slice(&cert.der);
+            }
+            let mut body = vec![];
+            put_u24(&mut body, certlist.len());
+            body.extend(certlist);
+            self.write_handshake(&mut transcript, HS_CERTIFICATE, &body)?;
+        }
+
+        //ClientKeyExchange，预主密钥用服务端加密证书的公钥SM2加密
+        let mut premaster = TLCP_VERSION.to_vec();
+        premaster.extend((0..46).map(|_| rand::random::<u8>()));
+        let encrypted = sm2_encrypt(&server_encert.pubkey, &premaster)?;
+        let mut body = (encrypted.len() as u16).to_be_bytes().to_vec();
+        body.extend(encrypted);
+        self.write_handshake(&mut transcript, HS_CLIENT_KEY_EXCHANGE, &body)?;
+
+        if cert_requested {
+            let signature = sm2_sign(&signkey, &sm3(&transcript))?;
+            let mut body = (signature.len() as u16).to_be_bytes().to_vec();
+            body.extend(signature);
+            self.write_handshake(&mut transcript, HS_CERTIFICATE_VERIFY, &body)?;
+        }
+
+        //主密钥和双方的记录层密钥
+        let mut seed = client_random.to_vec();
+        seed.extend_from_slice(&server_random);
+        let master = prf_sm3(&premaster, "master secret", &seed, 48);
+        let mut seed = server_random.clone();
+        seed.extend_from_slice(&client_random);
+        let keyblock = prf_sm3(&master, "key expansion", &seed, MAC_LEN * 2 + BLOCK_LEN * 4);
+        let (client_mac, rest) = keyblock.split_at(MAC_LEN);
+        let (server_mac, rest) = rest.split_at(MAC_LEN);
+        let (client_key, rest) = rest.split_at(BLOCK_LEN);
+        let (server_key, _) = rest.split_at(BLOCK_LEN);
+
+        self.write_record(RECORD_CHANGE_CIPHER_SPEC, &[1])?;
+        self.writer = Some(TlcpCipherState::new(client_mac, client_key));
+        let verify_data = prf_sm3(
+            &master,
+            "client finished",
+            &sm3(&transcript),
+            VERIFY_DATA_LEN,
+        );
+        self.write_handshake(&mut transcript, HS_FINISHED, &verify_data)?;
+
+        let (rtype, body) = self.read_record_blocking()?;
+        if rtype != RECORD_CHANGE_CIPHER_SPEC {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "expect tlcp change cipher spec, got {}",
+                rtype
+            );
+        }
+        self.reader = Some(TlcpCipherState::new(server_mac, server_key));
+        let expected = prf_sm3(
+            &master,
+            "server finished",
+            &sm3(&transcript),
+            VERIFY_DATA_LEN,
+        );
+        let body = self.expect_handshake(&mut transcript, HS_FINISHED)?;
+        if !constant_time_eq(&body, &expected) {
+            return kisserr!(KissErrKind::EFormat, "tlcp server finished mismatch");
+        }
+        Ok(())
+    }
+
+    fn write_handshake(
+        &mut self,
+        transcript: &mut Vec<u8>,
+        hstype: u8,
+        body: &[u8],
+    ) -> Result<(), KissError> {
+        let mut msg = vec![hstype];
+        put_u24(&mut msg, body.len());
+        msg.extend_from_slice(body);
+        transcript.extend_from_slice(&msg);
+        self.write_record(RECORD_HANDSHAKE, &msg)
+    }
+
+    ///读一条完整的握手消息，消息原文计入transcript
+    fn read_handshake(&mut self, transcript: &mut Vec<u8>) -> Result<(u8, Vec<u8>), KissError> {
+        loop {
+            if self.handshake_buf.len() >= 4 {
+                let len = get_u24(&self.handshake_buf[1..4]);
+                if self.handshake_buf.len() >= 4 + len {
+                    let msg: Vec<u8> = self.handshake_buf.drain(..4 + len).collect();
+                    transcript.extend_from_slice(&msg);
+                    return Ok((msg[0], msg[4..].to_vec()));
+                }
+            }
+            let (rtype, body) = self.read_record_blocking()?;
+            if rtype != RECORD_HANDSHAKE {
+                return kisserr!(
+                    KissErrKind::EFormat,
+                    "unexpected tlcp record type {}",
+                    rtype
+                );
+            }
+            self.handshake_buf.extend(body);
+        }
+    }
+
+    fn expect_handshake(
+        &mut self,
+        transcript: &mut Vec<u8>,
+        expected: u8,
+    ) -> Result<Vec<u8>, KissError> {
+        let (hstype, body) = self.read_handshake(transcript)?;
+        if hstype != expected {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "expect tlcp handshake {}, got {}",
+                expected,
+                hstype
+            );
+        }
+        Ok(body)
+    }
+
+    ///按最大分片切成record，有写密钥时加密
+    fn write_record(&mut self, rtype: u8, data: &[u8]) -> Result<(), KissError> {
+        for fragment in data.chunks(MAX_FRAGMENT_LEN) {
+            let payload = match self.writer.as_mut() {
+                Some(writer) => writer.encrypt(rtype, fragment),
+                None => fragment.to_vec(),
+            };
+            self.outbuf.push(rtype);
+            self.outbuf.extend_from_slice(&TLCP_VERSION);
+            self.outbuf
+                .extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            self.outbuf.extend(payload);
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<(), KissError> {
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return kisserr!(KissErrKind::ENetwork, "tlcp channel is not connected"),
+        };
+        while !self.outbuf.is_empty() {
+            match stream.write(&self.outbuf) {
+                Ok(0) => return kisserr!(KissErrKind::ENetwork, "tlcp connection closed"),
+                Ok(n) => {
+                    self.outbuf.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return kisserr!(KissErrKind::ENetwork, "tlcp send fail {:?}", e),
+            }
+        }
+        Ok(())
+    }
+
+    ///从socket读一次，读到0字节表示对端关闭，非阻塞时没有数据返回0
+    fn fill_inbuf(&mut self) -> Result<usize, KissError> {
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return kisserr!(KissErrKind::ENetwork, "tlcp channel is not connected"),
+        };
+        let mut buf = vec![0u8; 10 * 1024];
+        match stream.read(&mut buf) {
+            Ok(0) => kisserr!(KissErrKind::ENetwork, "connection closed by peer"),
+            Ok(n) => {
+                self.inbuf.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+            Err(e) => kisserr!(KissErrKind::ENetwork, "tlcp recv fail {:?}", e),
+        }
+    }
+
+    ///从inbuf里取出一条完整的record并解密，alert直接转成错误
+    fn take_record(&mut self) -> Result<Option<(u8, Vec<u8>)>, KissError> {
+        if self.inbuf.len() < RECORD_HEADER_LEN {
+            return Ok(None);
+        }
+        let len = ((self.inbuf[3] as usize) << 8) | self.inbuf[4] as usize;
+        if self.inbuf.len() < RECORD_HEADER_LEN + len {
+            return Ok(None);
+        }
+        let record: Vec<u8> = self.inbuf.drain(..RECORD_HEADER_LEN + len).collect();
+        let rtype = record[0];
+        let body = match self.reader.as_mut() {
+            Some(reader) => reader.decrypt(rtype, &record[RECORD_HEADER_LEN..])?,
+            None => record[RECORD_HEADER_LEN..].to_vec(),
+        };
+        if rtype == RECORD_ALERT {
+            let desc = body.get(1).copied().unwrap_or(0);
+            if desc == ALERT_CLOSE_NOTIFY {
+                return kisserr!(KissErrKind::ENetwork, "tlcp connection closed by peer");
+            }
+            return kisserr!(KissErrKind::ENetwork, "tlcp alert from peer {:?}", body);
+        }
+        Ok(Some((rtype, body)))
+    }
+
+    fn read_record_blocking(&mut self) -> Result<(u8, Vec<u8>), KissError> {
+        loop {
+            if let Some(record) = self.take_record()? {
+                return Ok(record);
+            }
+            self.fill_inbuf()?;
+        }
+    }
+
+    fn read_available(&mut self) -> Result<Vec<u8>, KissError> {
+        self.fill_inbuf()?;
+        let mut out = vec![];
+        while let Some((rtype, body)) = self.take_record()? {
+            if rtype == RECORD_APPLICATION_DATA {
+                out.extend(body);
+            } else {
+                log::warn!("ignore tlcp record type {} after handshake", rtype);
+            }
+        }
+        Ok(out)
+    }
+}
+
+///hello里的随机数，前4字节是unix时间
+fn make_hello_random() -> [u8; 32] {
+    let mut random: [u8; 32] = rand::random();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0);
+    random[..4].copy_from_slice(&now.to_be_bytes());
+    random
+}
+
+///服务端证书消息的前两张是签名证书和加密证书，按keyUsage纠正顺序
+fn pick_server_certs(
+    certs: &[SM2Certificate],
+) -> Result<(SM2Certificate, SM2Certificate), KissError> {
+    if certs.len() < 2 {
+        return kisserr!(
+            KissErrKind::EFormat,
+            "tlcp server should send sign and encrypt certificates, got {}",
+            certs.len()
+        );
+    }
+    let (first, second) = (&certs[0], &certs[1]);
+    if !first.can_sign() && second.can_sign() {
+        return Ok((second.clone(), first.clone()));
+    }
+    Ok((first.clone(), second.clone()))
+}
+
+///沿着服务端发来的证书链往上找，直到某个ca证书签发了链上的证书，
+/// 链上的每个签发者都必须是ca(basicConstraints cA且有keyCertSign)，并满足pathLen
+fn verify_cert_chain(
+    cert: &SM2Certificate,
+    chain: &[SM2Certificate],
+    cacerts: &[SM2Certificate],
+    now: &chrono::NaiveDateTime,
+) -> Result<(), KissError> {
+    let mut current = cert.clone();
+    //below是要找的签发者下面的中间ca数(不含叶子证书)
+    for below in 0..MAX_CHAIN_DEPTH as u32 {
+        current.check_validity(now)?;
+        if let Some(ca) = cacerts
+            .iter()
+            .find(|ca| ca.can_issue(below) && current.verify_signed_by(ca))
+        {
+            return ca.check_validity(now);
+        }
+        match chain
+            .iter()
+            .find(|c| c.der != current.der && c.can_issue(below) && current.verify_signed_by(c))
+        {
+            Some(issuer) => current = issuer.clone(),
+            None => break,
+        }
+    }
+    kisserr!(
+        KissErrKind::EFormat,
+        "tlcp server certificate is not issued by gmcacert"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::bcos_channel_client::BcosChannelClient;
+    use crate::bcossdkutil::bcosclientconfig::BcosCryptoKind;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use crate::testsupport::mock_tlcp::TlcpHandshakeInfo;
+
+    #[test]
+    fn test_tlcp_record_roundtrip() {
+        let mut writer = TlcpCipherState::new(&[7u8; 32], &[9u8; 16]);
+        let mut reader = TlcpCipherState::new(&[7u8; 32], &[9u8; 16]);
+        for len in [0usize, 1, 15, 16, 47, 1000].iter() {
+            let data: Vec<u8> = (0..*len).map(|i| i as u8).collect();
+            let fragment = writer.encrypt(RECORD_APPLICATION_DATA, &data);
+            assert_eq!(fragment.len() % BLOCK_LEN, 0);
+            assert_eq!(
+                reader.decrypt(RECORD_APPLICATION_DATA, &fragment).unwrap(),
+                data
+            );
+        }
+        //序号不一致时mac校验失败
+        let fragment = writer.encrypt(RECORD_APPLICATION_DATA, b"hello");
+        writer.encrypt(RECORD_APPLICATION_DATA, b"skipped");
+        let fragment2 = writer.encrypt(RECORD_APPLICATION_DATA, b"hello");
+        assert!(reader.decrypt(RECORD_APPLICATION_DATA, &fragment).is_ok());
+        assert!(reader.decrypt(RECORD_APPLICATION_DATA, &fragment2).is_err());
+    }
+
+    #[test]
+    fn test_tlcp_handshake_with_mock_node() {
+        let node = MockChannelNode::start_gm().unwrap();
+        let config = node.channel_config();
+        assert_eq!(config.tlskind, BcosCryptoKind::GM);
+        assert!(!config.gmtls_native);
+        //默认走纯rust实现，握手后channel协议照常收发
+        let mut client = BcosChannelClient::new(&config).unwrap();
+        let request =
+            r#"{"jsonrpc":"2.0","method":"getBlockNumber","params":[1],"id":1}"#.to_string();
+        let response = client.request_sync(&request).unwrap();
+        assert!(response.contains("result"));
+        client.finish();
+        assert_eq!(
+            node.state().tlcp_handshakes,
+            vec![TlcpHandshakeInfo {
+                suite: ECC_SM4_CBC_SM3,
+                client_cn: "sdk".to_string(),
+            }]
+        );
+
+        //不是本节点ca签发的服务端证书，握手失败
+        let other = MockChannelNode::start_gm().unwrap();
+        let mut config = node.channel_config();
+        config.gmcacert = other.channel_config().gmcacert;
+        let mut tlcp = BcosTlcpClient::default(&config);
+        let err = tlcp.build().unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ENetwork);
+        assert!(err.msg.contains("gmcacert"), "{}", err.msg);
+        assert_eq!(node.state().tlcp_handshakes.len(), 1);
+
+        //节点证书由sdk证书签发，签名能验过，但sdk证书不是ca，握手失败
+        let forged = MockChannelNode::start_gm_forged().unwrap();
+        let mut tlcp = BcosTlcpClient::default(&forged.channel_config());
+        let err = tlcp.build().unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ENetwork);
+        assert!(err.msg.contains("gmcacert"), "{}", err.msg);
+        assert!(forged.state().tlcp_handshakes.is_empty());
+    }
+}
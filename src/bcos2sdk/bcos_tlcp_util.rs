@@ -0,0 +1,500 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 国密tls(TLCP)握手用到的密码学和证书工具：SM3的HMAC/PRF/KDF，SM2加密，
+//! 以及一个只够解析SM2证书和私钥的最小DER解析器
+
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+use chrono::NaiveDateTime;
+use wedpr_l_libsm::sm2::ecc::{EccCtx, Point};
+use wedpr_l_libsm::sm2::signature::{SigCtx, Signature};
+use wedpr_l_libsm::sm3::hash::Sm3Hash;
+
+pub const DER_TAG_INTEGER: u8 = 0x02;
+pub const DER_TAG_BIT_STRING: u8 = 0x03;
+pub const DER_TAG_OCTET_STRING: u8 = 0x04;
+pub const DER_TAG_OID: u8 = 0x06;
+pub const DER_TAG_UTC_TIME: u8 = 0x17;
+pub const DER_TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const DER_TAG_SEQUENCE: u8 = 0x30;
+
+/// keyUsage扩展的oid 2.5.29.15
+const OID_KEY_USAGE: [u8; 3] = [0x55, 0x1D, 0x0F];
+const KEY_USAGE_DIGITAL_SIGNATURE: u8 = 0x80;
+const KEY_USAGE_KEY_ENCIPHERMENT: u8 = 0x20;
+const KEY_USAGE_DATA_ENCIPHERMENT: u8 = 0x10;
+const KEY_USAGE_KEY_CERT_SIGN: u8 = 0x04;
+/// basicConstraints扩展的oid 2.5.29.19
+const OID_BASIC_CONSTRAINTS: [u8; 3] = [0x55, 0x1D, 0x13];
+const DER_TAG_BOOLEAN: u8 = 0x01;
+
+pub fn sm3(data: &[u8]) -> [u8; 32] {
+    Sm3Hash::new(data).get_hash()
+}
+
+///常量时间比较，用于mac和finished校验，长度不同直接返回false
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && openssl::memcmp::eq(a, b)
+}
+
+///HMAC-SM3，SM3的分组长度是64字节
+pub fn hmac_sm3(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut keyblock = [0u8; 64];
+    if key.len() > 64 {
+        keyblock[..32].copy_from_slice(&sm3(key));
+    } else {
+        keyblock[..key.len()].copy_from_slice(key);
+    }
+    let mut inner: Vec<u8> = keyblock.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(data);
+    let mut outer: Vec<u8> = keyblock.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sm3(&inner));
+    sm3(&outer)
+}
+
+///TLCP的PRF，即TLS1.2的P_hash，哈希用SM3
+pub fn prf_sm3(secret: &[u8], label: &str, seed: &[u8], outlen: usize) -> Vec<u8> {
+    let mut labelseed = label.as_bytes().to_vec();
+    labelseed.extend_from_slice(seed);
+    let mut out: Vec<u8> = Vec::with_capacity(outlen + 32);
+    let mut a = hmac_sm3(secret, &labelseed);
+    while out.len() < outlen {
+        let mut input = a.to_vec();
+        input.extend_from_slice(&labelseed);
+        out.extend_from_slice(&hmac_sm3(secret, &input));
+        a = hmac_sm3(secret, &a);
+    }
+    out.truncate(outlen);
+    out
+}
+
+///SM2加密用的密钥派生函数，GM/T 0003.4
+pub fn kdf_sm3(z: &[u8], klen: usize) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(klen + 32);
+    let mut ct: u32 = 1;
+    while out.len() < klen {
+        let mut input = z.to_vec();
+        input.extend_from_slice(&ct.to_be_bytes());
+        out.extend_from_slice(&sm3(&input));
+        ct += 1;
+    }
+    out.truncate(klen);
+    out
+}
+
+///SM2公钥加密，密文按GM/T 0009编码为DER：SEQUENCE{x, y, C3(hash), C2(密文)}
+pub fn sm2_encrypt(pubkey: &[u8], msg: &[u8]) -> Result<Vec<u8>, KissError> {
+    let ecc = EccCtx::new();
+    let pk = match ecc.bytes_to_point(pubkey) {
+        Ok(p) => p,
+        Err(_) => return kisserr!(KissErrKind::EFormat, "bad sm2 public key for encrypt"),
+    };
+    loop {
+        let k = ecc.random_uint();
+        let (x1, y1) = ecc.to_affine(&ecc.g_mul(&k));
+        let (x2, y2) = ecc.to_affine(&ecc.mul(&k, &pk));
+        let (x2, y2) = (x2.to_bytes(), y2.to_bytes());
+        let mut z = x2.clone();
+        z.extend_from_slice(&y2);
+        let t = kdf_sm3(&z, msg.len());
+        if t.iter().all(|b| *b == 0) {
+            continue;
+        }
+        let c2: Vec<u8> = msg.iter().zip(t.iter()).map(|(m, k)| m ^ k).collect();
+        let mut c3input = x2;
+        c3input.extend_from_slice(msg);
+        c3input.extend_from_slice(&y2);
+        let c3 = sm3(&c3input);
+
+        let mut body = der_encode_uint(&x1.to_bytes());
+        body.extend(der_encode_uint(&y1.to_bytes()));
+        body.extend(der_encode(DER_TAG_OCTET_STRING, &c3));
+        body.extend(der_encode(DER_TAG_OCTET_STRING, &c2));
+        return Ok(der_encode(DER_TAG_SEQUENCE, &body));
+    }
+}
+
+///用私钥做SM2签名（带默认ID的Z值预处理），返回DER编码的签名
+pub fn sm2_sign(privkey: &[u8], msg: &[u8]) -> Result<Vec<u8>, KissError> {
+    let ctx = SigCtx::new();
+    let sk = match ctx.load_seckey(privkey) {
+        Ok(k) => k,
+        Err(_) => return kisserr!(KissErrKind::EFormat, "bad sm2 private key"),
+    };
+    let pk = ctx.pk_from_sk(&sk);
+    Ok(ctx.sign(msg, &sk, &pk).der_encode())
+}
+
+///验证DER编码的SM2签名（带默认ID的Z值预处理）
+pub fn sm2_verify(pubkey: &[u8], msg: &[u8], sig: &[u8]) -> bool {
+    let ctx = SigCtx::new();
+    let pk: Point = match ctx.load_pubkey(pubkey) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    match Signature::der_decode(sig) {
+        Ok(s) => ctx.verify(msg, &pk, &s),
+        Err(_) => false,
+    }
+}
+
+//--------------------DER------------------------------------------
+
+pub fn der_encode(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = body.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let lenbytes: Vec<u8> = len
+            .to_be_bytes()
+            .iter()
+            .copied()
+            .skip_while(|b| *b == 0)
+            .collect();
+        out.push(0x80 | lenbytes.len() as u8);
+        out.extend(lenbytes);
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+///大端无符号整数编码成DER INTEGER，去掉前导0，最高位为1时补0
+pub fn der_encode_uint(value: &[u8]) -> Vec<u8> {
+    let mut body: Vec<u8> = value.iter().copied().skip_while(|b| *b == 0).collect();
+    if body.is_empty() || body[0] & 0x80 != 0 {
+        body.insert(0, 0);
+    }
+    der_encode(DER_TAG_INTEGER, &body)
+}
+
+///一个DER元素，raw是包含tag和长度的完整编码
+#[derive(Debug, Clone, Copy)]
+pub struct DerItem<'a> {
+    pub tag: u8,
+    pub body: &'a [u8],
+    pub raw: &'a [u8],
+}
+
+impl<'a> DerItem<'a> {
+    ///解析buf开头的一个元素，返回元素和剩余的字节
+    pub fn parse(buf: &'a [u8]) -> Result<(DerItem<'a>, &'a [u8]), KissError> {
+        if buf.len() < 2 {
+            return kisserr!(KissErrKind::EFormat, "der data too short");
+        }
+        let tag = buf[0];
+        let (len, headlen) = if buf[1] < 0x80 {
+            (buf[1] as usize, 2)
+        } else {
+            let n = (buf[1] & 0x7f) as usize;
+            if n == 0 || n > 4 || buf.len() < 2 + n {
+                return kisserr!(KissErrKind::EFormat, "bad der length");
+            }
+            let len = buf[2..2 + n]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (len, 2 + n)
+        };
+        if buf.len() < headlen + len {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "der item length {} exceed data {}",
+                len,
+                buf.len() - headlen
+            );
+        }
+        let item = DerItem {
+            tag,
+            body: &buf[headlen..headlen + len],
+            raw: &buf[..headlen + len],
+        };
+        Ok((item, &buf[headlen + len..]))
+    }
+
+    ///把构造类型（SEQUENCE等）的内容拆成子元素
+    pub fn children(&self) -> Result<Vec<DerItem<'a>>, KissError> {
+        let mut items = vec![];
+        let mut rest = self.body;
+        while !rest.is_empty() {
+            let (item, next) = DerItem::parse(rest)?;
+            items.push(item);
+            rest = next;
+        }
+        Ok(items)
+    }
+
+    pub fn expect(&self, tag: u8) -> Result<&DerItem<'a>, KissError> {
+        if self.tag != tag {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "expect der tag {:#x} but got {:#x}",
+                tag,
+                self.tag
+            );
+        }
+        Ok(self)
+    }
+
+    ///BIT STRING去掉第一个“未使用位数”字节后的内容
+    pub fn bit_string(&self) -> Result<&'a [u8], KissError> {
+        self.expect(DER_TAG_BIT_STRING)?;
+        if self.body.is_empty() {
+            return kisserr!(KissErrKind::EFormat, "empty der bit string");
+        }
+        Ok(&self.body[1..])
+    }
+}
+
+fn parse_der_time(item: &DerItem) -> Result<NaiveDateTime, KissError> {
+    let text = String::from_utf8_lossy(item.body).to_string();
+    let res = match item.tag {
+        DER_TAG_UTC_TIME => NaiveDateTime::parse_from_str(&text, "%y%m%d%H%M%SZ"),
+        DER_TAG_GENERALIZED_TIME => NaiveDateTime::parse_from_str(&text, "%Y%m%d%H%M%SZ"),
+        _ => return kisserr!(KissErrKind::EFormat, "bad cert time tag {:#x}", item.tag),
+    };
+    match res {
+        Ok(t) => Ok(t),
+        Err(e) => kisserr!(KissErrKind::EFormat, "bad cert time {} {:?}", text, e),
+    }
+}
+
+//--------------------证书和私钥------------------------------------------
+
+///从X.509证书里取出握手要用到的字段
+#[derive(Debug, Clone)]
+pub struct SM2Certificate {
+    pub der: Vec<u8>,
+    pub tbs: Vec<u8>,
+    pub issuer: Vec<u8>,
+    pub subject: Vec<u8>,
+    pub not_before: NaiveDateTime,
+    pub not_after: NaiveDateTime,
+    pub pubkey: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub key_usage: Option<u8>,
+    ///basicConstraints的cA，没有该扩展时为false
+    pub is_ca: bool,
+    ///basicConstraints的pathLenConstraint
+    pub path_len: Option<u32>,
+}
+
+impl SM2Certificate {
+    pub fn from_der(der: &[u8]) -> Result<SM2Certificate, KissError> {
+        let (cert, _) = DerItem::parse(der)?;
+        let parts = cert.expect(DER_TAG_SEQUENCE)?.children()?;
+        if parts.len() != 3 {
+            return kisserr!(KissErrKind::EFormat, "bad certificate structure");
+        }
+        let tbs = parts[0].expect(DER_TAG_SEQUENCE)?;
+        let mut fields = tbs.children()?;
+        //version是可选的[0]
+        if !fields.is_empty() && fields[0].tag == 0xA0 {
+            fields.remove(0);
+        }
+        //serial, signature, issuer, validity, subject, spki
+        if fields.len() < 6 {
+            return kisserr!(KissErrKind::EFormat, "bad tbs certificate");
+        }
+        let validity = fields[3].expect(DER_TAG_SEQUENCE)?.children()?;
+        if validity.len() != 2 {
+            return kisserr!(KissErrKind::EFormat, "bad certificate validity");
+        }
+        let spki = fields[5].expect(DER_TAG_SEQUENCE)?.children()?;
+        if spki.len() != 2 {
+            return kisserr!(KissErrKind::EFormat, "bad subject public key info");
+        }
+        let mut key_usage = None;
+        let mut is_ca = false;
+        let mut path_len = None;
+        for field in fields.iter().skip(6) {
+            //extensions是[3]
+            if field.tag != 0xA3 {
+                continue;
+            }
+            let (exts, _) = DerItem::parse(field.body)?;
+            for ext in exts.children()? {
+                let ext = ext.children()?;
+                if ext.len() < 2 {
+                    continue;
+                }
+                let value = ext[ext.len() - 1].expect(DER_TAG_OCTET_STRING)?;
+                let (item, _) = DerItem::parse(value.body)?;
+                if ext[0].body == OID_KEY_USAGE {
+                    key_usage = item.bit_string()?.first().copied();
+                } else if ext[0].body == OID_BASIC_CONSTRAINTS {
+                    //SEQUENCE { cA BOOLEAN DEFAULT FALSE, pathLenConstraint INTEGER OPTIONAL }
+                    for c in item.expect(DER_TAG_SEQUENCE)?.children()? {
+                        match c.tag {
+                            DER_TAG_BOOLEAN => is_ca = c.body.iter().any(|b| *b != 0),
+                            DER_TAG_INTEGER => {
+                                if c.body.len() > 4 {
+                                    return kisserr!(KissErrKind::EFormat, "bad pathLenConstraint");
+                                }
+                                path_len =
+                                    Some(c.body.iter().fold(0u32, |v, b| (v << 8) | *b as u32));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        Ok(SM2Certificate {
+            der: cert.raw.to_vec(),
+            tbs: tbs.raw.to_vec(),
+            issuer: fields[2].raw.to_vec(),
+            subject: fields[4].raw.to_vec(),
+            not_before: parse_der_time(&validity[0])?,
+            not_after: parse_der_time(&validity[1])?,
+            pubkey: spki[1].bit_string()?.to_vec(),
+            signature: parts[2].bit_string()?.to_vec(),
+            key_usage,
+            is_ca,
+            path_len,
+        })
+    }
+
+    ///没有keyUsage扩展时两种用途都认
+    pub fn can_sign(&self) -> bool {
+        match self.key_usage {
+            Some(u) => u & KEY_USAGE_DIGITAL_SIGNATURE != 0,
+            None => true,
+        }
+    }
+
+    pub fn can_encrypt(&self) -> bool {
+        match self.key_usage {
+            Some(u) => u & (KEY_USAGE_KEY_ENCIPHERMENT | KEY_USAGE_DATA_ENCIPHERMENT) != 0,
+            None => true,
+        }
+    }
+
+    ///能否作为签发者：必须是ca，有keyUsage时要带keyCertSign，
+    /// below是它下面已经经过的中间ca数，不能超过pathLenConstraint
+    pub fn can_issue(&self, below: u32) -> bool {
+        let cert_sign = match self.key_usage {
+            Some(u) => u & KEY_USAGE_KEY_CERT_SIGN != 0,
+            None => true,
+        };
+        self.is_ca && cert_sign && !matches!(self.path_len, Some(n) if below > n)
+    }
+
+    ///用签发者的公钥验证本证书的SM2签名
+    pub fn verify_signed_by(&self, issuer: &SM2Certificate) -> bool {
+        self.issuer == issuer.subject && sm2_verify(&issuer.pubkey, &self.tbs, &self.signature)
+    }
+
+    pub fn check_validity(&self, now: &NaiveDateTime) -> Result<(), KissError> {
+        if *now < self.not_before || *now > self.not_after {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "certificate expired or not yet valid, {} ~ {}",
+                self.not_before,
+                self.not_after
+            );
+        }
+        Ok(())
+    }
+}
+
+///加载pem文件(或pem内容)里的所有证书，证书文件里可以带证书链
+pub fn load_certs_from_pem(pemfile: &str) -> Result<Vec<SM2Certificate>, KissError> {
+    let content = fileutils::read_pem(pemfile)?;
+    let mut certs = vec![];
+    for p in pem::parse_many(content) {
+        if p.tag == "CERTIFICATE" {
+            certs.push(SM2Certificate::from_der(&p.contents)?);
+        }
+    }
+    if certs.is_empty() {
+        return kisserr!(
+            KissErrKind::EFormat,
+            "no certificate in {}",
+            fileutils::pem_source_name(pemfile)
+        );
+    }
+    Ok(certs)
+}
+
+///从SEC1格式的EC私钥里取出32字节私钥
+fn privkey_from_sec1(der: &[u8]) -> Result<Vec<u8>, KissError> {
+    let (key, _) = DerItem::parse(der)?;
+    let fields = key.expect(DER_TAG_SEQUENCE)?.children()?;
+    if fields.len() < 2 {
+        return kisserr!(KissErrKind::EFormat, "bad ec private key");
+    }
+    let privkey = fields[1].expect(DER_TAG_OCTET_STRING)?.body;
+    Ok(privkey.to_vec())
+}
+
+///加载pem文件(或pem内容)里的SM2私钥，支持PKCS#8（PRIVATE KEY）和SEC1（EC PRIVATE KEY）
+pub fn load_sm2_privkey_from_pem(pemfile: &str) -> Result<Vec<u8>, KissError> {
+    let content = fileutils::read_pem(pemfile)?;
+    for p in pem::parse_many(content) {
+        match p.tag.as_str() {
+            "EC PRIVATE KEY" => return privkey_from_sec1(&p.contents),
+            "PRIVATE KEY" => {
+                let (key, _) = DerItem::parse(&p.contents)?;
+                let fields = key.expect(DER_TAG_SEQUENCE)?.children()?;
+                if fields.len() < 3 {
+                    return kisserr!(KissErrKind::EFormat, "bad pkcs8 private key");
+                }
+                return privkey_from_sec1(fields[2].expect(DER_TAG_OCTET_STRING)?.body);
+            }
+            _ => continue,
+        }
+    }
+    kisserr!(
+        KissErrKind::EFormat,
+        "no private key in {}",
+        fileutils::pem_source_name(pemfile)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::mock_tlcp::sm2_decrypt;
+
+    #[test]
+    fn test_sm2_encrypt_and_sign() {
+        let ctx = SigCtx::new();
+        let (pk, sk) = ctx.new_keypair();
+        let privkey = ctx.serialize_seckey(&sk);
+        let pubkey = ctx.serialize_pubkey(&pk, false);
+
+        let premaster: Vec<u8> = (0u8..48).collect();
+        let cipher = sm2_encrypt(&pubkey, &premaster).unwrap();
+        assert_eq!(sm2_decrypt(&privkey, &cipher).unwrap(), premaster);
+
+        let sig = sm2_sign(&privkey, b"tlcp handshake").unwrap();
+        assert!(sm2_verify(&pubkey, b"tlcp handshake", &sig));
+        assert!(!sm2_verify(&pubkey, b"tlcp handshake!", &sig));
+
+        let out = prf_sm3(b"secret", "master secret", b"seed", 48);
+        assert_eq!(out.len(), 48);
+        assert_eq!(
+            &prf_sm3(b"secret", "master secret", b"seed", 12)[..],
+            &out[..12]
+        );
+    }
+}
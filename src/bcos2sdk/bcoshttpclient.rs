@@ -18,20 +18,39 @@
   @author: kentzhang
   @date: 2021-07
 */
-use reqwest::header::HeaderMap;
+use std::time::Duration;
 
+use openssl::pkey::PKey;
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Identity, Proxy};
+
+use crate::bcossdkutil::bcosclientconfig::RpcConfig;
+use crate::bcossdkutil::fileutils;
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::{kisserr, printlnex};
 
 //---------------------------------------------------------------------------------
-/// http的网络客户端
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+/// http的网络客户端，reqwest的Client在from_config时建好，之后的请求复用连接池
+#[derive(Clone, Debug, Default)]
 pub struct HttpJsonRpcClient {
     pub target_url: String,
     pub timeout: u32,
     headers: HeaderMap,
+    client: Option<Client>,
+}
+
+///reqwest的Client没有实现比较，按目标地址、超时和http头比较
+impl PartialEq for HttpJsonRpcClient {
+    fn eq(&self, other: &Self) -> bool {
+        self.target_url == other.target_url
+            && self.timeout == other.timeout
+            && self.headers == other.headers
+    }
 }
 
+impl Eq for HttpJsonRpcClient {}
+
 impl HttpJsonRpcClient {
     pub fn new() -> HttpJsonRpcClient {
         // 组装header
@@ -41,23 +60,100 @@ impl HttpJsonRpcClient {
             target_url: "".to_string(),
             timeout: 10,
             headers: headers,
+            client: None,
+        }
+    }
+
+    ///按rpc配置建立客户端：超时、ca证书、双向tls的客户端证书、附加的http头、代理和连接池
+    pub fn from_config(config: &RpcConfig) -> Result<HttpJsonRpcClient, KissError> {
+        let mut rpcclient = HttpJsonRpcClient::new();
+        rpcclient.target_url = config.url.clone();
+        rpcclient.timeout = config.timeout;
+        for (name, value) in config.headers.iter() {
+            let headername = match HeaderName::from_bytes(name.as_bytes()) {
+                Ok(n) => n,
+                Err(e) => {
+                    return kisserr!(KissErrKind::EArgument, "bad http header {} {:?}", name, e)
+                }
+            };
+            let headervalue = match HeaderValue::from_str(value) {
+                Ok(v) => v,
+                Err(e) => {
+                    return kisserr!(
+                        KissErrKind::EArgument,
+                        "bad http header value {} {:?}",
+                        name,
+                        e
+                    )
+                }
+            };
+            rpcclient.headers.insert(headername, headervalue);
+        }
+
+        let mut builder = Client::builder()
+            .pool_max_idle_per_host(config.pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_sec));
+        if config.timeout > 0 {
+            builder = builder.timeout(Duration::from_secs(config.timeout as u64));
+        }
+        if config.tcp_keepalive_sec > 0 {
+            builder = builder.tcp_keepalive(Duration::from_secs(config.tcp_keepalive_sec));
+        }
+        if !config.cacert.is_empty() {
+            let pem = fileutils::read_pem(&config.cacert)?;
+            let certs = match Certificate::from_pem_bundle(&pem) {
+                Ok(c) => c,
+                Err(e) => return kisserr!(KissErrKind::EFormat, "bad rpc cacert {:?}", e),
+            };
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if !config.sdkcert.is_empty() {
+            builder = builder.identity(load_identity(&config.sdkcert, &config.sdkkey)?);
+        }
+        if !config.proxy.is_empty() {
+            match Proxy::all(&config.proxy) {
+                Ok(p) => builder = builder.proxy(p),
+                Err(e) => {
+                    return kisserr!(
+                        KissErrKind::EArgument,
+                        "bad rpc proxy {} {:?}",
+                        config.proxy,
+                        e
+                    )
+                }
+            }
+        }
+        match builder.build() {
+            Ok(c) => rpcclient.client = Some(c),
+            Err(e) => return kisserr!(KissErrKind::EArgument, "build http client error {:?}", e),
         }
+        Ok(rpcclient)
     }
+
     pub fn set_target(&mut self, target_url: &str) {
         self.target_url = target_url.to_string();
     }
 
     ///同步的http请求，正确的话返回http的body，也就是一段json串
     pub fn request_sync(&self, outbuffer: &str) -> Result<String, KissError> {
-        let client = reqwest::blocking::Client::new();
+        //用new()建的对象没有预先建好的client，每次请求临时建一个默认的
+        let client = match &self.client {
+            Some(c) => c.clone(),
+            None => Client::new(),
+        };
 
-        /*todo 改成支持timeout*/
         printlnex!("request target url : {:?}", &self.target_url);
-        let postResult = client
+        let mut request = client
             .post(&self.target_url)
             .headers(self.headers.clone())
-            .body(outbuffer.to_string())
-            .send();
+            .body(outbuffer.to_string());
+        //单个请求的超时，BcosRPC按请求策略设置，覆盖建client时的默认超时
+        if self.timeout > 0 {
+            request = request.timeout(Duration::from_secs(self.timeout as u64));
+        }
+        let postResult = request.send();
         //post 是否正确
         match postResult {
             Ok(response) => {
@@ -84,3 +180,133 @@ impl HttpJsonRpcClient {
         }
     }
 }
+
+///读取客户端证书和私钥，私钥不是pkcs8格式时(如"EC PRIVATE KEY")先转换
+fn load_identity(certfile: &str, keyfile: &str) -> Result<Identity, KissError> {
+    let certpem = fileutils::read_pem(certfile)?;
+    let keypem = fileutils::read_pem(keyfile)?;
+    let pkcs8 = match PKey::private_key_from_pem(&keypem).and_then(|k| k.private_key_to_pem_pkcs8())
+    {
+        Ok(k) => k,
+        Err(e) => {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "bad rpc sdkkey {} {:?}",
+                fileutils::pem_source_name(keyfile),
+                e
+            )
+        }
+    };
+    match Identity::from_pkcs8_pem(&certpem, &pkcs8) {
+        Ok(i) => Ok(i),
+        Err(e) => kisserr!(
+            KissErrKind::EFormat,
+            "bad rpc client cert {} {:?}",
+            fileutils::pem_source_name(certfile),
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::mock_rpc_node::MockRpcNode;
+    use serde_json::json;
+
+    #[test]
+    fn test_client_from_config() {
+        let node = MockRpcNode::start().unwrap();
+        let mut config = node.client_config().unwrap().rpc;
+        config
+            .headers
+            .insert("X-Api-Key".to_string(), "testkey".to_string());
+        config.tcp_keepalive_sec = 30;
+        let client = HttpJsonRpcClient::from_config(&config).unwrap();
+        let request = json!({"jsonrpc": "2.0", "method": "getBlockNumber", "params": [1], "id": 1});
+        for _ in 0..2 {
+            client.request_sync(&request.to_string()).unwrap();
+        }
+        let state = node.state();
+        assert_eq!(state.received_headers.len(), 2);
+        for headers in state.received_headers.iter() {
+            assert_eq!(headers["x-api-key"], "testkey");
+            assert_eq!(headers["content-type"], "application/json");
+        }
+
+        config
+            .headers
+            .insert("bad header".to_string(), "v".to_string());
+        let err = HttpJsonRpcClient::from_config(&config).unwrap_err();
+        assert_eq!(err.kind, KissErrKind::EArgument);
+        config.headers.clear();
+        config.sdkcert = "notexist.crt".to_string();
+        assert!(HttpJsonRpcClient::from_config(&config).is_err());
+    }
+    #[test]
+    fn test_client_eq() {
+        let node = MockRpcNode::start().unwrap();
+        let config = node.client_config().unwrap().rpc;
+        let a = HttpJsonRpcClient::from_config(&config).unwrap();
+        let b = HttpJsonRpcClient::from_config(&config).unwrap();
+        assert_eq!(a, b);
+        let mut c = HttpJsonRpcClient::new();
+        c.set_target(&config.url);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_https_cacert_and_client_cert() {
+        let node = MockRpcNode::start_https().unwrap();
+        let other = MockRpcNode::start_https().unwrap();
+        let request = json!({"jsonrpc": "2.0", "method": "getBlockNumber", "params": [1], "id": 1});
+        let mut config = node.client_config().unwrap().rpc;
+        assert!(config.url.starts_with("https://"));
+
+        //没有配ca证书，节点证书验证不过
+        let client = HttpJsonRpcClient::from_config(&config).unwrap();
+        let err = client.request_sync(&request.to_string()).unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ENetwork);
+
+        //ca证书文件里有多个证书，其中一个能验证节点即可，不带客户端证书
+        let bundle = format!(
+            "{}{}",
+            std::fs::read_to_string(other.cert_path("ca.crt")).unwrap(),
+            std::fs::read_to_string(node.cert_path("ca.crt")).unwrap()
+        );
+        let bundlefile = node.workdir.join("bundle.crt");
+        std::fs::write(&bundlefile, bundle).unwrap();
+        config.cacert = bundlefile.to_string_lossy().to_string();
+        let client = HttpJsonRpcClient::from_config(&config).unwrap();
+        client.request_sync(&request.to_string()).unwrap();
+        assert_eq!(node.state().client_cert_cns, vec!["".to_string()]);
+
+        //双向tls，节点能看到客户端证书
+        config.sdkcert = node.cert_path("sdk.crt");
+        config.sdkkey = node.cert_path("sdk.key");
+        let client = HttpJsonRpcClient::from_config(&config).unwrap();
+        client.request_sync(&request.to_string()).unwrap();
+        assert_eq!(node.state().client_cert_cns.last().unwrap(), "sdk");
+    }
+
+    #[test]
+    fn test_proxy() {
+        //mock节点当作http代理，代理请求的目标是绝对地址，host头是原目标
+        let proxy = MockRpcNode::start().unwrap();
+        let mut config = proxy.client_config().unwrap().rpc;
+        config.url = "http://bcos-node.invalid:8545".to_string();
+        config.proxy = proxy.url().replace("http://", "http://user:pass@");
+        let client = HttpJsonRpcClient::from_config(&config).unwrap();
+        let request = json!({"jsonrpc": "2.0", "method": "getBlockNumber", "params": [1], "id": 1});
+        client.request_sync(&request.to_string()).unwrap();
+        let state = proxy.state();
+        assert_eq!(state.received_headers.len(), 1);
+        let headers = &state.received_headers[0];
+        assert_eq!(headers["host"], "bcos-node.invalid:8545");
+        assert!(headers["proxy-authorization"].starts_with("Basic "));
+
+        config.proxy = "not a url".to_string();
+        let err = HttpJsonRpcClient::from_config(&config).unwrap_err();
+        assert_eq!(err.kind, KissErrKind::EArgument);
+    }
+}
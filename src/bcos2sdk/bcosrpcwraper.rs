@@ -18,12 +18,15 @@
     unused_variables,
     unused_assignments
 )]
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Result as JsonResult, Value as JsonValue};
 
 use crate::bcos2sdk::bcos_channel_client::{BcosChannelClient, IBcosChannel};
 use crate::bcos2sdk::bcoshttpclient::HttpJsonRpcClient;
-use crate::bcossdkutil::bcosclientconfig::{BcosClientProtocol, ClientConfig};
+use crate::bcos2sdk::channelpack::{ChannelPack, CHANNEL_PACK_TYPE};
+use crate::bcossdkutil::bcosclientconfig::{BcosClientProtocol, ClientConfig, RequestPolicy};
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::{kisserr, printlnex};
 
@@ -33,7 +36,7 @@ pub struct RpcRequestData {
     pub jsonrpc: String,
     pub method: String,
     pub params: JsonValue,
-    pub id: u8,
+    pub id: u64,
 }
 
 impl RpcRequestData {
@@ -45,6 +48,14 @@ impl RpcRequestData {
             params: json! {[1]},
         }
     }
+    ///按命令和参数构建请求，id为1，批量请求时会重新编号
+    pub fn new(cmd: &str, params: &JsonValue) -> Self {
+        RpcRequestData {
+            method: cmd.to_string(),
+            params: params.clone(),
+            ..RpcRequestData::default()
+        }
+    }
     ///采用serde_json encode为json格式
     pub fn encode(&self) -> Result<String, KissError> {
         let res = serde_json::to_string(self);
@@ -67,6 +78,52 @@ impl RpcRequestData {
     }
 }
 
+///会改变链上状态的请求，失败后不能自动重发，以免重复执行
+pub fn is_idempotent_rpc(cmd: &str) -> bool {
+    match cmd {
+        "sendRawTransaction"
+        | "sendRawTransactionAndGetProof"
+        | "generateGroup"
+        | "startGroup"
+        | "stopGroup"
+        | "removeGroup"
+        | "recoverGroup" => false,
+        _ => true,
+    }
+}
+
+///一次批量请求最多带的请求数，更多的请求分成几批发。
+/// channel方式下一批的回包都先放在pool里，不能超过CHANNEL_PACK_POOL_MAX
+pub const RPC_BATCH_MAX: usize = 256;
+
+///按id把批量的回包对回请求的顺序，有请求没有回包时返回EFormat
+pub fn match_batch_responses(
+    requests: &[RpcRequestData],
+    responses: Vec<JsonValue>,
+) -> Result<Vec<JsonValue>, KissError> {
+    let mut byid: HashMap<u64, JsonValue> = HashMap::new();
+    for response in responses {
+        if let Some(id) = response["id"].as_u64() {
+            byid.insert(id, response);
+        }
+    }
+    let mut matched = vec![];
+    for req in requests {
+        match byid.remove(&req.id) {
+            Some(response) => matched.push(response),
+            None => {
+                return kisserr!(
+                    KissErrKind::EFormat,
+                    "no response for batch request id {} method {}",
+                    req.id,
+                    req.method
+                )
+            }
+        }
+    }
+    Ok(matched)
+}
+
 //-----------------------------------------------------------------------------------
 ///统一对外暴露这个实现,封装向网络提交请求的部分
 #[derive()]
@@ -75,6 +132,7 @@ pub struct BcosRPC {
     pub config: ClientConfig,
     pub jsonrpc_client: HttpJsonRpcClient,
     pub channel_client: BcosChannelClient,
+    pub policy: RequestPolicy, //当前生效的请求策略，初始为配置里的policy
 }
 //unsafe impl Send for BcosRPC{}
 //unsafe impl Sync for BcosRPC{}
@@ -85,23 +143,58 @@ impl BcosRPC {
         let mut jsonrpc_client = HttpJsonRpcClient::new();
         jsonrpc_client.target_url = config.rpc.url.clone();
         jsonrpc_client.timeout = config.rpc.timeout;
-        let channel_client: BcosChannelClient;
+        if config.bcos2.protocol == BcosClientProtocol::RPC {
+            jsonrpc_client = HttpJsonRpcClient::from_config(&config.rpc)?;
+        }
+        let mut channel_client: BcosChannelClient;
         if config.bcos2.protocol == BcosClientProtocol::CHANNEL {
             channel_client = BcosChannelClient::new(&config.channel)?;
+            if let Err(e) = channel_client.subscribe_block_notify(config.bcos2.groupid) {
+                log::warn!("subscribe block notify error {:?}", e);
+            }
         } else {
             channel_client = BcosChannelClient::default(&config.channel);
+            channel_client.blocknotifier.groupid = config.bcos2.groupid;
             printlnex!("done channel_client");
         }
-        Ok(BcosRPC {
+        let mut rpc = BcosRPC {
             config: config.clone(),
             jsonrpc_client: jsonrpc_client,
             channel_client: channel_client,
-        })
+            policy: config.policy.clone(),
+        };
+        rpc.set_policy(config.policy.clone());
+        Ok(rpc)
+    }
+
+    ///换一个请求策略，单个请求的超时同时设置到http和channel客户端上
+    pub fn set_policy(&mut self, policy: RequestPolicy) {
+        self.jsonrpc_client.timeout = policy.timeout as u32;
+        self.channel_client.request_timeout = policy.timeout;
+        self.policy = policy;
     }
 
     pub fn finish(&mut self) {
         self.channel_client.finish();
     }
+
+    ///channel方式下节点推送的最新块高，还没有收到推送时返回None
+    pub fn notified_blocknumber(&mut self) -> Option<u64> {
+        if self.config.bcos2.protocol != BcosClientProtocol::CHANNEL {
+            return None;
+        }
+        //推送订阅的是别的群组(切换过群组)时不能用
+        if self.channel_client.blocknotifier.groupid != self.config.bcos2.groupid {
+            return None;
+        }
+        if let Err(e) = self.channel_client.poll_pushes() {
+            log::warn!("poll channel pushes error {:?}", e);
+        }
+        match self.channel_client.blocknotifier.latest() {
+            0 => None,
+            n => Some(n),
+        }
+    }
     pub fn switch_rpc_request_sync(&mut self, outbuffer: &String) -> Result<String, KissError> {
         //let mut response_text =String::default();
         match self.config.bcos2.protocol {
@@ -118,6 +211,34 @@ impl BcosRPC {
         //Ok(response_text);
     }
 
+    ///遇到网络错误、超时等可重试的错误时，channel方式下先换节点重连，
+    /// 查询类的请求按请求策略退避后重试，发交易等非幂等的请求不重试，把错误返回给调用者
+    pub fn request_with_failover(
+        &mut self,
+        cmd: &str,
+        outbuffer: &String,
+    ) -> Result<String, KissError> {
+        let mut attempt: u32 = 0;
+        loop {
+            let err = match self.switch_rpc_request_sync(outbuffer) {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            if !RequestPolicy::is_retryable(&err) {
+                return Err(err);
+            }
+            if self.config.bcos2.protocol == BcosClientProtocol::CHANNEL {
+                log::warn!("channel request {} failed {:?}, try failover", cmd, err);
+                self.channel_client.failover()?;
+            }
+            if !is_idempotent_rpc(cmd) || attempt >= self.policy.max_retries {
+                return Err(err);
+            }
+            std::thread::sleep(self.policy.backoff(attempt));
+            attempt += 1;
+        }
+    }
+
     ///同步调用的客户端请求，输入cmd，如 getBlockNumber，value:参数，参考bcos rpc接口文档，参数中应包含groupid
     /// todo：异步请求待实现
     pub fn rpc_request_sync(
@@ -134,31 +255,172 @@ impl BcosRPC {
         let outbuffer = req.encode()?;
         printlnex!("request: {:?}", outbuffer);
         log::info!("request: {:?}", outbuffer);
-        let responsebuffer = self.switch_rpc_request_sync(&outbuffer)?;
+        let responsebuffer = self.request_with_failover(cmd, &outbuffer)?;
         log::info!("response:  {:?}", &responsebuffer);
-        let jsonres: JsonResult<JsonValue> = serde_json::from_str(responsebuffer.as_str());
-        match jsonres {
-            Ok(jsonval) => {
-                printlnex!("request response: {:?}", jsonval);
-                Ok(jsonval)
+        parse_rpc_response(&responsebuffer)
+    }
+
+    ///批量请求，返回和requests顺序一致的完整response，请求的id会重新编号为1..n。
+    /// RPC方式把一批请求打成json数组一次发出，节点不支持批量(回包不是数组)时退回逐个请求；
+    /// channel方式把一批请求作为并发的包全部发出，再按seq收回包
+    pub fn rpc_batch_request_sync(
+        &mut self,
+        mut requests: Vec<RpcRequestData>,
+    ) -> Result<Vec<JsonValue>, KissError> {
+        for (i, req) in requests.iter_mut().enumerate() {
+            req.id = (i + 1) as u64;
+        }
+        let mut responses = vec![];
+        for chunk in requests.chunks(RPC_BATCH_MAX) {
+            let mut chunkres = match self.config.bcos2.protocol {
+                BcosClientProtocol::RPC => self.http_batch_request(chunk)?,
+                BcosClientProtocol::CHANNEL => self.channel_batch_request(chunk)?,
+                _ => {
+                    return kisserr!(
+                        KissErrKind::EArgument,
+                        "unhandled protocal {:?}",
+                        self.config.bcos2.protocol
+                    )
+                }
+            };
+            responses.append(&mut chunkres);
+        }
+        Ok(responses)
+    }
+
+    fn http_batch_request(
+        &mut self,
+        requests: &[RpcRequestData],
+    ) -> Result<Vec<JsonValue>, KissError> {
+        let outbuffer = match serde_json::to_string(requests) {
+            Ok(s) => s,
+            Err(e) => return kisserr!(KissErrKind::EFormat, "batch encode error {:?}", e),
+        };
+        log::info!("batch request: {} items", requests.len());
+        let responsebuffer = self.jsonrpc_client.request_sync(&outbuffer)?;
+        match parse_rpc_response(&responsebuffer)? {
+            JsonValue::Array(items) => match_batch_responses(requests, items),
+            other => {
+                log::warn!("batch rpc not supported, response {:?}", other);
+                requests
+                    .iter()
+                    .map(|r| self.rpc_request_sync(&r.method, &r.params))
+                    .collect()
             }
-            Err(e) => {
-                log::error!(
-                    "parse json rpc response json error {},{:?}",
-                    responsebuffer,
-                    e
-                );
-                return kisserr!(
-                    KissErrKind::EFormat,
-                    "parse json rpc response json error {},{:?}",
-                    responsebuffer,
-                    e
-                );
+        }
+    }
+
+    ///可重试的错误和单个请求一样换节点，整批都是查询类请求时才重试
+    fn channel_batch_request(
+        &mut self,
+        requests: &[RpcRequestData],
+    ) -> Result<Vec<JsonValue>, KissError> {
+        let reqtexts = requests
+            .iter()
+            .map(|r| r.encode())
+            .collect::<Result<Vec<String>, KissError>>()?;
+        let idempotent = requests.iter().all(|r| is_idempotent_rpc(&r.method));
+        let mut attempt: u32 = 0;
+        loop {
+            let err = match self.channel_client.request_batch_sync(&reqtexts) {
+                Ok(responses) => return responses.iter().map(parse_rpc_response).collect(),
+                Err(e) => e,
+            };
+            if !RequestPolicy::is_retryable(&err) {
+                return Err(err);
+            }
+            log::warn!("channel batch request failed {:?}, try failover", err);
+            self.channel_client.failover()?;
+            if !idempotent || attempt >= self.policy.max_retries {
+                return Err(err);
             }
+            std::thread::sleep(self.policy.backoff(attempt));
+            attempt += 1;
+        }
+    }
+
+    ///channel方式下发出请求，再等待节点用同一个seq推送的pushtype包，如交易上链通知TX_COMMITTED。
+    /// 发交易不是幂等的，这里不做换节点重试
+    pub fn rpc_request_and_wait_push(
+        &mut self,
+        cmd: &str,
+        params_value: &JsonValue,
+        pushtype: CHANNEL_PACK_TYPE,
+        timeoutsec: u64,
+    ) -> Result<(JsonValue, ChannelPack), KissError> {
+        if self.config.bcos2.protocol != BcosClientProtocol::CHANNEL {
+            return kisserr!(
+                KissErrKind::EArgument,
+                "push is only supported by channel protocol, not {:?}",
+                self.config.bcos2.protocol
+            );
+        }
+        let req = RpcRequestData {
+            method: cmd.to_string(),
+            params: params_value.clone(),
+            ..RpcRequestData::default()
+        };
+        let outbuffer = req.encode()?;
+        log::info!("request: {:?}", outbuffer);
+        let (responsebuffer, seq) = self.channel_client.request_sync_with_seq(&outbuffer)?;
+        log::info!("response:  {:?}", &responsebuffer);
+        let response = parse_rpc_response(&responsebuffer)?;
+        if response["error"] != JsonValue::Null {
+            return kisserr!(KissErrKind::Error, "{} error {:?}", cmd, response["error"]);
+        }
+        let pushpack = self
+            .channel_client
+            .wait_channelpack(pushtype as u16, &seq, timeoutsec)?;
+        Ok((response, pushpack))
+    }
+}
+
+pub fn parse_rpc_response(responsebuffer: &String) -> Result<JsonValue, KissError> {
+    let jsonres: JsonResult<JsonValue> = serde_json::from_str(responsebuffer.as_str());
+    match jsonres {
+        Ok(jsonval) => {
+            printlnex!("request response: {:?}", jsonval);
+            Ok(jsonval)
+        }
+        Err(e) => {
+            log::error!(
+                "parse json rpc response json error {},{:?}",
+                responsebuffer,
+                e
+            );
+            return kisserr!(
+                KissErrKind::EFormat,
+                "parse json rpc response json error {},{:?}",
+                responsebuffer,
+                e
+            );
         }
     }
 }
 
+///TX_COMMITTED推送的包体就是回执，包装成和getTransactionReceipt一样的返回格式
+pub fn receipt_from_committed_pack(pack: &ChannelPack) -> Result<JsonValue, KissError> {
+    if pack.result != 0 {
+        return kisserr!(
+            KissErrKind::Error,
+            "transaction committed push with error result {}",
+            pack.result
+        );
+    }
+    let receipt: JsonValue = match serde_json::from_slice(&pack.data[..]) {
+        Ok(v) => v,
+        Err(e) => {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "parse committed receipt error {:?},{:?}",
+                String::from_utf8_lossy(&pack.data[..]),
+                e
+            )
+        }
+    };
+    Ok(json!({"jsonrpc":"2.0","id":1,"result":receipt}))
+}
+
 //----------------------------------------------------------------------
 pub fn test_json_rpc() {
     let groupid = 1;
@@ -168,3 +430,36 @@ pub fn test_json_rpc() {
     let response = client.rpc_request_sync("getBlockNumber", params);
     println!("{:?}", response);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use crate::testsupport::mock_rpc_node::MockRpcNode;
+
+    fn block_requests(count: u64) -> Vec<RpcRequestData> {
+        (0..count)
+            .map(|n| RpcRequestData::new("getBlockHashByNumber", &json!([1, format!("0x{:x}", n)])))
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_request() {
+        let node = MockRpcNode::start().unwrap();
+        node.chain().blocknumber = 300;
+        let mut rpc = BcosRPC::new(&node.client_config().unwrap()).unwrap();
+        let responses = rpc.rpc_batch_request_sync(block_requests(300)).unwrap();
+        assert_eq!(responses.len(), 300);
+        assert_eq!(responses[299]["result"], format!("0x{:064x}", 299));
+        //超过RPC_BATCH_MAX的请求分两批发
+        assert_eq!(node.received_requests().len(), 2);
+
+        let node = MockChannelNode::start().unwrap();
+        node.chain().blocknumber = 10;
+        let mut rpc = BcosRPC::new(&node.client_config().unwrap()).unwrap();
+        let responses = rpc.rpc_batch_request_sync(block_requests(10)).unwrap();
+        assert_eq!(responses[9]["id"], 10);
+        assert_eq!(responses[9]["result"], format!("0x{:064x}", 9));
+        rpc.finish();
+    }
+}
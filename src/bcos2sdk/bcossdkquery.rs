@@ -26,6 +26,7 @@ use ethabi::Log as ReceiptLog;
 use serde_json::{json, Value as JsonValue};
 
 use crate::bcos2sdk::bcos2client::Bcos2Client;
+use crate::bcos2sdk::bcosrpcwraper::RpcRequestData;
 use crate::bcossdkutil::commonhash::HashType;
 use crate::bcossdkutil::contractabi::ContractABI;
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
@@ -72,6 +73,14 @@ impl Bcos2Client {
         refer to Python SDK: https://github.com/FISCO-BCOS/python-sdk
     */
     pub fn getBlockLimit(&mut self) -> Result<u32, KissError> {
+        //channel方式下有节点推送的块高就直接用，不用再发rpc。
+        //推送的块高是最后一次的值，只有块高变大才算收到新推送，推送断了就回到下面的定时查询
+        if let Some(notified) = self.netclient.notified_blocknumber() {
+            if notified as u32 > self.lastblocknum {
+                self.lastblocknum = notified as u32;
+                self.updateblocknum_tick = time::now();
+            }
+        }
         let now = time::now();
         printlnex!("getblocknumber cause :{}", now - self.updateblocknum_tick);
         //每30秒获取一次
@@ -79,6 +88,10 @@ impl Bcos2Client {
             let block_num = self.getBlockNumber()?;
             self.lastblocknum = block_num;
             self.updateblocknum_tick = time::now();
+            let notifier = &self.netclient.channel_client.blocknotifier;
+            if notifier.groupid == self.config.bcos2.groupid {
+                notifier.update(block_num as u64);
+            }
         }
 
         Ok(self.lastblocknum + DELTABLOCKLIMIT)
@@ -200,6 +213,26 @@ impl Bcos2Client {
         self.netclient.rpc_request_sync(cmd, &paramobj)
     }
 
+    ///用批量请求获取[from, to]范围内的块，返回每个块的完整response，按块高排列
+    pub fn getBlocksByNumberRange(
+        &mut self,
+        from: u32,
+        to: u32,
+        includeTransactions: bool,
+    ) -> Result<Vec<JsonValue>, KissError> {
+        let groupid = self.config.bcos2.groupid;
+        let requests = (from..=to)
+            .map(|num| {
+                let hexnum = format!("0x{:02X}", num);
+                RpcRequestData::new(
+                    "getBlockByNumber",
+                    &json!([groupid, hexnum, includeTransactions]),
+                )
+            })
+            .collect();
+        self.netclient.rpc_batch_request_sync(requests)
+    }
+
     ///https://fisco-bcos-documentation.readthedocs.io/zh_CN/latest/docs/api.html#getBlockHashByNumber
     pub fn getBlockHashByNumber(&mut self, num: u32) -> Result<JsonValue, KissError> {
         let groupid = self.config.bcos2.groupid;
@@ -265,6 +298,19 @@ impl Bcos2Client {
         self.netclient.rpc_request_sync(cmd, &paramobj)
     }
 
+    ///用批量请求获取多个交易的回执，返回每个回执的完整response，顺序和txhashes一致
+    pub fn getTransactionReceipts(
+        &mut self,
+        txhashes: &[String],
+    ) -> Result<Vec<JsonValue>, KissError> {
+        let groupid = self.config.bcos2.groupid;
+        let requests = txhashes
+            .iter()
+            .map(|h| RpcRequestData::new("getTransactionReceipt", &json!([groupid, h])))
+            .collect();
+        self.netclient.rpc_batch_request_sync(requests)
+    }
+
     pub fn try_getTransactionReceipt(
         &mut self,
         txhash: &str,
@@ -273,7 +319,7 @@ impl Bcos2Client {
     ) -> Result<JsonValue, KissError> {
         let start = time::now();
         //let h = "0xd47832f4de959582fc1964cea04da09506200c41a81e59c8934b23017deca27a";
-        while time::now() - start < chrono::Duration::seconds(timeoutsec) {
+        while time::now() - start < time::Duration::seconds(timeoutsec) {
             //println!("go get receipt");
             let v = self.getTransactionReceipt(txhash)?;
             //println!("result {:?}",v);
@@ -287,11 +333,17 @@ impl Bcos2Client {
             return Ok(v);
         }
         return kisserr!(
-            KissErrKind::ENetwork,
+            KissErrKind::ETimeout,
             "getTransactionReceipt timeout or missing"
         );
     }
 
+    ///按请求策略的receipt_timeout轮询等待交易回执
+    pub fn wait_receipt(&mut self, txhash: &str) -> Result<JsonValue, KissError> {
+        let timeout = self.netclient.policy.receipt_timeout as i64;
+        self.try_getTransactionReceipt(txhash, timeout, false)
+    }
+
     ///https://fisco-bcos-documentation.readthedocs.io/zh_CN/latest/docs/api.html#gettransactionreceiptbyhashwithproof
     pub fn getTransactionReceiptByHashWithProof(
         &mut self,
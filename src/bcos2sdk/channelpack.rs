@@ -24,8 +24,11 @@
 use std::convert::From;
 use std::convert::TryInto;
 
+use bytes::{BufMut, Bytes, BytesMut};
 use ethereum_types::H256;
 use keccak_hash::keccak;
+use serde::{Deserialize, Serialize};
+use tokio_util::codec::{Decoder, Encoder};
 
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::kisserr;
@@ -36,10 +39,13 @@ pub enum CHANNEL_PACK_TYPE {
     HEART_BEAT = 0x13, //json格式心跳包{"heartbeat":"0"}	心跳包	0:SDK->节点，1:节点->SDK
     HAND_SHAKE = 0x14, //SDK->节点的包体{"minimumSupport":version,"maximumSupport":version,"clientType":"client type"},节点->SDK的包体{"protocol":version,"nodeVersion":"fisco-bcos version"	握手包，json格式的协议版本协商	SDK<->节点，双向
     CLIENT_REGISTER_EVENT_LOG = 0x15, // 注册消息监听
+    CLIENT_UNREGISTER_EVENT_LOG = 0x16, // 注销消息监听
     AMOP_REQ = 0x30,   //AMOP消息包包体	AMOP请求包	SDK<->节点，双向
     AMOP_RESP = 0x31,  //失败的AMOP消息的包体	AMOP失败响应包	节点->SDK或节点->节点
     TOPIC_REPORT = 0x32, //json数组，存储SDK监听的Topics	上报Topic信息	SDK->节点
     TOPIC_MULTICAST = 0x35, //AMOP消息包包体	AMOP多播消息	节点->节点
+    REQUEST_TOPICCERT = 0x37, //节点请求发布方SDK验证私有topic的订阅方	节点->SDK
+    UPDATE_TOPIC_STATUS = 0x38, //发布方SDK把私有topic的验证结果告诉节点	SDK->节点
     TX_COMMITTED = 0x1000, //json格式的交易上链通知	交易上链回调	节点->SDK
     TX_BLOCKNUM = 0x1001, //json格式的区块上链通知{"groupID":"groupID","blockNumber":"blockNumber"}
     EVENT_LOG_PUSH = 0x1002,
@@ -56,13 +62,15 @@ pub enum CHANNEL_PACK_TYPE {
 ///result	int32_t	4	错误码，大端
 ///
 ///data	bytes	length-42	数据包体，字节流
+///
+///解码时data直接引用读缓冲区里切出来的那一段，不复制，clone也只是增加引用计数
 #[derive(Default, Clone, Debug)]
 pub struct ChannelPack {
     pub length: usize,
     pub packtype: u16,
     pub seq: H256,
     pub result: u32,
-    pub data: Vec<u8>,
+    pub data: Bytes,
 }
 
 impl ChannelPack {
@@ -73,7 +81,7 @@ impl ChannelPack {
             self.packtype,
             self.seq,
             self.result,
-            String::from_utf8(self.data.clone()).unwrap().as_str()
+            String::from_utf8_lossy(&self.data)
         )
     }
 
@@ -88,36 +96,204 @@ impl ChannelPack {
         buffer.append(&mut Vec::from(self.packtype.to_be_bytes()));
         buffer.append(&mut Vec::from(self.seq.to_fixed_bytes()));
         buffer.append(&mut Vec::from(self.result.to_be_bytes()));
-        buffer.append(&mut self.data.clone());
+        buffer.extend_from_slice(&self.data);
         buffer
     }
 
+    ///从data头部解出一个包，长度按默认上限检查，流式读取请用ChannelPackCodec
     pub fn unpack(data: &Vec<u8>) -> Result<ChannelPack, KissError> {
-        if data.len() < 42 {
+        let length = match ChannelPackCodec::default().check_length(data)? {
+            Some(l) => l,
+            None => {
+                return kisserr!(
+                    KissErrKind::EFormat,
+                    "channelpack data length too short:  {}",
+                    data.len()
+                )
+            }
+        };
+        if data.len() < length {
+            //数据量不够
+            return kisserr!(KissErrKind::EFormat, "buffer size less than pack.length");
+        }
+        Ok(ChannelPack::from_frame(Bytes::copy_from_slice(&data[..length])))
+    }
+
+    ///frame是长度已经检查过的完整的包，包体和frame共用同一块内存
+    fn from_frame(frame: Bytes) -> ChannelPack {
+        ChannelPack {
+            length: frame.len(),
+            packtype: u16::from_be_bytes(frame[4..6].try_into().unwrap()),
+            seq: H256::from_slice(&frame[6..38]),
+            result: u32::from_be_bytes(frame[38..42].try_into().unwrap()),
+            data: frame.slice(CHANNEL_PACK_HEADER_LEN..),
+        }
+    }
+}
+
+///包头长度，也是包的最小长度
+pub const CHANNEL_PACK_HEADER_LEN: usize = 42;
+///默认的单个包长度上限，超过的按格式错误处理
+pub const CHANNEL_PACK_MAX_SIZE: usize = 32 * 1024 * 1024;
+
+///ChannelPack的编解码，按包头的长度从BytesMut里切出整包，剩下的数据不用搬动。
+/// 长度为负数、小于包头或者超过上限时返回EFormat，这时连接上的数据已经错位，应该断开
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelPackCodec {
+    pub max_packet_size: usize,
+}
+
+impl Default for ChannelPackCodec {
+    fn default() -> Self {
+        ChannelPackCodec::new(CHANNEL_PACK_MAX_SIZE)
+    }
+}
+
+impl ChannelPackCodec {
+    pub fn new(max_packet_size: usize) -> ChannelPackCodec {
+        ChannelPackCodec { max_packet_size }
+    }
+
+    ///检查包头里的长度字段，不够4个字节时返回None
+    pub fn check_length(&self, src: &[u8]) -> Result<Option<usize>, KissError> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let length = i32::from_be_bytes(src[..4].try_into().unwrap());
+        if length < 0 {
             return kisserr!(
                 KissErrKind::EFormat,
-                "channelpack data length too short:  {}",
-                data.len()
+                "negative channelpack length {}",
+                length
             );
         }
-        let mut pack = ChannelPack::default();
-        //deserialize::<ChannelPack>(data.as_slice()).unwrap();
-        pack.length = i32::from_be_bytes(data.as_slice()[..4].try_into().unwrap()) as usize;
-        if data.len() < pack.length {
-            //数据量不够
-            return kisserr!(KissErrKind::EFormat, "buffer size less than pack.length");
+        let length = length as usize;
+        if length < CHANNEL_PACK_HEADER_LEN {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "channelpack length {} less than header",
+                length
+            );
         }
-        let mut start = 4;
-        pack.packtype = u16::from_be_bytes(data.as_slice()[start..(start + 2)].try_into().unwrap());
-        start = start + 2;
-        pack.seq = H256::from_slice(&data[start..start + 32]);
-        start += 32;
-        pack.result = u32::from_be_bytes(data.as_slice()[start..start + 4].try_into().unwrap());
-        //start += 4;
-        pack.data = Vec::from(&data[42..pack.length]);
-        Ok(pack)
+        if length > self.max_packet_size {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "channelpack length {} exceeds max {}",
+                length,
+                self.max_packet_size
+            );
+        }
+        Ok(Some(length))
+    }
+}
+
+impl Decoder for ChannelPackCodec {
+    type Item = ChannelPack;
+    type Error = KissError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<ChannelPack>, KissError> {
+        let length = match self.check_length(src)? {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+        let frame = src.split_to(length).freeze();
+        Ok(Some(ChannelPack::from_frame(frame)))
     }
 }
+
+impl Encoder<ChannelPack> for ChannelPackCodec {
+    type Error = KissError;
+
+    ///长度字段按数据重新计算，不用pack.length
+    fn encode(&mut self, pack: ChannelPack, dst: &mut BytesMut) -> Result<(), KissError> {
+        let length = CHANNEL_PACK_HEADER_LEN + pack.data.len();
+        if length > self.max_packet_size || length > i32::MAX as usize {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "channelpack length {} exceeds max {}",
+                length,
+                self.max_packet_size
+            );
+        }
+        dst.reserve(length);
+        dst.put_u32(length as u32);
+        dst.put_u16(pack.packtype);
+        dst.put_slice(pack.seq.as_bytes());
+        dst.put_u32(pack.result);
+        dst.put_slice(&pack.data);
+        Ok(())
+    }
+}
+///本sdk支持的channel协议版本范围，握手时上报给节点
+pub const CHANNEL_PROTOCOL_MIN_SUPPORT: u32 = 1;
+pub const CHANNEL_PROTOCOL_MAX_SUPPORT: u32 = 3;
+pub const CHANNEL_CLIENT_TYPE: &str = "rust-gears-sdk";
+
+///SDK->节点的握手包体
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeRequest {
+    pub minimumSupport: u32,
+    pub maximumSupport: u32,
+    pub clientType: String,
+}
+
+///节点->SDK的握手包体，protocol是协商后的版本
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HandshakeResponse {
+    pub protocol: u32,
+    #[serde(default)]
+    pub nodeVersion: String,
+}
+
+pub fn make_handshake_pack() -> ChannelPack {
+    let req = HandshakeRequest {
+        minimumSupport: CHANNEL_PROTOCOL_MIN_SUPPORT,
+        maximumSupport: CHANNEL_PROTOCOL_MAX_SUPPORT,
+        clientType: CHANNEL_CLIENT_TYPE.to_string(),
+    };
+    let data = serde_json::to_string(&req).unwrap();
+    make_channel_pack(CHANNEL_PACK_TYPE::HAND_SHAKE, data.as_str()).unwrap()
+}
+
+///解析节点的握手回包，协商的版本不在本sdk支持的范围内时返回错误
+pub fn parse_handshake_response(pack: &ChannelPack) -> Result<HandshakeResponse, KissError> {
+    if pack.result != 0 {
+        return kisserr!(
+            KissErrKind::ENetwork,
+            "node refused handshake, result: {}",
+            pack.result
+        );
+    }
+    let response: HandshakeResponse = match serde_json::from_slice(&pack.data) {
+        Ok(r) => r,
+        Err(e) => {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "handshake response is not valid json: {:?}, {:?}",
+                String::from_utf8_lossy(&pack.data),
+                e
+            );
+        }
+    };
+    if response.protocol < CHANNEL_PROTOCOL_MIN_SUPPORT
+        || response.protocol > CHANNEL_PROTOCOL_MAX_SUPPORT
+    {
+        return kisserr!(
+            KissErrKind::Error,
+            "channel protocol version mismatch: node {} negotiated protocol {}, sdk supports [{},{}]",
+            response.nodeVersion,
+            response.protocol,
+            CHANNEL_PROTOCOL_MIN_SUPPORT,
+            CHANNEL_PROTOCOL_MAX_SUPPORT
+        );
+    }
+    Ok(response)
+}
+
 pub fn make_channel_pack(packtype: CHANNEL_PACK_TYPE, data: &str) -> Option<ChannelPack> {
     make_channel_pack_by_rawdata(packtype, &Vec::from(data))
 }
@@ -127,7 +303,7 @@ pub fn make_channel_pack_by_rawdata(
     data: &Vec<u8>,
 ) -> Option<ChannelPack> {
     let mut pack = ChannelPack::default();
-    pack.data.append(&mut data.clone());
+    pack.data = Bytes::copy_from_slice(data);
     pack.seq = ChannelPack::make_seq();
     pack.packtype = packtype as u16;
     pack.result = 0;
@@ -137,6 +313,17 @@ pub fn make_channel_pack_by_rawdata(
     Option::from(pack)
 }
 
+///心跳包，节点回{"heartbeat":"1"}
+pub fn make_heartbeat_pack() -> ChannelPack {
+    make_channel_pack(CHANNEL_PACK_TYPE::HEART_BEAT, r#"{"heartbeat":"0"}"#).unwrap()
+}
+
+///TOPIC_REPORT的包体是sdk监听的全部topic组成的json数组，每次上报都是全量
+pub fn make_topic_report_pack(topics: &Vec<String>) -> ChannelPack {
+    let data = serde_json::to_string(topics).unwrap();
+    make_channel_pack(CHANNEL_PACK_TYPE::TOPIC_REPORT, data.as_str()).unwrap()
+}
+
 ///
 ///  1字节头部长度（1+topic长度）+ topic + data
 pub fn pack_amop(topic: &Vec<u8>, data: &Vec<u8>) -> Vec<u8> {
@@ -147,9 +334,13 @@ pub fn pack_amop(topic: &Vec<u8>, data: &Vec<u8>) -> Vec<u8> {
     buffer.append(&mut data.clone());
     buffer
 }
-pub fn unpack_amop(buffer: &Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+pub fn unpack_amop(buffer: &[u8]) -> (Vec<u8>, Vec<u8>) {
     //let  start = 0;
-    let headerlen = u8::from_be_bytes(buffer.as_slice()[0..1].try_into().unwrap());
+    if buffer.len() == 0 || buffer[0] as usize > buffer.len() {
+        //不是合法的amop格式，整体当作数据
+        return (vec![], buffer.to_vec());
+    }
+    let headerlen = u8::from_be_bytes(buffer[0..1].try_into().unwrap());
     let mut topic = vec![];
     if headerlen > 1 {
         topic = Vec::from(&buffer[1..headerlen as usize])
@@ -162,7 +353,7 @@ pub fn unpack_amop(buffer: &Vec<u8>) -> (Vec<u8>, Vec<u8>) {
 pub fn test_channelpack() {
     let mut pack = ChannelPack::default();
     let data = "1234567890";
-    pack.data = Vec::from(data);
+    pack.data = Bytes::from(data);
     pack.seq = ChannelPack::make_seq();
     pack.packtype = 0x12;
     pack.result = 0;
@@ -181,7 +372,7 @@ pub fn test_channelpack() {
         println!("seq eq");
     }
     println!("unpack: {:?}", unpackres);
-    println!("data content {}", String::from_utf8(pack.data).unwrap());
+    println!("data content {}", String::from_utf8_lossy(&pack.data));
 }
 /*
 https://fisco-bcos-documentation.readthedocs.io/zh_CN/latest/docs/design/protocol_description.html#id5
@@ -205,3 +396,40 @@ code	message
 101	SDK不可达
 102	超时
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_bounds() {
+        let mut codec = ChannelPackCodec::new(1024);
+        let pack = make_channel_pack(CHANNEL_PACK_TYPE::RPC, "{\"id\":1}").unwrap();
+        let mut buf = BytesMut::new();
+        codec.encode(pack.clone(), &mut buf).unwrap();
+        codec.encode(pack.clone(), &mut buf).unwrap();
+        assert_eq!(&buf[..], [pack.pack(), pack.pack()].concat().as_slice());
+
+        //不完整的包留在缓冲区里
+        let mut partial = BytesMut::from(&buf[..pack.length + 10]);
+        let body = partial[CHANNEL_PACK_HEADER_LEN..].as_ptr();
+        let first = codec.decode(&mut partial).unwrap().unwrap();
+        assert_eq!(first.seq, pack.seq);
+        assert_eq!(first.data, pack.data);
+        //包体直接引用读缓冲区，没有复制
+        assert_eq!(first.data.as_ptr(), body);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        assert_eq!(partial.len(), 10);
+
+        let mut negative = BytesMut::from(&(-1i32).to_be_bytes()[..]);
+        assert_eq!(
+            codec.decode(&mut negative).unwrap_err().kind,
+            KissErrKind::EFormat
+        );
+        let mut oversized = BytesMut::from(&2048u32.to_be_bytes()[..]);
+        assert!(codec.decode(&mut oversized).is_err());
+        let mut short = BytesMut::from(&10u32.to_be_bytes()[..]);
+        assert!(codec.decode(&mut short).is_err());
+        assert!(ChannelPack::unpack(&10u32.to_be_bytes().to_vec()).is_err());
+    }
+}
@@ -10,169 +10,141 @@
     unused_assignments
 )]
 
-use crate::bcos2sdk::bcos_channel_handler_manager::{IChannelPushHandlerFacade, HANDLE_FACADE_OBJ};
 use crate::bcos2sdk::bcos_channel_threads_worker::BcosChannelWorker;
-use crate::bcos2sdk::channelpack::{ChannelPack, CHANNEL_PACK_TYPE};
-use crate::bcos2sdk::{bcos_channel_threads_worker, channelpack};
+use crate::bcos2sdk::bcos_channel_threads_worker;
+pub use crate::bcos2sdk::bcos_event_manager::RegisterEventRequest;
+use crate::bcos2sdk::bcos_event_manager::{
+    make_register_event_pack, make_unregister_event_pack, EventLogManager, EventLogSubscription,
+    IEventLogCallback, EVENT_CALLBACK_OBJ,
+};
+use crate::bcos2sdk::channelpack::CHANNEL_PACK_TYPE;
 use crate::bcossdkutil::contractabi::ContractABI;
 use crate::bcossdkutil::contracthistory::ContractHistory;
-use crate::bcossdkutil::kisserror::KissError;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::bcossdkutil::liteutils::datetime_str;
-use serde_derive::{Deserialize, Serialize};
-use serde_json::Value as JsonValue;
+use crate::kisserr;
+use ethabi::Log as ReceiptLog;
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
 
+///在线程模式的worker上订阅事件，可以同时注册多个过滤条件，推送按filterID分发给各自的回调
 pub struct EventHandler {
     pub worker: Arc<Mutex<BcosChannelWorker>>,
     pub contract: ContractABI,
-}
-
-/*
-    {
-  "fromBlock": "latest",
-  "toBlock": "latest",
-  "addresses": [
-    0xca5ed56862869c25da0bdf186e634aac6c6361ee
-  ],
-  "topics": [
-    "0x91c95f04198617c60eaf2180fbca88fc192db379657df0e412a9f7dd4ebbe95d"
-  ],
-  "groupID": "1",
-  "filterID": "bb31e4ec086c48e18f21cb994e2e5967"
-}*/
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct RegisterEventRequest {
-    fromBlock: String,
-    toBlock: String,
-    addresses: Vec<String>,
-    topics: Vec<String>,
-    groupID: u8,
-    filterID: String,
-}
-impl RegisterEventRequest {
-    pub fn new() -> Self {
-        RegisterEventRequest {
-            fromBlock: "latest".to_string(),
-            toBlock: "latest".to_string(),
-            addresses: vec![],
-            topics: vec![],
-            groupID: 1,
-            filterID: "bb31e4ec086c48e18f21cb994e2e5968".to_string(), //ChannelPack::make_seq()
-        }
-    }
+    pub eventmanager: EventLogManager,
 }
 
 impl EventHandler {
     pub fn new(configfile: &str, contract_name: &str) -> Self {
-        let worker = BcosChannelWorker::new(configfile);
+        let mut worker = BcosChannelWorker::new(configfile);
         let contract = ContractABI::new_by_name(
             contract_name,
             worker.bcossdk.config.common.contractpath.as_str(),
             &worker.bcossdk.hashtype,
         )
         .unwrap();
+        let eventmanager = EventLogManager::default();
+        //推送和注册/注销的回包都交给eventmanager，按filterID分发
+        for packtype in [
+            CHANNEL_PACK_TYPE::EVENT_LOG_PUSH,
+            CHANNEL_PACK_TYPE::CLIENT_REGISTER_EVENT_LOG,
+            CHANNEL_PACK_TYPE::CLIENT_UNREGISTER_EVENT_LOG,
+        ]
+        .iter()
+        {
+            worker.handlemanager.set_handle(
+                &(packtype.clone() as u16),
+                Arc::new(Mutex::new(eventmanager.clone())),
+            );
+        }
+        //重连后节点上的过滤条件已经丢失，重新注册一遍
+        let hookmanager = eventmanager.clone();
+        worker.add_reconnect_hook(Arc::new(move |channel_client| {
+            for req in hookmanager.requests().iter() {
+                let packdata = make_register_event_pack(req).pack();
+                if let Err(e) = channel_client.try_send(&packdata) {
+                    log::error!("re-register event filter {} error {:?}", req.filterID, e);
+                }
+            }
+        }));
 
         EventHandler {
-            contract: contract,
+            contract,
             worker: Arc::new(Mutex::new(worker)),
+            eventmanager,
         }
     }
-    pub fn register_eventlog_filter(
-        &mut self,
-        eventcallback: HANDLE_FACADE_OBJ,
-        address: &Vec<String>,
+
+    ///用本合约的事件定义生成过滤条件，群组取配置里的groupid，需要时再用with_block_range指定区块范围
+    pub fn make_event_filter(
+        &self,
+        address: &[String],
         event_name: &str,
         indexed_values: &Vec<&str>,
-    ) {
-        //注册回调的入口
-        self.worker
-            .lock().unwrap()
-            .handlemanager
-            .set_handle(&(CHANNEL_PACK_TYPE::EVENT_LOG_PUSH as u16), eventcallback);
+    ) -> Result<RegisterEventRequest, KissError> {
+        let groupid = self.worker.lock().unwrap().bcossdk.config.bcos2.groupid;
+        RegisterEventRequest::for_event(&self.contract, groupid, address, event_name, indexed_values)
+    }
+
+    ///发出注册请求，返回filterID。节点拒绝时由回调的on_error通知
+    pub fn register_eventlog_filter(
+        &mut self,
+        eventcallback: EVENT_CALLBACK_OBJ,
+        request: RegisterEventRequest,
+    ) -> Result<String, KissError> {
+        let filterid = request.filterID.clone();
+        let packdata = make_register_event_pack(&request).pack();
+        self.eventmanager.add(EventLogSubscription {
+            request,
+            contract: self.contract.clone(),
+            callback: eventcallback,
+        });
+        let res = self
+            .worker
+            .lock()
+            .unwrap()
+            .bcossdk
+            .netclient
+            .channel_client
+            .try_send(&packdata);
+        if let Err(e) = res {
+            self.eventmanager.remove(&filterid);
+            return Err(e);
+        }
+        Ok(filterid)
+    }
 
-        //构造注册消息监听的请求包json
-        let mut req = RegisterEventRequest::new();
-        req.addresses.append(&mut address.clone());
-        println!("event name is {}", event_name);
-        let eventres = self.contract.find_event_by_name(event_name);
-        //self.contract.print_event_namehash();
-        let event = match eventres {
-            Some(ev) => ev,
+    pub fn unregister_eventlog_filter(&mut self, filterid: &str) -> Result<(), KissError> {
+        let sub = match self.eventmanager.remove(filterid) {
+            Some(s) => s,
             None => {
-                //没有找到event定义，返回了
-                //println!("not found event for {}",event_name);
-                return;
+                return kisserr!(KissErrKind::EArgument, "event filter {} not found", filterid)
             }
         };
-        //这些打印都可以删掉
-        println!("event  is {:?}", event);
-        let evhash = self.contract.event_abi_utils.event_signature(&event);
-        req.topics.push(format!("0x{}", hex::encode(evhash)));
-        for t in &req.topics {
-            //println!("topic: {:?}",format!("0x{}",hex::encode(evhash)));
-        }
-        let indexedevents = self.contract.event_abi_utils.indexed_params(&event, true);
-        let mut i = 0;
-        for e in indexedevents {
-            if i >= indexed_values.len() {
-                break;
-            } //根据输入的参数个数
-              //将输入的参数值转换为indexed类型的topic
-            let topic = self
-                .contract
-                .event_abi_utils
-                .topic_by_indexed_params(&e.kind, &indexed_values.get(i).unwrap());
-            i = i + 1;
-            println!("topic is {},eventparam:{:?}", &topic, e);
-            //将topic加入到请求包体
-            req.topics.push(topic);
-        }
-        let reqencode = serde_json::to_string(&req).unwrap();
-        //println!("reqencode {}",reqencode);
-        //注册包是个amop类型的消息
-        let registerreqdata = channelpack::pack_amop(&Vec::from(""), &Vec::from(reqencode));
-        println!("register amop data : {:?}", registerreqdata);
-        //let (amoptopic ,amopdata ) = channelpack::unpack_amop(&registerreqdata);
-        //println!("amop data unpack {}",String::from_utf8(amopdata).unwrap());
-        let pack = channelpack::make_channel_pack_by_rawdata(
-            CHANNEL_PACK_TYPE::CLIENT_REGISTER_EVENT_LOG,
-            &registerreqdata,
-        );
-        let packdata = pack.unwrap().pack();
-        // 发送请求包
-        let mut worker = self.worker.lock().unwrap();
-        let res = worker.bcossdk.netclient.channel_client.try_send(&packdata);
-
-        //println!("register event result {:?},packdata {:?}",res,packdata);
+        let packdata = make_unregister_event_pack(&sub.request).pack();
+        self.worker
+            .lock()
+            .unwrap()
+            .bcossdk
+            .netclient
+            .channel_client
+            .try_send(&packdata)?;
+        Ok(())
     }
 }
 
-///事件回调演示，要实现IChannelPushHandlerFacade接口，包含的contract对象用来解析事件
-pub struct DemoEventHandler {
-    pub contract: ContractABI,
-}
+///事件回调演示，要实现IEventLogCallback接口，收到的日志已经按合约abi解析过
+pub struct DemoEventHandler {}
 
-impl DemoEventHandler {
-    pub fn new(ct: &ContractABI) -> Self {
-        DemoEventHandler {
-            contract: ct.clone(),
+///事件回调的处理，简单打印一下事件
+impl IEventLogCallback for DemoEventHandler {
+    fn on_logs(&self, filterid: &str, logs: &[ReceiptLog]) {
+        println!("!!!!!!!  EVENT CALL BACKUP filter {}", filterid);
+        for l in logs.iter() {
+            println!("{:?}", l);
         }
     }
 }
 
-///事件回调的处理，简单解析和打印一下事件
-impl IChannelPushHandlerFacade for DemoEventHandler {
-    fn handle(&self, pack: &ChannelPack) {
-        println!("!!!!!!!  EVENT CALL BACKUP {}", pack.detail());
-        let value: JsonValue =
-            serde_json::from_str(&String::from_utf8(pack.data.clone()).unwrap().as_str()).unwrap();
-        let parsed_logs = self.contract.parse_receipt_logs(&value["logs"]);
-        println!("{:?}", parsed_logs);
-    }
-}
-
 pub async fn event_demo(configfile: &str) -> Result<(), KissError> {
     let contract_name = "HelloEvent";
     let mut evh = EventHandler::new(configfile, contract_name);
@@ -190,19 +162,15 @@ pub async fn event_demo(configfile: &str) -> Result<(), KissError> {
         ContractHistory::get_last_from_file(history_file.as_str(), "bcos2", contract_name)?;
     println!("address is {}", address);
     println!("contract abi is {} ", evh.contract.abi_file);
-    //let handler = Arc::new(DemoEventHandler::new());
-    let demohandler = Arc::new(Mutex::new(DemoEventHandler::new(&evh.contract)));
+    let demohandler = Arc::new(DemoEventHandler {});
     let event_name = "on_two_indexed"; //具体定义参见合约sol文件和abi
                                        //event_name = "on_set";
                                        //event里的indexed类型的参数，传入时全部用字符串形式
     let indexed_value = vec!["5", "key123"];
     //注册事件监听
-    evh.register_eventlog_filter(
-        demohandler,
-        &vec![address.clone()],
-        event_name,
-        &indexed_value,
-    );
+    let filter = evh.make_event_filter(&[address.clone()], event_name, &indexed_value)?;
+    let filterid = evh.register_eventlog_filter(demohandler, filter)?;
+    println!("register event filter {}", filterid);
 
     println!("\n>>>>>>>>>>>>>>>>>>>>demo helloEvent settwo");
     //发个交易触发一下事件
@@ -220,11 +188,8 @@ pub async fn event_demo(configfile: &str) -> Result<(), KissError> {
     );
     println!("send_raw_transaction result {:?}", txres);
 
-    println!("go start worker");
+    println!("go start worker, ctrl-c to quit");
     bcos_channel_threads_worker::start_bcos_channel_worker(&evh.worker).await;
-    loop {
-        thread::sleep(Duration::from_secs(1));
-        // tokio::time::sleep(Duration::from_micros(500)).await;
-    }
-    //Ok(())
+    evh.unregister_eventlog_filter(filterid.as_str())?;
+    Ok(())
 }
@@ -14,15 +14,24 @@ pub mod bcoshttpclient;
 pub mod bcosrpcwraper;
 pub mod bcossdkquery;
 
+pub mod bcos_blocknumber_notify;
+pub mod bcos_channel_amop;
+pub mod bcos_channel_amop_private;
+pub mod bcos_channel_async_client;
 pub mod bcos_channel_client;
+pub mod bcos_channel_recorder;
 pub mod bcos_channel_threads_worker;
 pub mod bcos_ssl_native;
 pub mod bcos_ssl_normal;
+pub mod bcos_ssl_tlcp;
 pub mod bcossdkgroup;
 pub mod bcostransaction;
 
 pub mod bcos2_ssl_ffi;
 pub mod bcos_channel_handler_manager;
+pub mod bcos_channel_peers;
+pub mod bcos_event_manager;
 pub mod bcos_channel_tassl_sock_ffi;
+pub mod bcos_tlcp_util;
 pub mod channelpack;
 pub mod eventhandler;
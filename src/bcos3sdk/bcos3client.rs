@@ -1,5 +1,5 @@
 /*包装一次bcos3sdkwrapper里的ffi方法，映射成rust的典型写法，并将各方法采用同步方式实现
-  异步方式有待todo，需要异步方式的，可以参考回调函数定义来实现
+  异步(async)的版本见bcos3client_async.rs
 */
 
 use std::ffi::{CStr, CString};
@@ -13,11 +13,13 @@ use time::{Duration, Tm};
 
 use crate::{kisserr, kisserrcode, str2p};
 use crate::bcos3sdk::bcos3sdk_ini::Bcos3sdkIni;
+use crate::bcos3sdk::bcos3sdkcallback;
 use crate::bcos3sdk::bcos3sdkfuture::Bcos3SDKFuture;
+use crate::bcos3sdk::bcos3sdkresponse::Bcos3SDKResponse;
 use crate::bcos3sdk::bcos3sdkwrapper::*;
 use crate::bcos3sdk::bcos3sdkwrapper::bcos3sdk_def::*;
 use crate::bcossdkutil::accountutil::{account_from_pem, BcosAccount};
-use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ClientConfig};
+use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ClientConfig, RequestPolicy};
 use crate::bcossdkutil::commonhash::{CommonHash, HashType};
 use crate::bcossdkutil::contractabi::ContractABI;
 use crate::bcossdkutil::fileutils;
@@ -39,6 +41,32 @@ pub struct Bcos3Client {
     pub reqcounter: AtomicU64,
     pub lastblocklimittime: Tm,
     pub lastblocklimit: u64,
+    pub policy: RequestPolicy, //当前生效的请求策略，初始为配置里的policy
+}
+
+///一个事件订阅，subid是c sdk返回的订阅id，handle是回调登记表里的句柄
+#[derive(Debug)]
+pub struct Bcos3EventSubscription {
+    pub subid: String,
+    pub handle: u64,
+}
+
+//sdk和keypair是c sdk的句柄，c sdk的接口本身是线程安全的；回调context走登记表句柄，不再指向栈上的对象
+unsafe impl Send for Bcos3Client {}
+unsafe impl Sync for Bcos3Client {}
+
+//with_policy用的恢复策略的守卫，离开作用域时把保存的策略设置回去
+struct Bcos3PolicyGuard<'a> {
+    client: &'a mut Bcos3Client,
+    saved: Option<RequestPolicy>,
+}
+
+impl Drop for Bcos3PolicyGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            self.client.policy = saved;
+        }
+    }
 }
 
 impl Bcos3Client {
@@ -86,8 +114,13 @@ impl Bcos3Client {
         }
     }
     pub fn new(configfile: &str) -> Result<Self, KissError> {
+        Bcos3Client::new_with_config(ClientConfig::load(configfile)?)
+    }
+
+    ///用已经构造好的配置创建client，c sdk本身仍然需要bcos3.sdk_config_file指向的ini文件
+    pub fn new_with_config(config: ClientConfig) -> Result<Self, KissError> {
         unsafe {
-            let config = ClientConfig::load(configfile).unwrap();
+            load_bcos3sdk_lib(config.bcos3.sdk_lib_path.as_str())?;
             let sdk = init_bcos3sdk_lib(config.bcos3.sdk_config_file.as_str());
             if sdk == 0 as *const c_void {
                 return kisserr!(KissErrKind::Error,"BCOS3 C LIB is NOT init;ERROR:{}:{}",bcos_sdk_get_last_error(),Bcos3Client::getLastErrMessage());
@@ -120,6 +153,7 @@ impl Bcos3Client {
                 sdk: sdk,
                 group: config.bcos3.group.clone(),
                 chainid: "chain0".to_string(),
+                policy: config.policy.clone(),
                 config: config,
                 bcos3sdkini: bcos3sdkini,
                 keypair: keypair,
@@ -144,32 +178,68 @@ impl Bcos3Client {
         }
     }
 
+    ///临时换一个请求策略执行一次调用，调用完恢复原来的策略
+    pub fn with_policy<T, F>(&mut self, policy: RequestPolicy, f: F) -> T
+    where
+        F: FnOnce(&mut Self) -> T,
+    {
+        let saved = std::mem::replace(&mut self.policy, policy);
+        //f里panic时也要恢复原来的策略
+        let guard = Bcos3PolicyGuard {
+            client: self,
+            saved: Some(saved),
+        };
+        f(&mut *guard.client)
+    }
+
+    ///切换到同一个c sdk连接上的另一个群组，切换后blocklimit缓存作废
+    pub fn set_group(&mut self, group: &str) {
+        if self.group == group {
+            return;
+        }
+        self.group = group.to_string();
+        self.config.bcos3.group = group.to_string();
+        self.lastblocklimit = 0;
+    }
+
+    //发出一次请求并同步等待回调，issue里调用c sdk的接口，把回调函数和context传进去
+    fn request_once<F>(&self, name: &str, timeout: u64, issue: F) -> Result<JsonValue, KissError>
+    where
+        F: Fn(*const c_void),
+    {
+        let mut cbfuture = Bcos3SDKFuture::create(Bcos3SDKFuture::next_seq(), name, "");
+        cbfuture.timeout = timeout;
+        issue(Bcos3SDKFuture::to_c_ptr(&cbfuture));
+        cbfuture.wait_result()
+    }
+
+    //查询类的请求，超时和网络错误按请求策略重试
+    fn request_sync<F>(&self, name: &str, issue: F) -> Result<JsonValue, KissError>
+    where
+        F: Fn(*const c_void),
+    {
+        self.policy.retry(|| self.request_once(name, self.policy.timeout, &issue))
+    }
+
     pub fn getBlockNumber(&self) -> Result<u64, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getBlockNumber",
-                format!("").as_str(),
-            );
+        let result = self.request_sync("getBlockNumber", |context| unsafe {
             bcos_rpc_get_block_number(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-
-            let result = cbfuture.wait_result()?;
-            let num_option = result.as_u64();
-
-            match num_option {
-                Some(num) => {
-                    return Ok(num);
-                }
-                None => {
-                    return kisserr!(KissErrKind::Error, "getBlockNumber from result error");
-                }
+                context,
+            )
+        })?;
+        let num_option = result.as_u64();
+
+        match num_option {
+            Some(num) => {
+                return Ok(num);
+            }
+            None => {
+                return kisserr!(KissErrKind::Error, "getBlockNumber from result error");
             }
         }
     }
@@ -208,158 +278,122 @@ impl Bcos3Client {
 
     pub fn getPbftView(&self) -> Result<u64, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getPbftView",
-                format!("").as_str(),
-            );
+        let result = self.request_sync("getPbftView", |context| unsafe {
             bcos_rpc_get_pbft_view(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            let result = cbfuture.wait_result()?;
-            let num_option = result.as_u64();
-            match num_option {
-                Some(num) => {
-                    return Ok(num);
-                }
-                None => {
-                    return kisserr!(KissErrKind::Error, "getPbftView from result error");
-                }
+                context,
+            )
+        })?;
+        let num_option = result.as_u64();
+        match num_option {
+            Some(num) => {
+                return Ok(num);
+            }
+            None => {
+                return kisserr!(KissErrKind::Error, "getPbftView from result error");
             }
         }
     }
 
     pub fn getSealerList(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getPbftView",
-                format!("").as_str(),
-            );
+        self.request_sync("getPbftView", |context| unsafe {
             bcos_rpc_get_sealer_list(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getObserverList(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getObserverList",
-                format!("").as_str(),
-            );
+        self.request_sync("getObserverList", |context| unsafe {
             bcos_rpc_get_observer_list(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getConsensusStatus(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getConsensusStatus",
-                format!("").as_str(),
-            );
+        self.request_sync("getConsensusStatus", |context| unsafe {
             bcos_rpc_get_consensus_status(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getSyncStatus(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getSyncStatus",
-                format!("").as_str(),
-            );
+        self.request_sync("getSyncStatus", |context| unsafe {
             bcos_rpc_get_sync_status(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getPeers(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getPeers",
-                format!("").as_str(),
-            );
+        self.request_sync("getPeers", |context| unsafe {
             bcos_rpc_get_peers(
                 self.sdk,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getGroupPeers(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getGroupPeers",
-                format!("").as_str(),
-            );
+        self.request_sync("getGroupPeers", |context| unsafe {
             bcos_rpc_get_group_peers(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getGroupList(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getGroupList",
-                format!("").as_str(),
-            );
+        self.request_sync("getGroupList", |context| unsafe {
             bcos_rpc_get_group_list(
                 self.sdk,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
+    }
+
+    pub fn getGroupInfo(&self) -> Result<JsonValue, KissError> {
+        self.reqcounter.fetch_add(1, Ordering::Relaxed);
+        self.request_sync("getGroupInfo", |context| unsafe {
+            bcos_rpc_get_group_info(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
+                context,
+            )
+        })
     }
 
     pub fn getBlockByHash(
@@ -369,12 +403,7 @@ impl Bcos3Client {
         only_tx_hash: u32,
     ) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getBlockByHash",
-                format!("").as_str(),
-            );
+        self.request_sync("getBlockByHash", |context| unsafe {
             bcos_rpc_get_block_by_hash(
                 self.sdk,
                 str2p!(self.group.as_str()),
@@ -383,10 +412,9 @@ impl Bcos3Client {
                 only_header as c_int,
                 only_tx_hash as c_int,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getBlockByNumber(
@@ -396,12 +424,7 @@ impl Bcos3Client {
         only_tx_hash: u32,
     ) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getBlockByNumber",
-                format!("").as_str(),
-            );
+        self.request_sync("getBlockByNumber", |context| unsafe {
             bcos_rpc_get_block_by_number(
                 self.sdk,
                 str2p!(self.group.as_str()),
@@ -410,62 +433,43 @@ impl Bcos3Client {
                 only_header as c_int,
                 only_tx_hash as c_int,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getBlockHashByNumber(&self, num: u64) -> Result<String, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getBlockHashByNumber",
-                format!("").as_str(),
-            );
+        let v = self.request_sync("getBlockHashByNumber", |context| unsafe {
             bcos_rpc_get_block_hash_by_number(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 num as c_longlong,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-
-            let v = cbfuture.wait_result()?;
-            //println!("block v {:?}",v);
-            let hash = v.as_str().unwrap();
-            return Ok(hash.to_string());
-        }
+                context,
+            )
+        })?;
+        //println!("block v {:?}",v);
+        let hash = v.as_str().unwrap();
+        return Ok(hash.to_string());
     }
     pub fn getTotalTransactionCount(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getTotalTransactionCount",
-                format!("").as_str(),
-            );
+        self.request_sync("getTotalTransactionCount", |context| unsafe {
             bcos_rpc_get_total_transaction_count(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getTransactionByHash(&self, hash: &str, proof: i32) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getTransactionByHash",
-                format!("").as_str(),
-            );
+        self.request_sync("getTransactionByHash", |context| unsafe {
             bcos_rpc_get_transaction(
                 self.sdk,
                 str2p!(self.group.as_str()),
@@ -473,20 +477,14 @@ impl Bcos3Client {
                 str2p!(hash),
                 proof as c_int,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getTransactionReceipt(&self, hash: &str, proof: i32) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getTransactionReceipt",
-                format!("").as_str(),
-            );
+        self.request_sync("getTransactionReceipt", |context| unsafe {
             bcos_rpc_get_transaction_receipt(
                 self.sdk,
                 str2p!(self.group.as_str()),
@@ -494,66 +492,50 @@ impl Bcos3Client {
                 str2p!(hash),
                 proof,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getPendingTxSize(&self) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getPendingTxSize",
-                format!("").as_str(),
-            );
+        self.request_sync("getPendingTxSize", |context| unsafe {
             bcos_rpc_get_pending_tx_size(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getCode(&self, address: &str) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture =
-                Bcos3SDKFuture::create(Bcos3SDKFuture::next_seq(), "getCode", format!("").as_str());
+        self.request_sync("getCode", |context| unsafe {
             bcos_rpc_get_code(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 str2p!(address),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn getSystemConfigByKey(&self, key: &str) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "getSystemConfigByKey",
-                format!("").as_str(),
-            );
+        self.request_sync("getSystemConfigByKey", |context| unsafe {
             bcos_rpc_get_system_config_by_key(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 str2p!(self.node.as_str()),
                 str2p!(key),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            return cbfuture.wait_result();
-        }
+                context,
+            )
+        })
     }
 
     pub fn call(
@@ -564,44 +546,30 @@ impl Bcos3Client {
         abi: &ContractABI,
     ) -> Result<JsonValue, KissError> {
         self.reqcounter.fetch_add(1, Ordering::Relaxed);
-        unsafe {
-            let functiondata = abi
-                .encode_function_input_to_abi(funcname, &paramsvec, true)
-                .unwrap();
-            let seq = 0;
-            let cbfuture = Bcos3SDKFuture::create(Bcos3SDKFuture::next_seq(), funcname, "do call");
+        let functiondata = abi.encode_function_input_to_abi(funcname, &paramsvec, true)?;
+        self.request_sync(funcname, |context| unsafe {
             bcos_rpc_call(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 0 as *const c_char,
                 str2p!(to),
-                str2p!(functiondata),
+                str2p!(functiondata.as_str()),
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-
-            let result = cbfuture.wait_result()?;
-            Ok(result)
-        }
+                context,
+            )
+        })
     }
 
-    pub fn sendRawTransaction(
+    ///用客户端的私钥签名交易，返回签名后的交易(hex)，发送前才调用，以便拿到最新的blocklimit
+    pub fn create_signed_transaction(
         &mut self,
         to_address: &str,
-        methodname: &str,
         functiondata: &str,
-    ) -> Result<JsonValue, KissError> {
-        self.reqcounter.fetch_add(1, Ordering::Relaxed);
+    ) -> Result<String, KissError> {
         unsafe {
-            let cbfuture = Bcos3SDKFuture::create(
-                Bcos3SDKFuture::next_seq(),
-                "sendTransction",
-                format!("{}", methodname).as_str(),
-            );
-
             //println!("function data len {}, {}", functiondata.len(), functiondata);
-            let p_txhash = Box::into_raw(Box::new(0 as *mut c_char));
-            let p_signed_tx = Box::into_raw(Box::new(0 as *mut c_char));
+            let mut p_txhash = 0 as *mut c_char;
+            let mut p_signed_tx = 0 as *mut c_char;
             let blocklimit = self.getBlocklimit()?;
 
             bcos_sdk_create_signed_transaction(
@@ -613,35 +581,45 @@ impl Bcos3Client {
                 str2p!(""),
                 blocklimit as c_longlong,
                 0,
-                p_txhash,
-                p_signed_tx,
+                &mut p_txhash,
+                &mut p_signed_tx,
             );
             let lasterr = Bcos3Client::getLastError();
             if lasterr != 0 {
                 let last_err_msg = Bcos3Client::getLastErrMessage();
                 return kisserrcode!(KissErrKind::Error, lasterr as i64, "{}", last_err_msg);
             }
-            let txhash_str = CStr::from_ptr(*p_txhash);
-            let signed_tx_str = CStr::from_ptr(*p_signed_tx);
+            let signed_tx = CStr::from_ptr(p_signed_tx).to_string_lossy().to_string();
+            //println!("txhash {:?}", CStr::from_ptr(p_txhash));
+            bcos_sdk_c_free(p_txhash as *const c_void);
+            bcos_sdk_c_free(p_signed_tx as *const c_void);
+            Ok(signed_tx)
+        }
+    }
 
-            //println!("txhash {:?}", txhash_str);
-            //println!("signed_tx {:?}", signed_tx_str);
+    pub fn sendRawTransaction(
+        &mut self,
+        to_address: &str,
+        methodname: &str,
+        functiondata: &str,
+    ) -> Result<JsonValue, KissError> {
+        self.reqcounter.fetch_add(1, Ordering::Relaxed);
+        let signed_tx = self.create_signed_transaction(to_address, functiondata)?;
+        //发交易不是幂等的，不重试，等待回执的超时用请求策略的receipt_timeout
+        let timeout = self.policy.receipt_timeout;
+        let result = self.request_once("sendTransction", timeout, |context| unsafe {
             bcos_rpc_send_transaction(
                 self.sdk,
                 str2p!(self.group.as_str()),
                 0 as *const c_char,
-                *p_signed_tx,
+                str2p!(signed_tx.as_str()),
                 0,
                 Bcos3SDKFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
-                Bcos3SDKFuture::to_c_ptr(&cbfuture),
-            );
-            bcos_sdk_c_free(*p_txhash as *const c_void);
-            bcos_sdk_c_free(*p_signed_tx as *const c_void);
-
-            let result = cbfuture.wait_result()?;
+                context,
+            )
+        })?;
 
-            Ok(result)
-        }
+        Ok(result)
     }
 
     pub fn sendTransaction(
@@ -708,4 +686,67 @@ impl Bcos3Client {
         );
         self.deploy_file(binfile.as_str(), paramcode.as_str())
     }
+
+    //-----------------------------------------------------------------------------------
+    ///订阅当前群组的合约事件。handler由回调登记表持有，每次推送都在c sdk的回调线程里调用，
+    ///直到unsubscribe_event。订阅失败时句柄马上注销，handler随之释放
+    pub fn subscribe_event<F>(
+        &self,
+        param: &EventSubParam,
+        handler: F,
+    ) -> Result<Bcos3EventSubscription, KissError>
+    where
+        F: Fn(Bcos3SDKResponse) + Send + Sync + 'static,
+    {
+        let paramstr = match serde_json::to_string(param) {
+            Ok(s) => s,
+            Err(e) => return kisserr!(KissErrKind::EFormat, "event sub param error {:?}", e),
+        };
+        let handle = bcos3sdkcallback::register_callback(handler);
+        unsafe {
+            let p_subid = bcos_event_sub_subscribe_event(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(paramstr.as_str()),
+                bcos3sdkcallback::bcos_callback as BCOS3SDK_CALLBACK_FUNC,
+                bcos3sdkcallback::handle_to_context(handle),
+            );
+            let lasterr = Bcos3Client::getLastError();
+            if lasterr != 0 || p_subid.is_null() {
+                bcos3sdkcallback::unregister_callback(handle);
+                if !p_subid.is_null() {
+                    bcos_sdk_c_free(p_subid as *const c_void);
+                }
+                return kisserrcode!(
+                    KissErrKind::Error,
+                    lasterr as i64,
+                    "subscribe event error: {}",
+                    Bcos3Client::getLastErrMessage()
+                );
+            }
+            //订阅id是c sdk新分配的字符串，复制后释放
+            let subid = CStr::from_ptr(p_subid).to_string_lossy().to_string();
+            bcos_sdk_c_free(p_subid as *const c_void);
+            Ok(Bcos3EventSubscription { subid, handle })
+        }
+    }
+
+    ///取消订阅并注销回调句柄，之后迟到的推送会被登记表丢弃。c sdk报错时句柄也已经注销
+    pub fn unsubscribe_event(&self, sub: Bcos3EventSubscription) -> Result<(), KissError> {
+        let lasterr = unsafe {
+            bcos_event_sub_unsubscribe_event(self.sdk, str2p!(sub.subid.as_str()));
+            Bcos3Client::getLastError()
+        };
+        bcos3sdkcallback::unregister_callback(sub.handle);
+        if lasterr != 0 {
+            return kisserrcode!(
+                KissErrKind::Error,
+                lasterr as i64,
+                "unsubscribe event {} error: {}",
+                sub.subid,
+                Bcos3Client::getLastErrMessage()
+            );
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,462 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! Bcos3Client的async版本，方法名在同步版本后加Async
+//! c sdk本身是回调式的，发出请求后用Bcos3SDKAsyncFuture等回调，不占用tokio的工作线程
+//! 超时和重试按客户端的请求策略(policy)
+use std::ffi::{c_void, CString};
+use std::sync::atomic::Ordering;
+
+use libc::{c_char, c_int, c_longlong};
+use serde_json::Value as JsonValue;
+
+use crate::bcos3sdk::bcos3client::Bcos3Client;
+use crate::bcos3sdk::bcos3sdkfuture::{Bcos3SDKAsyncFuture, Bcos3SDKFuture};
+use crate::bcos3sdk::bcos3sdkwrapper::bcos3sdk_def::*;
+use crate::bcos3sdk::bcos3sdkwrapper::BCOS3SDK_CALLBACK_FUNC;
+use crate::bcossdkutil::commonhash::CommonHash;
+use crate::bcossdkutil::contractabi::ContractABI;
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::{kisserr, str2p};
+
+//issue里调用c sdk的接口，把回调函数和context传进去
+fn callback_func() -> BCOS3SDK_CALLBACK_FUNC {
+    Bcos3SDKAsyncFuture::bcos_callback as BCOS3SDK_CALLBACK_FUNC
+}
+
+fn result_to_u64(name: &str, result: &JsonValue) -> Result<u64, KissError> {
+    match result.as_u64() {
+        Some(num) => Ok(num),
+        None => kisserr!(KissErrKind::Error, "{} from result error", name),
+    }
+}
+
+impl Bcos3Client {
+    //发出一次请求，等待回调的超时为timeout秒
+    fn request_once_async<F>(&self, name: &str, timeout: u64, issue: F) -> Bcos3SDKAsyncFuture
+    where
+        F: Fn(*const c_void),
+    {
+        self.reqcounter.fetch_add(1, Ordering::Relaxed);
+        let (mut future, context) = Bcos3SDKAsyncFuture::create(Bcos3SDKFuture::next_seq(), name);
+        future.timeout = timeout;
+        issue(context);
+        future
+    }
+
+    //查询类的请求，超时和网络错误按请求策略重试，每次重试都重新发出请求
+    async fn request_async<F>(&self, name: &str, issue: F) -> Result<JsonValue, KissError>
+    where
+        F: Fn(*const c_void),
+    {
+        self.policy
+            .retry_async(|| self.request_once_async(name, self.policy.timeout, &issue))
+            .await
+    }
+
+    pub async fn getBlockNumberAsync(&self) -> Result<u64, KissError> {
+        let result = self
+            .request_async("getBlockNumber", |ctx| unsafe {
+                bcos_rpc_get_block_number(
+                    self.sdk,
+                    str2p!(self.group.as_str()),
+                    str2p!(self.node.as_str()),
+                    callback_func(),
+                    ctx,
+                )
+            })
+            .await?;
+        result_to_u64("getBlockNumber", &result)
+    }
+
+    pub async fn getPbftViewAsync(&self) -> Result<u64, KissError> {
+        let result = self
+            .request_async("getPbftView", |ctx| unsafe {
+                bcos_rpc_get_pbft_view(
+                    self.sdk,
+                    str2p!(self.group.as_str()),
+                    str2p!(self.node.as_str()),
+                    callback_func(),
+                    ctx,
+                )
+            })
+            .await?;
+        result_to_u64("getPbftView", &result)
+    }
+
+    pub async fn getSealerListAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getSealerList", |ctx| unsafe {
+            bcos_rpc_get_sealer_list(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getObserverListAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getObserverList", |ctx| unsafe {
+            bcos_rpc_get_observer_list(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getConsensusStatusAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getConsensusStatus", |ctx| unsafe {
+            bcos_rpc_get_consensus_status(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getSyncStatusAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getSyncStatus", |ctx| unsafe {
+            bcos_rpc_get_sync_status(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getPeersAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getPeers", |ctx| unsafe {
+            bcos_rpc_get_peers(self.sdk, callback_func(), ctx)
+        })
+        .await
+    }
+
+    pub async fn getGroupPeersAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getGroupPeers", |ctx| unsafe {
+            bcos_rpc_get_group_peers(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getGroupListAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getGroupList", |ctx| unsafe {
+            bcos_rpc_get_group_list(self.sdk, callback_func(), ctx)
+        })
+        .await
+    }
+
+    pub async fn getGroupInfoAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getGroupInfo", |ctx| unsafe {
+            bcos_rpc_get_group_info(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getBlockByHashAsync(
+        &self,
+        block_hash: &str,
+        only_header: u32,
+        only_tx_hash: u32,
+    ) -> Result<JsonValue, KissError> {
+        self.request_async("getBlockByHash", |ctx| unsafe {
+            bcos_rpc_get_block_by_hash(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                str2p!(block_hash),
+                only_header as c_int,
+                only_tx_hash as c_int,
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getBlockByNumberAsync(
+        &self,
+        num: u64,
+        only_header: u32,
+        only_tx_hash: u32,
+    ) -> Result<JsonValue, KissError> {
+        self.request_async("getBlockByNumber", |ctx| unsafe {
+            bcos_rpc_get_block_by_number(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                num as c_longlong,
+                only_header as c_int,
+                only_tx_hash as c_int,
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getBlockHashByNumberAsync(&self, num: u64) -> Result<String, KissError> {
+        let result = self
+            .request_async("getBlockHashByNumber", |ctx| unsafe {
+                bcos_rpc_get_block_hash_by_number(
+                    self.sdk,
+                    str2p!(self.group.as_str()),
+                    str2p!(self.node.as_str()),
+                    num as c_longlong,
+                    callback_func(),
+                    ctx,
+                )
+            })
+            .await?;
+        match result.as_str() {
+            Some(hash) => Ok(hash.to_string()),
+            None => kisserr!(KissErrKind::EFormat, "bad block hash {:?}", result),
+        }
+    }
+
+    pub async fn getTotalTransactionCountAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getTotalTransactionCount", |ctx| unsafe {
+            bcos_rpc_get_total_transaction_count(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getTransactionByHashAsync(
+        &self,
+        hash: &str,
+        proof: i32,
+    ) -> Result<JsonValue, KissError> {
+        self.request_async("getTransactionByHash", |ctx| unsafe {
+            bcos_rpc_get_transaction(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                str2p!(hash),
+                proof as c_int,
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getTransactionReceiptAsync(
+        &self,
+        hash: &str,
+        proof: i32,
+    ) -> Result<JsonValue, KissError> {
+        self.request_async("getTransactionReceipt", |ctx| unsafe {
+            bcos_rpc_get_transaction_receipt(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                str2p!(hash),
+                proof,
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getPendingTxSizeAsync(&self) -> Result<JsonValue, KissError> {
+        self.request_async("getPendingTxSize", |ctx| unsafe {
+            bcos_rpc_get_pending_tx_size(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getCodeAsync(&self, address: &str) -> Result<JsonValue, KissError> {
+        self.request_async("getCode", |ctx| unsafe {
+            bcos_rpc_get_code(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                str2p!(address),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn getSystemConfigByKeyAsync(&self, key: &str) -> Result<JsonValue, KissError> {
+        self.request_async("getSystemConfigByKey", |ctx| unsafe {
+            bcos_rpc_get_system_config_by_key(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                str2p!(self.node.as_str()),
+                str2p!(key),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn callAsync(
+        &self,
+        to: &str,
+        funcname: &str,
+        params: &[String],
+        abi: &ContractABI,
+    ) -> Result<JsonValue, KissError> {
+        let functiondata = abi.encode_function_input_to_abi(funcname, params, true)?;
+        self.request_async(funcname, |ctx| unsafe {
+            bcos_rpc_call(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                std::ptr::null(),
+                str2p!(to),
+                str2p!(functiondata.as_str()),
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    ///签名在调用线程上同步完成(本地计算，blocklimit有缓存)，之后异步等待回执
+    pub async fn sendRawTransactionAsync(
+        &mut self,
+        to_address: &str,
+        methodname: &str,
+        functiondata: &str,
+    ) -> Result<JsonValue, KissError> {
+        let signed_tx = self.create_signed_transaction(to_address, functiondata)?;
+        //发交易不重试，等待回执的超时用请求策略的receipt_timeout
+        let timeout = self.policy.receipt_timeout;
+        self.request_once_async(methodname, timeout, |ctx| unsafe {
+            bcos_rpc_send_transaction(
+                self.sdk,
+                str2p!(self.group.as_str()),
+                std::ptr::null(),
+                str2p!(signed_tx.as_str()),
+                0,
+                callback_func(),
+                ctx,
+            )
+        })
+        .await
+    }
+
+    pub async fn sendTransactionAsync(
+        &mut self,
+        to_address: &str,
+        methodname: &str,
+        params: &[String],
+        contract: &ContractABI,
+    ) -> Result<JsonValue, KissError> {
+        let functiondata = contract.encode_function_input_to_abi(methodname, params, true)?;
+        self.sendRawTransactionAsync(to_address, methodname, functiondata.as_str())
+            .await
+    }
+
+    pub async fn deploy_hexcode_async(&mut self, hexcode: &str) -> Result<JsonValue, KissError> {
+        self.sendRawTransactionAsync("", "", hexcode).await
+    }
+
+    pub async fn deploy_file_async(
+        &mut self,
+        binfile: &str,
+        params: &str,
+    ) -> Result<JsonValue, KissError> {
+        let hexcode = fileutils::readstring(binfile)?;
+        let codewithparam = format!("{}{}", hexcode, params); //追加参数
+        self.deploy_hexcode_async(codewithparam.as_str()).await
+    }
+
+    pub async fn deploy_code_withparam_async(
+        &mut self,
+        hexcode: &str,
+        contractname: &str,
+        params_array: &[String],
+    ) -> Result<JsonValue, KissError> {
+        let paramcode = self.encode_constructor_params(contractname, params_array)?;
+        let codewithparam = format!("{}{}", hexcode, paramcode); //追加参数
+        self.deploy_hexcode_async(codewithparam.as_str()).await
+    }
+
+    pub async fn deploy_withparam_async(
+        &mut self,
+        contractname: &str,
+        params_array: &[String],
+    ) -> Result<JsonValue, KissError> {
+        let paramcode = self.encode_constructor_params(contractname, params_array)?;
+        let binfile = format!("{}/{}.bin", self.config.common.contractpath, contractname);
+        self.deploy_file_async(binfile.as_str(), paramcode.as_str())
+            .await
+    }
+
+    fn encode_constructor_params(
+        &self,
+        contractname: &str,
+        params_array: &[String],
+    ) -> Result<String, KissError> {
+        let contract = ContractABI::new_by_name(
+            contractname,
+            self.config.common.contractpath.as_str(),
+            &CommonHash::crypto_to_hashtype(&self.config.common.crypto),
+        )?;
+        match contract.encode_construtor_input(vec![], params_array, true) {
+            Ok(code) => Ok(code),
+            Err(e) => kisserr!(
+                KissErrKind::EFormat,
+                "encode constructor of {} error {:?}",
+                contractname,
+                e
+            ),
+        }
+    }
+}
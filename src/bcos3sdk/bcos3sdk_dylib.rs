@@ -0,0 +1,291 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::missing_safety_doc,
+    clippy::too_many_arguments,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    unused_variables
+)]
+//! 运行时用libloading加载bcos-c-sdk动态库，代替编译时链接。
+//! 函数名和签名与bcos3sdkwrapper::bcos3sdk_def一致，开启bcos3sdk_dylib特性时bcos3sdk_def就是这个模块。
+//! 库路径由load_library指定(配置里的bcos3.sdk_lib_path)，没有指定时按平台的默认库名在系统路径里找。
+//! 加载时一次解析全部符号，缺少的符号会在错误信息里全部列出；库没有加载成功时调用接口什么都不做，
+//! 返回空指针或0，last error里是加载失败的原因。只用BCOS2的程序不调用这里的接口，也就不需要这个库
+use std::ffi::{CStr, CString};
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+use libc::{c_char, c_int, c_longlong, c_ulong, c_void};
+use libloading::Library;
+
+use crate::bcos3sdk::bcos3sdkwrapper::{BCOS3SDK_AMOP_SUB_CALLBACK_FUNC, BCOS3SDK_CALLBACK_FUNC};
+use crate::bcossdkutil::commonutil::{detect_os, OSKind};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+///库没有加载时接口返回的错误码
+pub const BCOS3SDK_DYLIB_NOT_LOADED: c_int = -32998;
+
+///库没有加载时各接口的返回值
+pub trait DylibDefault {
+    fn dylib_default() -> Self;
+}
+impl DylibDefault for () {
+    fn dylib_default() -> Self {}
+}
+impl DylibDefault for c_int {
+    fn dylib_default() -> Self {
+        0
+    }
+}
+impl DylibDefault for c_longlong {
+    fn dylib_default() -> Self {
+        0
+    }
+}
+impl<T> DylibDefault for *const T {
+    fn dylib_default() -> Self {
+        std::ptr::null()
+    }
+}
+
+lazy_static! {
+    static ref BCOS3SDK_LIB: Mutex<Option<Arc<Bcos3SdkLib>>> = Mutex::new(None);
+    //最近一次加载失败的原因，库没有加载时作为last error返回。
+    //每次失败时泄漏一个新串，返回给调用方的指针在进程内一直有效
+    static ref BCOS3SDK_LOAD_ERROR: Mutex<&'static CStr> =
+        Mutex::new(Box::leak(CString::default().into_boxed_c_str()));
+}
+
+///按平台的默认库名，由系统的动态库搜索路径去找
+pub fn default_lib_name() -> String {
+    match detect_os() {
+        OSKind::windows => "bcos-c-sdk.dll".to_string(),
+        OSKind::macos => "libbcos-c-sdk.dylib".to_string(),
+        _ => "libbcos-c-sdk.so".to_string(),
+    }
+}
+
+///加载动态库并设为全局使用的库，path为空时用默认库名。
+///已经加载了同一个路径或path为空时直接返回；已经加载后不能换成别的库，
+///因为已创建的sdk句柄和它的工作线程都属于原来的库，卸载后再用是未定义行为
+pub fn load_library(path: &str) -> Result<(), KissError> {
+    let mut current = BCOS3SDK_LIB.lock().unwrap();
+    if let Some(lib) = current.as_ref() {
+        if path.is_empty() || lib.path == path {
+            return Ok(());
+        }
+        return kisserr!(
+            KissErrKind::EArgument,
+            "bcos-c-sdk already loaded from [{}], can not load [{}]",
+            lib.path,
+            path
+        );
+    }
+    let path = if path.is_empty() {
+        default_lib_name()
+    } else {
+        path.to_string()
+    };
+    match Bcos3SdkLib::open(&path) {
+        Ok(lib) => {
+            *current = Some(Arc::new(lib));
+            Ok(())
+        }
+        Err(e) => {
+            let msg = CString::new(e.msg.replace('\0', " ")).unwrap_or_default();
+            *BCOS3SDK_LOAD_ERROR.lock().unwrap() = Box::leak(msg.into_boxed_c_str());
+            Err(e)
+        }
+    }
+}
+
+pub fn is_library_loaded() -> bool {
+    BCOS3SDK_LIB.lock().unwrap().is_some()
+}
+
+///取当前的库，没有加载过时按默认库名加载一次
+fn current_lib() -> Option<Arc<Bcos3SdkLib>> {
+    if let Some(lib) = BCOS3SDK_LIB.lock().unwrap().as_ref() {
+        return Some(lib.clone());
+    }
+    load_library("").ok()?;
+    BCOS3SDK_LIB.lock().unwrap().clone()
+}
+
+macro_rules! bcos3_dylib_api {
+    (
+        manual { $( fn $mname:ident ( $($marg:ident : $mty:ty),* ) $(-> $mret:ty)? ; )* }
+        $( fn $name:ident ( $($arg:ident : $ty:ty),* ) $(-> $ret:ty)? ; )*
+    ) => {
+        ///从动态库里解析出的函数指针，Library和指针一起保存，保证指针在使用期间有效
+        pub struct Bcos3SdkLib {
+            pub path: String,
+            lib: Library,
+            $( $mname: unsafe extern "C" fn($($mty),*) $(-> $mret)?, )*
+            $( $name: unsafe extern "C" fn($($ty),*) $(-> $ret)?, )*
+        }
+
+        impl Bcos3SdkLib {
+            pub fn open(path: &str) -> Result<Bcos3SdkLib, KissError> {
+                let lib = match unsafe { Library::new(path) } {
+                    Ok(lib) => lib,
+                    Err(e) => {
+                        return kisserr!(KissErrKind::EFileMiss, "load bcos-c-sdk [{}] error: {:?}", path, e)
+                    }
+                };
+                let mut missing: Vec<&str> = vec![];
+                $(
+                    let $mname = unsafe {
+                        lib.get::<unsafe extern "C" fn($($mty),*) $(-> $mret)?>(
+                            concat!(stringify!($mname), "\0").as_bytes(),
+                        )
+                    }
+                    .map(|s| *s)
+                    .map_err(|_| missing.push(stringify!($mname)))
+                    .ok();
+                )*
+                $(
+                    let $name = unsafe {
+                        lib.get::<unsafe extern "C" fn($($ty),*) $(-> $ret)?>(
+                            concat!(stringify!($name), "\0").as_bytes(),
+                        )
+                    }
+                    .map(|s| *s)
+                    .map_err(|_| missing.push(stringify!($name)))
+                    .ok();
+                )*
+                if !missing.is_empty() {
+                    return kisserr!(
+                        KissErrKind::EFormat,
+                        "bcos-c-sdk [{}] missing symbols: {}",
+                        path,
+                        missing.join(", ")
+                    );
+                }
+                Ok(Bcos3SdkLib {
+                    path: path.to_string(),
+                    lib,
+                    $( $mname: $mname.unwrap(), )*
+                    $( $name: $name.unwrap(), )*
+                })
+            }
+        }
+
+        $(
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                match current_lib() {
+                    Some(lib) => (lib.$name)($($arg),*),
+                    None => DylibDefault::dylib_default(),
+                }
+            }
+        )*
+    };
+}
+
+bcos3_dylib_api! {
+    manual {
+        fn bcos_sdk_get_last_error_msg() -> *const c_char;
+        fn bcos_sdk_is_last_opr_success() -> c_int;
+        fn bcos_sdk_get_last_error() -> c_int;
+    }
+    fn bcos_sdk_version() -> *const c_char;
+    fn bcos_sdk_create_by_config_file(configfile: *const c_char) -> *const c_void;
+    fn bcos_sdk_start(sdk: *const c_void);
+    fn bcos_sdk_stop(sdk: *const c_void);
+    fn bcos_sdk_destroy(sdk: *const c_void);
+    fn bcos_rpc_get_group_info(sdk: *const c_void, group: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_group_list(sdk: *const c_void, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_group_info_list(sdk: *const c_void, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_group_node_info(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_transaction(sdk: *const c_void, group: *const c_char, node: *const c_char, tx_hash: *const c_char, proof: c_int, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_transaction_receipt(sdk: *const c_void, group: *const c_char, node: *const c_char, tx_hash: *const c_char, proof: c_int, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_block_by_hash(sdk: *const c_void, group: *const c_char, node: *const c_char, block_hash: *const c_char, only_header: c_int, only_tx_hash: c_int, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_block_by_number(sdk: *const c_void, group: *const c_char, node: *const c_char, block_number: c_longlong, only_header: c_int, only_tx_hash: c_int, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_block_hash_by_number(sdk: *const c_void, group: *const c_char, node: *const c_char, block_number: c_longlong, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_block_limit(sdk: *const c_void, group: *const c_char) -> c_longlong;
+    fn bcos_rpc_get_block_number(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_code(sdk: *const c_void, group: *const c_char, node: *const c_char, address: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_sealer_list(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_observer_list(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_pbft_view(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_pending_tx_size(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_sync_status(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_consensus_status(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_system_config_by_key(sdk: *const c_void, group: *const c_char, node: *const c_char, key: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_total_transaction_count(sdk: *const c_void, group: *const c_char, node: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_group_peers(sdk: *const c_void, group: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_get_peers(sdk: *const c_void, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_event_sub_subscribe_event(sdk: *const c_void, group: *const c_char, param: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void) -> *const c_char;
+    fn bcos_event_sub_unsubscribe_event(sdk: *const c_void, group: *const c_char);
+    fn bcos_amop_subscribe_topic(sdk: *const c_void, topics: *const *const c_char, count: c_ulong);
+    fn bcos_amop_subscribe_topic_with_cb(sdk: *const c_void, topic: *const c_char, callback: BCOS3SDK_AMOP_SUB_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_sdk_create_keypair(crypto_type: c_int) -> *const c_void;
+    fn bcos_sdk_create_keypair_by_private_key(crypto_type: c_int, private_key: *const c_char);
+    fn bcos_sdk_create_keypair_by_hex_private_key(crypto_type: c_int, private_key: *const c_char) -> *const c_void;
+    fn bcos_sdk_get_keypair_type(private_key: *const c_char);
+    fn bcos_sdk_get_keypair_public_key(key_pair: *const c_void);
+    fn bcos_sdk_get_keypair_private_key(key_pair: *const c_void);
+    fn bcos_sdk_get_group_chain_id(sdk: *const c_void, group: *const c_char) -> *const c_char;
+    fn bcos_sdk_get_group_wasm_and_crypto(sdk: *const c_void, group: *const c_char, wasm: *mut c_int, sm_cryto: *mut c_int);
+    fn bcos_rpc_send_transaction(sdk: *const c_void, group: *const c_char, node: *const c_char, data: *const c_char, proof: c_int, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_rpc_call(sdk: *const c_void, group: *const c_char, node: *const c_char, to: *const c_char, data: *const c_char, callback: BCOS3SDK_CALLBACK_FUNC, context: *const c_void);
+    fn bcos_sdk_create_signed_transaction(key_pair: *const c_void, group_id: *const c_char, chain_id: *const c_char, to: *const c_char, data: *const c_char, abi: *const c_char, blocklimit: c_longlong, attribute: c_int, tx_hash: *mut *mut c_char, signed_hash: *mut *mut c_char);
+    fn bcos_sdk_c_free(p: *const c_void);
+}
+
+//last error类的接口：库没有加载时返回加载失败的原因
+pub unsafe fn bcos_sdk_get_last_error_msg() -> *const c_char {
+    match current_lib() {
+        Some(lib) => (lib.bcos_sdk_get_last_error_msg)(),
+        //加载错误的串是'static的，锁释放后指针仍然有效
+        None => BCOS3SDK_LOAD_ERROR.lock().unwrap().as_ptr(),
+    }
+}
+
+pub unsafe fn bcos_sdk_is_last_opr_success() -> c_int {
+    match current_lib() {
+        Some(lib) => (lib.bcos_sdk_is_last_opr_success)(),
+        None => 0,
+    }
+}
+
+pub unsafe fn bcos_sdk_get_last_error() -> c_int {
+    match current_lib() {
+        Some(lib) => (lib.bcos_sdk_get_last_error)(),
+        None => BCOS3SDK_DYLIB_NOT_LOADED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_errors() {
+        let e = Bcos3SdkLib::open("/no/such/dir/libbcos-c-sdk.so")
+            .err()
+            .unwrap();
+        assert_eq!(e.kind, KissErrKind::EFileMiss);
+        //任意一个不含sdk符号的库，错误信息里列出全部缺少的符号
+        if cfg!(target_os = "linux") {
+            let e = Bcos3SdkLib::open("libc.so.6").err().unwrap();
+            assert_eq!(e.kind, KissErrKind::EFormat);
+            assert!(e.msg.contains("bcos_sdk_version"));
+            assert!(e.msg.contains("bcos_sdk_c_free"));
+        }
+        //库没有加载时last error是加载失败的原因
+        assert!(load_library("/no/such/dir/libbcos-c-sdk.so").is_err());
+        let msg = unsafe { CStr::from_ptr(bcos_sdk_get_last_error_msg()) };
+        assert!(msg.to_string_lossy().contains("load bcos-c-sdk"));
+    }
+}
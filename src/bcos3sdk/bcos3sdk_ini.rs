@@ -1,21 +1,52 @@
 use std::collections::HashMap;
+use std::path::Path;
 
-use ini::Ini;
+use ini::{Ini, Properties};
 
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::kisserr;
 //用来读c sdk附带的ini文件，注意rust-ini模块要开启features = ["inline-comment"]
+//纯rust的sdk(bcos3sdk_native)也读同一个文件，用到[common]、[cert]和[peers]
 pub struct Bcos3sdkIni {
     pub values: toml::Value,
     pub peers: HashMap<String, String>,
+    pub disable_ssl: bool,
+    pub message_timeout_ms: u64,
+    //ssl或sm_ssl
+    pub ssl_type: String,
+    pub ca_path: String,
+    pub ca_cert: String,
+    pub sdk_key: String,
+    pub sdk_cert: String,
+    pub sm_ca_cert: String,
+    pub sm_sdk_key: String,
+    pub sm_sdk_cert: String,
+    pub sm_ens_sdk_key: String,
+    pub sm_ens_sdk_cert: String,
 }
 
 impl Bcos3sdkIni {
-    pub fn load(config_file: &str) -> Result<Bcos3sdkIni, KissError> {
-        let mut sdkini = Bcos3sdkIni {
+    pub fn default() -> Self {
+        Bcos3sdkIni {
             values: toml::Value::String("".to_string()),
             peers: HashMap::new(),
-        };
+            disable_ssl: false,
+            message_timeout_ms: 10000,
+            ssl_type: "ssl".to_string(),
+            ca_path: "./conf".to_string(),
+            ca_cert: "ca.crt".to_string(),
+            sdk_key: "sdk.key".to_string(),
+            sdk_cert: "sdk.crt".to_string(),
+            sm_ca_cert: "sm_ca.crt".to_string(),
+            sm_sdk_key: "sm_sdk.key".to_string(),
+            sm_sdk_cert: "sm_sdk.crt".to_string(),
+            sm_ens_sdk_key: "sm_ensdk.key".to_string(),
+            sm_ens_sdk_cert: "sm_ensdk.crt".to_string(),
+        }
+    }
+
+    pub fn load(config_file: &str) -> Result<Bcos3sdkIni, KissError> {
+        let mut sdkini = Bcos3sdkIni::default();
         let confres = Ini::load_from_file(config_file);
         if confres.is_err() {
             return kisserr!(KissErrKind::EFormat,"load config file error: {},{:?}",config_file,confres.err());
@@ -28,7 +59,60 @@ impl Bcos3sdkIni {
                 sdkini.peers.insert(key.to_string(), value.to_string());
             }
         }
+        if let Some(common) = config.section(Some("common")) {
+            if let Some(v) = ini_value(common, "disable_ssl") {
+                sdkini.disable_ssl = v == "true";
+            }
+            if let Some(v) = ini_value(common, "message_timeout_ms") {
+                sdkini.message_timeout_ms = match v.parse() {
+                    Ok(t) => t,
+                    Err(e) => return kisserr!(KissErrKind::EFormat, "bad message_timeout_ms {} {:?}", v, e),
+                };
+            }
+        }
+        if let Some(cert) = config.section(Some("cert")) {
+            let fields = [
+                ("ssl_type", &mut sdkini.ssl_type),
+                ("ca_path", &mut sdkini.ca_path),
+                ("ca_cert", &mut sdkini.ca_cert),
+                ("sdk_key", &mut sdkini.sdk_key),
+                ("sdk_cert", &mut sdkini.sdk_cert),
+                ("sm_ca_cert", &mut sdkini.sm_ca_cert),
+                ("sm_sdk_key", &mut sdkini.sm_sdk_key),
+                ("sm_sdk_cert", &mut sdkini.sm_sdk_cert),
+                ("sm_ens_sdk_key", &mut sdkini.sm_ens_sdk_key),
+                ("sm_ens_sdk_cert", &mut sdkini.sm_ens_sdk_cert),
+            ];
+            for (name, field) in fields {
+                if let Some(v) = ini_value(cert, name) {
+                    *field = v;
+                }
+            }
+        }
 
         Ok(sdkini)
     }
+
+    pub fn is_sm_ssl(&self) -> bool {
+        self.ssl_type == "sm_ssl"
+    }
+
+    //证书文件放在ca_path目录下
+    pub fn cert_path(&self, file: &str) -> String {
+        Path::new(&self.ca_path).join(file).to_string_lossy().to_string()
+    }
+
+    //按key排序后的节点列表，"ip:port"
+    pub fn peer_list(&self) -> Vec<String> {
+        let mut keys: Vec<&String> = self.peers.keys().collect();
+        keys.sort();
+        keys.iter().map(|k| self.peers[*k].clone()).collect()
+    }
+}
+
+//去掉行尾的";"或"#"注释和空白
+fn ini_value(section: &Properties, key: &str) -> Option<String> {
+    let value = section.get(key)?;
+    let value = value.split([';', '#']).next().unwrap_or("");
+    Some(value.trim().to_string())
 }
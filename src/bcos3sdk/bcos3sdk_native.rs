@@ -0,0 +1,586 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 纯rust实现的BCOS3 sdk：用websocket(boost-ssl的消息格式)连接节点，收发json rpc，不需要C语言sdk。
+//! 配置读C语言sdk的ini文件，tls连接复用bcos2sdk里的实现，国密用纯rust的tlcp。
+//! 请求是同步的：发出后在当前线程读网络直到等到同seq的回包，期间收到的块高推送顺便记下来，用于计算blocklimit。
+//! 事件订阅跟着连接走：推送先放进队列，由调用者用poll_events取走，连接断开时订阅失效，队列里放一个错误。
+//! bcos3sdkwrapper在bcos3sdk_native特性下用它实现和C语言sdk一样的接口，Bcos3Client不用改
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::BytesMut;
+use serde_json::{json, Value as JsonValue};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::bcos2sdk::bcos_channel_client::{BcosChannelClient, IBcosChannel, IBcosChannelImpl};
+use crate::bcos2sdk::bcos_channel_peers::ChannelPeerManager;
+use crate::bcos3sdk::bcos3sdk_ini::Bcos3sdkIni;
+use crate::bcos3sdk::bcos3sdk_websocket::{
+    check_upgrade_response, make_upgrade_request, make_websocket_key, pop_http_header, WsFrame,
+    WsFrameCodec, WS_OPCODE,
+};
+use crate::bcos3sdk::bcos3sdk_wsmessage::{
+    make_handshake_message, Bcos3GroupInfo, Bcos3HandshakeResponse, Bcos3WsMessage,
+    BCOS3_WS_MSG_TYPE,
+};
+use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ChannelConfig};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::{kisserr, kisserrcode};
+
+///blocklimit = 当前块高 + 这个范围，和C语言sdk一致
+pub const BCOS3_BLOCK_LIMIT_RANGE: i64 = 500;
+///读网络没有数据时的等待间隔
+const BCOS3_POLL_MS: u64 = 10;
+
+///事件订阅的推送：订阅id和推送的json文本，Err表示订阅已经失效，之后不会再有推送
+pub type Bcos3EventPush = (String, Result<String, KissError>);
+
+///不加密的tcp连接，ini里disable_ssl=true时用
+pub struct Bcos3PlainChannel {
+    pub addr: String,
+    stream: Option<TcpStream>,
+}
+
+impl Bcos3PlainChannel {
+    pub fn new(addr: &str) -> Bcos3PlainChannel {
+        Bcos3PlainChannel {
+            addr: addr.to_string(),
+            stream: None,
+        }
+    }
+}
+
+impl IBcosChannel for Bcos3PlainChannel {
+    fn connect(&mut self) -> Result<i32, KissError> {
+        let stream = match TcpStream::connect(self.addr.as_str()) {
+            Ok(s) => s,
+            Err(e) => {
+                return kisserr!(
+                    KissErrKind::ENetwork,
+                    "tcp connect {} error {:?}",
+                    self.addr,
+                    e
+                )
+            }
+        };
+        stream.set_nonblocking(true)?;
+        self.stream = Some(stream);
+        Ok(0)
+    }
+
+    fn send(&mut self, sendbuff: &Vec<u8>) -> Result<i32, KissError> {
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return kisserr!(KissErrKind::ENetwork, "tcp channel is not connected"),
+        };
+        match stream.write(sendbuff) {
+            Ok(n) => Ok(n as i32),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+            Err(e) => kisserr!(KissErrKind::ENetwork, "tcp send fail {:?}", e),
+        }
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, KissError> {
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return kisserr!(KissErrKind::ENetwork, "tcp channel is not connected"),
+        };
+        let mut buf = vec![0u8; 64 * 1024];
+        match stream.read(&mut buf) {
+            Ok(0) => kisserr!(KissErrKind::ENetwork, "connection closed by peer"),
+            Ok(n) => Ok(buf[..n].to_vec()),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(vec![]),
+            Err(e) => kisserr!(KissErrKind::ENetwork, "tcp recv fail {:?}", e),
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(s) = self.stream.take() {
+            let _ = s.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+///一条websocket连接，按消息收发
+pub struct Bcos3WsConnection {
+    pub peer: String,
+    channel: IBcosChannelImpl,
+    codec: WsFrameCodec,
+    inbuf: BytesMut,
+    //分片帧的数据，收到fin帧后拼成一个消息
+    fragments: Vec<u8>,
+}
+
+impl Bcos3WsConnection {
+    ///建立tls(或tcp)连接并完成websocket的升级握手
+    pub fn connect(
+        config: &ChannelConfig,
+        disable_ssl: bool,
+        timeout: Duration,
+    ) -> Result<Bcos3WsConnection, KissError> {
+        let peer = format!("{}:{}", config.ip, config.port);
+        let channel: IBcosChannelImpl = if disable_ssl {
+            let mut plain = Bcos3PlainChannel::new(&peer);
+            plain.connect()?;
+            Arc::new(Mutex::new(plain))
+        } else {
+            BcosChannelClient::build_channel_impl(config)?
+        };
+        let mut conn = Bcos3WsConnection {
+            peer,
+            channel,
+            codec: WsFrameCodec::client(),
+            inbuf: BytesMut::new(),
+            fragments: vec![],
+        };
+        let key = make_websocket_key();
+        conn.send_raw(make_upgrade_request(&conn.peer, &key).as_bytes())?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(header) = pop_http_header(&mut conn.inbuf)? {
+                check_upgrade_response(&header, &key)?;
+                return Ok(conn);
+            }
+            if Instant::now() > deadline {
+                conn.close();
+                return kisserr!(
+                    KissErrKind::ETimeout,
+                    "websocket upgrade timeout {}",
+                    conn.peer
+                );
+            }
+            conn.read_some()?;
+        }
+    }
+
+    fn send_raw(&mut self, data: &[u8]) -> Result<(), KissError> {
+        let mut sent = 0;
+        let mut idle = 0;
+        while sent < data.len() {
+            let n = self.channel.lock().unwrap().send(&data[sent..].to_vec())?;
+            if n > 0 {
+                sent += n as usize;
+                idle = 0;
+                continue;
+            }
+            idle += 1;
+            if idle > 500 {
+                return kisserr!(KissErrKind::ENetwork, "send to {} blocked", self.peer);
+            }
+            std::thread::sleep(Duration::from_millis(BCOS3_POLL_MS));
+        }
+        Ok(())
+    }
+
+    ///非阻塞地读一次网络，没有数据时稍等一下，返回读到的字节数
+    fn read_some(&mut self) -> Result<usize, KissError> {
+        let data = self.channel.lock().unwrap().recv()?;
+        if data.is_empty() {
+            std::thread::sleep(Duration::from_millis(BCOS3_POLL_MS));
+        }
+        self.inbuf.extend_from_slice(&data);
+        Ok(data.len())
+    }
+
+    fn send_frame(&mut self, frame: WsFrame) -> Result<(), KissError> {
+        let mut buf = BytesMut::new();
+        self.codec.encode(frame, &mut buf)?;
+        self.send_raw(&buf)
+    }
+
+    pub fn send_message(&mut self, msg: &Bcos3WsMessage) -> Result<(), KissError> {
+        self.send_frame(WsFrame::new(WS_OPCODE::BINARY, msg.encode()))
+    }
+
+    ///读出一个完整的消息，到deadline还没有时返回None。ping直接应答，close按网络错误处理
+    pub fn recv_message(&mut self, deadline: Instant) -> Result<Option<Bcos3WsMessage>, KissError> {
+        loop {
+            while let Some(frame) = self.codec.decode(&mut self.inbuf)? {
+                match frame.opcode {
+                    op if op == WS_OPCODE::PING as u8 => {
+                        self.send_frame(WsFrame::new(WS_OPCODE::PONG, frame.payload))?;
+                    }
+                    op if op == WS_OPCODE::PONG as u8 => {}
+                    op if op == WS_OPCODE::CLOSE as u8 => {
+                        return kisserr!(
+                            KissErrKind::ENetwork,
+                            "websocket closed by {}",
+                            self.peer
+                        );
+                    }
+                    _ => {
+                        self.fragments.extend_from_slice(&frame.payload);
+                        if frame.fin {
+                            let data = std::mem::take(&mut self.fragments);
+                            return Ok(Some(Bcos3WsMessage::decode(&data)?));
+                        }
+                    }
+                }
+            }
+            if Instant::now() > deadline {
+                return Ok(None);
+            }
+            self.read_some()?;
+        }
+    }
+
+    pub fn close(&mut self) {
+        let _ = self.send_frame(WsFrame::new(WS_OPCODE::CLOSE, vec![]));
+        self.channel.lock().unwrap().finish();
+    }
+}
+
+//-----------------------------------------------------------------------------
+pub struct Bcos3NativeSdk {
+    pub ini: Bcos3sdkIni,
+    pub channelconfig: ChannelConfig,
+    pub peermanager: ChannelPeerManager,
+    pub timeout: Duration,
+    pub protocol_version: i32,
+    ///握手和推送得到的群组信息，key是群组名
+    pub groups: HashMap<String, Bcos3GroupInfo>,
+    conn: Option<Bcos3WsConnection>,
+    reqid: u64,
+    //当前连接上的事件订阅，key是订阅id，value是群组
+    subscriptions: HashMap<String, String>,
+    event_pushes: Vec<Bcos3EventPush>,
+}
+
+impl Bcos3NativeSdk {
+    pub fn from_config_file(config_file: &str) -> Result<Bcos3NativeSdk, KissError> {
+        Bcos3NativeSdk::new(Bcos3sdkIni::load(config_file)?)
+    }
+
+    pub fn new(ini: Bcos3sdkIni) -> Result<Bcos3NativeSdk, KissError> {
+        let channelconfig = Bcos3NativeSdk::channel_config(&ini);
+        if channelconfig.peers.is_empty() {
+            return kisserr!(KissErrKind::EArgument, "no peers in bcos3 sdk config");
+        }
+        let peermanager = ChannelPeerManager::from_config(&channelconfig)?;
+        Ok(Bcos3NativeSdk {
+            timeout: Duration::from_millis(ini.message_timeout_ms.max(1)),
+            ini,
+            channelconfig,
+            peermanager,
+            protocol_version: 0,
+            groups: HashMap::new(),
+            conn: None,
+            reqid: 0,
+            subscriptions: HashMap::new(),
+            event_pushes: vec![],
+        })
+    }
+
+    ///把ini里的证书和节点转成channel的配置，用于建立tls连接
+    pub fn channel_config(ini: &Bcos3sdkIni) -> ChannelConfig {
+        let mut config = ChannelConfig::default();
+        config.timeout = (ini.message_timeout_ms / 1000).max(1) as u32;
+        config.peers = ini.peer_list();
+        config.cacert = ini.cert_path(&ini.ca_cert);
+        config.sdkcert = ini.cert_path(&ini.sdk_cert);
+        config.sdkkey = ini.cert_path(&ini.sdk_key);
+        config.gmcacert = ini.cert_path(&ini.sm_ca_cert);
+        config.gmsdkcert = ini.cert_path(&ini.sm_sdk_cert);
+        config.gmsdkkey = ini.cert_path(&ini.sm_sdk_key);
+        config.gmensdkcert = ini.cert_path(&ini.sm_ens_sdk_cert);
+        config.gmensdkkey = ini.cert_path(&ini.sm_ens_sdk_key);
+        if ini.is_sm_ssl() {
+            config.tlskind = BcosCryptoKind::GM;
+        }
+        config
+    }
+
+    pub fn start(&mut self) -> Result<(), KissError> {
+        self.reconnect()
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            conn.close();
+            for (id, _) in self.subscriptions.drain() {
+                let err = kisserr!(
+                    KissErrKind::ENetwork,
+                    "event subscription {} closed with the connection to {}",
+                    id,
+                    conn.peer
+                );
+                self.event_pushes.push((id, err));
+            }
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    ///按节点列表挑选可用的节点连接并握手，所有节点都失败时返回ENetwork
+    pub fn reconnect(&mut self) -> Result<(), KissError> {
+        self.stop();
+        let mut errors: Vec<String> = vec![];
+        for index in self.peermanager.candidates() {
+            self.peermanager.select(index);
+            let peerconfig = self.peermanager.current_config(&self.channelconfig);
+            let res = Bcos3WsConnection::connect(&peerconfig, self.ini.disable_ssl, self.timeout)
+                .and_then(|conn| {
+                    self.conn = Some(conn);
+                    self.handshake()
+                });
+            match res {
+                Ok(_) => {
+                    log::info!("bcos3 connected to {}:{}", peerconfig.ip, peerconfig.port);
+                    self.peermanager.mark_success();
+                    return Ok(());
+                }
+                Err(e) => {
+                    self.stop();
+                    self.peermanager.mark_failure();
+                    errors.push(format!("{}:{} {}", peerconfig.ip, peerconfig.port, e.msg));
+                }
+            }
+        }
+        kisserr!(
+            KissErrKind::ENetwork,
+            "connect to all bcos3 peers failed: {}",
+            errors.join("; ")
+        )
+    }
+
+    fn handshake(&mut self) -> Result<(), KissError> {
+        let response = self.request_message(&make_handshake_message())?;
+        let handshake = Bcos3HandshakeResponse::from_message(&response)?;
+        self.protocol_version = handshake.protocolVersion;
+        for info in handshake.group_infos() {
+            self.groups.insert(info.groupID.clone(), info);
+        }
+        log::info!(
+            "bcos3 handshake done, protocol {}, groups {:?}",
+            self.protocol_version,
+            self.groups.keys()
+        );
+        Ok(())
+    }
+
+    ///发出一个消息，等待同seq的回包。网络出错时断开，下次请求会换节点重连
+    pub fn request_message(&mut self, msg: &Bcos3WsMessage) -> Result<Bcos3WsMessage, KissError> {
+        if self.conn.is_none() {
+            self.reconnect()?;
+        }
+        let res = self.send_and_wait(msg);
+        if let Err(e) = &res {
+            if e.kind == KissErrKind::ENetwork {
+                self.stop();
+                self.peermanager.mark_failure();
+            }
+        }
+        res
+    }
+
+    fn send_and_wait(&mut self, msg: &Bcos3WsMessage) -> Result<Bcos3WsMessage, KissError> {
+        let deadline = Instant::now() + self.timeout;
+        let conn = self.conn.as_mut().unwrap();
+        conn.send_message(msg)?;
+        loop {
+            let received = match self.conn.as_mut().unwrap().recv_message(deadline)? {
+                Some(m) => m,
+                None => {
+                    return kisserr!(
+                        KissErrKind::ETimeout,
+                        "wait bcos3 response timeout, type: 0x{:x}, seq: {}",
+                        msg.msgtype,
+                        msg.seq
+                    )
+                }
+            };
+            if received.seq == msg.seq {
+                return Ok(received);
+            }
+            self.handle_push(&received);
+        }
+    }
+
+    ///处理节点主动推送的消息：块高、群组信息和事件推送
+    fn handle_push(&mut self, msg: &Bcos3WsMessage) {
+        let value = match msg.payload_json() {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("drop bcos3 push message {:?}", e);
+                return;
+            }
+        };
+        if msg.msgtype == BCOS3_WS_MSG_TYPE::BLOCK_NOTIFY as u16 {
+            let group = value["group"].as_str().unwrap_or("");
+            let number = value["blockNumber"].as_i64().unwrap_or(0);
+            if let Some(info) = self.groups.get_mut(group) {
+                info.blockNumber = info.blockNumber.max(number);
+            }
+        } else if msg.msgtype == BCOS3_WS_MSG_TYPE::GROUP_NOTIFY as u16 {
+            let mut info = Bcos3GroupInfo::from_json(&value);
+            if let Some(old) = self.groups.get(&info.groupID) {
+                info.blockNumber = old.blockNumber;
+            }
+            self.groups.insert(info.groupID.clone(), info);
+        } else if msg.msgtype == BCOS3_WS_MSG_TYPE::EVENT_LOG_PUSH as u16 {
+            let id = value["id"].as_str().unwrap_or("");
+            if self.subscriptions.contains_key(id) {
+                let text = String::from_utf8_lossy(&msg.payload).to_string();
+                self.event_pushes.push((id.to_string(), Ok(text)));
+            } else {
+                log::debug!("drop event push of unknown subscription {}", id);
+            }
+        } else {
+            log::debug!("ignore bcos3 push message 0x{:x}", msg.msgtype);
+        }
+    }
+
+    ///json rpc请求，返回节点回包的json文本
+    pub fn rpc_request(&mut self, method: &str, params: JsonValue) -> Result<String, KissError> {
+        self.reqid += 1;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": self.reqid,
+        });
+        let msg = Bcos3WsMessage::new(
+            BCOS3_WS_MSG_TYPE::RPC_REQUEST,
+            request.to_string().into_bytes(),
+        );
+        let response = self.request_message(&msg)?;
+        if response.status != 0 {
+            return kisserr!(
+                KissErrKind::Error,
+                "bcos3 rpc {} status {}",
+                method,
+                response.status
+            );
+        }
+        match String::from_utf8(response.payload) {
+            Ok(s) => Ok(s),
+            Err(e) => kisserr!(KissErrKind::EFormat, "rpc response is not string {:?}", e),
+        }
+    }
+
+    ///当前块高加上BCOS3_BLOCK_LIMIT_RANGE。块高来自握手和推送，还没有时向节点查询
+    pub fn block_limit(&mut self, group: &str) -> Result<i64, KissError> {
+        if self.conn.is_none() {
+            self.reconnect()?;
+        }
+        let number = match self.groups.get(group) {
+            Some(info) if info.blockNumber > 0 => info.blockNumber,
+            _ => {
+                let text = self.rpc_request("getBlockNumber", json!([group, ""]))?;
+                let value: JsonValue = match serde_json::from_str(&text) {
+                    Ok(v) => v,
+                    Err(e) => return kisserr!(KissErrKind::EFormat, "bad getBlockNumber {:?}", e),
+                };
+                match value["result"].as_i64() {
+                    Some(n) => n,
+                    None => return kisserr!(KissErrKind::Error, "getBlockNumber error {}", text),
+                }
+            }
+        };
+        Ok(number + BCOS3_BLOCK_LIMIT_RANGE)
+    }
+
+    pub fn group_info(&self, group: &str) -> Option<&Bcos3GroupInfo> {
+        self.groups.get(group)
+    }
+
+    ///订阅群组的合约事件，params是EventSubParam的json，返回订阅id。节点的应答作为第一个推送放进队列
+    pub fn subscribe_event(&mut self, group: &str, params: JsonValue) -> Result<String, KissError> {
+        let id = hex::encode(rand::random::<[u8; 16]>());
+        let request = json!({"id": id, "group": group, "params": params});
+        let msg = Bcos3WsMessage::new(
+            BCOS3_WS_MSG_TYPE::EVENT_SUBSCRIBE,
+            request.to_string().into_bytes(),
+        );
+        let response = self.request_message(&msg)?;
+        let value = response.payload_json()?;
+        let status = match value["status"].as_i64() {
+            Some(s) if s != 0 => s,
+            _ => response.status as i64,
+        };
+        if status != 0 {
+            return kisserrcode!(
+                KissErrKind::Error,
+                status,
+                "subscribe event of group {} error: {}",
+                group,
+                value
+            );
+        }
+        self.subscriptions.insert(id.clone(), group.to_string());
+        let text = String::from_utf8_lossy(&response.payload).to_string();
+        self.event_pushes.push((id.clone(), Ok(text)));
+        Ok(id)
+    }
+
+    ///取消订阅，之后同id的推送都丢弃
+    pub fn unsubscribe_event(&mut self, id: &str) -> Result<(), KissError> {
+        let group = match self.subscriptions.remove(id) {
+            Some(g) => g,
+            None => {
+                return kisserr!(
+                    KissErrKind::EArgument,
+                    "event subscription {} not found",
+                    id
+                )
+            }
+        };
+        self.event_pushes.retain(|(pushid, _)| pushid != id);
+        let request = json!({"id": id, "group": group});
+        let msg = Bcos3WsMessage::new(
+            BCOS3_WS_MSG_TYPE::EVENT_UNSUBSCRIBE,
+            request.to_string().into_bytes(),
+        );
+        self.request_message(&msg)?;
+        Ok(())
+    }
+
+    ///取走事件推送。有订阅时先读最多wait的网络，连接出错时断开，订阅随之失效
+    pub fn poll_events(&mut self, wait: Duration) -> Vec<Bcos3EventPush> {
+        if !self.subscriptions.is_empty() && wait > Duration::ZERO {
+            let deadline = Instant::now() + wait;
+            while let Some(conn) = self.conn.as_mut() {
+                match conn.recv_message(deadline) {
+                    Ok(Some(msg)) => self.handle_push(&msg),
+                    Ok(None) => break,
+                    Err(e) => {
+                        log::warn!("bcos3 connection lost while polling events {:?}", e);
+                        self.stop();
+                        self.peermanager.mark_failure();
+                    }
+                }
+            }
+        }
+        std::mem::take(&mut self.event_pushes)
+    }
+}
+
+impl Drop for Bcos3NativeSdk {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
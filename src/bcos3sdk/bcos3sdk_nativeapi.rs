@@ -0,0 +1,833 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::missing_safety_doc,
+    clippy::not_unsafe_ptr_arg_deref,
+    clippy::too_many_arguments,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    unused_variables
+)]
+//! 用纯rust的Bcos3NativeSdk实现bcos-c-sdk的C API，函数名和签名与bcos3sdkwrapper::bcos3sdk_def一致，
+//! 开启bcos3sdk_native特性时bcos3sdk_def就是这个模块，Bcos3Client不需要任何修改。
+//! 和C语言sdk一样：sdk和keypair是不透明指针，出错时记录last error，rpc结果通过callback返回。
+//! rpc请求交给每个sdk自己的工作线程发出，接口立即返回，callback在工作线程里执行，
+//! 不会阻塞调用线程(比如tokio的工作线程)；rpc的错误只通过callback返回，不记录last error。
+//! 事件订阅走同一条websocket：工作线程空闲时读推送，节点的订阅应答和之后的每个推送都在工作线程里回调，
+//! 连接断开时订阅失效，用错误回调一次。
+//! AMOP暂不支持，这里没有bcos_amop_*接口，开启bcos3sdk_native时用到AMOP的代码编译不过
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use libc::{c_char, c_int, c_longlong, c_void};
+use serde_json::{json, Value as JsonValue};
+
+use crate::bcos3sdk::bcos3sdk_native::{Bcos3EventPush, Bcos3NativeSdk};
+use crate::bcos3sdk::bcos3sdk_tars::{Bcos3Transaction, Bcos3TransactionData};
+use crate::bcos3sdk::bcos3sdkresponse::bcos_sdk_c_struct_response;
+use crate::bcos3sdk::bcos3sdkwrapper::BCOS3SDK_CALLBACK_FUNC;
+use crate::bcossdkutil::accountutil::{account_from_privkey, create_account, BcosAccount};
+use crate::bcossdkutil::bcosclientconfig::BcosCryptoKind;
+use crate::bcossdkutil::commonhash::{CommonHash, HashType};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+///和C语言sdk的约定一致：0是ecdsa，1是国密
+pub const BCOS3_NATIVE_ECDSA_TYPE: c_int = 0;
+pub const BCOS3_NATIVE_SM_TYPE: c_int = 1;
+///未归类的错误码
+pub const BCOS3_NATIVE_ERROR: c_int = -1;
+///工作线程没有rpc请求时，每隔这么久读一次事件推送
+const NATIVE_EVENT_POLL_MS: u64 = 50;
+
+static BCOS3_NATIVE_VERSION: &[u8] = b"bcos3sdk_native rust websocket sdk\0";
+
+thread_local! {
+    //最近一次调用的错误码和错误信息，成功时清空
+    static LAST_ERROR: RefCell<(c_int, CString)> = RefCell::new((0, CString::default()));
+}
+
+///sdk指针指向的对象
+pub struct Bcos3NativeHandle {
+    pub sdk: Arc<Mutex<Bcos3NativeSdk>>,
+    //bcos_sdk_get_group_chain_id返回的字符串，生命周期和sdk一致
+    chainids: Mutex<Vec<CString>>,
+    //发给工作线程的rpc请求，destroy时关闭
+    jobs: Mutex<Option<Sender<NativeRpcJob>>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    //事件订阅的callback和context，key是订阅id
+    events: Arc<Mutex<HashMap<String, (BCOS3SDK_CALLBACK_FUNC, usize)>>>,
+}
+
+//排队等工作线程发出的rpc请求，context按值带到工作线程，只原样传回callback
+struct NativeRpcJob {
+    method: String,
+    params: JsonValue,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: usize,
+}
+
+impl Bcos3NativeHandle {
+    fn new(sdk: Bcos3NativeSdk) -> Bcos3NativeHandle {
+        let sdk = Arc::new(Mutex::new(sdk));
+        let events: Arc<Mutex<HashMap<String, (BCOS3SDK_CALLBACK_FUNC, usize)>>> =
+            Default::default();
+        let (tx, rx) = channel::<NativeRpcJob>();
+        let worker = {
+            let (sdk, events) = (sdk.clone(), events.clone());
+            std::thread::spawn(move || loop {
+                //按顺序发出请求，空闲时读一下事件推送。回调前已经释放了sdk的锁，回调里可以继续调用sdk
+                let wait = match rx.recv_timeout(Duration::from_millis(NATIVE_EVENT_POLL_MS)) {
+                    Ok(job) => {
+                        let res = sdk.lock().unwrap().rpc_request(&job.method, job.params);
+                        unsafe { respond(res, job.callback, job.context as *const c_void) };
+                        Duration::ZERO
+                    }
+                    Err(RecvTimeoutError::Timeout) => Duration::from_millis(NATIVE_EVENT_POLL_MS),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let pushes = sdk.lock().unwrap().poll_events(wait);
+                unsafe { dispatch_events(&events, pushes) };
+            })
+        };
+        Bcos3NativeHandle {
+            sdk,
+            chainids: Mutex::new(vec![]),
+            jobs: Mutex::new(Some(tx)),
+            worker: Mutex::new(Some(worker)),
+            events,
+        }
+    }
+}
+
+//按订阅id找到callback回调，订阅失效时同时删掉callback。取消订阅后迟到的推送找不到callback，直接丢弃
+unsafe fn dispatch_events(
+    events: &Mutex<HashMap<String, (BCOS3SDK_CALLBACK_FUNC, usize)>>,
+    pushes: Vec<Bcos3EventPush>,
+) {
+    for (id, res) in pushes {
+        let entry = {
+            let mut events = events.lock().unwrap();
+            if res.is_err() {
+                events.remove(&id)
+            } else {
+                events.get(&id).copied()
+            }
+        };
+        if let Some((callback, context)) = entry {
+            respond(res, callback, context as *const c_void);
+        }
+    }
+}
+
+impl Drop for Bcos3NativeHandle {
+    //已经排队的请求都回调完再返回，在回调里destroy时不能等自己
+    fn drop(&mut self) {
+        self.jobs.lock().unwrap().take();
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            if worker.thread().id() != std::thread::current().id() {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+///keypair指针指向的对象
+pub struct Bcos3NativeKeyPair {
+    pub account: BcosAccount,
+    pub hashtype: HashType,
+}
+
+fn error_code(e: &KissError) -> c_int {
+    if e.code != 0 {
+        e.code as c_int
+    } else {
+        BCOS3_NATIVE_ERROR
+    }
+}
+
+fn set_last_error(code: c_int, msg: &str) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = (code, msg));
+}
+
+fn clear_last_error() {
+    set_last_error(0, "");
+}
+
+///按结果设置last error，出错时返回None
+fn record<T>(res: Result<T, KissError>) -> Option<T> {
+    match res {
+        Ok(v) => {
+            clear_last_error();
+            Some(v)
+        }
+        Err(e) => {
+            set_last_error(error_code(&e), &e.msg);
+            None
+        }
+    }
+}
+
+///空指针当作空串
+unsafe fn p2str(p: *const c_char) -> String {
+    if p.is_null() {
+        return "".to_string();
+    }
+    CStr::from_ptr(p).to_string_lossy().to_string()
+}
+
+unsafe fn handle<'a>(sdk: *const c_void) -> Result<&'a Bcos3NativeHandle, KissError> {
+    if sdk.is_null() {
+        return kisserr!(KissErrKind::EArgument, "bcos3 native sdk is null");
+    }
+    Ok(&*(sdk as *const Bcos3NativeHandle))
+}
+
+///把rpc请求交给工作线程，结果在工作线程里回调。sdk无效时在当前线程回调错误
+unsafe fn rpc_callback(
+    sdk: *const c_void,
+    method: &str,
+    params: JsonValue,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let h = match handle(sdk) {
+        Ok(h) => h,
+        Err(e) => return respond(Err(e), callback, context),
+    };
+    let job = NativeRpcJob {
+        method: method.to_string(),
+        params,
+        callback,
+        context: context as usize,
+    };
+    let sent = match h.jobs.lock().unwrap().as_ref() {
+        Some(jobs) => jobs.send(job).is_ok(),
+        None => false,
+    };
+    if !sent {
+        let err = kisserr!(KissErrKind::ENetwork, "bcos3 native sdk is destroyed");
+        respond(err, callback, context);
+    } else {
+        clear_last_error();
+    }
+}
+
+///按结果设置last error，并把结果或错误通过callback返回
+unsafe fn respond(
+    res: Result<String, KissError>,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let (error, desc, data) = match res {
+        Ok(text) => (0, CString::default(), text),
+        Err(e) => (
+            error_code(&e),
+            CString::new(e.msg.replace('\0', " ")).unwrap_or_default(),
+            "".to_string(),
+        ),
+    };
+    if error == 0 {
+        clear_last_error();
+    } else {
+        set_last_error(error, desc.to_str().unwrap_or(""));
+    }
+    let response = bcos_sdk_c_struct_response {
+        error,
+        desc: if error == 0 {
+            std::ptr::null()
+        } else {
+            desc.as_ptr()
+        },
+        data: if data.is_empty() {
+            std::ptr::null()
+        } else {
+            data.as_ptr() as *const c_void
+        },
+        size: data.len(),
+        context,
+    };
+    callback(&response);
+}
+
+unsafe fn group_node(group: *const c_char, node: *const c_char) -> Vec<JsonValue> {
+    vec![json!(p2str(group)), json!(p2str(node))]
+}
+
+unsafe fn group_node_params(
+    group: *const c_char,
+    node: *const c_char,
+    extra: &[JsonValue],
+) -> JsonValue {
+    let mut params = group_node(group, node);
+    params.extend_from_slice(extra);
+    JsonValue::Array(params)
+}
+
+//-----------------------------------------------------------------------------
+pub unsafe fn bcos_sdk_version() -> *const c_char {
+    BCOS3_NATIVE_VERSION.as_ptr() as *const c_char
+}
+
+pub unsafe fn bcos_sdk_create_by_config_file(configfile: *const c_char) -> *const c_void {
+    match record(Bcos3NativeSdk::from_config_file(&p2str(configfile))) {
+        Some(sdk) => Box::into_raw(Box::new(Bcos3NativeHandle::new(sdk))) as *const c_void,
+        None => std::ptr::null(),
+    }
+}
+
+pub unsafe fn bcos_sdk_start(sdk: *const c_void) {
+    record(handle(sdk).and_then(|h| h.sdk.lock().unwrap().start()));
+}
+
+pub unsafe fn bcos_sdk_stop(sdk: *const c_void) {
+    record(handle(sdk).map(|h| h.sdk.lock().unwrap().stop()));
+}
+
+pub unsafe fn bcos_sdk_destroy(sdk: *const c_void) {
+    if !sdk.is_null() {
+        drop(Box::from_raw(sdk as *mut Bcos3NativeHandle));
+    }
+}
+
+pub unsafe fn bcos_sdk_get_last_error_msg() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().1.as_ptr())
+}
+
+pub unsafe fn bcos_sdk_is_last_opr_success() -> c_int {
+    LAST_ERROR.with(|e| (e.borrow().0 == 0) as c_int)
+}
+
+pub unsafe fn bcos_sdk_get_last_error() -> c_int {
+    LAST_ERROR.with(|e| e.borrow().0)
+}
+
+pub unsafe fn bcos_rpc_get_group_info(
+    sdk: *const c_void,
+    group: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    rpc_callback(
+        sdk,
+        "getGroupInfo",
+        json!([p2str(group)]),
+        callback,
+        context,
+    );
+}
+
+pub unsafe fn bcos_rpc_get_group_list(
+    sdk: *const c_void,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    rpc_callback(sdk, "getGroupList", json!([]), callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_group_info_list(
+    sdk: *const c_void,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    rpc_callback(sdk, "getGroupInfoList", json!([]), callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_group_node_info(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getGroupNodeInfo", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_transaction(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    tx_hash: *const c_char,
+    proof: c_int,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[json!(p2str(tx_hash)), json!(proof != 0)]);
+    rpc_callback(sdk, "getTransaction", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_transaction_receipt(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    tx_hash: *const c_char,
+    proof: c_int,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[json!(p2str(tx_hash)), json!(proof != 0)]);
+    rpc_callback(sdk, "getTransactionReceipt", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_block_by_hash(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    block_hash: *const c_char,
+    only_header: c_int,
+    only_tx_hash: c_int,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(
+        group,
+        node,
+        &[
+            json!(p2str(block_hash)),
+            json!(only_header != 0),
+            json!(only_tx_hash != 0),
+        ],
+    );
+    rpc_callback(sdk, "getBlockByHash", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_block_by_number(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    block_number: c_longlong,
+    only_header: c_int,
+    only_tx_hash: c_int,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(
+        group,
+        node,
+        &[
+            json!(block_number),
+            json!(only_header != 0),
+            json!(only_tx_hash != 0),
+        ],
+    );
+    rpc_callback(sdk, "getBlockByNumber", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_block_hash_by_number(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    block_number: c_longlong,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[json!(block_number)]);
+    rpc_callback(sdk, "getBlockHashByNumber", params, callback, context);
+}
+
+///失败时返回0并记录last error
+pub unsafe fn bcos_rpc_get_block_limit(sdk: *const c_void, group: *const c_char) -> c_longlong {
+    let res = handle(sdk).and_then(|h| h.sdk.lock().unwrap().block_limit(&p2str(group)));
+    record(res).unwrap_or(0) as c_longlong
+}
+
+pub unsafe fn bcos_rpc_get_block_number(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getBlockNumber", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_code(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    address: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[json!(p2str(address))]);
+    rpc_callback(sdk, "getCode", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_sealer_list(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getSealerList", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_observer_list(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getObserverList", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_pbft_view(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getPbftView", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_pending_tx_size(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getPendingTxSize", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_sync_status(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getSyncStatus", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_consensus_status(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getConsensusStatus", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_system_config_by_key(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    key: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[json!(p2str(key))]);
+    rpc_callback(sdk, "getSystemConfigByKey", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_total_transaction_count(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[]);
+    rpc_callback(sdk, "getTotalTransactionCount", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_get_group_peers(
+    sdk: *const c_void,
+    group: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    rpc_callback(
+        sdk,
+        "getGroupPeers",
+        json!([p2str(group)]),
+        callback,
+        context,
+    );
+}
+
+pub unsafe fn bcos_rpc_get_peers(
+    sdk: *const c_void,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    rpc_callback(sdk, "getPeers", json!([]), callback, context);
+}
+
+//Event类接口
+///param是EventSubParam的json。订阅成功时返回新分配的订阅id，用bcos_sdk_c_free释放，
+///节点的应答和之后的推送都在工作线程里回调；失败时在当前线程用错误回调一次，返回空指针
+pub unsafe fn bcos_event_sub_subscribe_event(
+    sdk: *const c_void,
+    group: *const c_char,
+    param: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) -> *const c_char {
+    let res = handle(sdk).and_then(|h| {
+        let params: JsonValue = match serde_json::from_str(&p2str(param)) {
+            Ok(v) => v,
+            Err(e) => return kisserr!(KissErrKind::EArgument, "bad event sub param {:?}", e),
+        };
+        //先登记callback再让工作线程看到订阅，第一个推送不会找不到callback
+        let mut events = h.events.lock().unwrap();
+        let id = h
+            .sdk
+            .lock()
+            .unwrap()
+            .subscribe_event(&p2str(group), params)?;
+        events.insert(id.clone(), (callback, context as usize));
+        Ok(id)
+    });
+    match res {
+        Ok(id) => {
+            clear_last_error();
+            CString::new(id).unwrap_or_default().into_raw()
+        }
+        Err(e) => {
+            respond(Err(e), callback, context);
+            std::ptr::null()
+        }
+    }
+}
+
+///C语言sdk里第二个参数是订阅id
+pub unsafe fn bcos_event_sub_unsubscribe_event(sdk: *const c_void, id: *const c_char) {
+    let res = handle(sdk).and_then(|h| {
+        let id = p2str(id);
+        h.events.lock().unwrap().remove(&id);
+        h.sdk.lock().unwrap().unsubscribe_event(&id)
+    });
+    record(res);
+}
+
+fn crypto_kind(crypto_type: c_int) -> Result<BcosCryptoKind, KissError> {
+    match crypto_type {
+        BCOS3_NATIVE_ECDSA_TYPE => Ok(BcosCryptoKind::ECDSA),
+        BCOS3_NATIVE_SM_TYPE => Ok(BcosCryptoKind::GM),
+        _ => kisserr!(
+            KissErrKind::EArgument,
+            "unknown crypto type {}",
+            crypto_type
+        ),
+    }
+}
+
+fn keypair_ptr(account: BcosAccount, kind: &BcosCryptoKind) -> *const c_void {
+    Box::into_raw(Box::new(Bcos3NativeKeyPair {
+        account,
+        hashtype: CommonHash::crypto_to_hashtype(kind),
+    })) as *const c_void
+}
+
+pub unsafe fn bcos_sdk_create_keypair(crypto_type: c_int) -> *const c_void {
+    match record(crypto_kind(crypto_type)) {
+        Some(kind) => keypair_ptr(create_account(&kind), &kind),
+        None => std::ptr::null(),
+    }
+}
+
+///私钥是二进制，C语言sdk里需要另外传长度，这里没有长度参数，不支持
+pub unsafe fn bcos_sdk_create_keypair_by_private_key(
+    crypto_type: c_int,
+    private_key: *const c_char,
+) {
+    set_last_error(
+        BCOS3_NATIVE_ERROR,
+        "use bcos_sdk_create_keypair_by_hex_private_key instead",
+    );
+}
+
+pub unsafe fn bcos_sdk_create_keypair_by_hex_private_key(
+    crypto_type: c_int,
+    private_key: *const c_char,
+) -> *const c_void {
+    let res = crypto_kind(crypto_type).and_then(|kind| {
+        let keyhex = p2str(private_key);
+        let keybytes = match hex::decode(keyhex.trim_start_matches("0x")) {
+            Ok(k) => k,
+            Err(e) => return kisserr!(KissErrKind::EArgument, "bad hex private key {:?}", e),
+        };
+        let account = account_from_privkey(&keybytes, kind.clone())?;
+        Ok(keypair_ptr(account, &kind))
+    });
+    record(res).unwrap_or(std::ptr::null())
+}
+
+pub unsafe fn bcos_sdk_get_keypair_type(private_key: *const c_char) {}
+
+pub unsafe fn bcos_sdk_get_keypair_public_key(key_pair: *const c_void) {}
+pub unsafe fn bcos_sdk_get_keypair_private_key(key_pair: *const c_void) {}
+
+///返回的字符串由sdk持有，sdk销毁前有效
+pub unsafe fn bcos_sdk_get_group_chain_id(
+    sdk: *const c_void,
+    group: *const c_char,
+) -> *const c_char {
+    let res = handle(sdk).and_then(|h| {
+        let chainid = match h.sdk.lock().unwrap().group_info(&p2str(group)) {
+            Some(info) => info.chainID.clone(),
+            None => return kisserr!(KissErrKind::EArgument, "group {} not found", p2str(group)),
+        };
+        let chainid = CString::new(chainid).unwrap_or_default();
+        let p = chainid.as_ptr();
+        h.chainids.lock().unwrap().push(chainid);
+        Ok(p)
+    });
+    record(res).unwrap_or(std::ptr::null())
+}
+
+pub unsafe fn bcos_sdk_get_group_wasm_and_crypto(
+    sdk: *const c_void,
+    group: *const c_char,
+    wasm: *mut c_int,
+    sm_cryto: *mut c_int,
+) {
+    let res = handle(sdk).and_then(|h| match h.sdk.lock().unwrap().group_info(&p2str(group)) {
+        Some(info) => Ok((info.wasm, info.smCrypto)),
+        None => kisserr!(KissErrKind::EArgument, "group {} not found", p2str(group)),
+    });
+    if let Some((is_wasm, is_sm)) = record(res) {
+        if !wasm.is_null() {
+            *wasm = is_wasm as c_int;
+        }
+        if !sm_cryto.is_null() {
+            *sm_cryto = is_sm as c_int;
+        }
+    }
+}
+
+pub unsafe fn bcos_rpc_send_transaction(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    data: *const c_char,
+    proof: c_int,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[json!(p2str(data)), json!(proof != 0)]);
+    rpc_callback(sdk, "sendTransaction", params, callback, context);
+}
+
+pub unsafe fn bcos_rpc_call(
+    sdk: *const c_void,
+    group: *const c_char,
+    node: *const c_char,
+    to: *const c_char,
+    data: *const c_char,
+    callback: BCOS3SDK_CALLBACK_FUNC,
+    context: *const c_void,
+) {
+    let params = group_node_params(group, node, &[json!(p2str(to)), json!(p2str(data))]);
+    rpc_callback(sdk, "call", params, callback, context);
+}
+
+///tx_hash和signed_tx是新分配的字符串，用完后调用bcos_sdk_c_free释放
+pub unsafe fn bcos_sdk_create_signed_transaction(
+    key_pair: *const c_void,
+    group_id: *const c_char,
+    chain_id: *const c_char,
+    to: *const c_char,
+    data: *const c_char,
+    abi: *const c_char,
+    blocklimit: c_longlong,
+    attribute: c_int,
+    tx_hash: *mut *mut c_char,
+    signed_hash: *mut *mut c_char,
+) {
+    let res = (|| {
+        if key_pair.is_null() || tx_hash.is_null() || signed_hash.is_null() {
+            return kisserr!(
+                KissErrKind::EArgument,
+                "null pointer for signed transaction"
+            );
+        }
+        let keypair = &*(key_pair as *const Bcos3NativeKeyPair);
+        let datahex = p2str(data);
+        let input = match hex::decode(datahex.trim_start_matches("0x")) {
+            Ok(d) => d,
+            Err(e) => return kisserr!(KissErrKind::EArgument, "bad hex input data {:?}", e),
+        };
+        let mut txdata = Bcos3TransactionData::new(
+            &p2str(chain_id),
+            &p2str(group_id),
+            blocklimit,
+            &p2str(to),
+            &input,
+        );
+        txdata.abi = p2str(abi);
+        let tx = Bcos3Transaction::sign(txdata, &keypair.account, &keypair.hashtype, attribute)?;
+        *tx_hash = CString::new(tx.hash_hex()).unwrap_or_default().into_raw();
+        *signed_hash = CString::new(hex::encode(tx.encode()))
+            .unwrap_or_default()
+            .into_raw();
+        Ok(())
+    })();
+    record(res);
+}
+
+///只用于释放本模块分配的字符串
+pub unsafe fn bcos_sdk_c_free(p: *const c_void) {
+    if !p.is_null() {
+        drop(CString::from_raw(p as *mut c_char));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    static EVENT_ERROR: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn on_event(resp: *const bcos_sdk_c_struct_response) {
+        EVENT_ERROR.store(unsafe { (*resp).error }, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_event_subscribe_without_sdk() {
+        unsafe {
+            let subid = bcos_event_sub_subscribe_event(
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+                on_event,
+                std::ptr::null(),
+            );
+            assert!(subid.is_null());
+            assert_eq!(EVENT_ERROR.load(Ordering::SeqCst), BCOS3_NATIVE_ERROR);
+            assert_eq!(bcos_sdk_get_last_error(), BCOS3_NATIVE_ERROR);
+
+            bcos_event_sub_unsubscribe_event(std::ptr::null(), std::ptr::null());
+            assert_eq!(bcos_sdk_is_last_opr_success(), 0);
+        }
+    }
+}
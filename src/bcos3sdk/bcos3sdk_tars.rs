@@ -0,0 +1,503 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! BCOS3交易的tars编解码和签名，纯rust实现，不依赖C语言sdk。
+//! tars只实现交易用到的类型：整数、字符串、字节数组和结构体。
+//! 参见 https://github.com/FISCO-BCOS/FISCO-BCOS/blob/master/bcos-tars-protocol/bcos-tars-protocol/tars/Transaction.tars
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use ethereum_types::U256;
+
+use crate::bcossdkutil::accountutil::BcosAccount;
+use crate::bcossdkutil::commonhash::{CommonHash, HashType};
+use crate::bcossdkutil::commonsigner::{
+    CommonSignerWeDPR_SM2, CommonSignerWeDPR_Secp256, ICommonSigner, Secp256Signature,
+};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+///tars的数据类型，写在每个字段的头部
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TARS_TYPE {
+    INT1 = 0,
+    INT2 = 1,
+    INT4 = 2,
+    INT8 = 3,
+    FLOAT = 4,
+    DOUBLE = 5,
+    STRING1 = 6,
+    STRING4 = 7,
+    MAP = 8,
+    LIST = 9,
+    STRUCT_BEGIN = 10,
+    STRUCT_END = 11,
+    ZERO = 12,
+    SIMPLE_LIST = 13,
+}
+
+///解码出来的字段值，结构体按tag存放
+#[derive(Debug, Clone, PartialEq)]
+pub enum TarsValue {
+    Int(i64),
+    Str(String),
+    Bytes(Vec<u8>),
+    List(Vec<TarsValue>),
+    Map(Vec<(TarsValue, TarsValue)>),
+    Struct(BTreeMap<u8, TarsValue>),
+    Float(f64),
+}
+
+impl TarsValue {
+    pub fn as_int(&self) -> i64 {
+        match self {
+            TarsValue::Int(v) => *v,
+            _ => 0,
+        }
+    }
+    pub fn as_str(&self) -> String {
+        match self {
+            TarsValue::Str(s) => s.clone(),
+            _ => "".to_string(),
+        }
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            TarsValue::Bytes(b) => b.clone(),
+            _ => vec![],
+        }
+    }
+}
+
+///按tars格式写字段，optional字段是默认值时由调用者决定是否跳过
+#[derive(Debug, Default, Clone)]
+pub struct TarsWriter {
+    pub buf: Vec<u8>,
+}
+
+impl TarsWriter {
+    pub fn new() -> TarsWriter {
+        TarsWriter { buf: vec![] }
+    }
+
+    fn write_head(&mut self, tag: u8, tarstype: TARS_TYPE) {
+        if tag < 15 {
+            self.buf.push((tag << 4) | tarstype as u8);
+        } else {
+            self.buf.push(0xF0 | tarstype as u8);
+            self.buf.push(tag);
+        }
+    }
+
+    ///整数按值的大小选最短的编码，0只写头
+    pub fn write_int(&mut self, tag: u8, value: i64) {
+        if value == 0 {
+            self.write_head(tag, TARS_TYPE::ZERO);
+        } else if value >= i8::MIN as i64 && value <= i8::MAX as i64 {
+            self.write_head(tag, TARS_TYPE::INT1);
+            self.buf.push(value as i8 as u8);
+        } else if value >= i16::MIN as i64 && value <= i16::MAX as i64 {
+            self.write_head(tag, TARS_TYPE::INT2);
+            self.buf.extend_from_slice(&(value as i16).to_be_bytes());
+        } else if value >= i32::MIN as i64 && value <= i32::MAX as i64 {
+            self.write_head(tag, TARS_TYPE::INT4);
+            self.buf.extend_from_slice(&(value as i32).to_be_bytes());
+        } else {
+            self.write_head(tag, TARS_TYPE::INT8);
+            self.buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    pub fn write_string(&mut self, tag: u8, value: &str) {
+        let bytes = value.as_bytes();
+        if bytes.len() <= 255 {
+            self.write_head(tag, TARS_TYPE::STRING1);
+            self.buf.push(bytes.len() as u8);
+        } else {
+            self.write_head(tag, TARS_TYPE::STRING4);
+            self.buf
+                .extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        }
+        self.buf.extend_from_slice(bytes);
+    }
+
+    ///vector<byte>用SIMPLE_LIST编码
+    pub fn write_bytes(&mut self, tag: u8, value: &[u8]) {
+        self.write_head(tag, TARS_TYPE::SIMPLE_LIST);
+        self.write_head(0, TARS_TYPE::INT1);
+        self.write_int(0, value.len() as i64);
+        self.buf.extend_from_slice(value);
+    }
+
+    ///写入一个已经编码好的结构体
+    pub fn write_struct(&mut self, tag: u8, body: &[u8]) {
+        self.write_head(tag, TARS_TYPE::STRUCT_BEGIN);
+        self.buf.extend_from_slice(body);
+        self.write_head(0, TARS_TYPE::STRUCT_END);
+    }
+}
+
+///按tars格式读字段，不认识的字段按类型跳过
+pub struct TarsReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TarsReader<'a> {
+    pub fn new(buf: &'a [u8]) -> TarsReader<'a> {
+        TarsReader { buf, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], KissError> {
+        if self.buf.len() - self.pos < n {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "tars data too short, need {} at {}",
+                n,
+                self.pos
+            );
+        }
+        let data = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(data)
+    }
+
+    fn read_head(&mut self) -> Result<(u8, u8), KissError> {
+        let b = self.take(1)?[0];
+        let tarstype = b & 0x0F;
+        let mut tag = b >> 4;
+        if tag == 15 {
+            tag = self.take(1)?[0];
+        }
+        Ok((tag, tarstype))
+    }
+
+    fn read_len(&mut self) -> Result<usize, KissError> {
+        let (_, tarstype) = self.read_head()?;
+        let len = self.read_value(tarstype)?.as_int();
+        if len < 0 {
+            return kisserr!(KissErrKind::EFormat, "negative tars length {}", len);
+        }
+        Ok(len as usize)
+    }
+
+    fn read_value(&mut self, tarstype: u8) -> Result<TarsValue, KissError> {
+        let value = match tarstype {
+            0 => TarsValue::Int(self.take(1)?[0] as i8 as i64),
+            1 => TarsValue::Int(i16::from_be_bytes(self.take(2)?.try_into().unwrap()) as i64),
+            2 => TarsValue::Int(i32::from_be_bytes(self.take(4)?.try_into().unwrap()) as i64),
+            3 => TarsValue::Int(i64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            4 => TarsValue::Float(f32::from_be_bytes(self.take(4)?.try_into().unwrap()) as f64),
+            5 => TarsValue::Float(f64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            6 | 7 => {
+                let len = if tarstype == 6 {
+                    self.take(1)?[0] as usize
+                } else {
+                    u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as usize
+                };
+                TarsValue::Str(String::from_utf8_lossy(self.take(len)?).to_string())
+            }
+            8 => {
+                let len = self.read_len()?;
+                let mut items = vec![];
+                for _ in 0..len {
+                    let (_, kt) = self.read_head()?;
+                    let key = self.read_value(kt)?;
+                    let (_, vt) = self.read_head()?;
+                    items.push((key, self.read_value(vt)?));
+                }
+                TarsValue::Map(items)
+            }
+            9 => {
+                let len = self.read_len()?;
+                let mut items = vec![];
+                for _ in 0..len {
+                    let (_, t) = self.read_head()?;
+                    items.push(self.read_value(t)?);
+                }
+                TarsValue::List(items)
+            }
+            10 => TarsValue::Struct(self.read_struct_fields()?),
+            12 => TarsValue::Int(0),
+            13 => {
+                //元素类型的头，固定是INT1
+                self.read_head()?;
+                let len = self.read_len()?;
+                TarsValue::Bytes(self.take(len)?.to_vec())
+            }
+            t => return kisserr!(KissErrKind::EFormat, "unknown tars type {}", t),
+        };
+        Ok(value)
+    }
+
+    fn read_struct_fields(&mut self) -> Result<BTreeMap<u8, TarsValue>, KissError> {
+        let mut fields = BTreeMap::new();
+        loop {
+            let (tag, tarstype) = self.read_head()?;
+            if tarstype == TARS_TYPE::STRUCT_END as u8 {
+                return Ok(fields);
+            }
+            fields.insert(tag, self.read_value(tarstype)?);
+        }
+    }
+
+    ///读出顶层的全部字段，整个buf是一个结构体的内容(不含STRUCT_BEGIN/END)
+    pub fn read_all(&mut self) -> Result<BTreeMap<u8, TarsValue>, KissError> {
+        let mut fields = BTreeMap::new();
+        while self.pos < self.buf.len() {
+            let (tag, tarstype) = self.read_head()?;
+            fields.insert(tag, self.read_value(tarstype)?);
+        }
+        Ok(fields)
+    }
+}
+
+//-----------------------------------------------------------------------------
+///交易的内容部分，参与hash计算
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Bcos3TransactionData {
+    pub version: i32,
+    pub chainID: String,
+    pub groupID: String,
+    pub blockLimit: i64,
+    pub nonce: String,
+    pub to: String,
+    pub input: Vec<u8>,
+    pub abi: String,
+}
+
+impl Bcos3TransactionData {
+    ///nonce取随机的256位整数的十进制串，和C语言sdk一致
+    pub fn new(chainid: &str, group: &str, blocklimit: i64, to: &str, input: &[u8]) -> Self {
+        let nonce = U256::from_big_endian(&rand::random::<[u8; 32]>());
+        Bcos3TransactionData {
+            version: 0,
+            chainID: chainid.to_string(),
+            groupID: group.to_string(),
+            blockLimit: blocklimit,
+            nonce: nonce.to_string(),
+            to: to.to_string(),
+            input: input.to_vec(),
+            abi: "".to_string(),
+        }
+    }
+
+    ///optional字段是默认值时不写，和tars2cpp生成的代码一致
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = TarsWriter::new();
+        if self.version != 0 {
+            writer.write_int(1, self.version as i64);
+        }
+        if !self.chainID.is_empty() {
+            writer.write_string(2, &self.chainID);
+        }
+        if !self.groupID.is_empty() {
+            writer.write_string(3, &self.groupID);
+        }
+        if self.blockLimit != 0 {
+            writer.write_int(4, self.blockLimit);
+        }
+        if !self.nonce.is_empty() {
+            writer.write_string(5, &self.nonce);
+        }
+        if !self.to.is_empty() {
+            writer.write_string(6, &self.to);
+        }
+        if !self.input.is_empty() {
+            writer.write_bytes(7, &self.input);
+        }
+        if !self.abi.is_empty() {
+            writer.write_string(8, &self.abi);
+        }
+        writer.buf
+    }
+
+    pub fn from_fields(fields: &BTreeMap<u8, TarsValue>) -> Self {
+        let get = |tag: u8| fields.get(&tag).cloned().unwrap_or(TarsValue::Int(0));
+        Bcos3TransactionData {
+            version: get(1).as_int() as i32,
+            chainID: get(2).as_str(),
+            groupID: get(3).as_str(),
+            blockLimit: get(4).as_int(),
+            nonce: get(5).as_str(),
+            to: get(6).as_str(),
+            input: get(7).as_bytes(),
+            abi: get(8).as_str(),
+        }
+    }
+
+    ///3.2以后的节点按字段逐个计算交易hash，整数用大端
+    pub fn hash(&self, hashtype: &HashType) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![];
+        data.extend_from_slice(&self.version.to_be_bytes());
+        data.extend_from_slice(self.chainID.as_bytes());
+        data.extend_from_slice(self.groupID.as_bytes());
+        data.extend_from_slice(&self.blockLimit.to_be_bytes());
+        data.extend_from_slice(self.nonce.as_bytes());
+        data.extend_from_slice(self.to.as_bytes());
+        data.extend_from_slice(&self.input);
+        data.extend_from_slice(self.abi.as_bytes());
+        CommonHash::hash(&data, hashtype)
+    }
+}
+
+///签名后的交易，编码后的hex串即sendTransaction的参数
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Bcos3Transaction {
+    pub data: Bcos3TransactionData,
+    pub dataHash: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub importTime: i64,
+    pub attribute: i32,
+    pub sender: Vec<u8>,
+    pub extraData: String,
+}
+
+impl Bcos3Transaction {
+    ///签名串ecdsa是r,s,v(0或1)共65字节，国密是r,s和64字节的公钥
+    pub fn sign(
+        data: Bcos3TransactionData,
+        account: &BcosAccount,
+        hashtype: &HashType,
+        attribute: i32,
+    ) -> Result<Bcos3Transaction, KissError> {
+        let dataHash = data.hash(hashtype);
+        let signature = match hashtype {
+            HashType::WEDRP_SM3 => {
+                let signer = CommonSignerWeDPR_SM2 {
+                    account: account.clone(),
+                    ..Default::default()
+                };
+                signer.sign(dataHash.clone())?.to_vec()
+            }
+            _ => {
+                let signer = CommonSignerWeDPR_Secp256 {
+                    account: account.clone(),
+                    ..Default::default()
+                };
+                let sig = signer.sign(dataHash.clone())?;
+                //签名器把v调整成了8字节的27/28，这里还原成1字节的0/1
+                let v = u64::from_be_bytes(sig.v.as_slice().try_into().unwrap_or([0; 8]));
+                let mut buffer = sig.r.clone();
+                buffer.extend_from_slice(&sig.s);
+                buffer.push(Secp256Signature::make_stand_v(v) as u8);
+                buffer
+            }
+        };
+        Ok(Bcos3Transaction {
+            data,
+            dataHash,
+            signature,
+            importTime: 0,
+            attribute,
+            sender: vec![],
+            extraData: "".to_string(),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = TarsWriter::new();
+        writer.write_struct(1, &self.data.encode());
+        if !self.dataHash.is_empty() {
+            writer.write_bytes(2, &self.dataHash);
+        }
+        if !self.signature.is_empty() {
+            writer.write_bytes(3, &self.signature);
+        }
+        if self.importTime != 0 {
+            writer.write_int(4, self.importTime);
+        }
+        if self.attribute != 0 {
+            writer.write_int(5, self.attribute as i64);
+        }
+        if !self.sender.is_empty() {
+            writer.write_bytes(7, &self.sender);
+        }
+        if !self.extraData.is_empty() {
+            writer.write_string(8, &self.extraData);
+        }
+        writer.buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Bcos3Transaction, KissError> {
+        let fields = TarsReader::new(buf).read_all()?;
+        let get = |tag: u8| fields.get(&tag).cloned().unwrap_or(TarsValue::Int(0));
+        let data = match fields.get(&1) {
+            Some(TarsValue::Struct(f)) => Bcos3TransactionData::from_fields(f),
+            _ => return kisserr!(KissErrKind::EFormat, "tars transaction has no data"),
+        };
+        Ok(Bcos3Transaction {
+            data,
+            dataHash: get(2).as_bytes(),
+            signature: get(3).as_bytes(),
+            importTime: get(4).as_int(),
+            attribute: get(5).as_int() as i32,
+            sender: get(7).as_bytes(),
+            extraData: get(8).as_str(),
+        })
+    }
+
+    ///交易hash，带0x前缀
+    pub fn hash_hex(&self) -> String {
+        format!("0x{}", hex::encode(&self.dataHash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcossdkutil::accountutil::create_account;
+    use crate::bcossdkutil::bcosclientconfig::BcosCryptoKind;
+    use wedpr_l_crypto_signature_secp256k1::WedprSecp256k1Recover;
+
+    #[test]
+    fn test_tars_encoding() {
+        let mut writer = TarsWriter::new();
+        writer.write_int(1, 0);
+        writer.write_int(2, -2);
+        writer.write_int(3, 1000);
+        writer.write_int(20, 1 << 40);
+        writer.write_string(4, "abc");
+        writer.write_bytes(5, &[1, 2, 3]);
+        assert_eq!(hex::encode(&writer.buf[..8]), "1c20fe3103e8f314");
+        let fields = TarsReader::new(&writer.buf).read_all().unwrap();
+        assert_eq!(fields[&2], TarsValue::Int(-2));
+        assert_eq!(fields[&3], TarsValue::Int(1000));
+        assert_eq!(fields[&20], TarsValue::Int(1 << 40));
+        assert_eq!(fields[&4], TarsValue::Str("abc".to_string()));
+        assert_eq!(fields[&5], TarsValue::Bytes(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_sign_and_decode_transaction() {
+        let account = create_account(&BcosCryptoKind::ECDSA);
+        let hashtype = CommonHash::crypto_to_hashtype(&BcosCryptoKind::ECDSA);
+        let data = Bcos3TransactionData::new("chain0", "group0", 500, "0x1234", &[0xab; 300]);
+        let tx = Bcos3Transaction::sign(data, &account, &hashtype, 0).unwrap();
+        assert_eq!(tx.signature.len(), 65);
+        let decoded = Bcos3Transaction::decode(&tx.encode()).unwrap();
+        assert_eq!(decoded, tx);
+        assert_eq!(decoded.data.hash(&hashtype), tx.dataHash);
+        let recovered = WedprSecp256k1Recover::default()
+            .recover_public_key(&tx.dataHash, &tx.signature)
+            .unwrap();
+        assert_eq!(recovered, account.pubkey);
+    }
+}
@@ -0,0 +1,314 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! BCOS3节点的websocket连接(RFC6455)：http升级握手和帧的编解码。
+//! 只实现sdk用到的部分：客户端发出的帧都加掩码，收到的分片帧由调用者拼接
+use std::convert::TryInto;
+
+use bytes::{Buf, BufMut, BytesMut};
+use openssl::base64;
+use openssl::sha::sha1;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+///计算Sec-WebSocket-Accept用的固定串
+pub const WS_ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+///默认的单帧长度上限
+pub const WS_FRAME_MAX_SIZE: usize = 32 * 1024 * 1024;
+///升级握手的http头最大长度
+pub const WS_UPGRADE_HEADER_MAX: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WS_OPCODE {
+    CONTINUATION = 0x0,
+    TEXT = 0x1,
+    BINARY = 0x2,
+    CLOSE = 0x8,
+    PING = 0x9,
+    PONG = 0xA,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WsFrame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+impl WsFrame {
+    pub fn new(opcode: WS_OPCODE, payload: Vec<u8>) -> WsFrame {
+        WsFrame {
+            fin: true,
+            opcode: opcode as u8,
+            payload,
+        }
+    }
+}
+
+///帧的编解码，mask为true时编码的帧加掩码(客户端)，解码时按帧头的标记处理掩码
+#[derive(Debug, Clone, Copy)]
+pub struct WsFrameCodec {
+    pub mask: bool,
+    pub max_frame_size: usize,
+}
+
+impl WsFrameCodec {
+    pub fn client() -> WsFrameCodec {
+        WsFrameCodec {
+            mask: true,
+            max_frame_size: WS_FRAME_MAX_SIZE,
+        }
+    }
+    pub fn server() -> WsFrameCodec {
+        WsFrameCodec {
+            mask: false,
+            max_frame_size: WS_FRAME_MAX_SIZE,
+        }
+    }
+}
+
+impl Decoder for WsFrameCodec {
+    type Item = WsFrame;
+    type Error = KissError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<WsFrame>, KissError> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        let fin = src[0] & 0x80 != 0;
+        let opcode = src[0] & 0x0F;
+        let masked = src[1] & 0x80 != 0;
+        let (length, mut headerlen) = match src[1] & 0x7F {
+            126 if src.len() < 4 => return Ok(None),
+            126 => (u16::from_be_bytes([src[2], src[3]]) as u64, 4),
+            127 if src.len() < 10 => return Ok(None),
+            127 => (u64::from_be_bytes(src[2..10].try_into().unwrap()), 10),
+            l => (l as u64, 2),
+        };
+        if length > self.max_frame_size as u64 {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "websocket frame length {} exceeds max {}",
+                length,
+                self.max_frame_size
+            );
+        }
+        let maskpos = headerlen;
+        if masked {
+            headerlen += 4;
+        }
+        let total = headerlen + length as usize;
+        if src.len() < total {
+            src.reserve(total - src.len());
+            return Ok(None);
+        }
+        //服务端发来的帧不带掩码，只有masked时才有4字节的掩码
+        let maskkey: Option<Vec<u8>> = if masked {
+            Some(src[maskpos..maskpos + 4].to_vec())
+        } else {
+            None
+        };
+        src.advance(headerlen);
+        let mut payload = src.split_to(length as usize).to_vec();
+        if let Some(maskkey) = maskkey {
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= maskkey[i % 4];
+            }
+        }
+        Ok(Some(WsFrame {
+            fin,
+            opcode,
+            payload,
+        }))
+    }
+}
+
+impl Encoder<WsFrame> for WsFrameCodec {
+    type Error = KissError;
+
+    fn encode(&mut self, frame: WsFrame, dst: &mut BytesMut) -> Result<(), KissError> {
+        let len = frame.payload.len();
+        let maskbit = if self.mask { 0x80 } else { 0 };
+        dst.reserve(len + 14);
+        dst.put_u8(if frame.fin { 0x80 } else { 0 } | (frame.opcode & 0x0F));
+        if len < 126 {
+            dst.put_u8(maskbit | len as u8);
+        } else if len <= u16::MAX as usize {
+            dst.put_u8(maskbit | 126);
+            dst.put_u16(len as u16);
+        } else {
+            dst.put_u8(maskbit | 127);
+            dst.put_u64(len as u64);
+        }
+        if self.mask {
+            let maskkey: [u8; 4] = rand::random();
+            dst.put_slice(&maskkey);
+            dst.extend(
+                frame
+                    .payload
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| b ^ maskkey[i % 4]),
+            );
+        } else {
+            dst.put_slice(&frame.payload);
+        }
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------------------------------
+///随机生成Sec-WebSocket-Key
+pub fn make_websocket_key() -> String {
+    base64::encode_block(&rand::random::<[u8; 16]>())
+}
+
+///服务端按key应答的Sec-WebSocket-Accept
+pub fn websocket_accept_key(key: &str) -> String {
+    base64::encode_block(&sha1(format!("{}{}", key, WS_ACCEPT_GUID).as_bytes()))
+}
+
+pub fn make_upgrade_request(host: &str, key: &str) -> String {
+    format!(
+        "GET / HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        host, key
+    )
+}
+
+pub fn make_upgrade_response(key: &str) -> String {
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        websocket_accept_key(key)
+    )
+}
+
+///从缓冲区头部取出一段完整的http头，数据不够时返回None，取出后缓冲区里只剩后面的数据
+pub fn pop_http_header(src: &mut BytesMut) -> Result<Option<String>, KissError> {
+    match src.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => {
+            let header = src.split_to(pos + 4);
+            Ok(Some(String::from_utf8_lossy(&header).to_string()))
+        }
+        None if src.len() > WS_UPGRADE_HEADER_MAX => {
+            kisserr!(KissErrKind::EFormat, "websocket upgrade header too long")
+        }
+        None => Ok(None),
+    }
+}
+
+///按名字取http头的值，名字不区分大小写
+pub fn http_header_value(header: &str, name: &str) -> Option<String> {
+    header.lines().skip(1).find_map(|line| {
+        let (n, v) = line.split_once(':')?;
+        if n.trim().eq_ignore_ascii_case(name) {
+            Some(v.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+///检查升级握手的应答：状态码101，且accept和发出的key对得上
+pub fn check_upgrade_response(header: &str, key: &str) -> Result<(), KissError> {
+    let status = header.lines().next().unwrap_or("");
+    if status.split_whitespace().nth(1) != Some("101") {
+        return kisserr!(
+            KissErrKind::ENetwork,
+            "websocket upgrade refused: {}",
+            status
+        );
+    }
+    let accept = http_header_value(header, "Sec-WebSocket-Accept").unwrap_or_default();
+    if accept != websocket_accept_key(key) {
+        return kisserr!(
+            KissErrKind::ENetwork,
+            "websocket upgrade bad accept key {}",
+            accept
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_codec() {
+        //RFC6455里的例子
+        assert_eq!(
+            websocket_accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        let mut buf = BytesMut::new();
+        for len in [5usize, 300, 70000] {
+            let frame = WsFrame::new(WS_OPCODE::BINARY, vec![7u8; len]);
+            WsFrameCodec::client()
+                .encode(frame.clone(), &mut buf)
+                .unwrap();
+            let half = buf.split_to(buf.len() / 2);
+            let mut partial = half.clone();
+            assert_eq!(WsFrameCodec::server().decode(&mut partial).unwrap(), None);
+            let mut whole = half;
+            whole.unsplit(buf.split());
+            assert_eq!(
+                WsFrameCodec::server().decode(&mut whole).unwrap(),
+                Some(frame)
+            );
+            assert!(whole.is_empty());
+        }
+        let mut codec = WsFrameCodec::server();
+        codec.max_frame_size = 10;
+        let mut buf = BytesMut::from(&[0x82u8, 126, 0, 200][..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().kind,
+            KissErrKind::EFormat
+        );
+    }
+
+    #[test]
+    fn test_decode_short_unmasked_frames() {
+        //单独一个空的PONG，后面没有别的数据
+        let mut buf = BytesMut::from(&[0x8Au8, 0][..]);
+        assert_eq!(
+            WsFrameCodec::client().decode(&mut buf).unwrap(),
+            Some(WsFrame::new(WS_OPCODE::PONG, vec![]))
+        );
+        assert!(buf.is_empty());
+        //1到3字节的不带掩码的帧，包括2字节的CLOSE
+        for len in 1..4usize {
+            let frame = WsFrame::new(WS_OPCODE::CLOSE, vec![3u8; len]);
+            let mut buf = BytesMut::new();
+            WsFrameCodec::server()
+                .encode(frame.clone(), &mut buf)
+                .unwrap();
+            assert_eq!(buf.len(), 2 + len);
+            assert_eq!(
+                WsFrameCodec::client().decode(&mut buf).unwrap(),
+                Some(frame)
+            );
+            assert!(buf.is_empty());
+        }
+    }
+}
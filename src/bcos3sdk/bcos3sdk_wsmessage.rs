@@ -0,0 +1,236 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! BCOS3节点(boost-ssl)在websocket二进制帧里传输的消息。格式(整数都是大端)：
+//! type(2) status(2) seq长度(2) seq ext(2) payload，seq是32个字符的随机hex串，回包和请求的seq相同
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+///消息类型，参见bcos-cpp-sdk的ws/Common.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BCOS3_WS_MSG_TYPE {
+    HANDSHAKE = 0x100,
+    BLOCK_NOTIFY = 0x101,
+    RPC_REQUEST = 0x102,
+    GROUP_NOTIFY = 0x103,
+    AMOP_SUBTOPIC = 0x110,
+    AMOP_REQUEST = 0x111,
+    AMOP_BROADCAST = 0x112,
+    AMOP_RESPONSE = 0x113,
+    EVENT_SUBSCRIBE = 0x120,
+    EVENT_UNSUBSCRIBE = 0x121,
+    EVENT_LOG_PUSH = 0x122,
+}
+
+///sdk这一侧支持的协议版本范围
+pub const BCOS3_PROTOCOL_MIN: i32 = 1;
+pub const BCOS3_PROTOCOL_MAX: i32 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bcos3WsMessage {
+    pub msgtype: u16,
+    pub status: i16,
+    pub seq: String,
+    pub ext: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Bcos3WsMessage {
+    ///用新的随机seq构建一个请求
+    pub fn new(msgtype: BCOS3_WS_MSG_TYPE, payload: Vec<u8>) -> Bcos3WsMessage {
+        Bcos3WsMessage {
+            msgtype: msgtype as u16,
+            status: 0,
+            seq: hex::encode(rand::random::<[u8; 16]>()),
+            ext: 0,
+            payload,
+        }
+    }
+
+    ///回包，type和seq和请求一致
+    pub fn reply(&self, payload: Vec<u8>) -> Bcos3WsMessage {
+        Bcos3WsMessage {
+            msgtype: self.msgtype,
+            status: 0,
+            seq: self.seq.clone(),
+            ext: self.ext,
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(8 + self.seq.len() + self.payload.len());
+        buffer.extend_from_slice(&self.msgtype.to_be_bytes());
+        buffer.extend_from_slice(&self.status.to_be_bytes());
+        buffer.extend_from_slice(&(self.seq.len() as u16).to_be_bytes());
+        buffer.extend_from_slice(self.seq.as_bytes());
+        buffer.extend_from_slice(&self.ext.to_be_bytes());
+        buffer.extend_from_slice(&self.payload);
+        buffer
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Bcos3WsMessage, KissError> {
+        if data.len() < 8 {
+            return kisserr!(KissErrKind::EFormat, "ws message too short {}", data.len());
+        }
+        let seqlen = u16::from_be_bytes([data[4], data[5]]) as usize;
+        if data.len() < 8 + seqlen {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "ws message seq length {} exceeds data {}",
+                seqlen,
+                data.len()
+            );
+        }
+        let extpos = 6 + seqlen;
+        Ok(Bcos3WsMessage {
+            msgtype: u16::from_be_bytes([data[0], data[1]]),
+            status: i16::from_be_bytes([data[2], data[3]]),
+            seq: String::from_utf8_lossy(&data[6..extpos]).to_string(),
+            ext: u16::from_be_bytes([data[extpos], data[extpos + 1]]),
+            payload: data[extpos + 2..].to_vec(),
+        })
+    }
+
+    pub fn payload_json(&self) -> Result<JsonValue, KissError> {
+        match serde_json::from_slice(&self.payload) {
+            Ok(v) => Ok(v),
+            Err(e) => kisserr!(
+                KissErrKind::EFormat,
+                "ws message 0x{:x} payload is not json {:?}",
+                self.msgtype,
+                e
+            ),
+        }
+    }
+}
+
+///连接后sdk发出的握手请求
+pub fn make_handshake_message() -> Bcos3WsMessage {
+    let payload = json!({
+        "minimumSupport": BCOS3_PROTOCOL_MIN,
+        "maximumSupport": BCOS3_PROTOCOL_MAX,
+    });
+    Bcos3WsMessage::new(
+        BCOS3_WS_MSG_TYPE::HANDSHAKE,
+        payload.to_string().into_bytes(),
+    )
+}
+
+///群组信息里sdk关心的部分，从握手应答和GROUP_NOTIFY里得到
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bcos3GroupInfo {
+    pub chainID: String,
+    pub groupID: String,
+    pub smCrypto: bool,
+    pub wasm: bool,
+    pub blockNumber: i64,
+}
+
+impl Bcos3GroupInfo {
+    ///genesisConfig里的字段名各版本不完全一样，两种都认
+    pub fn from_json(v: &JsonValue) -> Bcos3GroupInfo {
+        let genesis = &v["genesisConfig"];
+        let flag = |a: &str, b: &str| {
+            genesis[a]
+                .as_bool()
+                .or_else(|| genesis[b].as_bool())
+                .unwrap_or(false)
+        };
+        Bcos3GroupInfo {
+            chainID: v["chainID"].as_str().unwrap_or("").to_string(),
+            groupID: v["groupID"].as_str().unwrap_or("").to_string(),
+            smCrypto: flag("smCrypto", "sm_crypto"),
+            wasm: flag("isWasm", "is_wasm"),
+            blockNumber: 0,
+        }
+    }
+}
+
+///握手应答：协商的协议版本、节点上的群组和各群组的块高
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bcos3HandshakeResponse {
+    #[serde(default)]
+    pub protocolVersion: i32,
+    #[serde(default)]
+    pub groupInfoList: Vec<JsonValue>,
+    #[serde(default)]
+    pub groupBlockNumber: Vec<JsonValue>,
+}
+
+impl Bcos3HandshakeResponse {
+    pub fn from_message(msg: &Bcos3WsMessage) -> Result<Bcos3HandshakeResponse, KissError> {
+        match serde_json::from_slice(&msg.payload) {
+            Ok(r) => Ok(r),
+            Err(e) => kisserr!(KissErrKind::EFormat, "bad handshake response {:?}", e),
+        }
+    }
+
+    ///groupBlockNumber的每一项形如{"group0": 10}
+    pub fn group_infos(&self) -> Vec<Bcos3GroupInfo> {
+        let mut infos: Vec<Bcos3GroupInfo> = self
+            .groupInfoList
+            .iter()
+            .map(Bcos3GroupInfo::from_json)
+            .collect();
+        for item in self.groupBlockNumber.iter() {
+            if let Some(obj) = item.as_object() {
+                for (group, number) in obj.iter() {
+                    if let Some(info) = infos.iter_mut().find(|i| &i.groupID == group) {
+                        info.blockNumber = number.as_i64().unwrap_or(0);
+                    }
+                }
+            }
+        }
+        infos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ws_message() {
+        let msg = Bcos3WsMessage::new(BCOS3_WS_MSG_TYPE::RPC_REQUEST, b"{}".to_vec());
+        assert_eq!(msg.seq.len(), 32);
+        let data = msg.encode();
+        assert_eq!(&data[..2], &[0x01, 0x02]);
+        assert_eq!(Bcos3WsMessage::decode(&data).unwrap(), msg);
+        assert!(Bcos3WsMessage::decode(&data[..20]).is_err());
+
+        let response = json!({
+            "protocolVersion": 1,
+            "groupInfoList": [{"chainID": "chain0", "groupID": "group0",
+                "genesisConfig": {"smCrypto": true, "isWasm": false}}],
+            "groupBlockNumber": [{"group0": 12}]
+        });
+        let reply = msg.reply(response.to_string().into_bytes());
+        let infos = Bcos3HandshakeResponse::from_message(&reply)
+            .unwrap()
+            .group_infos();
+        assert_eq!(infos[0].chainID, "chain0");
+        assert!(infos[0].smCrypto);
+        assert_eq!(infos[0].blockNumber, 12);
+    }
+}
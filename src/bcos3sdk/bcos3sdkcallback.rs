@@ -0,0 +1,181 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! c sdk回调上下文的全局登记表
+//! 传给c sdk的context不再是rust对象的指针，而是登记表里的u64句柄。回调时按句柄查找处理函数，
+//! 句柄已注销(等待方超时、放弃)或者重复回调时，查不到就直接丢弃，不会访问已释放的内存。
+//! 一次性的请求用register_once，回调一次后自动注销；事件订阅这类长期的回调用register_callback，
+//! 处理函数由登记表持有，直到unregister_callback
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+
+use crate::bcos3sdk::bcos3sdkresponse::{bcos_sdk_c_struct_response, Bcos3SDKResponse};
+
+pub type Bcos3CallbackHandler = Arc<dyn Fn(Bcos3SDKResponse) + Send + Sync>;
+
+struct Bcos3CallbackEntry {
+    handler: Bcos3CallbackHandler,
+    once: bool,
+}
+
+lazy_static! {
+    static ref BCOS3_CALLBACKS: Mutex<HashMap<u64, Bcos3CallbackEntry>> =
+        Mutex::new(HashMap::new());
+}
+
+//0留给"没有context"，句柄从1开始
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+fn register(handler: Bcos3CallbackHandler, once: bool) -> u64 {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    BCOS3_CALLBACKS
+        .lock()
+        .unwrap()
+        .insert(handle, Bcos3CallbackEntry { handler, once });
+    handle
+}
+
+///登记一个长期的回调，返回句柄，直到unregister_callback都会被调用
+pub fn register_callback<F>(handler: F) -> u64
+where
+    F: Fn(Bcos3SDKResponse) + Send + Sync + 'static,
+{
+    register(Arc::new(handler), false)
+}
+
+///登记一个只回调一次的处理函数，回调后自动注销
+pub fn register_once<F>(handler: F) -> u64
+where
+    F: Fn(Bcos3SDKResponse) + Send + Sync + 'static,
+{
+    register(Arc::new(handler), true)
+}
+
+///注销句柄，之后到达的回调都被丢弃。返回句柄之前是否还在登记表里
+pub fn unregister_callback(handle: u64) -> bool {
+    BCOS3_CALLBACKS.lock().unwrap().remove(&handle).is_some()
+}
+
+pub fn is_registered(handle: u64) -> bool {
+    BCOS3_CALLBACKS.lock().unwrap().contains_key(&handle)
+}
+
+pub fn registered_count() -> usize {
+    BCOS3_CALLBACKS.lock().unwrap().len()
+}
+
+///句柄按值放进c sdk的context参数里，c sdk只是原样带回，不会解引用
+pub fn handle_to_context(handle: u64) -> *const c_void {
+    handle as usize as *const c_void
+}
+
+pub fn context_to_handle(context: *const c_void) -> u64 {
+    context as usize as u64
+}
+
+///按句柄分发回调。处理函数在登记表的锁外调用，处理函数里可以再登记或注销
+pub fn dispatch(handle: u64, response: Bcos3SDKResponse) -> bool {
+    let handler = {
+        let mut callbacks = BCOS3_CALLBACKS.lock().unwrap();
+        let once = match callbacks.get(&handle) {
+            Some(entry) => entry.once,
+            None => return false,
+        };
+        if once {
+            callbacks.remove(&handle).map(|entry| entry.handler)
+        } else {
+            callbacks.get(&handle).map(|entry| entry.handler.clone())
+        }
+    };
+    match handler {
+        Some(h) => {
+            h(response);
+            true
+        }
+        None => false,
+    }
+}
+
+///传给c sdk的回调函数，context必须是handle_to_context得到的句柄
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn bcos_callback(resp: *const bcos_sdk_c_struct_response) {
+    if resp.is_null() {
+        return;
+    }
+    unsafe {
+        let handle = context_to_handle((*resp).context);
+        if handle == 0 {
+            return;
+        }
+        let response = Bcos3SDKResponse::from_callback(resp);
+        if !dispatch(handle, response) {
+            log::debug!("drop bcos3 callback for unknown handle {}", handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    use std::sync::atomic::AtomicUsize;
+
+    fn fake_response(handle: u64) -> bcos_sdk_c_struct_response {
+        bcos_sdk_c_struct_response {
+            error: 0,
+            desc: ptr::null(),
+            data: b"{\"result\":1}\0".as_ptr() as *const c_void,
+            size: 12,
+            context: handle_to_context(handle),
+        }
+    }
+
+    #[test]
+    fn test_callback_registry() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let h = hits.clone();
+        let once = register_once(move |_| {
+            h.fetch_add(1, Ordering::SeqCst);
+        });
+        let h = hits.clone();
+        let sub = register_callback(move |resp| {
+            assert_eq!(resp.data, "{\"result\":1}");
+            h.fetch_add(10, Ordering::SeqCst);
+        });
+
+        //一次性的句柄只回调一次，重复的回调被丢弃
+        bcos_callback(&fake_response(once));
+        bcos_callback(&fake_response(once));
+        assert!(!is_registered(once));
+        //长期的句柄一直有效，直到注销
+        bcos_callback(&fake_response(sub));
+        bcos_callback(&fake_response(sub));
+        assert!(unregister_callback(sub));
+        bcos_callback(&fake_response(sub));
+        //不认识的句柄和空context都直接丢弃
+        bcos_callback(&fake_response(u64::MAX));
+        bcos_callback(&fake_response(0));
+        assert_eq!(hits.load(Ordering::SeqCst), 21);
+    }
+}
@@ -3,20 +3,30 @@
 
 基本原理是通过mpsc的sender/receiver，当收到回调时fire一个消息到send队列里，同步等待的receiver即可得到消息
 
-可以将此future的对象指针直接传给sdk的回调context，也可以本地全局化一个mapping之类的，把key传给sdk，在回调时用key去找到这个future对象
+传给sdk的回调context是bcos3sdkcallback登记表里的u64句柄，不是future对象的指针。future释放时注销句柄，
+之后迟到的回调查不到句柄，直接丢弃，不会访问已释放的内存
 
 这里的实现仅供参考。在多线程、异步编程时，可以根据sdk的callback定义，自行实现灵活的回调处理
 
+Bcos3SDKAsyncFuture是给async代码用的版本：回调里通过tokio的oneshot把结果交给await方，不阻塞调用线程
+
 */
 
 use std::ffi::c_void;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
 
 use serde_json::Value as JsonValue;
+use tokio::sync::oneshot;
+use tokio::time::Sleep;
 
+use crate::bcos3sdk::bcos3sdkcallback;
 use crate::bcos3sdk::bcos3sdkresponse::{bcos_sdk_c_struct_response, Bcos3SDKResponse};
 use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
 use crate::kisserrcode;
@@ -27,7 +37,8 @@ pub struct Bcos3SDKFuture {
     pub seq: u64,
     pub name: String,
     pub desc: String,
-    pub timeout: u64, //等待返回的超时，默认为5秒，可以动态修改或配置化
+    pub timeout: u64, //等待返回的超时(秒)，默认为5秒，Bcos3Client按请求策略设置
+    pub handle: u64,  //回调登记表里的句柄，作为context传给c sdk
     // 描述
     pub tx: Sender<Bcos3SDKResponse>,
     pub rx: Receiver<Bcos3SDKResponse>,
@@ -42,34 +53,28 @@ impl Bcos3SDKFuture {
     //使用mpsc::channel组件实现异步回调的等待
     pub fn create(seq: u64, name: &str, desc: &str) -> Self {
         let (tx, rx) = mpsc::channel();
+        //登记的是持久回调，事件订阅等会多次回调，future释放时注销
+        let cbtx = Mutex::new(tx.clone());
+        let handle = bcos3sdkcallback::register_callback(move |resp| {
+            let _ = cbtx.lock().unwrap().send(resp);
+        });
 
         let future_context = Bcos3SDKFuture {
             seq: seq,
             name: name.to_string(),
             desc: desc.to_string(),
             timeout: 5,
+            handle: handle,
             tx: tx,
             rx: rx,
         };
         future_context
     }
 
-    //和C结构体指针互转，裸指针逻辑
-    //* C语言sdk要求传入指针，而回调的生命周期范围是由C语言SDK决定的
-    //* 使用这个指针时，必须保证指针指向的对象 活着，没有被释放，没有被borrow掉，
-    //* 保持简单，不要用box into_raw/from_raw这些“智能指针”，因为其内存管理可能会不符合C的预期，比如用一次再用就是double free
-    //* 如果自行alloc，比如在堆上，要记得释放
-    //* 如果要在rust里跨线程，要注意跨线程变量的生命周期范围，搞清楚内存模型
-    //* 如果要放到容器里，也要注意容器的clone,borrow等细节
-    //* 改成string,int什么的，效果也是一样的，在内存管理和生命周期管理方面没区别
-    //* 唯一比较安全的可能是用u64，指针本身就是u64值传递，然后再用这个u64值来查找对应关系
-    //* 总之，unsafe，小心！
+    //传给c sdk的context是登记表的句柄，按值传递，c sdk只原样带回。
+    //future被释放(比如wait超时后函数返回)时句柄随之注销，迟到的回调会被登记表丢弃
     pub fn to_c_ptr(c: &Self) -> *const c_void {
-        return c as *const Bcos3SDKFuture as *const c_void;
-    }
-
-    pub fn from_c_ptr(ptr: *const c_void) -> *const Bcos3SDKFuture {
-        return ptr as *const Bcos3SDKFuture;
+        bcos3sdkcallback::handle_to_context(c.handle)
     }
 
     pub fn display(&self) {
@@ -80,16 +85,9 @@ impl Bcos3SDKFuture {
     }
 
     //在rust里，要给c的回调传入method, not a field，跟python sdk的实现对比，和python可以将某个对象的方法地址传给c不同了。
-    //所以在这个回调方法里，reponse 里的context给的是future本身，以实例化该对象后，进行异步应答（mpsc::channel)
-
+    //所以在这个回调方法里，按reponse里的context句柄去登记表里找到对应的future，进行异步应答（mpsc::channel)
     pub extern "C" fn bcos_callback(resp: *const bcos_sdk_c_struct_response) {
-        unsafe {
-            let response = Bcos3SDKResponse::from_callback(resp);
-            if !(*resp).context.is_null() {
-                //强行的指针转换成struct指针，必须在生命周期内，其实是挺危险的。
-                (*Bcos3SDKFuture::from_c_ptr((*resp).context)).fire(&response)
-            }
-        }
+        bcos3sdkcallback::bcos_callback(resp)
     }
     pub fn fire(&self, resp: &Bcos3SDKResponse) {
         let res = self.tx.send(resp.clone());
@@ -115,3 +113,85 @@ impl Bcos3SDKFuture {
         response.get_result()
     }
 }
+
+impl Drop for Bcos3SDKFuture {
+    fn drop(&mut self) {
+        bcos3sdkcallback::unregister_callback(self.handle);
+    }
+}
+
+///异步等待c sdk回调的Future，await得到的是response里的["result"]段。
+/// 调用方先用create得到future和context，把context和bcos_callback传给c sdk的接口，再await这个future
+pub struct Bcos3SDKAsyncFuture {
+    pub seq: u64,
+    pub name: String,
+    pub timeout: u64, //等待回调的超时(秒)，从第一次poll开始计时，Bcos3Client按请求策略设置
+    pub handle: u64,
+    rx: oneshot::Receiver<Result<JsonValue, KissError>>,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl Bcos3SDKAsyncFuture {
+    pub fn create(seq: u64, name: &str) -> (Bcos3SDKAsyncFuture, *const c_void) {
+        let (tx, rx) = oneshot::channel();
+        let tx = Mutex::new(Some(tx));
+        let handle = bcos3sdkcallback::register_once(move |resp: Bcos3SDKResponse| {
+            if let Some(tx) = tx.lock().unwrap().take() {
+                let _ = tx.send(resp.get_result());
+            }
+        });
+        let future = Bcos3SDKAsyncFuture {
+            seq,
+            name: name.to_string(),
+            timeout: 5,
+            handle,
+            rx,
+            deadline: None,
+        };
+        (future, bcos3sdkcallback::handle_to_context(handle))
+    }
+
+    //回调只会送达一次，await方已经超时或放弃时句柄已注销，结果直接丢弃
+    pub extern "C" fn bcos_callback(resp: *const bcos_sdk_c_struct_response) {
+        bcos3sdkcallback::bcos_callback(resp)
+    }
+}
+
+impl Drop for Bcos3SDKAsyncFuture {
+    fn drop(&mut self) {
+        bcos3sdkcallback::unregister_callback(self.handle);
+    }
+}
+
+impl Future for Bcos3SDKAsyncFuture {
+    type Output = Result<JsonValue, KissError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        match Pin::new(&mut this.rx).poll(cx) {
+            Poll::Ready(Ok(result)) => return Poll::Ready(result),
+            Poll::Ready(Err(_)) => {
+                return Poll::Ready(kisserrcode!(
+                    KissErrKind::Error,
+                    -1,
+                    "{} callback dropped",
+                    this.name
+                ))
+            }
+            Poll::Pending => {}
+        }
+        let timeout = this.timeout;
+        let deadline = this.deadline.get_or_insert_with(|| {
+            Box::pin(tokio::time::sleep(std::time::Duration::from_secs(timeout)))
+        });
+        match deadline.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(kisserrcode!(
+                KissErrKind::ETimeout,
+                -1,
+                "{} timeout",
+                this.name
+            )),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -36,6 +36,9 @@ pub struct Bcos3SDKResponse {
     pub context_pointer: *const c_void,
 }
 
+//context_pointer只是调用时传入的context值(回调登记表的句柄)，不会被解引用，可以跨线程传递
+unsafe impl Send for Bcos3SDKResponse {}
+
 impl Bcos3SDKResponse {
     pub fn display(&self) {
         //println!("data {}", self.data);
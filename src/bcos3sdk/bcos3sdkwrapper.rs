@@ -16,6 +16,7 @@ use std::ffi::CString;
 use serde::{Deserialize, Serialize};
 
 use crate::bcos3sdk::bcos3sdkresponse::bcos_sdk_c_struct_response;
+use crate::bcossdkutil::kisserror::KissError;
 
 use crate::str2p;
 
@@ -36,7 +37,10 @@ pub struct EventSubParam {
 }
 
 //ffi方式链接bcos-c-sdk库且映射C API
-#[cfg(feature = "bcos3sdk_ffi")]
+#[cfg(all(
+    feature = "bcos3sdk_ffi",
+    not(any(feature = "bcos3sdk_native", feature = "bcos3sdk_dylib"))
+))]
 pub mod bcos3sdk_def {
 
     use crate::bcos3sdk::bcos3sdkwrapper::{
@@ -314,7 +318,11 @@ pub mod bcos3sdk_def {
 
 //----------------------------------------------------------
 //当未定义bcos3sdk_ffi时，声明一些unsafe的C语言库接口的“桩”方法，"骗过编"译器，实际上等于没有链接库，什么都做不了
-#[cfg(not(feature = "bcos3sdk_ffi"))]
+#[cfg(not(any(
+    feature = "bcos3sdk_ffi",
+    feature = "bcos3sdk_native",
+    feature = "bcos3sdk_dylib"
+)))]
 pub mod bcos3sdk_def {
     use crate::bcos3sdk::bcos3sdkwrapper::{
         BCOS3SDK_AMOP_SUB_CALLBACK_FUNC, BCOS3SDK_CALLBACK_FUNC,
@@ -633,6 +641,21 @@ pub mod bcos3sdk_def {
     pub unsafe fn bcos_sdk_c_free(p: *const c_void) {}
 }
 
+//开启bcos3sdk_native时用纯rust实现的同名接口，不需要链接bcos-c-sdk库
+#[cfg(feature = "bcos3sdk_native")]
+pub use crate::bcos3sdk::bcos3sdk_nativeapi as bcos3sdk_def;
+
+//开启bcos3sdk_dylib时运行时加载bcos-c-sdk动态库，编译和链接时都不需要这个库
+#[cfg(all(feature = "bcos3sdk_dylib", not(feature = "bcos3sdk_native")))]
+pub use crate::bcos3sdk::bcos3sdk_dylib as bcos3sdk_def;
+
+///bcos3sdk_dylib方式下按路径加载bcos-c-sdk动态库，path为空时用默认库名；其他方式下什么都不做
+pub fn load_bcos3sdk_lib(path: &str) -> Result<(), KissError> {
+    #[cfg(all(feature = "bcos3sdk_dylib", not(feature = "bcos3sdk_native")))]
+    crate::bcos3sdk::bcos3sdk_dylib::load_library(path)?;
+    Ok(())
+}
+
 use crate::bcos3sdk::bcos3sdkwrapper::bcos3sdk_def::{
     bcos_sdk_create_by_config_file, bcos_sdk_start,
 };
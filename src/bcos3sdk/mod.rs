@@ -8,8 +8,16 @@
   @date: 2021-07
 */
 pub mod bcos3client;
+pub mod bcos3client_async;
 pub mod bcos3sdkamop;
+pub mod bcos3sdkcallback;
 pub mod bcos3sdkfuture;
 pub mod bcos3sdkresponse;
 pub mod bcos3sdkwrapper;
 pub mod bcos3sdk_ini;
+pub mod bcos3sdk_dylib;
+pub mod bcos3sdk_native;
+pub mod bcos3sdk_nativeapi;
+pub mod bcos3sdk_tars;
+pub mod bcos3sdk_websocket;
+pub mod bcos3sdk_wsmessage;
@@ -67,12 +67,17 @@ pub fn save_key_to_pem(key: &Vec<u8>, pemfile: &str) -> Result<(), KissError> {
 
 ///从pem文件加载私钥
 pub fn load_key_from_pem(pemfile: &str) -> Result<Vec<u8>, KissError> {
-    let key = fileutils::read_all(pemfile)?;
+    let key = fileutils::read_pem(pemfile)?;
     let pemres = pem::parse(key);
     match pemres {
         Ok(pem) => Ok(pem.contents),
         Err(e) => {
-            kisserr!(KissErrKind::EFormat, "load pem {:?} error {:?}", pemfile, e)
+            kisserr!(
+                KissErrKind::EFormat,
+                "load pem {:?} error {:?}",
+                fileutils::pem_source_name(pemfile),
+                e
+            )
         }
     }
 }
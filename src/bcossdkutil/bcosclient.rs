@@ -0,0 +1,546 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! BCOS2和BCOS3客户端的公共接口，应用代码面向IBcosClient编程，不用再区分链的版本
+//! 所有返回的json都是节点返回的["result"]段，BCOS2的jsonrpc外壳在这里剥掉
+//! 发交易和部署都等到回执再返回，返回的是回执
+use std::convert::TryFrom;
+
+use serde_json::{json, Value as JsonValue};
+
+use crate::bcos2sdk::bcos2client::Bcos2Client;
+use crate::bcos3sdk::bcos3client::Bcos3Client;
+use crate::bcossdkutil::bcosclientconfig::{BcosClientProtocol, ClientConfig, RequestPolicy};
+use crate::bcossdkutil::bcosmodels::{
+    parse_model, Block, BlockHeader, ConsensusStatus, GroupInfo, NodeVersion, SyncStatus,
+    Transaction, TransactionReceipt,
+};
+use crate::bcossdkutil::commonhash::CommonHash;
+use crate::bcossdkutil::contractabi::ContractABI;
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+
+pub trait IBcosClient: Send {
+    fn config(&self) -> &ClientConfig;
+    fn protocol(&self) -> BcosClientProtocol;
+    ///当前群组，BCOS2为groupid的十进制字符串，BCOS3为group名
+    fn group(&self) -> String;
+    ///切换到同一连接上的另一个群组
+    fn set_group(&mut self, group: &str) -> Result<(), KissError>;
+    ///当前生效的请求策略(超时、重试)，初始为配置里的policy
+    fn policy(&self) -> &RequestPolicy;
+    fn set_policy(&mut self, policy: RequestPolicy);
+
+    fn get_block_number(&mut self) -> Result<u64, KissError>;
+    fn get_block_limit(&mut self) -> Result<u64, KissError>;
+    ///include_transactions为false时块里只带交易hash
+    fn get_block_by_number(
+        &mut self,
+        num: u64,
+        include_transactions: bool,
+    ) -> Result<JsonValue, KissError>;
+    fn get_block_by_hash(
+        &mut self,
+        blockhash: &str,
+        include_transactions: bool,
+    ) -> Result<JsonValue, KissError>;
+    fn get_block_hash_by_number(&mut self, num: u64) -> Result<String, KissError>;
+    fn get_transaction_by_hash(&mut self, txhash: &str) -> Result<JsonValue, KissError>;
+    fn get_transaction_receipt(&mut self, txhash: &str) -> Result<JsonValue, KissError>;
+    fn get_code(&mut self, address: &str) -> Result<JsonValue, KissError>;
+    fn get_system_config_by_key(&mut self, key: &str) -> Result<JsonValue, KissError>;
+    fn get_sync_status(&mut self) -> Result<JsonValue, KissError>;
+    fn get_consensus_status(&mut self) -> Result<JsonValue, KissError>;
+    fn get_node_version(&mut self) -> Result<JsonValue, KissError>;
+    fn get_group_info(&mut self) -> Result<JsonValue, KissError>;
+
+    fn call(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        method: &str,
+        params: &[String],
+    ) -> Result<JsonValue, KissError>;
+    ///发交易并等待回执
+    fn send_transaction(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        method: &str,
+        params: &[String],
+    ) -> Result<JsonValue, KissError>;
+    ///部署合约并等待回执，hexcode里已经拼好构造函数的参数
+    fn deploy_hexcode(&mut self, hexcode: &str) -> Result<JsonValue, KissError>;
+
+    ///按合约名从contractpath加载bin和abi，拼装构造函数参数后部署
+    fn deploy_withparam(
+        &mut self,
+        contractname: &str,
+        params: &[String],
+    ) -> Result<JsonValue, KissError> {
+        let contractpath = self.config().common.contractpath.clone();
+        let hashtype = CommonHash::crypto_to_hashtype(&self.config().common.crypto);
+        let contract = ContractABI::new_by_name(contractname, contractpath.as_str(), &hashtype)?;
+        let paramcode = match contract.encode_construtor_input(vec![], params, true) {
+            Ok(code) => code,
+            Err(e) => {
+                return kisserr!(
+                    KissErrKind::EFormat,
+                    "encode constructor of {} error {:?}",
+                    contractname,
+                    e
+                )
+            }
+        };
+        let binfile = format!("{}/{}.bin", contractpath, contractname);
+        let hexcode = fileutils::readstring(binfile.as_str())?;
+        self.deploy_hexcode(format!("{}{}", hexcode.trim(), paramcode).as_str())
+    }
+
+    fn finish(&mut self);
+
+    //------------------类型化的查询，见bcosmodels-------------------------------
+    fn get_block_by_number_typed(
+        &mut self,
+        num: u64,
+        include_transactions: bool,
+    ) -> Result<Block, KissError> {
+        parse_model(&self.get_block_by_number(num, include_transactions)?)
+    }
+    fn get_block_by_hash_typed(
+        &mut self,
+        blockhash: &str,
+        include_transactions: bool,
+    ) -> Result<Block, KissError> {
+        parse_model(&self.get_block_by_hash(blockhash, include_transactions)?)
+    }
+    fn get_block_header_by_number(&mut self, num: u64) -> Result<BlockHeader, KissError> {
+        parse_model(&self.get_block_by_number(num, false)?)
+    }
+    fn get_transaction_by_hash_typed(&mut self, txhash: &str) -> Result<Transaction, KissError> {
+        parse_model(&self.get_transaction_by_hash(txhash)?)
+    }
+    fn get_transaction_receipt_typed(
+        &mut self,
+        txhash: &str,
+    ) -> Result<TransactionReceipt, KissError> {
+        parse_model(&self.get_transaction_receipt(txhash)?)
+    }
+    fn get_sync_status_typed(&mut self) -> Result<SyncStatus, KissError> {
+        parse_model(&self.get_sync_status()?)
+    }
+    fn get_consensus_status_typed(&mut self) -> Result<ConsensusStatus, KissError> {
+        ConsensusStatus::from_json(&self.get_consensus_status()?)
+    }
+    fn get_node_version_typed(&mut self) -> Result<NodeVersion, KissError> {
+        parse_model(&self.get_node_version()?)
+    }
+    fn get_group_info_typed(&mut self) -> Result<GroupInfo, KissError> {
+        parse_model(&self.get_group_info()?)
+    }
+}
+
+///按配置里的bcos2.protocol创建客户端，BCOS3时创建Bcos3Client，RPC/CHANNEL时创建Bcos2Client
+pub fn new_bcos_client(configfile: &str) -> Result<Box<dyn IBcosClient>, KissError> {
+    new_bcos_client_with_config(ClientConfig::load(configfile)?)
+}
+
+///同new_bcos_client，配置由调用方构造好传入
+pub fn new_bcos_client_with_config(
+    config: ClientConfig,
+) -> Result<Box<dyn IBcosClient>, KissError> {
+    match config.bcos2.protocol {
+        BcosClientProtocol::BCOS3 => Ok(Box::new(Bcos3Client::new_with_config(config)?)),
+        BcosClientProtocol::RPC | BcosClientProtocol::CHANNEL => {
+            Ok(Box::new(Bcos2Client::new_with_config(config)?))
+        }
+    }
+}
+
+//剥掉jsonrpc的外壳，有error段的返回错误
+fn rpc_result(cmd: &str, response: JsonValue) -> Result<JsonValue, KissError> {
+    if response["error"] != JsonValue::Null {
+        return kisserr!(KissErrKind::Error, "{} error {:?}", cmd, response["error"]);
+    }
+    Ok(response["result"].clone())
+}
+
+///BCOS2的接口里块高是u32，超出范围时报错而不是截断
+fn bcos2_number(num: u64) -> Result<u32, KissError> {
+    match u32::try_from(num) {
+        Ok(n) => Ok(n),
+        Err(_) => kisserr!(
+            KissErrKind::EArgument,
+            "bcos2 number {} out of u32 range",
+            num
+        ),
+    }
+}
+
+impl IBcosClient for Bcos2Client {
+    fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+    fn protocol(&self) -> BcosClientProtocol {
+        self.config.bcos2.protocol.clone()
+    }
+    fn group(&self) -> String {
+        self.config.bcos2.groupid.to_string()
+    }
+    fn set_group(&mut self, group: &str) -> Result<(), KissError> {
+        match group.trim().parse::<u32>() {
+            Ok(groupid) => {
+                self.set_groupid(groupid);
+                Ok(())
+            }
+            Err(_) => kisserr!(KissErrKind::EArgument, "bad bcos2 groupid {}", group),
+        }
+    }
+    fn policy(&self) -> &RequestPolicy {
+        Bcos2Client::policy(self)
+    }
+    fn set_policy(&mut self, policy: RequestPolicy) {
+        Bcos2Client::set_policy(self, policy)
+    }
+
+    fn get_block_number(&mut self) -> Result<u64, KissError> {
+        Ok(self.getBlockNumber()? as u64)
+    }
+    fn get_block_limit(&mut self) -> Result<u64, KissError> {
+        Ok(self.getBlockLimit()? as u64)
+    }
+    fn get_block_by_number(
+        &mut self,
+        num: u64,
+        include_transactions: bool,
+    ) -> Result<JsonValue, KissError> {
+        let response = self.getBlockByNumber(bcos2_number(num)?, include_transactions)?;
+        rpc_result("getBlockByNumber", response)
+    }
+    fn get_block_by_hash(
+        &mut self,
+        blockhash: &str,
+        include_transactions: bool,
+    ) -> Result<JsonValue, KissError> {
+        let response = self.getBlockByHash(blockhash, include_transactions)?;
+        rpc_result("getBlockByHash", response)
+    }
+    fn get_block_hash_by_number(&mut self, num: u64) -> Result<String, KissError> {
+        let response = self.getBlockHashByNumber(bcos2_number(num)?)?;
+        let result = rpc_result("getBlockHashByNumber", response)?;
+        match result.as_str() {
+            Some(hash) => Ok(hash.to_string()),
+            None => kisserr!(KissErrKind::EFormat, "bad block hash {:?}", result),
+        }
+    }
+    fn get_transaction_by_hash(&mut self, txhash: &str) -> Result<JsonValue, KissError> {
+        let response = self.getTransactionByHash(txhash)?;
+        rpc_result("getTransactionByHash", response)
+    }
+    fn get_transaction_receipt(&mut self, txhash: &str) -> Result<JsonValue, KissError> {
+        let response = self.getTransactionReceipt(txhash)?;
+        rpc_result("getTransactionReceipt", response)
+    }
+    fn get_code(&mut self, address: &str) -> Result<JsonValue, KissError> {
+        let groupid = self.config.bcos2.groupid;
+        let response = self.getCode(groupid, address)?;
+        rpc_result("getCode", response)
+    }
+    fn get_system_config_by_key(&mut self, key: &str) -> Result<JsonValue, KissError> {
+        let groupid = self.config.bcos2.groupid;
+        let response = self.getSystemConfigByKey(groupid, key)?;
+        rpc_result("getSystemConfigByKey", response)
+    }
+    fn get_sync_status(&mut self) -> Result<JsonValue, KissError> {
+        let groupid = self.config.bcos2.groupid;
+        let response = self.getSyncStatus(groupid)?;
+        rpc_result("getSyncStatus", response)
+    }
+    fn get_consensus_status(&mut self) -> Result<JsonValue, KissError> {
+        let groupid = self.config.bcos2.groupid;
+        let response = self.getConsensusStatus(groupid)?;
+        rpc_result("getConsensusStatus", response)
+    }
+    fn get_node_version(&mut self) -> Result<JsonValue, KissError> {
+        self.getNodeVersion()
+    }
+    ///BCOS2没有getGroupInfo，用getGroupPeers拼出和BCOS3一样的结构
+    fn get_group_info(&mut self) -> Result<JsonValue, KissError> {
+        let groupid = self.config.bcos2.groupid;
+        let response = self.getGroupPeers(groupid)?;
+        let peers = rpc_result("getGroupPeers", response)?;
+        let nodelist: Vec<JsonValue> = match peers.as_array() {
+            Some(ids) => ids.iter().map(|id| json!({ "name": id })).collect(),
+            None => vec![],
+        };
+        Ok(json!({
+            "chainID": self.config.bcos2.chainid.to_string(),
+            "groupID": groupid.to_string(),
+            "nodeList": nodelist,
+        }))
+    }
+
+    fn call(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        method: &str,
+        params: &[String],
+    ) -> Result<JsonValue, KissError> {
+        let response = Bcos2Client::call(self, contract, to_address, method, params)?;
+        rpc_result("call", response)
+    }
+    fn send_transaction(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        method: &str,
+        params: &[String],
+    ) -> Result<JsonValue, KissError> {
+        let response = self.sendRawTransactionGetReceipt(contract, to_address, method, params)?;
+        rpc_result("sendRawTransaction", response)
+    }
+    fn deploy_hexcode(&mut self, hexcode: &str) -> Result<JsonValue, KissError> {
+        let response = Bcos2Client::deploy_hexcode(self, hexcode)?;
+        let txhash = rpc_result("sendRawTransaction", response)?;
+        let txhash = match txhash.as_str() {
+            Some(h) => h.to_string(),
+            None => return kisserr!(KissErrKind::EFormat, "bad transaction hash {:?}", txhash),
+        };
+        let response = self.wait_receipt(txhash.as_str())?;
+        rpc_result("getTransactionReceipt", response)
+    }
+
+    fn finish(&mut self) {
+        Bcos2Client::finish(self)
+    }
+}
+
+impl IBcosClient for Bcos3Client {
+    fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+    fn protocol(&self) -> BcosClientProtocol {
+        BcosClientProtocol::BCOS3
+    }
+    fn group(&self) -> String {
+        self.group.clone()
+    }
+    fn set_group(&mut self, group: &str) -> Result<(), KissError> {
+        if group.is_empty() {
+            return kisserr!(KissErrKind::EArgument, "bcos3 group is empty");
+        }
+        Bcos3Client::set_group(self, group);
+        Ok(())
+    }
+    fn policy(&self) -> &RequestPolicy {
+        &self.policy
+    }
+    fn set_policy(&mut self, policy: RequestPolicy) {
+        self.policy = policy;
+    }
+
+    fn get_block_number(&mut self) -> Result<u64, KissError> {
+        self.getBlockNumber()
+    }
+    fn get_block_limit(&mut self) -> Result<u64, KissError> {
+        self.getBlocklimit()
+    }
+    fn get_block_by_number(
+        &mut self,
+        num: u64,
+        include_transactions: bool,
+    ) -> Result<JsonValue, KissError> {
+        self.getBlockByNumber(num, 0, !include_transactions as u32)
+    }
+    fn get_block_by_hash(
+        &mut self,
+        blockhash: &str,
+        include_transactions: bool,
+    ) -> Result<JsonValue, KissError> {
+        self.getBlockByHash(blockhash, 0, !include_transactions as u32)
+    }
+    fn get_block_hash_by_number(&mut self, num: u64) -> Result<String, KissError> {
+        self.getBlockHashByNumber(num)
+    }
+    fn get_transaction_by_hash(&mut self, txhash: &str) -> Result<JsonValue, KissError> {
+        self.getTransactionByHash(txhash, 0)
+    }
+    fn get_transaction_receipt(&mut self, txhash: &str) -> Result<JsonValue, KissError> {
+        self.getTransactionReceipt(txhash, 0)
+    }
+    fn get_code(&mut self, address: &str) -> Result<JsonValue, KissError> {
+        self.getCode(address)
+    }
+    fn get_system_config_by_key(&mut self, key: &str) -> Result<JsonValue, KissError> {
+        self.getSystemConfigByKey(key)
+    }
+    fn get_sync_status(&mut self) -> Result<JsonValue, KissError> {
+        self.getSyncStatus()
+    }
+    fn get_consensus_status(&mut self) -> Result<JsonValue, KissError> {
+        self.getConsensusStatus()
+    }
+    ///c sdk的getVersion是sdk自己的版本，节点版本取群组里第一个节点的binaryInfo
+    fn get_node_version(&mut self) -> Result<JsonValue, KissError> {
+        let groupinfo = self.getGroupInfo()?;
+        let iniconfig = &groupinfo["nodeList"][0]["iniConfig"];
+        let mut version = iniconfig["binaryInfo"].clone();
+        if version.is_object() {
+            version["chainID"] = iniconfig["chainID"].clone();
+        }
+        Ok(version)
+    }
+    fn get_group_info(&mut self) -> Result<JsonValue, KissError> {
+        self.getGroupInfo()
+    }
+
+    fn call(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        method: &str,
+        params: &[String],
+    ) -> Result<JsonValue, KissError> {
+        Bcos3Client::call(self, to_address, method, &params.to_vec(), contract)
+    }
+    fn send_transaction(
+        &mut self,
+        contract: &ContractABI,
+        to_address: &str,
+        method: &str,
+        params: &[String],
+    ) -> Result<JsonValue, KissError> {
+        self.sendTransaction(to_address, method, params, contract)
+    }
+    fn deploy_hexcode(&mut self, hexcode: &str) -> Result<JsonValue, KissError> {
+        Bcos3Client::deploy_hexcode(self, hexcode)
+    }
+
+    fn finish(&mut self) {
+        Bcos3Client::finish(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcossdkutil::accountutil::create_account;
+    use crate::bcossdkutil::bcosclientconfig::BcosCryptoKind;
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+
+    #[test]
+    fn test_bcos2_client_by_trait() -> Result<(), KissError> {
+        let node = MockChannelNode::start()?;
+        let configfile = node.write_client_config()?;
+        let mut client = new_bcos_client(configfile.as_str())?;
+        assert_eq!(client.protocol(), BcosClientProtocol::CHANNEL);
+        let hashtype = CommonHash::crypto_to_hashtype(&client.config().common.crypto);
+        let contract = ContractABI::new("contracts/HelloWorld.abi", &hashtype)?;
+        let address = "0x882be29b2d5ac85d6c476fa3fd5f0cae4b4585cc";
+
+        let receipt = client.send_transaction(&contract, address, "set", &["hello".to_string()])?;
+        assert_eq!(receipt["status"], "0x0");
+        assert_eq!(client.get_block_number()?, 1);
+        let txhash = receipt["transactionHash"].as_str().unwrap();
+        assert_eq!(client.get_transaction_receipt(txhash)?, receipt);
+        assert_eq!(
+            client.get_transaction_by_hash(txhash)?["hash"],
+            json!(txhash)
+        );
+        let block = client.get_block_by_number(1, false)?;
+        assert_eq!(block["hash"], json!(client.get_block_hash_by_number(1)?));
+        let toolarge = u32::MAX as u64 + 2;
+        assert_eq!(
+            client
+                .get_block_by_number(toolarge, false)
+                .unwrap_err()
+                .kind,
+            KissErrKind::EArgument
+        );
+        assert_eq!(
+            client.get_block_hash_by_number(toolarge).unwrap_err().kind,
+            KissErrKind::EArgument
+        );
+
+        let output = client.call(&contract, address, "get", &[])?;
+        assert_eq!(output["status"], "0x0");
+
+        let typedreceipt = client.get_transaction_receipt_typed(txhash)?;
+        assert!(typedreceipt.is_success());
+        assert_eq!(typedreceipt.blockNumber, 1);
+        let typedblock = client.get_block_by_number_typed(1, false)?;
+        assert_eq!(typedblock.header.number, 1);
+        assert_eq!(typedblock.transactions[0].hash(), txhash);
+        assert!(!client.get_node_version_typed()?.version.is_empty());
+        node.chain().set_result(
+            "getSyncStatus",
+            json!({"blockNumber": 1, "isSyncing": false, "nodeId": "n1", "txPoolSize": "0", "peers": []}),
+        );
+        assert_eq!(client.get_sync_status_typed()?.nodeID, "n1");
+        client.finish();
+        Ok(())
+    }
+
+    #[test]
+    fn test_bcos2_client_with_config() -> Result<(), KissError> {
+        let node = MockChannelNode::start()?;
+        let channel = node.channel_config();
+        let account = create_account(&BcosCryptoKind::ECDSA);
+        let accountpem = pem::encode(&pem::Pem {
+            tag: "PRIVATE KEY".to_string(),
+            contents: account.privkey.clone(),
+        });
+        //证书、私钥和账户都用内存里的pem内容，不需要配置文件
+        let config = ClientConfig::builder()
+            .crypto(BcosCryptoKind::ECDSA)
+            .account_pem(&accountpem)
+            .protocol(BcosClientProtocol::CHANNEL)
+            .chain(1, node.chain().groupid)
+            .channel_node(&channel.ip, channel.port)
+            .channel_certs_bytes(
+                &fileutils::read_all(&channel.cacert)?,
+                &fileutils::read_all(&channel.sdkcert)?,
+                &fileutils::read_all(&channel.sdkkey)?,
+            )
+            .build()?;
+        let mut client = new_bcos_client_with_config(config)?;
+        assert_eq!(client.get_block_number()?, 0);
+        assert!(client.config().configfile.is_none());
+        client.finish();
+        Ok(())
+    }
+
+    #[cfg(feature = "bcos3sdk_native")]
+    #[test]
+    fn test_bcos3_client_by_trait() -> Result<(), KissError> {
+        use crate::testsupport::mock_bcos3_node::MockBcos3Node;
+
+        let node = MockBcos3Node::start()?;
+        let configfile = node.write_client_config()?;
+        let mut client = new_bcos_client(configfile.as_str())?;
+        assert_eq!(client.protocol(), BcosClientProtocol::BCOS3);
+        assert_eq!(client.get_block_number()?, 1);
+        let receipt = client.deploy_hexcode("6080")?;
+        assert_eq!(receipt["status"], json!(0));
+        client.finish();
+        Ok(())
+    }
+}
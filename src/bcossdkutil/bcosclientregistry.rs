@@ -0,0 +1,446 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 多链、多群组的客户端注册表
+//! 每个客户端有一个名字，按名字取得句柄。配置里除了群组以外都相同(同一节点、同一账户)的客户端共用一个连接：
+//! BCOS2共用一个channel/rpc连接，BCOS3共用一个c sdk句柄，每次请求前切换到句柄自己的群组。
+//! 客户端可以在运行时增加和删除，连接在最后一个用到它的句柄释放时关闭。
+//! 注册表里各客户端的配置文件不用全局的BCOS_*环境变量覆盖，否则一个变量会把所有链改成同一个配置；
+//! 要覆盖某个客户端的配置时用带名字的BCOS_<名字>_<段名>_<字段名>，如名为chain1的客户端用BCOS_CHAIN1_RPC_URL
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::bcossdkutil::bcosclient::{new_bcos_client_with_config, IBcosClient};
+use crate::bcossdkutil::bcosclientconfig::{BcosClientProtocol, ClientConfig, CONFIG_ENV_PREFIX};
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+
+///注册表配置里的一个客户端
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RegistryEntry {
+    pub name: String,
+    ///节点连接的配置文件，格式同config.toml
+    pub config: String,
+    ///BCOS2为groupid，BCOS3为group名，为空时用配置文件里的群组
+    #[serde(default)]
+    pub group: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ClientRegistryConfig {
+    #[serde(default)]
+    pub clients: Vec<RegistryEntry>,
+}
+
+impl ClientRegistryConfig {
+    pub fn load(config_file: &str) -> Result<ClientRegistryConfig, KissError> {
+        let text = fileutils::readstring(config_file)?;
+        match toml::from_str::<ClientRegistryConfig>(&text) {
+            Ok(c) => Ok(c),
+            Err(e) => kisserr!(
+                KissErrKind::EFormat,
+                "parse registry config error {},{:?}",
+                config_file,
+                e
+            ),
+        }
+    }
+}
+
+//多个句柄共用的连接，最后一个句柄释放时关闭
+struct SharedConnection {
+    key: String,
+    client: Mutex<Box<dyn IBcosClient>>,
+}
+
+impl Drop for SharedConnection {
+    fn drop(&mut self) {
+        let client = match self.client.get_mut() {
+            Ok(c) => c,
+            Err(e) => e.into_inner(),
+        };
+        client.finish();
+    }
+}
+
+///注册表里的一个客户端，可以clone后在多个线程里使用，共用连接的请求会排队执行
+#[derive(Clone)]
+pub struct BcosClientHandle {
+    pub name: String,
+    pub group: String,
+    conn: Arc<SharedConnection>,
+}
+
+impl fmt::Debug for BcosClientHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BcosClientHandle")
+            .field("name", &self.name)
+            .field("group", &self.group)
+            .finish()
+    }
+}
+
+impl BcosClientHandle {
+    ///锁住连接，切换到本客户端的群组后执行f
+    pub fn with<T, F>(&self, f: F) -> Result<T, KissError>
+    where
+        F: FnOnce(&mut dyn IBcosClient) -> Result<T, KissError>,
+    {
+        let mut client = match self.conn.client.lock() {
+            Ok(c) => c,
+            Err(e) => e.into_inner(),
+        };
+        client.set_group(&self.group)?;
+        f(client.as_mut())
+    }
+
+    pub fn protocol(&self) -> Result<BcosClientProtocol, KissError> {
+        self.with(|client| Ok(client.protocol()))
+    }
+
+    pub fn shares_connection(&self, other: &BcosClientHandle) -> bool {
+        Arc::ptr_eq(&self.conn, &other.conn)
+    }
+}
+
+///按名字管理客户端，增加、删除和取句柄都只需要&self，可以放在Arc里共享
+#[derive(Default)]
+pub struct BcosClientRegistry {
+    clients: Mutex<HashMap<String, BcosClientHandle>>,
+}
+
+impl BcosClientRegistry {
+    pub fn new() -> Self {
+        BcosClientRegistry::default()
+    }
+
+    pub fn from_config(config: &ClientRegistryConfig) -> Result<BcosClientRegistry, KissError> {
+        let registry = BcosClientRegistry::new();
+        for entry in config.clients.iter() {
+            registry.add_entry(entry)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn load(config_file: &str) -> Result<BcosClientRegistry, KissError> {
+        BcosClientRegistry::from_config(&ClientRegistryConfig::load(config_file)?)
+    }
+
+    ///加载entry的配置文件，用BCOS_<名字>_*环境变量和entry的群组覆盖后增加客户端
+    pub fn add_entry(&self, entry: &RegistryEntry) -> Result<BcosClientHandle, KissError> {
+        self.add_entry_with_vars(entry, std::env::vars())
+    }
+
+    fn add_entry_with_vars<I>(
+        &self,
+        entry: &RegistryEntry,
+        vars: I,
+    ) -> Result<BcosClientHandle, KissError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut config =
+            ClientConfig::load_file(&entry.config)?.apply_vars(entry_vars(&entry.name, vars))?;
+        if !entry.group.is_empty() {
+            set_config_group(&mut config, &entry.group)?;
+        }
+        self.add(&entry.name, config)
+    }
+
+    ///增加一个客户端，已经有除群组外配置相同的连接时直接共用，否则新建连接。
+    ///新建连接时不持有注册表的锁，连上后再检查一次名字和可共用的连接
+    pub fn add(&self, name: &str, config: ClientConfig) -> Result<BcosClientHandle, KissError> {
+        let group = config_group(&config);
+        let key = connection_key(&config)?;
+        let shared = {
+            let clients = self.clients.lock().unwrap();
+            if clients.contains_key(name) {
+                return kisserr!(KissErrKind::EArgument, "client {} already exists", name);
+            }
+            clients
+                .values()
+                .find(|h| h.conn.key == key)
+                .map(|h| h.conn.clone())
+        };
+        let newconn = match shared {
+            Some(_) => None,
+            None => Some(Arc::new(SharedConnection {
+                key: key.clone(),
+                client: Mutex::new(new_bcos_client_with_config(config)?),
+            })),
+        };
+        let mut clients = self.clients.lock().unwrap();
+        if clients.contains_key(name) {
+            //连接期间别的线程加了同名客户端，新建的连接在这里释放
+            return kisserr!(KissErrKind::EArgument, "client {} already exists", name);
+        }
+        //连接期间别的线程可能已经为同一配置建了连接，优先用已有的
+        let conn = match clients.values().find(|h| h.conn.key == key) {
+            Some(h) => h.conn.clone(),
+            None => match newconn.or(shared) {
+                Some(c) => c,
+                None => return kisserr!(KissErrKind::Error, "no connection for client {}", name),
+            },
+        };
+        let handle = BcosClientHandle {
+            name: name.to_string(),
+            group,
+            conn,
+        };
+        clients.insert(name.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    pub fn get(&self, name: &str) -> Option<BcosClientHandle> {
+        self.clients.lock().unwrap().get(name).cloned()
+    }
+
+    ///删除客户端，已经取出的句柄还可以继续用，连接在最后一个句柄释放时关闭
+    pub fn remove(&self, name: &str) -> bool {
+        self.clients.lock().unwrap().remove(name).is_some()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.clients.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///注册表里的客户端实际用到的连接数
+    pub fn connection_count(&self) -> usize {
+        let clients = self.clients.lock().unwrap();
+        let mut keys: Vec<&str> = clients.values().map(|h| h.conn.key.as_str()).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys.len()
+    }
+}
+
+///客户端专用的环境变量前缀，名字转大写，'-'换成'_'
+pub fn entry_env_prefix(name: &str) -> String {
+    format!(
+        "{}{}_",
+        CONFIG_ENV_PREFIX,
+        name.to_uppercase().replace('-', "_")
+    )
+}
+
+//只留下BCOS_<名字>_开头的变量，去掉名字后按普通的BCOS_*变量处理
+fn entry_vars<I>(name: &str, vars: I) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let prefix = entry_env_prefix(name);
+    vars.into_iter()
+        .filter_map(|(k, v)| {
+            k.strip_prefix(&prefix)
+                .map(|rest| (format!("{}{}", CONFIG_ENV_PREFIX, rest), v))
+        })
+        .collect()
+}
+
+fn config_group(config: &ClientConfig) -> String {
+    match config.bcos2.protocol {
+        BcosClientProtocol::BCOS3 => config.bcos3.group.clone(),
+        _ => config.bcos2.groupid.to_string(),
+    }
+}
+
+fn set_config_group(config: &mut ClientConfig, group: &str) -> Result<(), KissError> {
+    match config.bcos2.protocol {
+        BcosClientProtocol::BCOS3 => config.bcos3.group = group.to_string(),
+        _ => match group.trim().parse::<u32>() {
+            Ok(groupid) => config.bcos2.groupid = groupid,
+            Err(_) => return kisserr!(KissErrKind::EArgument, "bad bcos2 groupid {}", group),
+        },
+    }
+    Ok(())
+}
+
+//去掉群组和配置文件名后的完整配置，相同的就可以共用连接
+fn connection_key(config: &ClientConfig) -> Result<String, KissError> {
+    let mut keyconfig = config.clone();
+    keyconfig.bcos2.groupid = 0;
+    keyconfig.bcos3.group = "".to_string();
+    keyconfig.configfile = None;
+    match toml::Value::try_from(&keyconfig) {
+        Ok(v) => Ok(v.to_string()),
+        Err(e) => kisserr!(KissErrKind::EFormat, "config to toml error {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::mock_rpc_node::MockRpcNode;
+
+    #[test]
+    fn test_registry_shares_connections() -> Result<(), KissError> {
+        let node_a = MockRpcNode::start()?;
+        let node_b = MockRpcNode::start()?;
+        let configfile = node_a.write_client_config()?;
+        let text = format!(
+            r#"
+[[clients]]
+name = "a1"
+config = "{configfile}"
+
+[[clients]]
+name = "a2"
+config = "{configfile}"
+group = "2"
+"#,
+            configfile = configfile.replace('\\', "/")
+        );
+        let registry = BcosClientRegistry::from_config(&toml::from_str(&text).unwrap())?;
+        //同一节点的两个群组共用一个连接，请求里带的是各自的群组
+        let (a1, a2) = (registry.get("a1").unwrap(), registry.get("a2").unwrap());
+        assert!(a1.shares_connection(&a2));
+        assert_eq!(a2.with(|c| c.get_block_number())?, 0);
+        assert_eq!(a1.with(|c| c.get_block_number())?, 0);
+        let requests = node_a.received_requests();
+        assert_eq!(requests[0]["params"][0], 2);
+        assert_eq!(requests[1]["params"][0], 1);
+
+        //运行时增加另一个节点上的客户端，有自己的连接
+        let b1 = registry.add("b1", node_b.client_config()?)?;
+        assert!(!b1.shares_connection(&a1));
+        assert_eq!(registry.connection_count(), 2);
+        assert!(registry.add("b1", node_b.client_config()?).is_err());
+        b1.with(|c| c.get_block_number())?;
+        assert_eq!(node_b.received_requests().len(), 1);
+
+        assert!(registry.remove("a1"));
+        assert!(!registry.remove("a1"));
+        assert_eq!(registry.names(), vec!["a2", "b1"]);
+        assert_eq!(registry.connection_count(), 2);
+        //删除后已取出的句柄还能用
+        a1.with(|c| c.get_block_number())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_entry_env() -> Result<(), KissError> {
+        let node_a = MockRpcNode::start()?;
+        let node_b = MockRpcNode::start()?;
+        let entry_a = RegistryEntry {
+            name: "chain-a".to_string(),
+            config: node_a.write_client_config()?,
+            group: "".to_string(),
+        };
+        let entry_b = RegistryEntry {
+            name: "chain-b".to_string(),
+            config: node_b.write_client_config()?,
+            group: "".to_string(),
+        };
+        //全局的BCOS_RPC_URL不影响注册表里的客户端，两条链还是各连各的
+        let vars = || {
+            vec![
+                (
+                    "BCOS_RPC_URL".to_string(),
+                    node_a.client_config().unwrap().rpc.url,
+                ),
+                ("BCOS_CHAIN_B_POLICY_TIMEOUT".to_string(), "42".to_string()),
+            ]
+        };
+        let registry = BcosClientRegistry::new();
+        let a = registry.add_entry_with_vars(&entry_a, vars())?;
+        let b = registry.add_entry_with_vars(&entry_b, vars())?;
+        assert!(!a.shares_connection(&b));
+        assert_eq!(registry.connection_count(), 2);
+        b.with(|c| c.get_block_number())?;
+        assert_eq!(node_b.received_requests().len(), 1);
+        assert!(node_a.received_requests().is_empty());
+
+        //带名字的变量只改对应的客户端
+        let config_b = ClientConfig::load_file(&entry_b.config)?
+            .apply_vars(entry_vars(&entry_b.name, vars()))?;
+        assert_eq!(config_b.policy.timeout, 42);
+        let config_a = ClientConfig::load_file(&entry_a.config)?
+            .apply_vars(entry_vars(&entry_a.name, vars()))?;
+        assert_ne!(config_a.policy.timeout, 42);
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_concurrent_add() -> Result<(), KissError> {
+        let node = MockRpcNode::start()?;
+        let config = node.client_config()?;
+        let registry = Arc::new(BcosClientRegistry::new());
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let (registry, config) = (registry.clone(), config.clone());
+                std::thread::spawn(move || {
+                    let dup = registry.add("dup", config.clone()).is_ok();
+                    registry.add(&format!("c{}", i), config).unwrap();
+                    dup
+                })
+            })
+            .collect();
+        let added = threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .filter(|ok| *ok)
+            .count();
+        //同名只能加一次，同一配置只建一个连接
+        assert_eq!(added, 1);
+        assert_eq!(registry.len(), 5);
+        assert_eq!(registry.connection_count(), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "bcos3sdk_native")]
+    #[test]
+    fn test_registry_bcos3_groups() -> Result<(), KissError> {
+        use crate::testsupport::mock_bcos3_node::MockBcos3Node;
+        use serde_json::Value as JsonValue;
+
+        let node = MockBcos3Node::start()?;
+        let configfile = node.write_client_config()?;
+        let registry = BcosClientRegistry::new();
+        for (name, group) in [("g0", "group0"), ("g1", "group1")].iter() {
+            registry.add_entry(&RegistryEntry {
+                name: name.to_string(),
+                config: configfile.clone(),
+                group: group.to_string(),
+            })?;
+        }
+        //两个群组共用一个c sdk连接
+        assert_eq!(registry.connection_count(), 1);
+        registry.get("g1").unwrap().with(|c| c.get_block_number())?;
+        registry.get("g0").unwrap().with(|c| c.get_block_number())?;
+        let state = node.state();
+        assert_eq!(state.handshakes, 1);
+        let groups: Vec<&JsonValue> = state.requests.iter().map(|r| &r["params"][0]).collect();
+        assert_eq!(groups, vec!["group1", "group0"]);
+        Ok(())
+    }
+}
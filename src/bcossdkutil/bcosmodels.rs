@@ -0,0 +1,468 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 节点返回的块、交易、回执和节点状态的类型化结构
+//! 字段名保持和节点json一致；BCOS2的数字是"0x"开头的hex字符串，BCOS3是数字或十进制字符串，都按数字解析
+//! 两个版本字段名不同的(如logs/logEntries，nodeId/nodeID)用alias兼容
+use std::convert::TryFrom;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+
+///把json里的数字、hex字符串或十进制字符串转成数字，null按0处理
+pub fn json_to_number(v: &JsonValue) -> Option<u64> {
+    match v {
+        JsonValue::Null => Some(0),
+        JsonValue::Number(n) => n.as_u64(),
+        JsonValue::String(s) => {
+            let s = s.trim();
+            if s.is_empty() {
+                Some(0)
+            } else if s.starts_with("0x") || s.starts_with("0X") {
+                u64::from_str_radix(&s[2..], 16).ok()
+            } else {
+                s.parse().ok()
+            }
+        }
+        _ => None,
+    }
+}
+
+fn de_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    let v = JsonValue::deserialize(deserializer)?;
+    match json_to_number(&v).map(T::try_from) {
+        Some(Ok(n)) => Ok(n),
+        _ => Err(serde::de::Error::custom(format!("bad number {}", v))),
+    }
+}
+
+///同json_to_number，但允许负数，包括"-12"、"-0xc"这样带符号的字符串
+pub fn json_to_signed(v: &JsonValue) -> Option<i64> {
+    match v {
+        JsonValue::Number(n) => n.as_i64(),
+        JsonValue::String(s) => match s.trim().strip_prefix('-') {
+            Some(abs) => json_to_number(&JsonValue::String(abs.to_string()))
+                .and_then(|n| i64::try_from(n).ok())
+                .map(|n| -n),
+            None => json_to_number(v).and_then(|n| i64::try_from(n).ok()),
+        },
+        _ => json_to_number(v).and_then(|n| i64::try_from(n).ok()),
+    }
+}
+
+fn de_signed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64>,
+{
+    let v = JsonValue::deserialize(deserializer)?;
+    match json_to_signed(&v).map(T::try_from) {
+        Some(Ok(n)) => Ok(n),
+        _ => Err(serde::de::Error::custom(format!("bad number {}", v))),
+    }
+}
+
+///从查询结果解析出类型化结构。BCOS3的部分状态查询把json放在字符串里返回，这里一并展开
+pub fn parse_model<T: DeserializeOwned>(v: &JsonValue) -> Result<T, KissError> {
+    let res = match v {
+        JsonValue::String(s) => serde_json::from_str(s),
+        _ => T::deserialize(v),
+    };
+    match res {
+        Ok(m) => Ok(m),
+        Err(e) => kisserr!(
+            KissErrKind::EFormat,
+            "parse {} error {:?},{}",
+            std::any::type_name::<T>(),
+            e,
+            v
+        ),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Log {
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub data: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default, deserialize_with = "de_number")]
+    pub blockNumber: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TransactionReceipt {
+    #[serde(default)]
+    pub transactionHash: String,
+    #[serde(default)]
+    pub blockHash: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub blockNumber: u64,
+    #[serde(default, deserialize_with = "de_number")]
+    pub transactionIndex: u64,
+    #[serde(default)]
+    pub contractAddress: String,
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub to: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub gasUsed: u64,
+    #[serde(default)]
+    pub input: String,
+    #[serde(default)]
+    pub output: String,
+    #[serde(default, deserialize_with = "de_signed")]
+    pub status: i32,
+    //BCOS3失败时的错误描述
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, alias = "logEntries")]
+    pub logs: Vec<Log>,
+}
+
+impl TransactionReceipt {
+    pub fn is_success(&self) -> bool {
+        self.status == 0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Transaction {
+    #[serde(default)]
+    pub hash: String,
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub to: String,
+    #[serde(default)]
+    pub input: String,
+    //BCOS2是hex，BCOS3是十进制的大数，原样保留
+    #[serde(default)]
+    pub nonce: String,
+    #[serde(default)]
+    pub blockHash: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub blockNumber: u64,
+    #[serde(default, deserialize_with = "de_number")]
+    pub transactionIndex: u64,
+    #[serde(default, deserialize_with = "de_number")]
+    pub blockLimit: u64,
+    #[serde(default, deserialize_with = "de_number")]
+    pub gas: u64,
+    #[serde(default)]
+    pub gasPrice: JsonValue,
+    #[serde(default)]
+    pub value: JsonValue,
+    #[serde(default, alias = "chainId")]
+    pub chainID: JsonValue,
+    #[serde(default, alias = "groupId")]
+    pub groupID: JsonValue,
+    #[serde(default)]
+    pub signature: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub importTime: u64,
+}
+
+///块里的交易，只带hash时是字符串，否则是完整交易
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum BlockTransaction {
+    Hash(String),
+    Full(Box<Transaction>),
+}
+
+impl BlockTransaction {
+    pub fn hash(&self) -> &str {
+        match self {
+            BlockTransaction::Hash(h) => h.as_str(),
+            BlockTransaction::Full(tx) => tx.hash.as_str(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ParentInfo {
+    #[serde(default)]
+    pub blockHash: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub blockNumber: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BlockHeader {
+    #[serde(default, deserialize_with = "de_number")]
+    pub number: u64,
+    #[serde(default)]
+    pub hash: String,
+    //BCOS2用parentHash，BCOS3用parentInfo
+    #[serde(default)]
+    pub parentHash: String,
+    #[serde(default)]
+    pub parentInfo: Vec<ParentInfo>,
+    #[serde(default, deserialize_with = "de_number")]
+    pub timestamp: u64,
+    #[serde(default, deserialize_with = "de_number")]
+    pub sealer: u64,
+    #[serde(default)]
+    pub sealerList: Vec<String>,
+    #[serde(default)]
+    pub extraData: JsonValue,
+    #[serde(default, deserialize_with = "de_number")]
+    pub gasUsed: u64,
+    #[serde(default, deserialize_with = "de_number")]
+    pub gasLimit: u64,
+    #[serde(default)]
+    pub stateRoot: String,
+    #[serde(default)]
+    pub receiptsRoot: String,
+    #[serde(default, alias = "txsRoot")]
+    pub transactionsRoot: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub version: u64,
+}
+
+impl BlockHeader {
+    pub fn parent_hash(&self) -> &str {
+        match self.parentInfo.first() {
+            Some(p) => p.blockHash.as_str(),
+            None => self.parentHash.as_str(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Block {
+    #[serde(flatten)]
+    pub header: BlockHeader,
+    #[serde(default)]
+    pub transactions: Vec<BlockTransaction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SyncPeer {
+    #[serde(default, alias = "nodeId")]
+    pub nodeID: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub blockNumber: u64,
+    #[serde(default)]
+    pub genesisHash: String,
+    #[serde(default)]
+    pub latestHash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SyncStatus {
+    #[serde(default, alias = "nodeId")]
+    pub nodeID: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub blockNumber: u64,
+    #[serde(default)]
+    pub isSyncing: bool,
+    #[serde(default, deserialize_with = "de_number")]
+    pub knownHighestNumber: u64,
+    #[serde(default)]
+    pub knownLatestHash: String,
+    #[serde(default)]
+    pub latestHash: String,
+    #[serde(default)]
+    pub genesisHash: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub txPoolSize: u64,
+    #[serde(default)]
+    pub peers: Vec<SyncPeer>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ConsensusNode {
+    #[serde(default, alias = "nodeId")]
+    pub nodeID: String,
+    #[serde(default, deserialize_with = "de_number")]
+    pub index: u64,
+    #[serde(default, deserialize_with = "de_number")]
+    pub weight: u64,
+}
+
+///BCOS2返回[状态, 各节点view]的数组，取第一项；BCOS3直接是对象
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ConsensusStatus {
+    #[serde(default, alias = "nodeId")]
+    pub nodeID: String,
+    #[serde(default, alias = "node_index", deserialize_with = "de_number")]
+    pub index: u64,
+    #[serde(default, alias = "currentView", deserialize_with = "de_number")]
+    pub view: u64,
+    #[serde(default, alias = "highestblockNumber", deserialize_with = "de_number")]
+    pub blockNumber: u64,
+    #[serde(default, alias = "highestblockHash")]
+    pub hash: String,
+    #[serde(default, alias = "nodeNum", deserialize_with = "de_number")]
+    pub consensusNodesNum: u64,
+    #[serde(default)]
+    pub consensusNodeList: Vec<ConsensusNode>,
+}
+
+impl ConsensusStatus {
+    pub fn from_json(v: &JsonValue) -> Result<ConsensusStatus, KissError> {
+        match v {
+            JsonValue::Array(items) if !items.is_empty() => parse_model(&items[0]),
+            _ => parse_model(v),
+        }
+    }
+}
+
+///BCOS2的getNodeVersion，或BCOS3节点信息里的binaryInfo
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NodeVersion {
+    #[serde(default, alias = "FISCO-BCOS Version")]
+    pub version: String,
+    #[serde(default, alias = "Supported Version")]
+    pub supportedVersion: String,
+    #[serde(default, alias = "Chain Id")]
+    pub chainID: String,
+    #[serde(default, alias = "Build Time")]
+    pub buildTime: String,
+    #[serde(default, alias = "Build Type")]
+    pub buildType: String,
+    #[serde(default, alias = "Git Branch")]
+    pub gitBranch: String,
+    #[serde(default, alias = "Git Commit Hash")]
+    pub gitCommitHash: String,
+    #[serde(default)]
+    pub platform: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GroupNodeConfig {
+    #[serde(default)]
+    pub nodeID: String,
+    #[serde(default)]
+    pub nodeName: String,
+    #[serde(default)]
+    pub chainID: String,
+    #[serde(default)]
+    pub groupID: String,
+    #[serde(default)]
+    pub isWasm: bool,
+    #[serde(default)]
+    pub smCryptoType: bool,
+    #[serde(default)]
+    pub binaryInfo: NodeVersion,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GroupNodeInfo {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub nodeType: i32,
+    #[serde(default)]
+    pub iniConfig: GroupNodeConfig,
+}
+
+///BCOS3的getGroupInfo；BCOS2没有对应接口，由groupid和getGroupPeers拼出同样的结构
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GroupInfo {
+    #[serde(default)]
+    pub chainID: String,
+    #[serde(default)]
+    pub groupID: String,
+    #[serde(default)]
+    pub genesisConfig: JsonValue,
+    #[serde(default)]
+    pub nodeList: Vec<GroupNodeInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_bcos2_and_bcos3_models() -> Result<(), KissError> {
+        let receipt2: TransactionReceipt = parse_model(&json!({
+            "blockNumber": "0x1a", "gasUsed": "0x5798", "status": "0x16",
+            "transactionHash": "0xabc", "logs": [{"address": "0x01", "data": "0x", "topics": ["0x02"]}]
+        }))?;
+        assert_eq!(receipt2.blockNumber, 26);
+        assert_eq!(receipt2.gasUsed, 0x5798);
+        assert_eq!(receipt2.status, 22);
+        assert_eq!(receipt2.logs[0].topics, vec!["0x02".to_string()]);
+        let receipt3: TransactionReceipt = parse_model(&json!({
+            "blockNumber": 26, "gasUsed": "22424", "status": 0, "hash": "0xdef",
+            "logEntries": [{"address": "0x01", "data": "0x", "topics": []}]
+        }))?;
+        assert_eq!(receipt3.gasUsed, 22424);
+        assert!(receipt3.is_success());
+        assert_eq!(receipt3.logs.len(), 1);
+        for (status, expect) in [(json!(-1), -1), (json!("-0x10"), -16), (json!("-7"), -7)] {
+            let receipt: TransactionReceipt = parse_model(&json!({ "status": status }))?;
+            assert_eq!(receipt.status, expect);
+        }
+        assert!(parse_model::<TransactionReceipt>(&json!({"status": "0x100000000"})).is_err());
+
+        let block2: Block = parse_model(&json!({
+            "number": "0x2", "hash": "0x22", "parentHash": "0x11", "sealer": "0x1",
+            "timestamp": "0x17b0ba6e0c0", "transactions": ["0xaa"]
+        }))?;
+        assert_eq!(block2.header.number, 2);
+        assert_eq!(block2.header.parent_hash(), "0x11");
+        assert_eq!(block2.transactions[0].hash(), "0xaa");
+        let block3: Block = parse_model(&json!({
+            "number": 2, "hash": "0x22", "parentInfo": [{"blockHash": "0x11", "blockNumber": 1}],
+            "txsRoot": "0x33", "transactions": [{"hash": "0xaa", "nonce": "1234", "blockLimit": 500}]
+        }))?;
+        assert_eq!(block3.header.parent_hash(), "0x11");
+        assert_eq!(block3.header.transactionsRoot, "0x33");
+        assert_eq!(block3.transactions[0].hash(), "0xaa");
+
+        let sync3: SyncStatus = parse_model(&json!(
+            "{\"blockNumber\":5,\"isSyncing\":false,\"nodeID\":\"n1\",\"peers\":[{\"nodeID\":\"n2\",\"blockNumber\":5}]}"
+        ))?;
+        assert_eq!(sync3.blockNumber, 5);
+        assert_eq!(sync3.peers[0].nodeID, "n2");
+
+        let consensus2 = ConsensusStatus::from_json(&json!([
+            {"nodeId": "n1", "node_index": 2, "currentView": 10, "highestblockNumber": 5, "nodeNum": 4},
+            [{"nodeId": "n1", "view": 10}]
+        ]))?;
+        assert_eq!(consensus2.index, 2);
+        assert_eq!(consensus2.view, 10);
+        assert_eq!(consensus2.consensusNodesNum, 4);
+
+        let version2: NodeVersion = parse_model(&json!({
+            "FISCO-BCOS Version": "2.9.0", "Supported Version": "2.9.0", "Chain Id": "1"
+        }))?;
+        assert_eq!(version2.version, "2.9.0");
+        assert_eq!(version2.chainID, "1");
+
+        assert!(parse_model::<Block>(&json!({"number": "0xzz"})).is_err());
+        Ok(())
+    }
+}
@@ -18,23 +18,34 @@
     unused_variables,
     unused_assignments
 )]
-///简单封装。后续加入线程安全等特性
+use bytes::{Buf, BytesMut};
+
+///收包缓冲区，底层是BytesMut，从头部去掉已解码的数据时不用搬动剩下的数据
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct BufferQueue {
-    pub queue: Vec<u8>,
+    pub queue: BytesMut,
 }
 
 impl BufferQueue {
     pub fn new() -> BufferQueue {
-        BufferQueue { queue: Vec::new() }
+        BufferQueue {
+            queue: BytesMut::new(),
+        }
     }
-    ///简单的将书加入缓冲区
+    ///简单的将书加入缓冲区，newdata会被清空，和Vec::append一致
     pub fn append(&mut self, newdata: &mut Vec<u8>) {
-        self.queue.append(newdata);
+        self.queue.extend_from_slice(newdata);
+        newdata.clear();
     }
     ///从缓冲区的头部去掉n个部分
     pub fn cut(&mut self, pos: usize) {
-        self.queue = self.queue.as_slice()[pos..].to_vec();
+        self.queue.advance(pos);
+    }
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
     }
 }
 
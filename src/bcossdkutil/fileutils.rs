@@ -95,3 +95,25 @@ pub fn is_file_exist(fullpath: &str) -> bool {
     let res = Path::new(fullpath).exists();
     return res;
 }
+
+///配置里的证书、私钥可以是文件路径，也可以直接是pem内容(以-----BEGIN开头)
+pub fn is_inline_pem(path_or_pem: &str) -> bool {
+    path_or_pem.trim_start().starts_with("-----BEGIN")
+}
+
+///读取pem：配置的是pem内容时直接返回，否则按文件路径读取
+pub fn read_pem(path_or_pem: &str) -> Result<Vec<u8>, KissError> {
+    if is_inline_pem(path_or_pem) {
+        return Ok(path_or_pem.trim_start().as_bytes().to_vec());
+    }
+    read_all(path_or_pem)
+}
+
+///用于日志和错误信息，pem内容不打印出来
+pub fn pem_source_name(path_or_pem: &str) -> &str {
+    if is_inline_pem(path_or_pem) {
+        "<inline pem>"
+    } else {
+        path_or_pem
+    }
+}
@@ -99,6 +99,17 @@ impl KissError {
     }
 }
 
+///网络读写的io错误，codec的Decoder/Encoder要求错误类型能从io::Error转换
+impl From<std::io::Error> for KissError {
+    fn from(e: std::io::Error) -> Self {
+        KissError::new(
+            KissErrKind::ENetwork,
+            e.raw_os_error().unwrap_or(-1) as i64,
+            format!("{:?}", e).as_str(),
+        )
+    }
+}
+
 //----------------------------------------------------------------------------------------------
 
 pub fn test_fire_error(i: u32) -> Result<String, KissError> {
@@ -14,7 +14,10 @@ pub mod kisserror;
 pub mod abi_parser;
 pub mod abi_tokenizer;
 pub mod accountutil;
+pub mod bcosclient;
 pub mod bcosclientconfig;
+pub mod bcosclientregistry;
+pub mod bcosmodels;
 pub mod bufferqueue;
 pub mod commonhash;
 pub mod commonsigner;
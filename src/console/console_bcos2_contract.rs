@@ -63,7 +63,7 @@ pub fn deploy(cli: &Cli) -> Result<(), KissError> {
         }
         Err(e) => return Err(e),
     };
-    let res = bcossdk.try_getTransactionReceipt(txhash.as_str(), 3, false);
+    let res = bcossdk.wait_receipt(txhash.as_str());
     match res {
         Ok(v) => {
             let address = v["result"]["contractAddress"].as_str().unwrap();
@@ -126,7 +126,7 @@ pub fn sendtx(cli: &Cli) -> Result<(), KissError> {
     //println!("response[\"result\"] {:?}",response);
     let txhash = response["result"].as_str().unwrap();
     println!("\n>>>>>>>>>>>>>>>>>>>after sendtx getTransactionByHash");
-    let receiptresult = bcossdk.try_getTransactionReceipt(txhash, 3, false);
+    let receiptresult = bcossdk.wait_receipt(txhash);
     //println!("receiptresult result :{:?} ",receiptresult);
     match receiptresult {
         Ok(receipt) => {
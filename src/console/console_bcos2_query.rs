@@ -9,6 +9,7 @@ use fisco_bcos_rust_gears_sdk::bcos2sdk::bcos2client::Bcos2Client;
 use fisco_bcos_rust_gears_sdk::bcos2sdk::bcossdkquery::json_hextoint;
 use fisco_bcos_rust_gears_sdk::bcossdkutil::kisserror::{KissErrKind, KissError};
 use fisco_bcos_rust_gears_sdk::bcossdkutil::liteutils::get_opt_str;
+use fisco_bcos_rust_gears_sdk::kisserr;
 use serde::de::Unexpected::Option as JsonOption;
 use serde_json::Value as JsonValue;
 use std::str::FromStr;
@@ -25,6 +26,7 @@ impl Bcos2Query {
         //于是后续调用时，用名字去match就行了,且可以做到大小写不敏感
         cmdmap!(query.cmdmap.cmd_func_map, getBlockNumber);
         cmdmap!(query.cmdmap.cmd_func_map, getBlockByNumber);
+        cmdmap!(query.cmdmap.cmd_func_map, getBlocksByRange);
         cmdmap!(query.cmdmap.cmd_func_map, getBlockByHash);
         cmdmap!(query.cmdmap.cmd_func_map, getTransactionByHash);
         cmdmap!(query.cmdmap.cmd_func_map, getTransactionByHash);
@@ -82,6 +84,46 @@ pub fn getBlockByNumber(cli: &Cli) -> Result<(), KissError> {
     Ok(())
 }
 
+///getBlocksByRange from to [includeTransactions]，用批量请求一次取回范围内的块
+pub fn getBlocksByRange(cli: &Cli) -> Result<(), KissError> {
+    let usage = "usage: getBlocksByRange from to [includeTransactions]";
+    if cli.params.len() < 2 {
+        return kisserr!(KissErrKind::EArgument, "{}", usage);
+    }
+    let (from, to) = match (
+        u32::from_str_radix(cli.params[0].as_str(), 10),
+        u32::from_str_radix(cli.params[1].as_str(), 10),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => return kisserr!(KissErrKind::EArgument, "bad block number, {}", usage),
+    };
+    let mut includeTransactions = false;
+    if cli.params.len() > 2 {
+        includeTransactions = match bool::from_str(cli.params[2].as_str()) {
+            Ok(b) => b,
+            Err(_) => {
+                return kisserr!(KissErrKind::EArgument, "bad includeTransactions, {}", usage)
+            }
+        };
+    }
+    let mut bcossdk = Bcos2Client::new_from_config(cli.default_configfile().as_str())?;
+    let blocks = bcossdk.getBlocksByNumberRange(from, to, includeTransactions)?;
+    for v in blocks.iter() {
+        if includeTransactions {
+            println!("\n{}\n", serde_json::to_string_pretty(&v).unwrap());
+            continue;
+        }
+        let block = &v["result"];
+        println!(
+            "block {} hash {} transactions {}",
+            block["number"].as_str().unwrap_or("-"),
+            block["hash"].as_str().unwrap_or("-"),
+            block["transactions"].as_array().map_or(0, |t| t.len())
+        );
+    }
+    Ok(())
+}
+
 pub fn getBlockByHash(cli: &Cli) -> Result<(), KissError> {
     let mut bcossdk = Bcos2Client::new_from_config(cli.default_configfile().as_str())?;
     let hash = cli.params[0].as_str();
@@ -13,31 +13,30 @@
 pub mod bcos2sdk;
 pub mod bcos3sdk;
 pub mod bcossdkutil;
+#[cfg(any(test, feature = "testsupport"))]
+pub mod testsupport;
 
 #[cfg(test)]
 mod tests {
     //set RUST_TEST_NOCAPTURE=1
+    //用进程内的mock节点测试，不需要真实的链
     use crate::bcos2sdk::bcos2client::Bcos2Client;
-    use crate::bcossdkutil;
-    use crate::bcossdkutil::kisserror::KissError;
-    use fisco_bcos_rust_gears_sdk::console::cli_common::Cli;
-    use structopt::StructOpt;
-
-    // use super::*;
-    pub fn default_config(params: &Vec<String>) -> String {
-        if params.len() >= 1 {
-            return params[0].clone();
-        } else {
-            return "conf/config.toml".to_string();
-        }
+    use crate::bcos2sdk::channelpack::ChannelPack;
+    use crate::bcossdkutil::contractabi::ContractABI;
+    use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+    use crate::testsupport::mock_channel_node::MockChannelNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn mock_client(node: &MockChannelNode) -> Result<Bcos2Client, KissError> {
+        let configfile = node.write_client_config()?;
+        Bcos2Client::new_from_config(configfile.as_str())
     }
 
     #[test]
     fn lib_test_init() -> Result<(), KissError> {
-        let cli: Cli = Cli::from_args();
-        println!("cli : {:?}", cli);
-        let configfile = default_config(&cli.params);
-        let bcossdk = Bcos2Client::new_from_config(configfile.as_str())?;
+        let node = MockChannelNode::start()?;
+        let bcossdk = mock_client(&node)?;
         println!("bcossdkutil : {:?}", bcossdk.to_summary());
         assert!(bcossdk.account.privkey.len() > 0);
         Ok(())
@@ -45,15 +44,81 @@ mod tests {
 
     #[test]
     fn lib_test_getNodeVersion() -> Result<(), KissError> {
-        let cli: Cli = Cli::from_args();
-        println!("cli : {:?}", cli);
-        bcossdkutil::macrodef::set_debugprint(true);
-        let configfile = default_config(&cli.params);
-        let mut bcossdk = Bcos2Client::new_from_config(configfile.as_str())?;
-        println!("start to getNodeVersion");
+        let node = MockChannelNode::start()?;
+        let mut bcossdk = mock_client(&node)?;
         let result = bcossdk.getNodeVersion()?;
         println!("lib_test_getNodeVersion:  {:?}", result);
         assert!(result["FISCO-BCOS Version"].as_str() != None);
+        assert!(node.topics().contains(&"_block_notify_1".to_string()));
+        bcossdk.finish();
+        Ok(())
+    }
+
+    #[test]
+    fn lib_test_send_transaction() -> Result<(), KissError> {
+        let node = MockChannelNode::start()?;
+        let mut bcossdk = mock_client(&node)?;
+        let contract = ContractABI::new("contracts/HelloWorld.abi", &bcossdk.hashtype)?;
+        let address = "0x882be29b2d5ac85d6c476fa3fd5f0cae4b4585cc";
+        let receipt =
+            bcossdk.sendRawTransactionGetReceipt(&contract, address, "set", &["hello".to_string()])?;
+        assert_eq!(receipt["result"]["status"], "0x0");
+        assert_eq!(receipt["result"]["blockNumber"], "0x1");
+        assert_eq!(bcossdk.getBlockNumber()?, 1);
+
+        let tx = node.chain().last_transaction().unwrap();
+        assert_eq!(hex::encode(&tx.transaction.to_address), &address[2..]);
+        let input = contract.encode_function_input_to_abi("set", &["hello".to_string()], true)?;
+        assert_eq!(hex::encode(&tx.transaction.data), input.trim_start_matches("0x"));
+        bcossdk.finish();
+        Ok(())
+    }
+
+    ///回执来自节点按请求seq推送的TX_COMMITTED，推送晚到时一直等到超时
+    #[test]
+    fn lib_test_receipt_by_tx_committed() -> Result<(), KissError> {
+        let node = MockChannelNode::start()?;
+        let mut bcossdk = mock_client(&node)?;
+        let contract = ContractABI::new("contracts/HelloWorld.abi", &bcossdk.hashtype)?;
+        let address = "0x882be29b2d5ac85d6c476fa3fd5f0cae4b4585cc";
+        let params =
+            contract.convert_function_input_str_to_token("set", &["hi".to_string()], true)?;
+        node.state().hold_tx_committed = true;
+
+        //链上已经有回执，但没有推送，等待超时而不是去轮询
+        let err = bcossdk
+            .send_raw_transaction_wait_receipt(&contract, address, "set", &params, 1)
+            .unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ETimeout);
+
+        //第二笔交易发出1秒后才按它的seq推送，回执以推送的为准
+        let receipt = std::thread::scope(|scope| {
+            let pusher = scope.spawn(|| {
+                let seq = loop {
+                    let sent: Vec<ChannelPack> = node
+                        .received_packs()
+                        .into_iter()
+                        .filter(|p| String::from_utf8_lossy(&p.data).contains("sendRawTransaction"))
+                        .collect();
+                    if sent.len() == 2 {
+                        break sent[1].seq;
+                    }
+                    std::thread::sleep(Duration::from_millis(20));
+                };
+                std::thread::sleep(Duration::from_millis(1000));
+                node.push_tx_committed(&seq, &json!({"status": "0x0", "blockNumber": "0x9"}))
+            });
+            let receipt =
+                bcossdk.send_raw_transaction_wait_receipt(&contract, address, "set", &params, 5);
+            assert_eq!(pusher.join().unwrap(), 1);
+            receipt
+        })?;
+        assert_eq!(receipt["result"]["blockNumber"], "0x9");
+        assert!(!node
+            .received_packs()
+            .iter()
+            .any(|p| String::from_utf8_lossy(&p.data).contains("getTransactionReceipt")));
+        bcossdk.finish();
         Ok(())
     }
 }
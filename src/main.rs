@@ -10,7 +10,8 @@
     unused_results,
     unused_variables
 )]
-mod bcossdkutil;
+use fisco_bcos_rust_gears_sdk::bcossdkutil;
+use fisco_bcos_rust_gears_sdk::{kisserr, kisserrcode};
 mod console;
 mod sample;
 
@@ -29,6 +30,7 @@ use crate::sample::demo_bcos3event;
 use console::cli_common::Cli;
 use fisco_bcos_rust_gears_sdk::bcos2sdk;
 use fisco_bcos_rust_gears_sdk::bcos2sdk::bcos2client::Bcos2Client;
+use fisco_bcos_rust_gears_sdk::bcos2sdk::bcos_channel_amop;
 use fisco_bcos_rust_gears_sdk::bcos2sdk::bcos_channel_tassl_sock_ffi;
 use fisco_bcos_rust_gears_sdk::bcos2sdk::bcos_channel_threads_worker;
 use fisco_bcos_rust_gears_sdk::bcos2sdk::eventhandler;
@@ -108,6 +110,15 @@ pub async fn main() {
         "event_demo" => {
             let res = eventhandler::event_demo(cli.default_configfile().as_str()).await;
         }
+        "amop_demo" => {
+            //cargo run amop_demo sub|pub topic
+            let mode = cli.params.get(0).cloned().unwrap_or("pub".to_string());
+            let topic = cli.params.get(1).cloned().unwrap_or("rust_amop".to_string());
+            let res =
+                bcos_channel_amop::demo_amop(configfile.as_str(), mode.as_str(), topic.as_str())
+                    .await;
+            println!("amop demo result {:?}", res);
+        }
         "ssock_ffi" => {
             bcos_channel_tassl_sock_ffi::test_ssock();
         }
@@ -7,6 +7,7 @@ use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
 
+#[cfg(not(feature = "bcos3sdk_native"))]
 use fisco_bcos_rust_gears_sdk::bcos3sdk::bcos3sdkamop::amop_sub_callback;
 use fisco_bcos_rust_gears_sdk::bcos3sdk::bcos3sdkfuture::Bcos3SDKFuture;
 use libc::c_char;
@@ -407,25 +408,29 @@ pub fn test_bcos3sdk_ffi_rpc() -> Result<i32, KissError> {
 
         bcos_event_sub_unsubscribe_event(p_sdk, "param".as_ptr() as *const c_char);
 
-        seq = Bcos3SDKFuture::next_seq();
-        println!("----------- start seq: ,bcos_amop_subscribe_topic{}", seq);
-        let guangzhou = "guangzhou003";
-        let shenzhen = "shenzhen0755";
-        let topics = vec![str2p!(guangzhou), str2p!(shenzhen)];
-        bcos_amop_subscribe_topic(p_sdk, topics.as_ptr(), 2);
-        println!("bcos_amop_subscribe_topic done");
-        //--------------------------------------------------------------------------------------
-        seq = Bcos3SDKFuture::next_seq();
-        println!("----------- start seq: ,bcos_amop_subscribe_topic{}", seq);
-        let cbfuture = Bcos3SDKFuture::create(seq, "bcos_amop_subscribe_topic_with_cb", "for test");
-        let topic = CString::new("cbtest001").unwrap();
-        bcos_amop_subscribe_topic_with_cb(
-            p_sdk,
-            topic.as_ptr(),
-            amop_sub_callback,
-            Bcos3SDKFuture::to_c_ptr(&cbfuture),
-        );
-        println!("bcos_amop_subscribe_topic_with_cb done");
+        //纯rust的sdk(bcos3sdk_native)没有AMOP接口
+        #[cfg(not(feature = "bcos3sdk_native"))]
+        {
+            seq = Bcos3SDKFuture::next_seq();
+            println!("----------- start seq: ,bcos_amop_subscribe_topic{}", seq);
+            let guangzhou = "guangzhou003";
+            let shenzhen = "shenzhen0755";
+            let topics = vec![str2p!(guangzhou), str2p!(shenzhen)];
+            bcos_amop_subscribe_topic(p_sdk, topics.as_ptr(), 2);
+            println!("bcos_amop_subscribe_topic done");
+            //--------------------------------------------------------------------------------------
+            seq = Bcos3SDKFuture::next_seq();
+            println!("----------- start seq: ,bcos_amop_subscribe_topic{}", seq);
+            let cbfuture = Bcos3SDKFuture::create(seq, "bcos_amop_subscribe_topic_with_cb", "for test");
+            let topic = CString::new("cbtest001").unwrap();
+            bcos_amop_subscribe_topic_with_cb(
+                p_sdk,
+                topic.as_ptr(),
+                amop_sub_callback,
+                Bcos3SDKFuture::to_c_ptr(&cbfuture),
+            );
+            println!("bcos_amop_subscribe_topic_with_cb done");
+        }
 
         println!("ready to quit");
         thread::sleep(Duration::from_secs(2));
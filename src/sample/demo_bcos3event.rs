@@ -34,20 +34,16 @@ struct EventCallbackContext {
     hashtype: HashType,
 }
 
-pub extern "C" fn demo_bcos3_event_callback(resp: *const bcos_sdk_c_struct_response) {
-    let response = Bcos3SDKResponse::from_callback(resp);
+//事件回调的处理函数，context由回调登记表持有，直到取消订阅
+fn demo_bcos3_event_callback(context: &EventCallbackContext, response: Bcos3SDKResponse) {
+    println!("context is {:?}", context);
+    let contractabi = ContractABI::new_by_name(
+        context.contractname.as_str(),
+        context.contractpath.as_str(),
+        &HashType::KECCAK,
+    )
+    .unwrap();
     unsafe {
-        let pcontext = response.context_pointer as *const EventCallbackContext;
-        println!("context is {:?}", (*pcontext));
-        let contractname = (*pcontext).contractname.clone();
-        let contextpath = (*pcontext).contractpath.clone();
-        let contractabi = ContractABI::new_by_name(
-            contractname.as_str(),
-            contextpath.as_str(),
-            &HashType::KECCAK,
-        )
-        .unwrap();
-
         let v = response.get_result().unwrap();
 
         g_counter = g_counter + 1;
@@ -103,19 +99,15 @@ pub fn demo_event(cli: &Cli) -> Result<(), KissError> {
 
         if mode.as_str() == "async" {
             //给bcos3 c sdk传入一个独立的函数回调，不用wait模式，在生命周期中，监听的事件会一直出发回调函数
-            //这里构造的一个上下文结构体，将其指针传给了c sdk，会带到回调函数里，要保证其实例一直没有被释放，即指针有效
+            //上下文结构体交给回调登记表持有，传给c sdk的只是登记表的句柄，取消订阅时注销
             let event_context = EventCallbackContext {
                 contractname: contractname.to_string(),
-                contractpath: bcos3client.config.common.contractpath,
+                contractpath: bcos3client.config.common.contractpath.clone(),
                 hashtype: CommonHash::crypto_to_hashtype(&bcos3client.config.common.crypto),
             };
-            bcos_event_sub_subscribe_event(
-                bcos3client.sdk,
-                str2p!(bcos3client.group.as_str()),
-                str2p!(paramstr.as_str()),
-                demo_bcos3_event_callback,
-                &event_context as *const EventCallbackContext as *const c_void,
-            );
+            let sub = bcos3client.subscribe_event(&event_sub_param, move |response| {
+                demo_bcos3_event_callback(&event_context, response)
+            })?;
             let mut n = 0;
             while n < 15 {
                 thread::sleep(Duration::from_secs(1));
@@ -124,6 +116,8 @@ pub fn demo_event(cli: &Cli) -> Result<(), KissError> {
                 }
                 n = n + 1;
             }
+            bcos3client.unsubscribe_event(sub)?;
+            bcos3client.finish();
             return Ok(());
         } else {
             //采用wait模式，构建一个bcos3特有的future对象，采用mpsc channel实现同步轮询等待回调
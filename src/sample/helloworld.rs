@@ -42,7 +42,7 @@ pub fn demo_deploy_helloworld(bcossdk: &mut Bcos2Client) -> Result<String, KissE
     let response = v.unwrap();
     let txhash = response["result"].as_str().unwrap();
     //thread::sleep(Duration::from_secs(1));
-    let recepitresult = bcossdk.try_getTransactionReceipt(txhash, 3, false);
+    let recepitresult = bcossdk.wait_receipt(txhash);
     //println!("receipt {:?}",recepitresult);
     let receipt = recepitresult.unwrap();
     console_utils::display_transaction_receipt(&receipt, &Option::None, &bcossdk.config);
@@ -109,7 +109,7 @@ pub fn demo(configfile: &str) {
     println!("tx input :{:?}", inputdecode);
 
     println!("\n>>>>>>>>>>>>>>>>>>>>demo helloworld getTransactionReceipt");
-    let recepitresult = bcossdk.try_getTransactionReceipt(txhash, 3, false);
+    let recepitresult = bcossdk.wait_receipt(txhash);
     console_utils::display_transaction_receipt(
         &recepitresult.unwrap(),
         &Option::from(&contract),
@@ -40,7 +40,7 @@ pub fn demo_deploy_simpleinfo(bcossdk: &mut Bcos2Client) -> Result<String, KissE
     println!("request response {:?}", v);
     let response = v.unwrap();
     let txhash = response["result"].as_str().unwrap();
-    let recepitresult = bcossdk.try_getTransactionReceipt(txhash, 3, false);
+    let recepitresult = bcossdk.wait_receipt(txhash);
     //println!("receipt {:?}",recepitresult);
 
     let receipt = recepitresult.unwrap();
@@ -43,7 +43,7 @@ pub fn demo_deploy(bcossdk: &mut Bcos2Client, contract: &ContractABI) -> Result<
     println!("request response {:?}", v);
     let response = v.unwrap();
     let txhash = response["result"].as_str().unwrap();
-    let recepitresult = bcossdk.try_getTransactionReceipt(txhash, 3, false);
+    let recepitresult = bcossdk.wait_receipt(txhash);
     println!("receipt {:?}", recepitresult);
     let receipt = recepitresult.unwrap();
     let addr: String = receipt["result"]["contractAddress"]
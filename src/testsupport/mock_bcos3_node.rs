@@ -0,0 +1,703 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 进程内的FISCO BCOS 3.x节点，用于纯rust的bcos3 sdk(bcos3sdk_native)离线测试。
+//! tls证书和2.x的mock节点一样生成，连接后做websocket升级，按boost-ssl的消息格式收发：
+//! 握手时返回群组信息和块高，rpc请求按方法名应答，sendTransaction会解码tars交易、校验hash并出块，
+//! 出块后给所有连接推送BLOCK_NOTIFY。测试可以用set_result固定某个方法的返回值。
+//! 事件订阅只记下订阅请求，测试用push_event_logs给每个订阅推送一批日志
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use bytes::BytesMut;
+use openssl::ssl::{SslAcceptor, SslStream};
+use serde_json::{json, Value as JsonValue};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::bcos3sdk::bcos3sdk_tars::Bcos3Transaction;
+use crate::bcos3sdk::bcos3sdk_websocket::{
+    http_header_value, make_upgrade_response, pop_http_header, WsFrame, WsFrameCodec, WS_OPCODE,
+};
+use crate::bcos3sdk::bcos3sdk_wsmessage::{Bcos3WsMessage, BCOS3_WS_MSG_TYPE};
+use crate::bcossdkutil::bcosclientconfig::ChannelConfig;
+use crate::bcossdkutil::commonhash::HashType;
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+use crate::testsupport::mock_chain::rpc_error;
+use crate::testsupport::mock_channel_node::make_certs_and_acceptor;
+use crate::testsupport::write_mock_client_config;
+
+const SESSION_POLL_MS: u64 = 20;
+pub const MOCK_BCOS3_CHAIN: &str = "chain0";
+pub const MOCK_BCOS3_GROUP: &str = "group0";
+
+///节点状态，测试可以读出来做断言
+#[derive(Default)]
+pub struct MockBcos3State {
+    pub blocknumber: i64,
+    ///上链的交易，按收到的顺序
+    pub transactions: Vec<Bcos3Transaction>,
+    pub receipts: HashMap<String, JsonValue>,
+    ///固定返回值的方法，优先于内置的实现
+    pub results: HashMap<String, JsonValue>,
+    ///收到的rpc请求
+    pub requests: Vec<JsonValue>,
+    pub handshakes: u32,
+    ///每个rpc请求回包前的延迟
+    pub latency: Duration,
+    ///事件订阅，key是订阅id，value是订阅请求
+    pub event_subs: HashMap<String, JsonValue>,
+    ///非0时拒绝事件订阅，应答里的status就是这个值
+    pub event_sub_status: i32,
+    sessions: Vec<Sender<Bcos3WsMessage>>,
+}
+
+impl MockBcos3State {
+    fn handle_rpc(&mut self, request: &JsonValue) -> (JsonValue, bool) {
+        self.requests.push(request.clone());
+        let method = request["method"].as_str().unwrap_or("");
+        let params = &request["params"];
+        let mut newblock = false;
+        let result = match self.results.get(method) {
+            Some(r) => Ok(r.clone()),
+            None => match method {
+                "getBlockNumber" => Ok(json!(self.blocknumber)),
+                "sendTransaction" => {
+                    let res = self.apply_transaction(params[2].as_str().unwrap_or(""));
+                    newblock = res.is_ok();
+                    res
+                }
+                "getTransactionReceipt" => {
+                    let hash = params[2].as_str().unwrap_or("");
+                    Ok(self.receipts.get(hash).cloned().unwrap_or(JsonValue::Null))
+                }
+                "call" => Ok(json!({"blockNumber": self.blocknumber, "output": "0x", "status": 0})),
+                _ => Err(rpc_error(-32601, &format!("method {} not found", method))),
+            },
+        };
+        let mut response = json!({"jsonrpc": "2.0", "id": request["id"].clone()});
+        match result {
+            Ok(r) => response["result"] = r,
+            Err(e) => response["error"] = e,
+        }
+        (response, newblock)
+    }
+
+    fn apply_transaction(&mut self, txhex: &str) -> Result<JsonValue, JsonValue> {
+        let raw = match hex::decode(txhex.trim_start_matches("0x")) {
+            Ok(r) => r,
+            Err(e) => return Err(rpc_error(-32602, &format!("bad tx hex {:?}", e))),
+        };
+        let tx = match Bcos3Transaction::decode(&raw) {
+            Ok(t) => t,
+            Err(e) => return Err(rpc_error(-32602, &e.msg)),
+        };
+        if tx.data.hash(&HashType::WEDPR_KECCAK) != tx.dataHash {
+            return Err(rpc_error(10000, "transaction hash mismatch"));
+        }
+        self.blocknumber += 1;
+        let txhash = tx.hash_hex();
+        let receipt = json!({
+            "transactionHash": txhash,
+            "blockNumber": self.blocknumber,
+            "status": 0,
+            "output": "0x",
+            "to": tx.data.to,
+            "logEntries": [],
+        });
+        self.receipts.insert(txhash, receipt.clone());
+        self.transactions.push(tx);
+        Ok(receipt)
+    }
+
+    fn block_notify(&self) -> Bcos3WsMessage {
+        let payload = json!({"group": MOCK_BCOS3_GROUP, "blockNumber": self.blocknumber});
+        Bcos3WsMessage::new(
+            BCOS3_WS_MSG_TYPE::BLOCK_NOTIFY,
+            payload.to_string().into_bytes(),
+        )
+    }
+
+    fn handle_event_sub(&mut self, msg: &Bcos3WsMessage) -> Bcos3WsMessage {
+        let request: JsonValue = serde_json::from_slice(&msg.payload).unwrap_or(JsonValue::Null);
+        let id = request["id"].as_str().unwrap_or("").to_string();
+        let mut status = 0;
+        if msg.msgtype == BCOS3_WS_MSG_TYPE::EVENT_UNSUBSCRIBE as u16 {
+            self.event_subs.remove(&id);
+        } else if self.event_sub_status != 0 {
+            status = self.event_sub_status;
+        } else {
+            self.event_subs.insert(id.clone(), request);
+        }
+        msg.reply(json!({"id": id, "status": status}).to_string().into_bytes())
+    }
+
+    fn push(&mut self, msg: &Bcos3WsMessage) -> usize {
+        self.sessions.retain(|s| s.send(msg.clone()).is_ok());
+        self.sessions.len()
+    }
+}
+
+pub struct MockBcos3Node {
+    pub addr: SocketAddr,
+    pub certdir: PathBuf,
+    pub state: Arc<Mutex<MockBcos3State>>,
+    stop: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl MockBcos3Node {
+    pub fn start() -> Result<MockBcos3Node, KissError> {
+        let certdir =
+            std::env::temp_dir().join(format!("bcos3_mock_node_{:016x}", rand::random::<u64>()));
+        if let Err(e) = std::fs::create_dir_all(&certdir) {
+            return kisserr!(KissErrKind::Error, "create dir {:?} error {:?}", certdir, e);
+        }
+        let acceptor = match make_certs_and_acceptor(&certdir) {
+            Ok(a) => a,
+            Err(e) => return kisserr!(KissErrKind::Error, "make mock node certs error {:?}", e),
+        };
+        let listener = match TcpListener::bind("127.0.0.1:0") {
+            Ok(l) => l,
+            Err(e) => return kisserr!(KissErrKind::ENetwork, "bind mock node error {:?}", e),
+        };
+        let addr = listener.local_addr().unwrap();
+        let _ = listener.set_nonblocking(true);
+        let state: Arc<Mutex<MockBcos3State>> = Default::default();
+        state.lock().unwrap().blocknumber = 1;
+        let stop = Arc::new(AtomicBool::new(false));
+        let accept_thread = {
+            let (state, stop) = (state.clone(), stop.clone());
+            std::thread::spawn(move || accept_loop(listener, acceptor, state, stop))
+        };
+        Ok(MockBcos3Node {
+            addr,
+            certdir,
+            state,
+            stop,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockBcos3State> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn set_result(&self, method: &str, result: JsonValue) {
+        self.state().results.insert(method.to_string(), result);
+    }
+
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    ///收到的某个方法的rpc请求次数
+    pub fn request_count(&self, method: &str) -> usize {
+        let state = self.state();
+        state
+            .requests
+            .iter()
+            .filter(|r| r["method"] == json!(method))
+            .count()
+    }
+
+    ///出一个空块并推送块高，返回推送到的连接数
+    pub fn push_block(&self) -> usize {
+        let mut state = self.state();
+        state.blocknumber += 1;
+        let msg = state.block_notify();
+        state.push(&msg)
+    }
+
+    ///给每个事件订阅推送一批日志，推送到所有连接，sdk会丢掉不是自己的订阅。返回订阅数
+    pub fn push_event_logs(&self, logs: JsonValue) -> usize {
+        let mut state = self.state();
+        let ids: Vec<String> = state.event_subs.keys().cloned().collect();
+        for id in ids.iter() {
+            let payload = json!({"id": id, "status": 0, "result": logs});
+            let msg = Bcos3WsMessage::new(
+                BCOS3_WS_MSG_TYPE::EVENT_LOG_PUSH,
+                payload.to_string().into_bytes(),
+            );
+            state.push(&msg);
+        }
+        ids.len()
+    }
+
+    ///在证书目录里写bcos3 sdk的ini文件，返回文件路径
+    pub fn write_sdk_ini(&self) -> Result<String, KissError> {
+        let text = format!(
+            "[common]\nmessage_timeout_ms = 5000\n\n[cert]\nssl_type = ssl\nca_path = {}\n\n[peers]\nnode.0 = {}\n",
+            self.certdir.to_string_lossy().replace('\\', "/"),
+            self.addr
+        );
+        let inifile = self
+            .certdir
+            .join("bcos3_sdk_config.ini")
+            .to_string_lossy()
+            .to_string();
+        fileutils::write_all(&inifile, text.into_bytes())?;
+        Ok(inifile)
+    }
+
+    ///写sdk的ini和连到本节点的客户端配置(bcos2.protocol为BCOS3)，返回客户端配置文件路径
+    pub fn write_client_config(&self) -> Result<String, KissError> {
+        let inifile = self.write_sdk_ini()?;
+        let configfile = write_mock_client_config(
+            &self.certdir,
+            1,
+            "BCOS3",
+            "http://127.0.0.1:8545",
+            &ChannelConfig::default(),
+        )?;
+        let text = fileutils::readstring(&configfile)?.replace(
+            "./bcos3sdklib/bcos3_sdk_config.ini",
+            &inifile.replace('\\', "/"),
+        );
+        fileutils::write_all(&configfile, text.into_bytes())?;
+        Ok(configfile)
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(t) = self.accept_thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for MockBcos3Node {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = std::fs::remove_dir_all(&self.certdir);
+    }
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+    state: Arc<Mutex<MockBcos3State>>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut sessions: Vec<JoinHandle<()>> = vec![];
+    while !stop.load(Ordering::SeqCst) {
+        let tcp = match listener.accept() {
+            Ok((tcp, _)) => tcp,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(SESSION_POLL_MS));
+                continue;
+            }
+            Err(e) => continue,
+        };
+        let _ = tcp.set_nonblocking(false);
+        let _ = tcp.set_read_timeout(Some(Duration::from_secs(5)));
+        let stream = match acceptor.accept(tcp) {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!("mock bcos3 node tls accept error {:?}", e);
+                continue;
+            }
+        };
+        let _ = stream
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_millis(SESSION_POLL_MS)));
+        let (pushtx, pushrx) = channel();
+        state.lock().unwrap().sessions.push(pushtx);
+        let session = MockBcos3Session {
+            stream,
+            state: state.clone(),
+            codec: WsFrameCodec::server(),
+            upgraded: false,
+        };
+        let stop = stop.clone();
+        sessions.push(std::thread::spawn(move || session.run(pushrx, stop)));
+    }
+    for s in sessions {
+        let _ = s.join();
+    }
+}
+
+struct MockBcos3Session {
+    stream: SslStream<TcpStream>,
+    state: Arc<Mutex<MockBcos3State>>,
+    codec: WsFrameCodec,
+    upgraded: bool,
+}
+
+impl MockBcos3Session {
+    fn run(mut self, pushrx: Receiver<Bcos3WsMessage>, stop: Arc<AtomicBool>) {
+        let mut inbuf = BytesMut::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        while !stop.load(Ordering::SeqCst) {
+            while self.upgraded {
+                match pushrx.try_recv() {
+                    Ok(msg) if self.write(&msg).is_ok() => {}
+                    Ok(_) => return,
+                    Err(_) => break,
+                }
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => return,
+                Ok(n) => inbuf.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    continue
+                }
+                Err(e) => return,
+            }
+            if !self.upgraded {
+                let header = match pop_http_header(&mut inbuf) {
+                    Ok(Some(h)) => h,
+                    Ok(None) => continue,
+                    Err(e) => return,
+                };
+                let key = http_header_value(&header, "Sec-WebSocket-Key").unwrap_or_default();
+                if self
+                    .stream
+                    .write_all(make_upgrade_response(&key).as_bytes())
+                    .is_err()
+                {
+                    return;
+                }
+                self.upgraded = true;
+            }
+            loop {
+                let frame = match self.codec.decode(&mut inbuf) {
+                    Ok(Some(f)) => f,
+                    Ok(None) => break,
+                    Err(e) => return,
+                };
+                if frame.opcode == WS_OPCODE::CLOSE as u8 {
+                    return;
+                }
+                let msg = match Bcos3WsMessage::decode(&frame.payload) {
+                    Ok(m) => m,
+                    Err(e) => return,
+                };
+                let latency = self.state.lock().unwrap().latency;
+                if msg.msgtype == BCOS3_WS_MSG_TYPE::RPC_REQUEST as u16 && latency > Duration::ZERO
+                {
+                    std::thread::sleep(latency);
+                }
+                for reply in self.handle(&msg) {
+                    if self.write(&reply).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        let _ = self.stream.shutdown();
+    }
+
+    fn write(&mut self, msg: &Bcos3WsMessage) -> Result<(), KissError> {
+        let mut out = BytesMut::new();
+        self.codec
+            .encode(WsFrame::new(WS_OPCODE::BINARY, msg.encode()), &mut out)?;
+        self.stream.write_all(&out)?;
+        Ok(())
+    }
+
+    fn handle(&mut self, msg: &Bcos3WsMessage) -> Vec<Bcos3WsMessage> {
+        let mut state = self.state.lock().unwrap();
+        if msg.msgtype == BCOS3_WS_MSG_TYPE::HANDSHAKE as u16 {
+            state.handshakes += 1;
+            let response = json!({
+                "protocolVersion": 1,
+                "groupInfoList": [{"chainID": MOCK_BCOS3_CHAIN, "groupID": MOCK_BCOS3_GROUP,
+                    "genesisConfig": {"smCrypto": false, "isWasm": false}}],
+                "groupBlockNumber": [{MOCK_BCOS3_GROUP: state.blocknumber}],
+            });
+            return vec![msg.reply(response.to_string().into_bytes())];
+        }
+        if msg.msgtype == BCOS3_WS_MSG_TYPE::EVENT_SUBSCRIBE as u16
+            || msg.msgtype == BCOS3_WS_MSG_TYPE::EVENT_UNSUBSCRIBE as u16
+        {
+            return vec![state.handle_event_sub(msg)];
+        }
+        if msg.msgtype != BCOS3_WS_MSG_TYPE::RPC_REQUEST as u16 {
+            return vec![];
+        }
+        let request: JsonValue = serde_json::from_slice(&msg.payload).unwrap_or(JsonValue::Null);
+        let (response, newblock) = state.handle_rpc(&request);
+        let mut replies = vec![];
+        if newblock {
+            //真实节点先推送块高再回包，sdk要能在等回包时处理推送
+            replies.push(state.block_notify());
+        }
+        replies.push(msg.reply(response.to_string().into_bytes()));
+        replies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos3sdk::bcos3sdk_native::{Bcos3NativeSdk, BCOS3_BLOCK_LIMIT_RANGE};
+    use crate::bcos3sdk::bcos3sdk_tars::Bcos3TransactionData;
+    use crate::bcossdkutil::accountutil::create_account;
+    use crate::bcossdkutil::bcosclientconfig::BcosCryptoKind;
+
+    #[test]
+    fn test_native_sdk_with_mock_node() {
+        let node = MockBcos3Node::start().unwrap();
+        let mut sdk = Bcos3NativeSdk::from_config_file(&node.write_sdk_ini().unwrap()).unwrap();
+        sdk.start().unwrap();
+        assert_eq!(
+            sdk.group_info(MOCK_BCOS3_GROUP).unwrap().chainID,
+            MOCK_BCOS3_CHAIN
+        );
+        assert_eq!(
+            sdk.block_limit(MOCK_BCOS3_GROUP).unwrap(),
+            1 + BCOS3_BLOCK_LIMIT_RANGE
+        );
+
+        let text = sdk
+            .rpc_request("getBlockNumber", json!([MOCK_BCOS3_GROUP, ""]))
+            .unwrap();
+        assert!(text.contains("\"result\":1"));
+
+        let account = create_account(&BcosCryptoKind::ECDSA);
+        let data = Bcos3TransactionData::new(MOCK_BCOS3_CHAIN, MOCK_BCOS3_GROUP, 501, "", b"abc");
+        let tx = Bcos3Transaction::sign(data, &account, &HashType::WEDPR_KECCAK, 0).unwrap();
+        let text = sdk
+            .rpc_request(
+                "sendTransaction",
+                json!([MOCK_BCOS3_GROUP, "", hex::encode(tx.encode()), false]),
+            )
+            .unwrap();
+        let response: JsonValue = serde_json::from_str(&text).unwrap();
+        assert_eq!(response["result"]["transactionHash"], json!(tx.hash_hex()));
+        //回包前推送的块高已经记下
+        assert_eq!(sdk.group_info(MOCK_BCOS3_GROUP).unwrap().blockNumber, 2);
+        assert_eq!(node.state().transactions[0].data.input, b"abc".to_vec());
+
+        let text = sdk.rpc_request("noSuchMethod", json!([])).unwrap();
+        assert!(text.contains("-32601"));
+        sdk.stop();
+    }
+
+    #[cfg(feature = "bcos3sdk_native")]
+    #[test]
+    fn test_bcos3client_native() {
+        use crate::bcos3sdk::bcos3client::Bcos3Client;
+
+        let node = MockBcos3Node::start().unwrap();
+        let configfile = node.write_client_config().unwrap();
+        let mut client = Bcos3Client::new(&configfile).unwrap();
+        assert_eq!(client.getBlockNumber().unwrap(), 1);
+        let receipt = client.deploy_hexcode("6080").unwrap();
+        assert_eq!(receipt["status"], json!(0));
+        assert_eq!(node.state().transactions[0].data.input, vec![0x60, 0x80]);
+        client.finish();
+    }
+
+    #[cfg(feature = "bcos3sdk_native")]
+    #[tokio::test]
+    async fn test_bcos3client_native_async() {
+        use crate::bcos3sdk::bcos3client::Bcos3Client;
+
+        let node = MockBcos3Node::start().unwrap();
+        let configfile = node.write_client_config().unwrap();
+        let mut client = Bcos3Client::new(&configfile).unwrap();
+        assert_eq!(client.getBlockNumberAsync().await.unwrap(), 1);
+        let receipt = client.deploy_hexcode_async("6080").await.unwrap();
+        assert_eq!(receipt["status"], json!(0));
+        let txhash = receipt["transactionHash"].as_str().unwrap().to_string();
+        let fetched = client.getTransactionReceiptAsync(&txhash, 0).await.unwrap();
+        assert_eq!(fetched, receipt);
+        assert_eq!(client.getBlockNumberAsync().await.unwrap(), 2);
+        let err = client.getPeersAsync().await.unwrap_err();
+        assert_eq!(err.code, -32601);
+        client.finish();
+    }
+    #[cfg(feature = "bcos3sdk_native")]
+    #[tokio::test]
+    async fn test_bcos3client_native_async_timeout_and_cancel() {
+        use crate::bcos3sdk::bcos3client::Bcos3Client;
+        use crate::bcossdkutil::kisserror::KissErrKind;
+        use std::sync::atomic::AtomicU32;
+        use std::time::Instant;
+
+        let node = MockBcos3Node::start().unwrap();
+        let configfile = node.write_client_config().unwrap();
+        let mut client = Bcos3Client::new(&configfile).unwrap();
+        client.policy.timeout = 1;
+        client.policy.max_retries = 0;
+        node.set_latency(Duration::from_millis(1500));
+
+        //单线程的runtime，等待回包时其他任务照常运行，超时按策略返回
+        let ticks = Arc::new(AtomicU32::new(0));
+        let ticker = {
+            let ticks = ticks.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+        let start = Instant::now();
+        let err = client.getBlockNumberAsync().await.unwrap_err();
+        assert_eq!(err.kind, KissErrKind::ETimeout);
+        assert!(start.elapsed() < Duration::from_millis(1400));
+        assert!(ticks.load(Ordering::SeqCst) >= 10);
+        ticker.abort();
+
+        //丢弃还没完成的future，迟到的回调被丢弃，之后的请求不受影响
+        let pending = client.getBlockNumberAsync();
+        assert!(tokio::time::timeout(Duration::from_millis(100), pending)
+            .await
+            .is_err());
+        node.set_latency(Duration::ZERO);
+        client.policy.timeout = 5;
+        assert_eq!(client.getBlockNumberAsync().await.unwrap(), 1);
+        assert_eq!(node.request_count("getBlockNumber"), 3);
+        client.finish();
+    }
+
+    #[cfg(feature = "bcos3sdk_native")]
+    #[test]
+    fn test_bcos3client_native_cross_thread() {
+        use crate::bcos3sdk::bcos3client::Bcos3Client;
+
+        let node = MockBcos3Node::start().unwrap();
+        let configfile = node.write_client_config().unwrap();
+        let client = Arc::new(Bcos3Client::new(&configfile).unwrap());
+        node.set_latency(Duration::from_millis(100));
+        //同一个client在多个线程里同时发请求，每个请求拿到的都是自己的回包
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let client = client.clone();
+                std::thread::spawn(move || client.getBlockNumber())
+            })
+            .collect();
+        for w in workers {
+            assert_eq!(w.join().unwrap().unwrap(), 1);
+        }
+        assert_eq!(node.request_count("getBlockNumber"), 4);
+        match Arc::try_unwrap(client) {
+            Ok(mut client) => client.finish(),
+            Err(_) => panic!("client still shared"),
+        }
+    }
+
+    #[cfg(feature = "bcos3sdk_native")]
+    #[test]
+    fn test_bcos3client_native_event_subscribe() {
+        use crate::bcos3sdk::bcos3client::{Bcos3Client, Bcos3EventSubscription};
+        use crate::bcos3sdk::bcos3sdkcallback;
+        use crate::bcos3sdk::bcos3sdkwrapper::EventSubParam;
+        use std::time::Instant;
+
+        //等到handler收到n个回调，返回(error, data)列表
+        fn wait_for(received: &Mutex<Vec<(i32, String)>>, n: usize) -> Vec<(i32, String)> {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while received.lock().unwrap().len() < n && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            received.lock().unwrap().clone()
+        }
+
+        let mut node = MockBcos3Node::start().unwrap();
+        let configfile = node.write_client_config().unwrap();
+        let mut client = Bcos3Client::new(&configfile).unwrap();
+        let param = EventSubParam {
+            fromBlock: 1,
+            toBlock: 0,
+            addresses: vec!["0x1234".to_string()],
+            topics: vec![],
+        };
+        let received: Arc<Mutex<Vec<(i32, String)>>> = Default::default();
+        let handler = |received: &Arc<Mutex<Vec<(i32, String)>>>| {
+            let received = received.clone();
+            move |resp: crate::bcos3sdk::bcos3sdkresponse::Bcos3SDKResponse| {
+                received.lock().unwrap().push((resp.error, resp.data));
+            }
+        };
+
+        //订阅应答和推送的日志按顺序回调
+        let sub = client.subscribe_event(&param, handler(&received)).unwrap();
+        let request = node.state().event_subs[&sub.subid].clone();
+        assert_eq!(request["group"], json!(MOCK_BCOS3_GROUP));
+        assert_eq!(request["params"]["addresses"], json!(["0x1234"]));
+        assert_eq!(
+            node.push_event_logs(json!([{"address": "0x1234", "blockNumber": 2}])),
+            1
+        );
+        let got = wait_for(&received, 2);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].0, 0);
+        assert_eq!(got[1].0, 0);
+        let push: JsonValue = serde_json::from_str(&got[1].1).unwrap();
+        assert_eq!(push["id"], json!(sub.subid));
+        assert_eq!(push["result"][0]["blockNumber"], json!(2));
+        //订阅期间rpc照常
+        assert_eq!(client.getBlockNumber().unwrap(), 1);
+
+        //取消订阅后节点上没有订阅，句柄已注销
+        let handle = sub.handle;
+        client.unsubscribe_event(sub).unwrap();
+        assert!(node.state().event_subs.is_empty());
+        assert!(!bcos3sdkcallback::is_registered(handle));
+        let err = client
+            .unsubscribe_event(Bcos3EventSubscription {
+                subid: "sub-1".to_string(),
+                handle: bcos3sdkcallback::register_callback(|_| {}),
+            })
+            .unwrap_err();
+        assert_eq!(err.kind, KissErrKind::Error);
+
+        //节点拒绝订阅：错误回调一次，订阅失败，handler随之释放
+        node.state().event_sub_status = -1001;
+        let held = Arc::new(());
+        let rejected: Arc<Mutex<Vec<(i32, String)>>> = Default::default();
+        let h = held.clone();
+        let on_reject = handler(&rejected);
+        let err = client
+            .subscribe_event(&param, move |resp| {
+                let _ = &h;
+                on_reject(resp);
+            })
+            .unwrap_err();
+        assert_eq!(err.code, -1001);
+        assert_eq!(rejected.lock().unwrap().len(), 1);
+        assert_eq!(rejected.lock().unwrap()[0].0, -1001);
+        assert_eq!(Arc::strong_count(&held), 1);
+
+        //连接断开时订阅失效，用错误回调一次
+        node.state().event_sub_status = 0;
+        let dropped: Arc<Mutex<Vec<(i32, String)>>> = Default::default();
+        let sub = client.subscribe_event(&param, handler(&dropped)).unwrap();
+        assert_eq!(wait_for(&dropped, 1).len(), 1);
+        node.stop();
+        let got = wait_for(&dropped, 2);
+        assert_eq!(got.len(), 2);
+        assert_ne!(got[1].0, 0);
+        assert!(client.unsubscribe_event(sub).is_err());
+        client.finish();
+    }
+}
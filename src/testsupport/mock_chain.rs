@@ -0,0 +1,390 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 测试用的内存链模型：块高、交易和回执都放在内存里，每笔交易单独出一个块。
+//! rpc方法按名字注册在表里，测试可以覆盖或增加方法，channel和http的mock节点共用
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use serde_json::{json, Value as JsonValue};
+
+use crate::bcos2sdk::bcostransaction::BcosTransactionWithSig;
+use crate::bcossdkutil::bcosclientconfig::BcosCryptoKind;
+use crate::bcossdkutil::commonhash::{CommonHash, HashType};
+
+///rpc方法的实现，返回result或者json rpc的error对象
+pub type MOCK_RPC_METHOD =
+    Arc<dyn Fn(&mut MockChain, &JsonValue) -> Result<JsonValue, JsonValue> + Send + Sync>;
+
+pub const MOCK_NODE_VERSION: &str = "2.9.0";
+
+pub fn rpc_error(code: i64, message: &str) -> JsonValue {
+    json!({"code": code, "message": message})
+}
+
+fn to_hex_number(n: u64) -> String {
+    format!("0x{:x}", n)
+}
+
+///内存里的一条链，交易按收到的顺序上链，不执行合约
+pub struct MockChain {
+    pub groupid: u32,
+    pub crypto: BcosCryptoKind,
+    pub blocknumber: u64,
+    pub transactions: HashMap<String, JsonValue>,
+    pub receipts: HashMap<String, JsonValue>,
+    ///每个块只有一笔交易，blocks[n-1]是块n里的交易hash
+    pub blocks: Vec<String>,
+    ///按上链顺序保存的原始交易字节，测试可以解码后检查sdk签出来的内容
+    pub rawtxs: Vec<Vec<u8>>,
+    ///call的返回值，key是合约地址(小写不带0x)，没有设置时返回"0x"
+    pub call_outputs: HashMap<String, String>,
+    ///下一笔交易回执里带上的日志，上链后清空
+    pub next_logs: Vec<JsonValue>,
+    pub methods: HashMap<String, MOCK_RPC_METHOD>,
+    ///注入的错误，按方法名排队，每次调用消耗一个
+    pub injected_errors: HashMap<String, VecDeque<JsonValue>>,
+}
+
+impl MockChain {
+    pub fn new(groupid: u32, crypto: &BcosCryptoKind) -> MockChain {
+        let mut chain = MockChain {
+            groupid,
+            crypto: crypto.clone(),
+            blocknumber: 0,
+            transactions: HashMap::new(),
+            receipts: HashMap::new(),
+            blocks: vec![],
+            rawtxs: vec![],
+            call_outputs: HashMap::new(),
+            next_logs: vec![],
+            methods: HashMap::new(),
+            injected_errors: HashMap::new(),
+        };
+        chain.register_default_methods();
+        chain
+    }
+
+    ///注册或覆盖一个rpc方法
+    pub fn set_method<F>(&mut self, name: &str, method: F)
+    where
+        F: Fn(&mut MockChain, &JsonValue) -> Result<JsonValue, JsonValue> + Send + Sync + 'static,
+    {
+        self.methods.insert(name.to_string(), Arc::new(method));
+    }
+
+    ///固定返回某个值的rpc方法
+    pub fn set_result(&mut self, name: &str, result: JsonValue) {
+        self.set_method(name, move |_, _| Ok(result.clone()));
+    }
+
+    pub fn remove_method(&mut self, name: &str) {
+        self.methods.remove(name);
+    }
+
+    ///让接下来对该方法的一次调用返回error，多次调用依次排队
+    pub fn fail_next(&mut self, name: &str, error: JsonValue) {
+        self.injected_errors
+            .entry(name.to_string())
+            .or_default()
+            .push_back(error);
+    }
+
+    pub fn set_call_output(&mut self, address: &str, output: &str) {
+        self.call_outputs
+            .insert(normalize_address(address), output.to_string());
+    }
+
+    fn register_default_methods(&mut self) {
+        self.set_method("getClientVersion", |chain, _| {
+            Ok(json!({
+                "Build Time": "20210701 00:00:00",
+                "Build Type": "Linux/clang/Release",
+                "Chain Id": "1",
+                "FISCO-BCOS Version": MOCK_NODE_VERSION,
+                "Git Branch": "mock",
+                "Git Commit Hash": "0000000000000000000000000000000000000000",
+                "Supported Version": MOCK_NODE_VERSION,
+            }))
+        });
+        self.set_method("getBlockNumber", |chain, _| {
+            Ok(json!(to_hex_number(chain.blocknumber)))
+        });
+        self.set_method("getGroupList", |chain, _| Ok(json!([chain.groupid])));
+        self.set_method("sendRawTransaction", |chain, params| {
+            let receipt = chain.apply_raw_transaction(&params[1])?;
+            Ok(receipt["transactionHash"].clone())
+        });
+        self.set_method("getTransactionReceipt", |chain, params| {
+            let txhash = params[1].as_str().unwrap_or("").to_lowercase();
+            Ok(chain
+                .receipts
+                .get(&txhash)
+                .cloned()
+                .unwrap_or(JsonValue::Null))
+        });
+        self.set_method("getTransactionByHash", |chain, params| {
+            let txhash = params[1].as_str().unwrap_or("").to_lowercase();
+            Ok(chain
+                .transactions
+                .get(&txhash)
+                .cloned()
+                .unwrap_or(JsonValue::Null))
+        });
+        self.set_method("getTotalTransactionCount", |chain, _| {
+            Ok(json!({
+                "blockNumber": to_hex_number(chain.blocknumber),
+                "failedTxSum": "0x0",
+                "txSum": to_hex_number(chain.rawtxs.len() as u64),
+            }))
+        });
+        self.set_method("getPendingTxSize", |_, _| Ok(json!("0x0")));
+        self.set_method("getBlockHashByNumber", |chain, params| {
+            match chain.block_number_param(&params[1]) {
+                Some(n) => Ok(json!(block_hash(n))),
+                None => Err(rpc_error(-40001, "block not found")),
+            }
+        });
+        self.set_method("getBlockByNumber", |chain, params| {
+            match chain.block_number_param(&params[1]) {
+                Some(n) => Ok(chain.block_json(n, params[2].as_bool().unwrap_or(false))),
+                None => Err(rpc_error(-40001, "block not found")),
+            }
+        });
+        self.set_method("getBatchReceiptsByBlockNumberAndRange", |chain, params| {
+            let n = match chain.block_number_param(&params[1]) {
+                Some(n) => n,
+                None => return Err(rpc_error(-40001, "block not found")),
+            };
+            let receipts = chain.block_receipts(n);
+            let from: usize = params[2].as_str().unwrap_or("0").parse().unwrap_or(0);
+            let count: i64 = params[3].as_str().unwrap_or("-1").parse().unwrap_or(-1);
+            let selected: Vec<JsonValue> = receipts
+                .iter()
+                .skip(from)
+                .take(if count < 0 {
+                    usize::MAX
+                } else {
+                    count as usize
+                })
+                .cloned()
+                .collect();
+            Ok(json!({
+                "blockInfo": {
+                    "blockHash": block_hash(n),
+                    "blockNumber": to_hex_number(n),
+                    "receiptRoot": format!("0x{:064x}", 0),
+                    "receiptsCount": to_hex_number(receipts.len() as u64),
+                },
+                "transactionReceipts": selected,
+            }))
+        });
+        self.set_method("call", |chain, params| {
+            let to = normalize_address(params[1]["to"].as_str().unwrap_or(""));
+            let output = chain
+                .call_outputs
+                .get(&to)
+                .cloned()
+                .unwrap_or_else(|| "0x".to_string());
+            Ok(json!({
+                "currentBlockNumber": to_hex_number(chain.blocknumber),
+                "output": output,
+                "status": "0x0",
+            }))
+        });
+    }
+
+    ///按json rpc请求调用对应的方法，返回完整的response
+    pub fn handle_request(&mut self, request: &JsonValue) -> JsonValue {
+        let id = request["id"].clone();
+        let method = request["method"].as_str().unwrap_or("").to_string();
+        let injected = self
+            .injected_errors
+            .get_mut(&method)
+            .and_then(|q| q.pop_front());
+        let result = match (injected, self.methods.get(&method).cloned()) {
+            (Some(e), _) => Err(e),
+            (None, Some(f)) => f(self, &request["params"]),
+            (None, None) => Err(rpc_error(-32601, "Method not found")),
+        };
+        match result {
+            Ok(v) => json!({"jsonrpc": "2.0", "id": id, "result": v}),
+            Err(e) => json!({"jsonrpc": "2.0", "id": id, "error": e}),
+        }
+    }
+
+    ///请求可以是单个对象，也可以是批量的数组
+    pub fn handle_body(&mut self, body: &JsonValue) -> JsonValue {
+        match body {
+            JsonValue::Array(requests) => {
+                JsonValue::Array(requests.iter().map(|r| self.handle_request(r)).collect())
+            }
+            _ => self.handle_request(body),
+        }
+    }
+
+    pub fn hashtype(&self) -> HashType {
+        CommonHash::crypto_to_hashtype(&self.crypto)
+    }
+
+    ///解码签名交易，出一个新块，返回回执
+    pub fn apply_raw_transaction(&mut self, rawhex: &JsonValue) -> Result<JsonValue, JsonValue> {
+        let rawhex = rawhex.as_str().unwrap_or("");
+        let raw = match hex::decode(rawhex.trim_start_matches("0x")) {
+            Ok(r) => r,
+            Err(e) => return Err(rpc_error(-32602, &format!("bad raw tx hex {:?}", e))),
+        };
+        let tx = match BcosTransactionWithSig::decode_bytes(&raw) {
+            Ok(t) => t,
+            Err(e) => return Err(rpc_error(-32602, &format!("decode raw tx error {:?}", e))),
+        };
+        let hashtype = self.hashtype();
+        let txhash = format!("0x{}", hex::encode(CommonHash::hash(&raw, &hashtype)));
+        self.blocknumber += 1;
+        let blocknumber = to_hex_number(self.blocknumber);
+        let to = if tx.transaction.to_address.is_empty() {
+            "0x0000000000000000000000000000000000000000".to_string()
+        } else {
+            format!("0x{}", hex::encode(&tx.transaction.to_address))
+        };
+        //部署交易用交易hash算一个合约地址
+        let contract_address = if tx.transaction.to_address.is_empty() {
+            let h = CommonHash::hash(&txhash.as_bytes().to_vec(), &hashtype);
+            format!("0x{}", hex::encode(&h[12..]))
+        } else {
+            "0x0000000000000000000000000000000000000000".to_string()
+        };
+        let input = format!("0x{}", hex::encode(&tx.transaction.data));
+        let logs: Vec<JsonValue> = self.next_logs.drain(..).collect();
+        let receipt = json!({
+            "blockHash": block_hash(self.blocknumber),
+            "blockNumber": blocknumber,
+            "contractAddress": contract_address,
+            "from": "0x0000000000000000000000000000000000000000",
+            "gasUsed": "0x5208",
+            "input": input,
+            "logs": logs,
+            "output": "0x",
+            "status": "0x0",
+            "to": to,
+            "transactionHash": txhash,
+            "transactionIndex": "0x0",
+        });
+        let transaction = json!({
+            "blockHash": receipt["blockHash"],
+            "blockNumber": blocknumber,
+            "gas": format!("0x{:x}", tx.transaction.gas_limit),
+            "gasPrice": format!("0x{:x}", tx.transaction.gas_price),
+            "hash": txhash,
+            "input": input,
+            "to": to,
+            "transactionIndex": "0x0",
+            "value": format!("0x{:x}", tx.transaction.value),
+        });
+        self.rawtxs.push(raw);
+        self.blocks.push(txhash.clone());
+        self.transactions.insert(txhash.clone(), transaction);
+        self.receipts.insert(txhash, receipt.clone());
+        Ok(receipt)
+    }
+
+    ///解码全部上链的交易，按上链顺序
+    pub fn decoded_transactions(&self) -> Vec<BcosTransactionWithSig> {
+        self.rawtxs
+            .iter()
+            .filter_map(|raw| BcosTransactionWithSig::decode_bytes(raw).ok())
+            .collect()
+    }
+
+    ///"0x1"或者"1"形式的块高，超出已有块高时返回None。块0是创世块，没有交易
+    fn block_number_param(&self, param: &JsonValue) -> Option<u64> {
+        let text = param.as_str()?;
+        let n = match text.strip_prefix("0x") {
+            Some(h) => u64::from_str_radix(h, 16).ok()?,
+            None => text.parse().ok()?,
+        };
+        if n > self.blocknumber {
+            return None;
+        }
+        Some(n)
+    }
+
+    fn block_receipts(&self, n: u64) -> Vec<JsonValue> {
+        if n == 0 {
+            return vec![];
+        }
+        self.blocks
+            .get(n as usize - 1)
+            .and_then(|h| self.receipts.get(h))
+            .cloned()
+            .into_iter()
+            .collect()
+    }
+
+    fn block_json(&self, n: u64, include_tx: bool) -> JsonValue {
+        let txhashes: Vec<String> = if n == 0 {
+            vec![]
+        } else {
+            self.blocks
+                .get(n as usize - 1)
+                .cloned()
+                .into_iter()
+                .collect()
+        };
+        let transactions: Vec<JsonValue> = txhashes
+            .iter()
+            .map(|h| {
+                if include_tx {
+                    self.transactions.get(h).cloned().unwrap_or(JsonValue::Null)
+                } else {
+                    json!(h)
+                }
+            })
+            .collect();
+        json!({
+            "extraData": [],
+            "gasLimit": "0x0",
+            "gasUsed": "0x0",
+            "hash": block_hash(n),
+            "number": to_hex_number(n),
+            "parentHash": block_hash(n.saturating_sub(1)),
+            "sealer": "0x0",
+            "sealerList": [],
+            "timestamp": "0x0",
+            "transactions": transactions,
+        })
+    }
+
+    ///解码最近一笔上链的交易
+    pub fn last_transaction(&self) -> Option<BcosTransactionWithSig> {
+        self.rawtxs
+            .last()
+            .and_then(|raw| BcosTransactionWithSig::decode_bytes(raw).ok())
+    }
+}
+
+///mock链上的块hash直接用块高填充
+fn block_hash(n: u64) -> String {
+    format!("0x{:064x}", n)
+}
+
+fn normalize_address(address: &str) -> String {
+    address.trim_start_matches("0x").to_lowercase()
+}
@@ -0,0 +1,922 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 进程内的FISCO BCOS 2.x channel节点，用于离线测试。
+//! 启动时生成自签名的ECDSA证书，监听127.0.0.1的随机端口，按ChannelPack格式收发：
+//! 握手、心跳、rpc（由MockChain应答）、topic上报、事件注册/注销，
+//! 交易上链后用请求的seq推送TX_COMMITTED，并给订阅了块高的连接推送TX_BLOCKNUM。
+//! 测试也可以随时主动推送EVENT_LOG_PUSH/TX_COMMITTED等包。
+//! AMOP_REQ转给另一个订阅了topic的连接，订阅方的AMOP_RESP按seq转回发送方；TOPIC_MULTICAST转给所有订阅方。
+//! 私有topic：订阅方上报VerifyChannel时给发布方发REQUEST_TOPICCERT，发布方的随机数转给这个订阅方，
+//! 发布方回UPDATE_TOPIC_STATUS后，验证通过的订阅方才能收到私有topic上的消息。
+//! 用start_gm启动时走国密tls(TLCP)，证书见mock_tlcp
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use ethereum_types::H256;
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{SslAcceptor, SslMethod, SslStream, SslVerifyMode, SslVersion};
+use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+use openssl::x509::{X509Name, X509NameBuilder, X509};
+use serde_json::{json, Value as JsonValue};
+
+use crate::bcos2sdk::bcos_blocknumber_notify::block_notify_topic;
+use crate::bcos2sdk::bcos_channel_amop::AMOP_RESULT_NODE_UNREACHABLE;
+use crate::bcos2sdk::bcos_channel_amop_private::{
+    RequestTopicCert, UpdateTopicStatus, PUSH_CHANNEL_PREFIX, TOPIC_CHECK_SUCCESS,
+    TOPIC_NEED_VERIFY_PREFIX, VERIFY_CHANNEL_PREFIX,
+};
+use crate::bcos2sdk::bcos_channel_client::BcosChannelClient;
+use crate::bcos2sdk::bcos_event_manager::{RegisterEventRequest, EVENT_LOG_SUCCESS};
+use crate::bcos2sdk::channelpack::{
+    make_channel_pack, make_channel_pack_by_rawdata, pack_amop, unpack_amop, ChannelPack,
+    CHANNEL_PACK_TYPE, CHANNEL_PROTOCOL_MAX_SUPPORT,
+};
+use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ChannelConfig, ClientConfig};
+use crate::bcossdkutil::bufferqueue::BufferQueue;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+use crate::testsupport::mock_chain::{MockChain, MOCK_NODE_VERSION};
+use crate::testsupport::mock_tlcp::{
+    make_gm_certs, TlcpHandshakeInfo, TlcpServerKeys, TlcpServerStream,
+};
+use crate::testsupport::write_mock_client_config;
+
+///连接线程读socket的超时，也是检查推送队列和退出标记的间隔
+const SESSION_POLL_MS: u64 = 20;
+
+///各连接共享的状态，测试可以读出来做断言
+#[derive(Default)]
+pub struct MockChannelState {
+    pub topics: Vec<String>,
+    pub filters: Vec<RegisterEventRequest>,
+    ///收到的全部包，按收到的顺序
+    pub received: Vec<ChannelPack>,
+    ///国密节点上每次握手的结果
+    pub tlcp_handshakes: Vec<TlcpHandshakeInfo>,
+    ///为true时新连接一接上就关掉，用来模拟节点不可用
+    pub refuse_connections: bool,
+    ///握手回包里的协议版本，None时回本sdk支持的最高版本
+    pub handshake_protocol: Option<u32>,
+    ///为true时交易上链后不自动推送TX_COMMITTED，由测试用push_tx_committed推送
+    pub hold_tx_committed: bool,
+    ///注册事件过滤条件的回包result，不是EVENT_LOG_SUCCESS时节点拒绝注册
+    pub event_register_result: i32,
+    ///发布方回的私有topic验证结果，按收到的顺序
+    pub topic_status: Vec<UpdateTopicStatus>,
+    sessions: Vec<MockSessionEntry>,
+    next_session: u64,
+    ///转发出去还没回包的AMOP_REQ，seq -> 发送方连接
+    amop_pending: HashMap<H256, u64>,
+    ///发给发布方还没回UPDATE_TOPIC_STATUS的REQUEST_TOPICCERT，seq -> (订阅方连接, VerifyChannel topic)
+    topiccert_pending: HashMap<H256, (u64, String)>,
+}
+
+///连接在共享状态里的登记，推送和AMOP转发都通过它
+struct MockSessionEntry {
+    id: u64,
+    push: Sender<ChannelPack>,
+    topics: Vec<String>,
+    ///验证通过的私有topic
+    verified: Vec<String>,
+}
+
+impl MockChannelState {
+    ///发给某个连接，连接已经结束时返回false
+    fn send_to(&self, id: u64, pack: &ChannelPack) -> bool {
+        self.sessions
+            .iter()
+            .any(|s| s.id == id && s.push.send(pack.clone()).is_ok())
+    }
+
+    ///给私有topic的发布方发REQUEST_TOPICCERT，验证订阅方subscriber
+    fn request_topic_cert(&mut self, subscriber: u64, verifytopic: &str) {
+        let topic = verifytopic.trim_start_matches(VERIFY_CHANNEL_PREFIX);
+        let pushtopic = format!("{}{}", PUSH_CHANNEL_PREFIX, topic);
+        let publisher = match self
+            .sessions
+            .iter()
+            .find(|s| s.id != subscriber && s.topics.contains(&pushtopic))
+        {
+            Some(s) => s.id,
+            None => return,
+        };
+        let request = RequestTopicCert {
+            topic: topic.to_string(),
+            topicForCert: verifytopic.to_string(),
+            nodeId: format!("mock-session-{}", subscriber),
+        };
+        let pack = make_channel_pack(
+            CHANNEL_PACK_TYPE::REQUEST_TOPICCERT,
+            serde_json::to_string(&request).unwrap().as_str(),
+        )
+        .unwrap();
+        self.topiccert_pending
+            .insert(pack.seq, (subscriber, verifytopic.to_string()));
+        self.send_to(publisher, &pack);
+    }
+}
+
+pub struct MockChannelNode {
+    pub addr: SocketAddr,
+    pub certdir: PathBuf,
+    pub chain: Arc<Mutex<MockChain>>,
+    pub state: Arc<Mutex<MockChannelState>>,
+    pub tlskind: BcosCryptoKind,
+    acceptor: Arc<MockAcceptor>,
+    stop: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl MockChannelNode {
+    ///用群组1启动一个节点
+    pub fn start() -> Result<MockChannelNode, KissError> {
+        MockChannelNode::start_with_group(1)
+    }
+
+    pub fn start_with_group(groupid: u32) -> Result<MockChannelNode, KissError> {
+        MockChannelNode::start_inner(groupid, BcosCryptoKind::ECDSA, false)
+    }
+
+    ///用群组1启动一个国密tls的节点
+    pub fn start_gm() -> Result<MockChannelNode, KissError> {
+        MockChannelNode::start_inner(1, BcosCryptoKind::GM, false)
+    }
+
+    ///国密节点，但节点证书是由sdk证书(不是ca)签发的伪造证书，客户端握手时应当拒绝
+    pub fn start_gm_forged() -> Result<MockChannelNode, KissError> {
+        MockChannelNode::start_inner(1, BcosCryptoKind::GM, true)
+    }
+
+    fn start_inner(
+        groupid: u32,
+        tlskind: BcosCryptoKind,
+        forged: bool,
+    ) -> Result<MockChannelNode, KissError> {
+        let certdir =
+            std::env::temp_dir().join(format!("bcos_mock_node_{:016x}", rand::random::<u64>()));
+        if let Err(e) = std::fs::create_dir_all(&certdir) {
+            return kisserr!(KissErrKind::Error, "create dir {:?} error {:?}", certdir, e);
+        }
+        let acceptor = match tlskind {
+            BcosCryptoKind::GM => MockAcceptor::Tlcp(Box::new(make_gm_certs(&certdir, forged)?)),
+            BcosCryptoKind::ECDSA => match make_certs_and_acceptor(&certdir) {
+                Ok(a) => MockAcceptor::Ssl(a),
+                Err(e) => {
+                    return kisserr!(KissErrKind::Error, "make mock node certs error {:?}", e)
+                }
+            },
+        };
+        let chain = Arc::new(Mutex::new(MockChain::new(groupid, &BcosCryptoKind::ECDSA)));
+        MockChannelNode::listen(certdir, Arc::new(acceptor), chain, tlskind)
+    }
+
+    ///同一条链上的另一个节点：监听新的端口，证书和链与本节点相同，用来测试多节点切换
+    pub fn start_peer(&self) -> Result<MockChannelNode, KissError> {
+        let certdir =
+            std::env::temp_dir().join(format!("bcos_mock_node_{:016x}", rand::random::<u64>()));
+        let copied = std::fs::create_dir_all(&certdir).and_then(|_| {
+            for entry in std::fs::read_dir(&self.certdir)? {
+                let path = entry?.path();
+                if let Some(name) = path.file_name() {
+                    std::fs::copy(&path, certdir.join(name))?;
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = copied {
+            return kisserr!(
+                KissErrKind::Error,
+                "copy certs to {:?} error {:?}",
+                certdir,
+                e
+            );
+        }
+        MockChannelNode::listen(
+            certdir,
+            self.acceptor.clone(),
+            self.chain.clone(),
+            self.tlskind.clone(),
+        )
+    }
+
+    fn listen(
+        certdir: PathBuf,
+        acceptor: Arc<MockAcceptor>,
+        chain: Arc<Mutex<MockChain>>,
+        tlskind: BcosCryptoKind,
+    ) -> Result<MockChannelNode, KissError> {
+        let listener = match TcpListener::bind("127.0.0.1:0") {
+            Ok(l) => l,
+            Err(e) => return kisserr!(KissErrKind::ENetwork, "bind mock node error {:?}", e),
+        };
+        let addr = listener.local_addr().unwrap();
+        let _ = listener.set_nonblocking(true);
+
+        let state: Arc<Mutex<MockChannelState>> = Default::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let accept_thread = {
+            let (acceptor, chain, state, stop) =
+                (acceptor.clone(), chain.clone(), state.clone(), stop.clone());
+            std::thread::spawn(move || accept_loop(listener, acceptor, chain, state, stop))
+        };
+        Ok(MockChannelNode {
+            addr,
+            certdir,
+            chain,
+            state,
+            tlskind,
+            acceptor,
+            stop,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn chain(&self) -> MutexGuard<'_, MockChain> {
+        self.chain.lock().unwrap()
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockChannelState> {
+        self.state.lock().unwrap()
+    }
+
+    fn certfile(&self, name: &str) -> String {
+        self.certdir.join(name).to_string_lossy().to_string()
+    }
+
+    ///连接本节点的channel配置，证书用启动时生成的
+    pub fn channel_config(&self) -> ChannelConfig {
+        let mut config = ChannelConfig::default();
+        config.ip = self.addr.ip().to_string();
+        config.port = self.addr.port() as u32;
+        config.timeout = 5;
+        config.cacert = self.certfile("ca.crt");
+        config.sdkcert = self.certfile("sdk.crt");
+        config.sdkkey = self.certfile("sdk.key");
+        if self.tlskind == BcosCryptoKind::GM {
+            config.tlskind = BcosCryptoKind::GM;
+            config.gmcacert = self.certfile("gmca.crt");
+            config.gmsdkcert = self.certfile("gmsdk.crt");
+            config.gmsdkkey = self.certfile("gmsdk.key");
+            config.gmensdkcert = self.certfile("gmensdk.crt");
+            config.gmensdkkey = self.certfile("gmensdk.key");
+        }
+        config
+    }
+
+    ///在证书目录里生成账户和channel方式的配置文件，返回配置文件路径，可以直接给Bcos2Client::new_from_config
+    pub fn write_client_config(&self) -> Result<String, KissError> {
+        let groupid = self.chain().groupid;
+        write_mock_client_config(
+            &self.certdir,
+            groupid,
+            "CHANNEL",
+            "http://127.0.0.1:8545",
+            &self.channel_config(),
+        )
+    }
+
+    pub fn client_config(&self) -> Result<ClientConfig, KissError> {
+        ClientConfig::load(&self.write_client_config()?)
+    }
+
+    ///推送给所有连接，返回推送到的连接数
+    pub fn push(&self, pack: &ChannelPack) -> usize {
+        let mut state = self.state();
+        state.sessions.retain(|s| s.push.send(pack.clone()).is_ok());
+        state.sessions.len()
+    }
+
+    ///按filterID推送事件日志，包体和节点一样是topic为空的amop格式
+    pub fn push_event_log(&self, filterid: &str, result: i32, logs: &JsonValue) -> usize {
+        let body = json!({"filterID": filterid, "result": result, "logs": logs});
+        let data = pack_amop(&vec![], &body.to_string().into_bytes());
+        let pack = make_channel_pack_by_rawdata(CHANNEL_PACK_TYPE::EVENT_LOG_PUSH, &data).unwrap();
+        self.push(&pack)
+    }
+
+    ///用发交易请求的seq推送上链通知
+    pub fn push_tx_committed(&self, seq: &H256, receipt: &JsonValue) -> usize {
+        self.push(&make_push_pack(
+            CHANNEL_PACK_TYPE::TX_COMMITTED,
+            seq,
+            receipt.to_string().into_bytes(),
+        ))
+    }
+
+    ///把链上当前块高推送给所有连接
+    pub fn push_block_number(&self) -> usize {
+        let pack = {
+            let chain = self.chain();
+            make_block_notify_pack(chain.groupid, chain.blocknumber)
+        };
+        self.push(&pack)
+    }
+
+    ///断开当前所有的连接，之后的新连接不受影响
+    pub fn close_sessions(&self) {
+        self.state().sessions.clear();
+    }
+
+    pub fn set_refuse_connections(&self, refuse: bool) {
+        self.state().refuse_connections = refuse;
+    }
+
+    pub fn topics(&self) -> Vec<String> {
+        self.state().topics.clone()
+    }
+
+    pub fn event_filters(&self) -> Vec<RegisterEventRequest> {
+        self.state().filters.clone()
+    }
+
+    pub fn received_packs(&self) -> Vec<ChannelPack> {
+        self.state().received.clone()
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(t) = self.accept_thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for MockChannelNode {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = std::fs::remove_dir_all(&self.certdir);
+    }
+}
+
+fn make_push_pack(packtype: CHANNEL_PACK_TYPE, seq: &H256, data: Vec<u8>) -> ChannelPack {
+    ChannelPack {
+        length: 42 + data.len(),
+        packtype: packtype as u16,
+        seq: *seq,
+        result: 0,
+        data: data.into(),
+    }
+}
+
+///回包和请求同type同seq
+fn make_reply(pack: &ChannelPack, data: Vec<u8>) -> ChannelPack {
+    ChannelPack {
+        length: 42 + data.len(),
+        packtype: pack.packtype,
+        seq: pack.seq,
+        result: 0,
+        data: data.into(),
+    }
+}
+
+fn make_block_notify_pack(groupid: u32, blocknumber: u64) -> ChannelPack {
+    let body = json!({"groupID": groupid.to_string(), "blockNumber": blocknumber.to_string()});
+    make_channel_pack_by_rawdata(
+        CHANNEL_PACK_TYPE::TX_BLOCKNUM,
+        &body.to_string().into_bytes(),
+    )
+    .unwrap()
+}
+
+//--------------------证书------------------------------------------
+
+fn make_name(cn: &str) -> Result<X509Name, ErrorStack> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "fisco-bcos-mock")?;
+    name.append_entry_by_nid(Nid::COMMONNAME, cn)?;
+    Ok(name.build())
+}
+
+fn make_key() -> Result<PKey<Private>, ErrorStack> {
+    make_key_on(Nid::SECP256K1)
+}
+
+fn make_key_on(curve: Nid) -> Result<PKey<Private>, ErrorStack> {
+    let group = EcGroup::from_curve_name(curve)?;
+    PKey::from_ec_key(EcKey::generate(&group)?)
+}
+
+///issuer为None时生成自签名的ca证书
+fn make_cert(
+    cn: &str,
+    serial: u32,
+    key: &PKey<Private>,
+    issuer: Option<(&X509, &PKey<Private>)>,
+) -> Result<X509, ErrorStack> {
+    let name = make_name(cn)?;
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    let serial = BigNum::from_u32(serial)?.to_asn1_integer()?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_pubkey(key)?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(3650)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    match issuer {
+        Some((cacert, cakey)) => {
+            //https客户端按地址校验服务端证书，节点证书都带上本机地址
+            let san = SubjectAlternativeName::new()
+                .ip("127.0.0.1")
+                .dns("localhost")
+                .build(&builder.x509v3_context(Some(cacert), None))?;
+            builder.append_extension(san)?;
+            builder.set_issuer_name(cacert.subject_name())?;
+            builder.sign(cakey, MessageDigest::sha256())?;
+        }
+        None => {
+            builder.set_issuer_name(&name)?;
+            builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+            builder.sign(key, MessageDigest::sha256())?;
+        }
+    }
+    Ok(builder.build())
+}
+
+///生成ca、节点和sdk证书，sdk的证书私钥写到certdir，返回要求客户端证书的tls acceptor
+pub(crate) fn make_certs_and_acceptor(certdir: &Path) -> anyhow::Result<SslAcceptor> {
+    let cakey = make_key()?;
+    let cacert = make_cert("ca", 1, &cakey, None)?;
+    let nodekey = make_key()?;
+    let nodecert = make_cert("node", 2, &nodekey, Some((&cacert, &cakey)))?;
+    let sdkkey = make_key()?;
+    let sdkcert = make_cert("sdk", 3, &sdkkey, Some((&cacert, &cakey)))?;
+    std::fs::write(certdir.join("ca.crt"), cacert.to_pem()?)?;
+    std::fs::write(certdir.join("sdk.crt"), sdkcert.to_pem()?)?;
+    std::fs::write(certdir.join("sdk.key"), sdkkey.private_key_to_pem_pkcs8()?)?;
+
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    builder.set_private_key(&nodekey)?;
+    builder.set_certificate(&nodecert)?;
+    builder.check_private_key()?;
+    builder.cert_store_mut().add_cert(cacert)?;
+    builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    //和真实节点一样只说tls1.2，ecdh用secp256k1
+    builder.set_max_proto_version(Some(SslVersion::TLS1_2))?;
+    builder.set_groups_list("secp256k1")?;
+    Ok(builder.build())
+}
+
+///https用的证书和acceptor：通用的P-256曲线，ca、节点和sdk证书写到certdir，
+/// 客户端证书可选，带了就校验
+pub(crate) fn make_https_certs_and_acceptor(certdir: &Path) -> anyhow::Result<SslAcceptor> {
+    //ca名字各不相同，几个ca证书放在一个文件里时才能按名字找到签发者
+    let cakey = make_key_on(Nid::X9_62_PRIME256V1)?;
+    let caname = format!("ca-{:08x}", rand::random::<u32>());
+    let cacert = make_cert(&caname, 1, &cakey, None)?;
+    let nodekey = make_key_on(Nid::X9_62_PRIME256V1)?;
+    let nodecert = make_cert("node", 2, &nodekey, Some((&cacert, &cakey)))?;
+    let sdkkey = make_key_on(Nid::X9_62_PRIME256V1)?;
+    let sdkcert = make_cert("sdk", 3, &sdkkey, Some((&cacert, &cakey)))?;
+    std::fs::write(certdir.join("ca.crt"), cacert.to_pem()?)?;
+    std::fs::write(certdir.join("sdk.crt"), sdkcert.to_pem()?)?;
+    std::fs::write(certdir.join("sdk.key"), sdkkey.private_key_to_pem_pkcs8()?)?;
+
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    builder.set_private_key(&nodekey)?;
+    builder.set_certificate(&nodecert)?;
+    builder.check_private_key()?;
+    builder.cert_store_mut().add_cert(cacert)?;
+    builder.set_verify(SslVerifyMode::PEER);
+    Ok(builder.build())
+}
+
+//--------------------连接处理------------------------------------------
+
+///tls握手后的连接，close时发送关闭通知
+trait MockChannelStream: Read + Write + Send {
+    fn close(&mut self);
+}
+
+impl MockChannelStream for SslStream<TcpStream> {
+    fn close(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+impl MockChannelStream for TlcpServerStream {
+    fn close(&mut self) {
+        TlcpServerStream::close(self)
+    }
+}
+
+enum MockAcceptor {
+    Ssl(SslAcceptor),
+    Tlcp(Box<TlcpServerKeys>),
+}
+
+impl MockAcceptor {
+    fn accept(
+        &self,
+        tcp: TcpStream,
+        state: &Mutex<MockChannelState>,
+    ) -> Result<Box<dyn MockChannelStream>, String> {
+        match self {
+            MockAcceptor::Ssl(acceptor) => match acceptor.accept(tcp) {
+                Ok(s) => Ok(Box::new(s)),
+                Err(e) => Err(format!("{:?}", e)),
+            },
+            MockAcceptor::Tlcp(keys) => match TlcpServerStream::accept(tcp, keys) {
+                Ok((s, info)) => {
+                    state.lock().unwrap().tlcp_handshakes.push(info);
+                    Ok(Box::new(s))
+                }
+                Err(e) => Err(e.msg),
+            },
+        }
+    }
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    acceptor: Arc<MockAcceptor>,
+    chain: Arc<Mutex<MockChain>>,
+    state: Arc<Mutex<MockChannelState>>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut sessions: Vec<JoinHandle<()>> = vec![];
+    while !stop.load(Ordering::SeqCst) {
+        let tcp = match listener.accept() {
+            Ok((tcp, _)) => tcp,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(SESSION_POLL_MS));
+                continue;
+            }
+            Err(e) => {
+                log::warn!("mock node accept error {:?}", e);
+                continue;
+            }
+        };
+        if state.lock().unwrap().refuse_connections {
+            continue;
+        }
+        let _ = tcp.set_nonblocking(false);
+        let _ = tcp.set_read_timeout(Some(Duration::from_secs(5)));
+        //握手用较长的超时，握手后改成轮询间隔，socket选项在clone之间共享
+        let rawtcp = tcp.try_clone();
+        let stream = match acceptor.accept(tcp, &state) {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!("mock node tls accept error {}", e);
+                continue;
+            }
+        };
+        if let Ok(rawtcp) = rawtcp {
+            let _ = rawtcp.set_read_timeout(Some(Duration::from_millis(SESSION_POLL_MS)));
+        }
+        let (pushtx, pushrx) = channel();
+        let id = {
+            let mut state = state.lock().unwrap();
+            state.next_session += 1;
+            let id = state.next_session;
+            state.sessions.push(MockSessionEntry {
+                id,
+                push: pushtx,
+                topics: vec![],
+                verified: vec![],
+            });
+            id
+        };
+        let session = MockChannelSession {
+            id,
+            stream,
+            chain: chain.clone(),
+            state: state.clone(),
+            topics: vec![],
+        };
+        let stop = stop.clone();
+        sessions.push(std::thread::spawn(move || session.run(pushrx, stop)));
+    }
+    for s in sessions {
+        let _ = s.join();
+    }
+}
+
+struct MockChannelSession {
+    id: u64,
+    stream: Box<dyn MockChannelStream>,
+    chain: Arc<Mutex<MockChain>>,
+    state: Arc<Mutex<MockChannelState>>,
+    topics: Vec<String>,
+}
+
+impl MockChannelSession {
+    fn run(mut self, pushrx: Receiver<ChannelPack>, stop: Arc<AtomicBool>) {
+        self.serve(&pushrx, &stop);
+        self.stream.close();
+        let id = self.id;
+        self.state.lock().unwrap().sessions.retain(|s| s.id != id);
+    }
+
+    fn serve(&mut self, pushrx: &Receiver<ChannelPack>, stop: &AtomicBool) {
+        let mut queue = BufferQueue::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        while !stop.load(Ordering::SeqCst) {
+            //推送队列的发送端被close_sessions丢掉时断开连接
+            loop {
+                match pushrx.try_recv() {
+                    Ok(pack) => {
+                        if self.write(&pack).is_err() {
+                            return;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => return,
+                Ok(n) => queue.append(&mut buf[..n].to_vec()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    continue
+                }
+                Err(e) => return,
+            }
+            let packs = match BcosChannelClient::pop_queue_to_packet(&mut queue) {
+                Ok(packs) => packs,
+                Err(e) => return,
+            };
+            for pack in packs {
+                self.state.lock().unwrap().received.push(pack.clone());
+                for reply in self.handle(&pack) {
+                    if self.write(&reply).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, pack: &ChannelPack) -> std::io::Result<()> {
+        self.stream.write_all(&pack.pack())
+    }
+
+    fn handle(&mut self, pack: &ChannelPack) -> Vec<ChannelPack> {
+        let packtype = pack.packtype;
+        if packtype == CHANNEL_PACK_TYPE::HAND_SHAKE as u16 {
+            let protocol = self
+                .state
+                .lock()
+                .unwrap()
+                .handshake_protocol
+                .unwrap_or(CHANNEL_PROTOCOL_MAX_SUPPORT);
+            let body = json!({"protocol": protocol, "nodeVersion": MOCK_NODE_VERSION});
+            vec![make_reply(pack, body.to_string().into_bytes())]
+        } else if packtype == CHANNEL_PACK_TYPE::HEART_BEAT as u16 {
+            vec![make_reply(pack, br#"{"heartbeat":"1"}"#.to_vec())]
+        } else if packtype == CHANNEL_PACK_TYPE::RPC as u16 {
+            self.handle_rpc(pack)
+        } else if packtype == CHANNEL_PACK_TYPE::TOPIC_REPORT as u16 {
+            let topics: Vec<String> = serde_json::from_slice(&pack.data).unwrap_or_default();
+            let added: Vec<String> = topics
+                .iter()
+                .filter(|t| !self.topics.contains(t))
+                .cloned()
+                .collect();
+            self.topics = topics;
+            let mut state = self.state.lock().unwrap();
+            for topic in self.topics.iter() {
+                if !state.topics.contains(topic) {
+                    state.topics.push(topic.clone());
+                }
+            }
+            let id = self.id;
+            if let Some(entry) = state.sessions.iter_mut().find(|s| s.id == id) {
+                entry.topics = self.topics.clone();
+            }
+            //新的订阅方要验证身份；新的发布方要验证已经在等的订阅方
+            for topic in added {
+                if topic.starts_with(VERIFY_CHANNEL_PREFIX) {
+                    state.request_topic_cert(id, &topic);
+                } else if topic.starts_with(PUSH_CHANNEL_PREFIX) {
+                    let verifytopic = format!(
+                        "{}{}",
+                        VERIFY_CHANNEL_PREFIX,
+                        topic.trim_start_matches(PUSH_CHANNEL_PREFIX)
+                    );
+                    let waiting: Vec<u64> = state
+                        .sessions
+                        .iter()
+                        .filter(|s| s.id != id && s.topics.contains(&verifytopic))
+                        .map(|s| s.id)
+                        .collect();
+                    for subscriber in waiting {
+                        state.request_topic_cert(subscriber, &verifytopic);
+                    }
+                }
+            }
+            vec![]
+        } else if packtype == CHANNEL_PACK_TYPE::AMOP_REQ as u16
+            || packtype == CHANNEL_PACK_TYPE::AMOP_RESP as u16
+            || packtype == CHANNEL_PACK_TYPE::TOPIC_MULTICAST as u16
+        {
+            self.handle_amop(pack)
+        } else if packtype == CHANNEL_PACK_TYPE::CLIENT_REGISTER_EVENT_LOG as u16
+            || packtype == CHANNEL_PACK_TYPE::CLIENT_UNREGISTER_EVENT_LOG as u16
+        {
+            self.handle_event_filter(pack)
+        } else if packtype == CHANNEL_PACK_TYPE::UPDATE_TOPIC_STATUS as u16 {
+            self.handle_topic_status(pack)
+        } else {
+            vec![]
+        }
+    }
+
+    fn handle_rpc(&mut self, pack: &ChannelPack) -> Vec<ChannelPack> {
+        let request: JsonValue = match serde_json::from_slice(&pack.data) {
+            Ok(v) => v,
+            Err(e) => {
+                let body = json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32700, "message": "Parse error"}});
+                return vec![make_reply(pack, body.to_string().into_bytes())];
+            }
+        };
+        let (response, groupid, blocknumber) = {
+            let mut chain = self.chain.lock().unwrap();
+            let response = chain.handle_request(&request);
+            (response, chain.groupid, chain.blocknumber)
+        };
+        let mut replies = vec![make_reply(pack, response.to_string().into_bytes())];
+        if request["method"] == "sendRawTransaction" && response["result"].is_string() {
+            let txhash = response["result"].as_str().unwrap().to_string();
+            let hold = self.state.lock().unwrap().hold_tx_committed;
+            let receipt = self.chain.lock().unwrap().receipts.get(&txhash).cloned();
+            if let Some(receipt) = receipt.filter(|_| !hold) {
+                replies.push(make_push_pack(
+                    CHANNEL_PACK_TYPE::TX_COMMITTED,
+                    &pack.seq,
+                    receipt.to_string().into_bytes(),
+                ));
+            }
+            if self.topics.contains(&block_notify_topic(groupid)) {
+                replies.push(make_block_notify_pack(groupid, blocknumber));
+            }
+        }
+        replies
+    }
+
+    ///单播转给第一个订阅方，没有订阅方时回AMOP_RESULT_NODE_UNREACHABLE
+    fn handle_amop(&mut self, pack: &ChannelPack) -> Vec<ChannelPack> {
+        let mut state = self.state.lock().unwrap();
+        if pack.packtype == CHANNEL_PACK_TYPE::AMOP_RESP as u16 {
+            if let Some(sender) = state.amop_pending.remove(&pack.seq) {
+                state.send_to(sender, pack);
+            }
+            return vec![];
+        }
+        let (topic, _data) = unpack_amop(&pack.data);
+        let topic = String::from_utf8_lossy(&topic).to_string();
+        let id = self.id;
+        //VerifyChannel上的随机数只发给正在验证的订阅方，私有topic只发给验证通过的订阅方
+        let verifying = state
+            .topiccert_pending
+            .values()
+            .find(|(_, t)| *t == topic)
+            .map(|(s, _)| *s);
+        let needverify = topic.starts_with(TOPIC_NEED_VERIFY_PREFIX);
+        let subscribers: Vec<u64> = state
+            .sessions
+            .iter()
+            .filter(|s| s.id != id && s.topics.contains(&topic))
+            .filter(|s| !matches!(verifying, Some(v) if v != s.id))
+            .filter(|s| !needverify || s.verified.contains(&topic))
+            .map(|s| s.id)
+            .collect();
+        if pack.packtype == CHANNEL_PACK_TYPE::TOPIC_MULTICAST as u16 {
+            for s in subscribers {
+                state.send_to(s, pack);
+            }
+            return vec![];
+        }
+        if let Some(s) = subscribers.first() {
+            state.amop_pending.insert(pack.seq, id);
+            if state.send_to(*s, pack) {
+                return vec![];
+            }
+            state.amop_pending.remove(&pack.seq);
+        }
+        let mut reply = make_reply(pack, vec![]);
+        reply.packtype = CHANNEL_PACK_TYPE::AMOP_RESP as u16;
+        reply.result = AMOP_RESULT_NODE_UNREACHABLE;
+        vec![reply]
+    }
+
+    ///发布方回的验证结果，通过时记到订阅方连接上
+    fn handle_topic_status(&mut self, pack: &ChannelPack) -> Vec<ChannelPack> {
+        let status: UpdateTopicStatus = match serde_json::from_slice(&pack.data) {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!("mock node bad topic status {:?}", e);
+                return vec![];
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        if let Some((subscriber, _)) = state.topiccert_pending.remove(&pack.seq) {
+            if status.checkResult == TOPIC_CHECK_SUCCESS {
+                if let Some(entry) = state.sessions.iter_mut().find(|s| s.id == subscriber) {
+                    entry.verified.push(status.topic.clone());
+                }
+            }
+        }
+        state.topic_status.push(status);
+        vec![]
+    }
+
+    fn handle_event_filter(&mut self, pack: &ChannelPack) -> Vec<ChannelPack> {
+        let (_topic, data) = unpack_amop(&pack.data);
+        let request: RegisterEventRequest = match serde_json::from_slice(&data) {
+            Ok(r) => r,
+            Err(e) => {
+                log::warn!("mock node bad event filter {:?}", e);
+                return vec![];
+            }
+        };
+        let filterid = request.filterID.clone();
+        let result = {
+            let mut state = self.state.lock().unwrap();
+            state.filters.retain(|f| f.filterID != filterid);
+            if pack.packtype != CHANNEL_PACK_TYPE::CLIENT_REGISTER_EVENT_LOG as u16 {
+                EVENT_LOG_SUCCESS
+            } else if state.event_register_result != EVENT_LOG_SUCCESS {
+                state.event_register_result
+            } else {
+                state.filters.push(request);
+                EVENT_LOG_SUCCESS
+            }
+        };
+        let body = json!({"filterID": filterid, "result": result});
+        vec![make_reply(
+            pack,
+            pack_amop(&vec![], &body.to_string().into_bytes()),
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::bcos_channel_client::IBcosChannel;
+    use crate::bcos2sdk::bcos_event_manager::{make_register_event_pack, parse_event_log_response};
+    use std::time::Instant;
+
+    #[test]
+    fn test_mock_node_event_push() {
+        let node = MockChannelNode::start().unwrap();
+        let mut client = BcosChannelClient::new(&node.channel_config()).unwrap();
+        let request = RegisterEventRequest::new(1);
+        let reply = client
+            .request_channelpack_sync(&make_register_event_pack(&request))
+            .unwrap();
+        assert_eq!(
+            parse_event_log_response(&reply).unwrap().result,
+            EVENT_LOG_SUCCESS
+        );
+        assert_eq!(node.event_filters()[0].filterID, request.filterID);
+
+        assert_eq!(node.push_event_log(&request.filterID, 0, &json!([])), 1);
+        let start = Instant::now();
+        let mut pushed = None;
+        while pushed.is_none() && start.elapsed() < Duration::from_secs(5) {
+            pushed = client
+                .read_packets()
+                .unwrap()
+                .into_iter()
+                .find(|p| p.packtype == CHANNEL_PACK_TYPE::EVENT_LOG_PUSH as u16);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let resp = parse_event_log_response(&pushed.unwrap()).unwrap();
+        assert_eq!(resp.filterID, request.filterID);
+        client.finish();
+    }
+}
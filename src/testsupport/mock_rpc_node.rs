@@ -0,0 +1,485 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+//! 进程内的FISCO BCOS 2.x json rpc节点(http)，用于RPC方式的离线测试。
+//! 监听127.0.0.1的随机端口，请求交给MockChain应答，支持批量请求和keep-alive。
+//! 可以脚本化：固定返回值和错误用MockChain的set_result/fail_next，
+//! 延迟和http状态码在本节点上注入。
+//! 用start_https启动时走https，证书在workdir里，客户端证书可选
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use openssl::nid::Nid;
+use openssl::ssl::SslAcceptor;
+use serde_json::{json, Value as JsonValue};
+
+use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ChannelConfig, ClientConfig};
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+use crate::testsupport::mock_chain::MockChain;
+use crate::testsupport::mock_channel_node::make_https_certs_and_acceptor;
+use crate::testsupport::write_mock_client_config;
+
+///连接线程读socket的超时，也是检查退出标记的间隔
+const SESSION_POLL_MS: u64 = 20;
+///请求头最大长度，超过就断开连接
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+///注入的传输层行为和收到的请求，测试可以读出来做断言
+#[derive(Default)]
+pub struct MockRpcState {
+    ///每个请求回包前的延迟
+    pub latency: Duration,
+    ///按方法名的延迟，有设置时覆盖latency，批量请求取其中最大的
+    pub method_latency: HashMap<String, Duration>,
+    ///接下来的请求直接回这些http状态码，不交给链处理
+    pub http_statuses: VecDeque<u16>,
+    ///收到的请求body，按收到的顺序
+    pub received: Vec<JsonValue>,
+    ///收到的请求头，名字转成小写，和received一一对应
+    pub received_headers: Vec<HashMap<String, String>>,
+    ///https方式下每个连接的客户端证书CN，没有带客户端证书时为空串
+    pub client_cert_cns: Vec<String>,
+}
+
+impl MockRpcState {
+    fn latency_for(&self, body: &JsonValue) -> Duration {
+        let latency_of = |request: &JsonValue| {
+            request["method"]
+                .as_str()
+                .and_then(|m| self.method_latency.get(m))
+                .cloned()
+                .unwrap_or(self.latency)
+        };
+        match body {
+            JsonValue::Array(requests) => requests
+                .iter()
+                .map(latency_of)
+                .max()
+                .unwrap_or(self.latency),
+            _ => latency_of(body),
+        }
+    }
+}
+
+pub struct MockRpcNode {
+    pub addr: SocketAddr,
+    ///放账户和配置文件的临时目录
+    pub workdir: PathBuf,
+    pub chain: Arc<Mutex<MockChain>>,
+    pub state: Arc<Mutex<MockRpcState>>,
+    https: bool,
+    stop: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl MockRpcNode {
+    ///用群组1启动一个节点
+    pub fn start() -> Result<MockRpcNode, KissError> {
+        MockRpcNode::start_with_group(1)
+    }
+
+    pub fn start_with_group(groupid: u32) -> Result<MockRpcNode, KissError> {
+        let chain = Arc::new(Mutex::new(MockChain::new(groupid, &BcosCryptoKind::ECDSA)));
+        MockRpcNode::start_with_chain(chain)
+    }
+
+    ///和其他mock节点共用一条链，比如channel和rpc两种方式看到同样的数据
+    pub fn start_with_chain(chain: Arc<Mutex<MockChain>>) -> Result<MockRpcNode, KissError> {
+        MockRpcNode::start_inner(chain, false)
+    }
+
+    ///用群组1启动一个https节点，ca.crt、sdk.crt和sdk.key在workdir里
+    pub fn start_https() -> Result<MockRpcNode, KissError> {
+        let chain = Arc::new(Mutex::new(MockChain::new(1, &BcosCryptoKind::ECDSA)));
+        MockRpcNode::start_inner(chain, true)
+    }
+
+    fn start_inner(chain: Arc<Mutex<MockChain>>, https: bool) -> Result<MockRpcNode, KissError> {
+        let workdir =
+            std::env::temp_dir().join(format!("bcos_mock_rpc_{:016x}", rand::random::<u64>()));
+        if let Err(e) = std::fs::create_dir_all(&workdir) {
+            return kisserr!(KissErrKind::Error, "create dir {:?} error {:?}", workdir, e);
+        }
+        let acceptor = if https {
+            match make_https_certs_and_acceptor(&workdir) {
+                Ok(a) => Some(a),
+                Err(e) => return kisserr!(KissErrKind::Error, "make mock rpc certs error {:?}", e),
+            }
+        } else {
+            None
+        };
+        let listener = match TcpListener::bind("127.0.0.1:0") {
+            Ok(l) => l,
+            Err(e) => return kisserr!(KissErrKind::ENetwork, "bind mock rpc node error {:?}", e),
+        };
+        let addr = listener.local_addr().unwrap();
+        let _ = listener.set_nonblocking(true);
+
+        let state: Arc<Mutex<MockRpcState>> = Default::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let accept_thread = {
+            let (chain, state, stop) = (chain.clone(), state.clone(), stop.clone());
+            std::thread::spawn(move || accept_loop(listener, acceptor, chain, state, stop))
+        };
+        Ok(MockRpcNode {
+            addr,
+            workdir,
+            chain,
+            state,
+            https,
+            stop,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn chain(&self) -> MutexGuard<'_, MockChain> {
+        self.chain.lock().unwrap()
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockRpcState> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn url(&self) -> String {
+        let scheme = if self.https { "https" } else { "http" };
+        format!("{}://{}", scheme, self.addr)
+    }
+
+    ///https节点生成的证书和私钥文件，name为ca.crt、sdk.crt或sdk.key
+    pub fn cert_path(&self, name: &str) -> String {
+        self.workdir.join(name).to_string_lossy().to_string()
+    }
+
+    ///生成账户和RPC方式的配置文件，返回配置文件路径，可以直接给Bcos2Client::new_from_config
+    pub fn write_client_config(&self) -> Result<String, KissError> {
+        let groupid = self.chain().groupid;
+        write_mock_client_config(
+            &self.workdir,
+            groupid,
+            "RPC",
+            &self.url(),
+            &ChannelConfig::default(),
+        )
+    }
+
+    pub fn client_config(&self) -> Result<ClientConfig, KissError> {
+        ClientConfig::load(&self.write_client_config()?)
+    }
+
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    pub fn set_method_latency(&self, method: &str, latency: Duration) {
+        self.state()
+            .method_latency
+            .insert(method.to_string(), latency);
+    }
+
+    ///让接下来的一个请求回指定的http状态码，多次调用依次排队
+    pub fn fail_next_http(&self, status: u16) {
+        self.state().http_statuses.push_back(status);
+    }
+
+    pub fn received_requests(&self) -> Vec<JsonValue> {
+        self.state().received.clone()
+    }
+
+    ///收到的某个方法的请求次数，批量请求里的每一项都算
+    pub fn request_count(&self, method: &str) -> usize {
+        self.state()
+            .received
+            .iter()
+            .flat_map(|body| match body {
+                JsonValue::Array(requests) => requests.clone(),
+                _ => vec![body.clone()],
+            })
+            .filter(|r| r["method"] == method)
+            .count()
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(t) = self.accept_thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for MockRpcNode {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = std::fs::remove_dir_all(&self.workdir);
+    }
+}
+
+///http或https的连接
+trait MockRpcStream: Read + Write + Send {}
+impl<T: Read + Write + Send> MockRpcStream for T {}
+
+fn accept_loop(
+    listener: TcpListener,
+    acceptor: Option<SslAcceptor>,
+    chain: Arc<Mutex<MockChain>>,
+    state: Arc<Mutex<MockRpcState>>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut sessions: Vec<JoinHandle<()>> = vec![];
+    while !stop.load(Ordering::SeqCst) {
+        let tcp = match listener.accept() {
+            Ok((tcp, _)) => tcp,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(SESSION_POLL_MS));
+                continue;
+            }
+            Err(e) => {
+                log::warn!("mock rpc node accept error {:?}", e);
+                continue;
+            }
+        };
+        let _ = tcp.set_nonblocking(false);
+        let stream: Box<dyn MockRpcStream> = match &acceptor {
+            None => {
+                let _ = tcp.set_read_timeout(Some(Duration::from_millis(SESSION_POLL_MS)));
+                Box::new(tcp)
+            }
+            Some(acceptor) => {
+                let _ = tcp.set_read_timeout(Some(Duration::from_secs(5)));
+                let stream = match acceptor.accept(tcp) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        log::warn!("mock rpc node tls accept error {:?}", e);
+                        continue;
+                    }
+                };
+                let cn = stream
+                    .ssl()
+                    .peer_certificate()
+                    .and_then(|c| {
+                        let cn = c.subject_name().entries_by_nid(Nid::COMMONNAME).next()?;
+                        Some(String::from_utf8_lossy(cn.data().as_slice()).to_string())
+                    })
+                    .unwrap_or_default();
+                state.lock().unwrap().client_cert_cns.push(cn);
+                let _ = stream
+                    .get_ref()
+                    .set_read_timeout(Some(Duration::from_millis(SESSION_POLL_MS)));
+                Box::new(stream)
+            }
+        };
+        let session = MockRpcSession {
+            stream,
+            chain: chain.clone(),
+            state: state.clone(),
+        };
+        let stop = stop.clone();
+        sessions.push(std::thread::spawn(move || session.run(stop)));
+    }
+    for s in sessions {
+        let _ = s.join();
+    }
+}
+
+///一个http请求，只关心头、body和是否要求关闭连接
+struct HttpRequest {
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    close: bool,
+}
+
+///从缓冲区头部解析出一个完整的请求，数据不够时返回None
+fn pop_http_request(buf: &mut Vec<u8>) -> Option<HttpRequest> {
+    let header_end = buf.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let header = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut headers = HashMap::new();
+    for line in header.lines().skip(1) {
+        if let Some((n, v)) = line.split_once(':') {
+            headers.insert(n.trim().to_lowercase(), v.trim().to_string());
+        }
+    }
+    let content_length = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let close = match headers.get("connection") {
+        Some(v) => v.eq_ignore_ascii_case("close"),
+        None => false,
+    };
+    if buf.len() < header_end + content_length {
+        return None;
+    }
+    let body = buf[header_end..header_end + content_length].to_vec();
+    buf.drain(..header_end + content_length);
+    Some(HttpRequest {
+        headers,
+        body,
+        close,
+    })
+}
+
+fn http_response(status: u16, body: &str) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+struct MockRpcSession {
+    stream: Box<dyn MockRpcStream>,
+    chain: Arc<Mutex<MockChain>>,
+    state: Arc<Mutex<MockRpcState>>,
+}
+
+impl MockRpcSession {
+    fn run(mut self, stop: Arc<AtomicBool>) {
+        let mut inbuf: Vec<u8> = vec![];
+        let mut buf = vec![0u8; 64 * 1024];
+        while !stop.load(Ordering::SeqCst) {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return,
+                Ok(n) => inbuf.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    continue
+                }
+                Err(e) => return,
+            }
+            while let Some(request) = pop_http_request(&mut inbuf) {
+                let close = request.close;
+                let response = self.handle(request);
+                if self.stream.write_all(&response).is_err() || close {
+                    return;
+                }
+            }
+            if inbuf.len() > MAX_HEADER_LEN && !inbuf.windows(4).any(|w| w == b"\r\n\r\n") {
+                return;
+            }
+        }
+    }
+
+    fn handle(&mut self, http_request: HttpRequest) -> Vec<u8> {
+        let request: JsonValue = match serde_json::from_slice(&http_request.body) {
+            Ok(v) => v,
+            Err(e) => {
+                let response = json!({"jsonrpc": "2.0", "id": JsonValue::Null,
+                    "error": {"code": -32700, "message": "Parse error"}});
+                return http_response(200, &response.to_string());
+            }
+        };
+        let (latency, status) = {
+            let mut state = self.state.lock().unwrap();
+            state.received.push(request.clone());
+            state.received_headers.push(http_request.headers);
+            (state.latency_for(&request), state.http_statuses.pop_front())
+        };
+        if !latency.is_zero() {
+            std::thread::sleep(latency);
+        }
+        if let Some(status) = status {
+            return http_response(status, "");
+        }
+        let response = self.chain.lock().unwrap().handle_body(&request);
+        http_response(200, &response.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcos2sdk::bcos2client::Bcos2Client;
+    use crate::bcossdkutil::contractabi::ContractABI;
+    use crate::bcossdkutil::kisserror::KissErrKind;
+    use crate::testsupport::mock_chain::rpc_error;
+    use std::time::Instant;
+
+    #[test]
+    fn test_mock_rpc_node_scripted() {
+        let node = MockRpcNode::start().unwrap();
+        let mut client =
+            Bcos2Client::new_from_config(&node.write_client_config().unwrap()).unwrap();
+
+        let contract = ContractABI::new("contracts/HelloWorld.abi", &client.hashtype).unwrap();
+        let address = "0x882be29b2d5ac85d6c476fa3fd5f0cae4b4585cc";
+        let receipt = client
+            .sendRawTransactionGetReceipt(&contract, address, "set", &["hello".to_string()])
+            .unwrap();
+        assert_eq!(receipt["result"]["status"], "0x0");
+        let tx = &node.chain().decoded_transactions()[0];
+        assert_eq!(hex::encode(&tx.transaction.to_address), &address[2..]);
+        let batch = client
+            .getBatchReceiptsByBlockNumberAndRange(1, 0, -1, false)
+            .unwrap();
+        assert_eq!(
+            batch["result"]["transactionReceipts"][0]["transactionHash"],
+            receipt["result"]["transactionHash"]
+        );
+
+        let sent = node.request_count("getBlockNumber");
+        node.chain().set_result("getBlockNumber", json!("0x64"));
+        assert_eq!(client.getBlockNumber().unwrap(), 100);
+        node.chain()
+            .fail_next("getBlockNumber", rpc_error(-32000, "injected"));
+        assert!(client.getBlockNumber().is_err());
+        node.fail_next_http(500);
+        let noretry = client.policy().clone().with_retries(0);
+        assert_eq!(
+            client
+                .with_policy(noretry, |c| c.getBlockNumber())
+                .unwrap_err()
+                .kind,
+            KissErrKind::ENetwork
+        );
+        //调用里panic时也恢复原来的策略
+        let saved = client.policy().clone();
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            client.with_policy(saved.clone().with_retries(0), |_| panic!("in with_policy"))
+        }));
+        assert!(res.is_err());
+        assert_eq!(client.policy(), &saved);
+        //默认的请求策略重试网络错误
+        node.fail_next_http(500);
+        assert_eq!(client.getBlockNumber().unwrap(), 100);
+
+        node.set_method_latency("getBlockNumber", Duration::from_millis(200));
+        let start = Instant::now();
+        assert_eq!(client.getBlockNumber().unwrap(), 100);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(node.request_count("getBlockNumber"), sent + 6);
+    }
+}
@@ -0,0 +1,592 @@
+#![allow(
+    clippy::unreadable_literal,
+    clippy::upper_case_acronyms,
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    overflowing_literals,
+    unused_variables,
+    unused_assignments
+)]
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+//! mock节点用的国密tls(TLCP)服务端，和bcos_ssl_tlcp的客户端对接。
+//! 只支持ECC_SM4_CBC_SM3套件，总是要求客户端证书；
+//! 证书用SM2现场生成，写到证书目录里给客户端配置用
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::path::Path;
+
+use wedpr_l_libsm::sm2::ecc::EccCtx;
+use wedpr_l_libsm::sm2::signature::SigCtx;
+
+use crate::bcos2sdk::bcos_ssl_tlcp::{
+    get_u24, put_u24, HandshakeParser, TlcpCipherState, ALERT_CLOSE_NOTIFY, BLOCK_LEN,
+    ECC_SM4_CBC_SM3, HS_CERTIFICATE, HS_CERTIFICATE_REQUEST, HS_CERTIFICATE_VERIFY,
+    HS_CLIENT_HELLO, HS_CLIENT_KEY_EXCHANGE, HS_FINISHED, HS_SERVER_HELLO, HS_SERVER_HELLO_DONE,
+    HS_SERVER_KEY_EXCHANGE, MAC_LEN, MAX_FRAGMENT_LEN, RECORD_ALERT, RECORD_APPLICATION_DATA,
+    RECORD_CHANGE_CIPHER_SPEC, RECORD_HANDSHAKE, RECORD_HEADER_LEN, TLCP_VERSION, VERIFY_DATA_LEN,
+};
+use crate::bcos2sdk::bcos_tlcp_util::{
+    der_encode, der_encode_uint, kdf_sm3, prf_sm3, sm2_sign, sm2_verify, sm3, DerItem,
+    SM2Certificate, DER_TAG_BIT_STRING, DER_TAG_OCTET_STRING, DER_TAG_OID, DER_TAG_SEQUENCE,
+    DER_TAG_UTC_TIME,
+};
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::{KissErrKind, KissError};
+use crate::kisserr;
+
+/// 1.2.156.10197.1.501 SM2签名+SM3
+const OID_SM2_SM3: [u8; 8] = [0x2A, 0x81, 0x1C, 0xCF, 0x55, 0x01, 0x83, 0x75];
+/// 1.2.840.10045.2.1 ecPublicKey
+const OID_EC_PUBKEY: [u8; 7] = [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+/// 1.2.156.10197.1.301 SM2曲线
+const OID_SM2_CURVE: [u8; 8] = [0x2A, 0x81, 0x1C, 0xCF, 0x55, 0x01, 0x82, 0x2D];
+/// 2.5.4.3 commonName
+const OID_COMMON_NAME: [u8; 3] = [0x55, 0x04, 0x03];
+const OID_KEY_USAGE: [u8; 3] = [0x55, 0x1D, 0x0F];
+const OID_BASIC_CONSTRAINTS: [u8; 3] = [0x55, 0x1D, 0x13];
+const KEY_USAGE_DIGITAL_SIGNATURE: u8 = 0x80;
+const KEY_USAGE_KEY_ENCIPHERMENT: u8 = 0x20;
+const KEY_USAGE_KEY_CERT_SIGN: u8 = 0x04;
+const ALERT_HANDSHAKE_FAILURE: u8 = 40;
+
+///一次握手的结果，测试用来断言协商出的套件和客户端证书
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlcpHandshakeInfo {
+    pub suite: [u8; 2],
+    pub client_cn: String,
+}
+
+///服务端的签名证书、加密证书和私钥，以及验证客户端证书用的ca
+pub(crate) struct TlcpServerKeys {
+    signcert: Vec<u8>,
+    signkey: Vec<u8>,
+    encert: Vec<u8>,
+    enkey: Vec<u8>,
+    ///跟在签名/加密证书后面一起发给客户端的证书链
+    chain: Vec<Vec<u8>>,
+    cacert: SM2Certificate,
+}
+
+struct SM2KeyPair {
+    privkey: Vec<u8>,
+    pubkey: Vec<u8>,
+}
+
+fn make_sm2_key() -> SM2KeyPair {
+    let ctx = SigCtx::new();
+    let (pk, sk) = ctx.new_keypair();
+    SM2KeyPair {
+        privkey: ctx.serialize_seckey(&sk),
+        pubkey: ctx.serialize_pubkey(&pk, false),
+    }
+}
+
+fn make_sm2_name(cn: &str) -> Vec<u8> {
+    let mut atv = der_encode(DER_TAG_OID, &OID_COMMON_NAME);
+    atv.extend(der_encode(0x0C, cn.as_bytes())); //UTF8String
+    let rdn = der_encode(0x31, &der_encode(DER_TAG_SEQUENCE, &atv)); //SET
+    der_encode(DER_TAG_SEQUENCE, &rdn)
+}
+
+fn make_extension(oid: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut ext = der_encode(DER_TAG_OID, oid);
+    ext.extend_from_slice(&[0x01, 0x01, 0xFF]); //critical
+    ext.extend(der_encode(DER_TAG_OCTET_STRING, value));
+    der_encode(DER_TAG_SEQUENCE, &ext)
+}
+
+fn der_time(t: &chrono::NaiveDateTime) -> Vec<u8> {
+    der_encode(
+        DER_TAG_UTC_TIME,
+        t.format("%y%m%d%H%M%SZ").to_string().as_bytes(),
+    )
+}
+
+///生成SM2证书，issuer为None时自签名并作为ca证书
+fn make_sm2_cert(
+    cn: &str,
+    serial: u8,
+    key: &SM2KeyPair,
+    key_usage: u8,
+    issuer: Option<(&str, &SM2KeyPair)>,
+) -> Result<Vec<u8>, KissError> {
+    let (issuername, signkey) = issuer.unwrap_or((cn, key));
+    let sigalg = der_encode(DER_TAG_SEQUENCE, &der_encode(DER_TAG_OID, &OID_SM2_SM3));
+    let now = chrono::Utc::now().naive_utc();
+    let mut validity = der_time(&(now - chrono::Duration::days(1)));
+    validity.extend(der_time(&(now + chrono::Duration::days(3650))));
+
+    let mut algo = der_encode(DER_TAG_OID, &OID_EC_PUBKEY);
+    algo.extend(der_encode(DER_TAG_OID, &OID_SM2_CURVE));
+    let mut spki = der_encode(DER_TAG_SEQUENCE, &algo);
+    let mut bits = vec![0u8];
+    bits.extend_from_slice(&key.pubkey);
+    spki.extend(der_encode(DER_TAG_BIT_STRING, &bits));
+
+    let unused = key_usage.trailing_zeros() as u8;
+    let mut exts = make_extension(
+        &OID_KEY_USAGE,
+        &der_encode(DER_TAG_BIT_STRING, &[unused, key_usage]),
+    );
+    if issuer.is_none() {
+        let ca = der_encode(DER_TAG_SEQUENCE, &[0x01, 0x01, 0xFF]);
+        exts.extend(make_extension(&OID_BASIC_CONSTRAINTS, &ca));
+    }
+
+    let mut tbs = der_encode(0xA0, &der_encode_uint(&[2])); //v3
+    tbs.extend(der_encode_uint(&[serial]));
+    tbs.extend_from_slice(&sigalg);
+    tbs.extend(make_sm2_name(issuername));
+    tbs.extend(der_encode(DER_TAG_SEQUENCE, &validity));
+    tbs.extend(make_sm2_name(cn));
+    tbs.extend(der_encode(DER_TAG_SEQUENCE, &spki));
+    tbs.extend(der_encode(0xA3, &der_encode(DER_TAG_SEQUENCE, &exts)));
+    let tbs = der_encode(DER_TAG_SEQUENCE, &tbs);
+
+    let mut signature = vec![0u8];
+    signature.extend(sm2_sign(&signkey.privkey, &tbs)?);
+    let mut cert = tbs;
+    cert.extend(sigalg);
+    cert.extend(der_encode(DER_TAG_BIT_STRING, &signature));
+    Ok(der_encode(DER_TAG_SEQUENCE, &cert))
+}
+
+fn write_pem(path: &Path, tag: &str, der: Vec<u8>) -> Result<(), KissError> {
+    let text = pem::encode(&pem::Pem {
+        tag: tag.to_string(),
+        contents: der,
+    });
+    fileutils::write_all(&path.to_string_lossy(), text.into_bytes())
+}
+
+///生成国密ca、节点和sdk的签名/加密证书，sdk用的gmca.crt、gmsdk.crt、gmsdk.key、
+/// gmensdk.crt、gmensdk.key写到certdir。
+/// issued_by_sdk为true时节点证书改由sdk签名证书(不是ca)签发，并把sdk证书放进证书链，
+/// 模拟拿别的机构的sdk证书伪造节点证书
+pub(crate) fn make_gm_certs(
+    certdir: &Path,
+    issued_by_sdk: bool,
+) -> Result<TlcpServerKeys, KissError> {
+    let cakey = make_sm2_key();
+    let caname = format!("gmca-{:08x}", rand::random::<u32>());
+    let cacert = make_sm2_cert(&caname, 1, &cakey, KEY_USAGE_KEY_CERT_SIGN, None)?;
+    let issuer = Some((caname.as_str(), &cakey));
+    let sdksignkey = make_sm2_key();
+    let sdksigncert = make_sm2_cert("sdk", 4, &sdksignkey, KEY_USAGE_DIGITAL_SIGNATURE, issuer)?;
+    let sdkenkey = make_sm2_key();
+    let sdkencert = make_sm2_cert("sdk", 5, &sdkenkey, KEY_USAGE_KEY_ENCIPHERMENT, issuer)?;
+    let (nodeissuer, chain) = if issued_by_sdk {
+        (Some(("sdk", &sdksignkey)), vec![sdksigncert.clone()])
+    } else {
+        (issuer, vec![])
+    };
+    let nodesignkey = make_sm2_key();
+    let nodesigncert = make_sm2_cert(
+        "node",
+        2,
+        &nodesignkey,
+        KEY_USAGE_DIGITAL_SIGNATURE,
+        nodeissuer,
+    )?;
+    let nodeenkey = make_sm2_key();
+    let nodeencert = make_sm2_cert(
+        "node",
+        3,
+        &nodeenkey,
+        KEY_USAGE_KEY_ENCIPHERMENT,
+        nodeissuer,
+    )?;
+
+    //私钥按SEC1格式保存：SEQUENCE{version 1, privateKey}
+    let sec1 = |key: &SM2KeyPair| {
+        let mut body = der_encode_uint(&[1]);
+        body.extend(der_encode(DER_TAG_OCTET_STRING, &key.privkey));
+        der_encode(DER_TAG_SEQUENCE, &body)
+    };
+    write_pem(&certdir.join("gmca.crt"), "CERTIFICATE", cacert.clone())?;
+    write_pem(&certdir.join("gmsdk.crt"), "CERTIFICATE", sdksigncert)?;
+    write_pem(
+        &certdir.join("gmsdk.key"),
+        "EC PRIVATE KEY",
+        sec1(&sdksignkey),
+    )?;
+    write_pem(&certdir.join("gmensdk.crt"), "CERTIFICATE", sdkencert)?;
+    write_pem(
+        &certdir.join("gmensdk.key"),
+        "EC PRIVATE KEY",
+        sec1(&sdkenkey),
+    )?;
+    Ok(TlcpServerKeys {
+        signcert: nodesigncert,
+        signkey: nodesignkey.privkey,
+        encert: nodeencert,
+        enkey: nodeenkey.privkey,
+        chain,
+        cacert: SM2Certificate::from_der(&cacert)?,
+    })
+}
+
+///SM2解密，密文是sm2_encrypt输出的DER编码
+pub(crate) fn sm2_decrypt(privkey: &[u8], cipher: &[u8]) -> Result<Vec<u8>, KissError> {
+    let ecc = EccCtx::new();
+    let sk = match SigCtx::new().load_seckey(privkey) {
+        Ok(k) => k,
+        Err(_) => return kisserr!(KissErrKind::EFormat, "bad sm2 private key"),
+    };
+    let (seq, _) = DerItem::parse(cipher)?;
+    let fields = seq.expect(DER_TAG_SEQUENCE)?.children()?;
+    if fields.len() != 4 {
+        return kisserr!(KissErrKind::EFormat, "bad sm2 cipher");
+    }
+    let coord = |item: &DerItem| {
+        let v: Vec<u8> = item.body.iter().copied().skip_while(|b| *b == 0).collect();
+        let mut out = vec![0u8; 32usize.saturating_sub(v.len())];
+        out.extend(v);
+        out
+    };
+    let mut c1 = vec![4u8];
+    c1.extend(coord(&fields[0]));
+    c1.extend(coord(&fields[1]));
+    let c1 = match ecc.bytes_to_point(&c1) {
+        Ok(p) => p,
+        Err(_) => return kisserr!(KissErrKind::EFormat, "bad sm2 cipher point"),
+    };
+    let (x2, y2) = ecc.to_affine(&ecc.mul(&sk, &c1));
+    let mut z = x2.to_bytes();
+    z.extend(y2.to_bytes());
+    let c2 = fields[3].body;
+    let t = kdf_sm3(&z, c2.len());
+    let msg: Vec<u8> = c2.iter().zip(t.iter()).map(|(c, k)| c ^ k).collect();
+    let mut c3input = x2.to_bytes();
+    c3input.extend_from_slice(&msg);
+    c3input.extend(y2.to_bytes());
+    if fields[2].body != &sm3(&c3input)[..] {
+        return kisserr!(KissErrKind::EFormat, "sm2 cipher hash mismatch");
+    }
+    Ok(msg)
+}
+
+///从证书的subject里取出CN
+fn subject_cn(subject: &[u8]) -> Result<String, KissError> {
+    let (name, _) = DerItem::parse(subject)?;
+    for rdn in name.children()? {
+        for atv in rdn.children()? {
+            let atv = atv.children()?;
+            if atv.len() == 2 && atv[0].body == OID_COMMON_NAME {
+                return Ok(String::from_utf8_lossy(atv[1].body).to_string());
+            }
+        }
+    }
+    Ok("".to_string())
+}
+
+///握手完成后的连接，读写的是解密后的应用数据，读超时等错误原样透传
+pub(crate) struct TlcpServerStream {
+    tcp: TcpStream,
+    inbuf: Vec<u8>,
+    plain: Vec<u8>,
+    handshake_buf: Vec<u8>,
+    reader: Option<TlcpCipherState>,
+    writer: Option<TlcpCipherState>,
+}
+
+impl TlcpServerStream {
+    ///在已建立的tcp连接上做服务端握手，失败时给客户端发handshake_failure
+    pub(crate) fn accept(
+        tcp: TcpStream,
+        keys: &TlcpServerKeys,
+    ) -> Result<(TlcpServerStream, TlcpHandshakeInfo), KissError> {
+        let mut stream = TlcpServerStream {
+            tcp,
+            inbuf: vec![],
+            plain: vec![],
+            handshake_buf: vec![],
+            reader: None,
+            writer: None,
+        };
+        match stream.handshake(keys) {
+            Ok(info) => Ok((stream, info)),
+            Err(e) => {
+                let _ = stream.write_record(RECORD_ALERT, &[2, ALERT_HANDSHAKE_FAILURE]);
+                let _ = stream.tcp.shutdown(Shutdown::Both);
+                Err(e)
+            }
+        }
+    }
+
+    ///发close_notify并关闭连接
+    pub(crate) fn close(&mut self) {
+        let _ = self.write_record(RECORD_ALERT, &[1, ALERT_CLOSE_NOTIFY]);
+        let _ = self.tcp.shutdown(Shutdown::Both);
+    }
+
+    fn handshake(&mut self, keys: &TlcpServerKeys) -> Result<TlcpHandshakeInfo, KissError> {
+        let mut transcript: Vec<u8> = vec![];
+
+        //ClientHello，套件列表里必须有ECC_SM4_CBC_SM3
+        let body = self.expect_handshake(&mut transcript, HS_CLIENT_HELLO)?;
+        let mut parser = HandshakeParser { buf: &body };
+        let version = parser.take(2)?;
+        if version != TLCP_VERSION {
+            return kisserr!(KissErrKind::EFormat, "bad tlcp version {:?}", version);
+        }
+        let client_random = parser.take(32)?.to_vec();
+        parser.take_u8_vec()?;
+        let suites = parser.take_u16_vec()?;
+        if !suites.chunks(2).any(|s| s == ECC_SM4_CBC_SM3) {
+            return kisserr!(KissErrKind::EFormat, "no supported suite in {:?}", suites);
+        }
+
+        let server_random: [u8; 32] = rand::random();
+        let mut hello = TLCP_VERSION.to_vec();
+        hello.extend_from_slice(&server_random);
+        hello.push(0);
+        hello.extend_from_slice(&ECC_SM4_CBC_SM3);
+        hello.push(0);
+        self.write_handshake(&mut transcript, HS_SERVER_HELLO, &hello)?;
+
+        let mut certlist = vec![];
+        for cert in [&keys.signcert, &keys.encert]
+            .iter()
+            .copied()
+            .chain(keys.chain.iter())
+        {
+            put_u24(&mut certlist, cert.len());
+            certlist.extend_from_slice(cert);
+        }
+        let mut body = vec![];
+        put_u24(&mut body, certlist.len());
+        body.extend(certlist);
+        self.write_handshake(&mut transcript, HS_CERTIFICATE, &body)?;
+
+        let mut signed = client_random.clone();
+        signed.extend_from_slice(&server_random);
+        put_u24(&mut signed, keys.encert.len());
+        signed.extend_from_slice(&keys.encert);
+        let signature = sm2_sign(&keys.signkey, &signed)?;
+        let mut body = (signature.len() as u16).to_be_bytes().to_vec();
+        body.extend(signature);
+        self.write_handshake(&mut transcript, HS_SERVER_KEY_EXCHANGE, &body)?;
+
+        //要求客户端证书，证书类型ecdsa_sign，不限定ca
+        self.write_handshake(&mut transcript, HS_CERTIFICATE_REQUEST, &[1, 64, 0, 0])?;
+        self.write_handshake(&mut transcript, HS_SERVER_HELLO_DONE, &[])?;
+
+        //客户端的签名证书和加密证书
+        let body = self.expect_handshake(&mut transcript, HS_CERTIFICATE)?;
+        let mut parser = HandshakeParser {
+            buf: HandshakeParser { buf: &body }.take_u24_vec()?,
+        };
+        let mut clientcerts = vec![];
+        while !parser.buf.is_empty() {
+            clientcerts.push(SM2Certificate::from_der(parser.take_u24_vec()?)?);
+        }
+        if clientcerts.len() < 2 || !clientcerts[0].verify_signed_by(&keys.cacert) {
+            return kisserr!(
+                KissErrKind::EFormat,
+                "tlcp client certificate not issued by ca"
+            );
+        }
+
+        let body = self.expect_handshake(&mut transcript, HS_CLIENT_KEY_EXCHANGE)?;
+        let encrypted = HandshakeParser { buf: &body }.take_u16_vec()?;
+        let premaster = sm2_decrypt(&keys.enkey, encrypted)?;
+        if premaster.len() != 48 || premaster[..2] != TLCP_VERSION {
+            return kisserr!(KissErrKind::EFormat, "bad tlcp premaster secret");
+        }
+
+        let hash = sm3(&transcript);
+        let body = self.expect_handshake(&mut transcript, HS_CERTIFICATE_VERIFY)?;
+        let signature = HandshakeParser { buf: &body }.take_u16_vec()?;
+        if !sm2_verify(&clientcerts[0].pubkey, &hash, signature) {
+            return kisserr!(KissErrKind::EFormat, "tlcp certificate verify mismatch");
+        }
+
+        let mut seed = client_random.clone();
+        seed.extend_from_slice(&server_random);
+        let master = prf_sm3(&premaster, "master secret", &seed, 48);
+        let mut seed = server_random.to_vec();
+        seed.extend_from_slice(&client_random);
+        let keyblock = prf_sm3(&master, "key expansion", &seed, MAC_LEN * 2 + BLOCK_LEN * 4);
+        let (client_mac, rest) = keyblock.split_at(MAC_LEN);
+        let (server_mac, rest) = rest.split_at(MAC_LEN);
+        let (client_key, rest) = rest.split_at(BLOCK_LEN);
+        let (server_key, _) = rest.split_at(BLOCK_LEN);
+
+        let (rtype, _) = self.read_record()?;
+        if rtype != RECORD_CHANGE_CIPHER_SPEC {
+            return kisserr!(KissErrKind::EFormat, "expect change cipher spec");
+        }
+        self.reader = Some(TlcpCipherState::new(client_mac, client_key));
+        let expected = prf_sm3(
+            &master,
+            "client finished",
+            &sm3(&transcript),
+            VERIFY_DATA_LEN,
+        );
+        if self.expect_handshake(&mut transcript, HS_FINISHED)? != expected {
+            return kisserr!(KissErrKind::EFormat, "tlcp client finished mismatch");
+        }
+
+        self.write_record(RECORD_CHANGE_CIPHER_SPEC, &[1])?;
+        self.writer = Some(TlcpCipherState::new(server_mac, server_key));
+        let verify_data = prf_sm3(
+            &master,
+            "server finished",
+            &sm3(&transcript),
+            VERIFY_DATA_LEN,
+        );
+        self.write_handshake(&mut transcript, HS_FINISHED, &verify_data)?;
+        Ok(TlcpHandshakeInfo {
+            suite: ECC_SM4_CBC_SM3,
+            client_cn: subject_cn(&clientcerts[0].subject)?,
+        })
+    }
+
+    fn write_handshake(
+        &mut self,
+        transcript: &mut Vec<u8>,
+        hstype: u8,
+        body: &[u8],
+    ) -> Result<(), KissError> {
+        let mut msg = vec![hstype];
+        put_u24(&mut msg, body.len());
+        msg.extend_from_slice(body);
+        transcript.extend_from_slice(&msg);
+        self.write_record(RECORD_HANDSHAKE, &msg)
+    }
+
+    fn expect_handshake(
+        &mut self,
+        transcript: &mut Vec<u8>,
+        expected: u8,
+    ) -> Result<Vec<u8>, KissError> {
+        loop {
+            if self.handshake_buf.len() >= 4 {
+                let len = get_u24(&self.handshake_buf[1..4]);
+                if self.handshake_buf.len() >= 4 + len {
+                    let msg: Vec<u8> = self.handshake_buf.drain(..4 + len).collect();
+                    if msg[0] != expected {
+                        return kisserr!(
+                            KissErrKind::EFormat,
+                            "expect tlcp handshake {}, got {}",
+                            expected,
+                            msg[0]
+                        );
+                    }
+                    transcript.extend_from_slice(&msg);
+                    return Ok(msg[4..].to_vec());
+                }
+            }
+            let (rtype, body) = self.read_record()?;
+            if rtype != RECORD_HANDSHAKE {
+                return kisserr!(KissErrKind::EFormat, "unexpected record type {}", rtype);
+            }
+            self.handshake_buf.extend(body);
+        }
+    }
+
+    fn write_record(&mut self, rtype: u8, data: &[u8]) -> Result<(), KissError> {
+        let mut out = vec![];
+        for fragment in data.chunks(MAX_FRAGMENT_LEN) {
+            let payload = match self.writer.as_mut() {
+                Some(writer) => writer.encrypt(rtype, fragment),
+                None => fragment.to_vec(),
+            };
+            out.push(rtype);
+            out.extend_from_slice(&TLCP_VERSION);
+            out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            out.extend(payload);
+        }
+        match self.tcp.write_all(&out) {
+            Ok(_) => Ok(()),
+            Err(e) => kisserr!(KissErrKind::ENetwork, "tlcp write error {:?}", e),
+        }
+    }
+
+    fn take_record(&mut self) -> Result<Option<(u8, Vec<u8>)>, KissError> {
+        if self.inbuf.len() < RECORD_HEADER_LEN {
+            return Ok(None);
+        }
+        let len = ((self.inbuf[3] as usize) << 8) | self.inbuf[4] as usize;
+        if self.inbuf.len() < RECORD_HEADER_LEN + len {
+            return Ok(None);
+        }
+        let record: Vec<u8> = self.inbuf.drain(..RECORD_HEADER_LEN + len).collect();
+        let body = match self.reader.as_mut() {
+            Some(reader) => reader.decrypt(record[0], &record[RECORD_HEADER_LEN..])?,
+            None => record[RECORD_HEADER_LEN..].to_vec(),
+        };
+        Ok(Some((record[0], body)))
+    }
+
+    fn fill_inbuf(&mut self) -> std::io::Result<usize> {
+        let mut buf = vec![0u8; 16 * 1024];
+        let n = self.tcp.read(&mut buf)?;
+        self.inbuf.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    ///握手阶段阻塞读一条record，alert转成错误
+    fn read_record(&mut self) -> Result<(u8, Vec<u8>), KissError> {
+        loop {
+            if let Some((rtype, body)) = self.take_record()? {
+                if rtype == RECORD_ALERT {
+                    return kisserr!(KissErrKind::ENetwork, "tlcp alert from client {:?}", body);
+                }
+                return Ok((rtype, body));
+            }
+            match self.fill_inbuf() {
+                Ok(0) => return kisserr!(KissErrKind::ENetwork, "connection closed by client"),
+                Ok(_) => continue,
+                Err(e) => return kisserr!(KissErrKind::ENetwork, "tlcp read error {:?}", e),
+            }
+        }
+    }
+}
+
+impl Read for TlcpServerStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.plain.is_empty() {
+            let record = self
+                .take_record()
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e.msg))?;
+            match record {
+                Some((RECORD_APPLICATION_DATA, body)) => self.plain.extend(body),
+                Some((RECORD_ALERT, _)) => return Ok(0),
+                Some(_) => continue,
+                None => {
+                    if self.fill_inbuf()? == 0 {
+                        return Ok(0);
+                    }
+                }
+            }
+        }
+        let n = buf.len().min(self.plain.len());
+        buf[..n].copy_from_slice(&self.plain[..n]);
+        self.plain.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for TlcpServerStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_record(RECORD_APPLICATION_DATA, buf)
+            .map_err(|e| std::io::Error::new(ErrorKind::BrokenPipe, e.msg))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.tcp.flush()
+    }
+}
@@ -0,0 +1,96 @@
+/*
+  FISCO BCOS/rust-SDK is a rust client for FISCO BCOS2.0 (https://github.com/FISCO-BCOS/)
+  FISCO BCOS/rust-SDK is free software: you can redistribute it and/or modify it under the
+  terms of the MIT License as published by the Free Software Foundation. This project is
+  distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+  the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+  @author: kentzhang
+  @date: 2021-07
+*/
+//! 离线测试用的mock节点，单元测试里总是可用，其他crate的测试需要打开testsupport feature
+
+pub mod mock_bcos3_node;
+pub mod mock_chain;
+pub mod mock_channel_node;
+pub mod mock_rpc_node;
+pub mod mock_tlcp;
+
+use std::path::Path;
+
+use crate::bcossdkutil::accountutil::{create_account, save_key_to_pem};
+use crate::bcossdkutil::bcosclientconfig::{BcosCryptoKind, ChannelConfig};
+use crate::bcossdkutil::fileutils;
+use crate::bcossdkutil::kisserror::KissError;
+
+///在dir里生成一个ECDSA账户和连接mock节点的配置文件，返回配置文件路径。
+/// protocol是"CHANNEL"或"RPC"，两种方式的地址都写进去
+pub fn write_mock_client_config(
+    dir: &Path,
+    groupid: u32,
+    protocol: &str,
+    rpcurl: &str,
+    channel: &ChannelConfig,
+) -> Result<String, KissError> {
+    let accountpem = dir.join("client.pem").to_string_lossy().to_string();
+    let account = create_account(&BcosCryptoKind::ECDSA);
+    save_key_to_pem(&account.privkey, &accountpem)?;
+    let text = format!(
+        r#"[common]
+crypto = "ECDSA"
+accountpem = "{accountpem}"
+contractpath = "./contracts"
+solc = "./bin/solc"
+solcgm = "./bin/solc-gm"
+
+[bcos3]
+sdk_config_file = "./bcos3sdklib/bcos3_sdk_config.ini"
+group = "group0"
+
+[bcos2]
+chainid = 1
+groupid = {groupid}
+protocol = "{protocol}"
+
+[policy]
+receipt_timeout = 5
+
+[rpc]
+url = "{rpcurl}"
+timeout = 3
+
+[channel]
+ip = "{ip}"
+port = {port}
+tlskind = "{tlskind:?}"
+timeout = {timeout}
+nativelib_echo_mode = 0
+cacert = "{cacert}"
+sdkcert = "{sdkcert}"
+sdkkey = "{sdkkey}"
+gmcacert = "{gmcacert}"
+gmsdkcert = "{gmsdkcert}"
+gmsdkkey = "{gmsdkkey}"
+gmensdkcert = "{gmensdkcert}"
+gmensdkkey = "{gmensdkkey}"
+"#,
+        accountpem = accountpem.replace('\\', "/"),
+        groupid = groupid,
+        protocol = protocol,
+        rpcurl = rpcurl,
+        ip = channel.ip,
+        port = channel.port,
+        timeout = channel.timeout,
+        tlskind = channel.tlskind,
+        cacert = channel.cacert.replace('\\', "/"),
+        sdkcert = channel.sdkcert.replace('\\', "/"),
+        sdkkey = channel.sdkkey.replace('\\', "/"),
+        gmcacert = channel.gmcacert.replace('\\', "/"),
+        gmsdkcert = channel.gmsdkcert.replace('\\', "/"),
+        gmsdkkey = channel.gmsdkkey.replace('\\', "/"),
+        gmensdkcert = channel.gmensdkcert.replace('\\', "/"),
+        gmensdkkey = channel.gmensdkkey.replace('\\', "/"),
+    );
+    let configfile = dir.join("config.toml").to_string_lossy().to_string();
+    fileutils::write_all(&configfile, text.into_bytes())?;
+    Ok(configfile)
+}